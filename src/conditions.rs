@@ -7,23 +7,36 @@ use crate::FastMap;
 /// mutated) values by column index.
 pub trait RowRead {
     fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
     fn value_at(&self, idx: usize) -> &str;
 }
 
+/// Pseudo column name that makes a condition test the cell being mutated.
+pub const SELF_COLUMN: &str = "self";
+
 /// Check if a compiled condition list matches the current row.
 /// Returns true if the list is empty, or if at least one condition matches.
+/// `self_idx` is the index of the column being mutated, which conditions on
+/// `SELF_COLUMN` read.
 pub fn check_conditions(
     conditions: &[CompiledCondition],
     row: &dyn RowRead,
     column_indices: &FastMap<Arc<str>, usize>,
+    self_idx: usize,
 ) -> bool {
     if conditions.is_empty() {
         return true;
     }
     for condition in conditions {
-        let col_idx = match column_indices.get(condition.column_name.as_ref()) {
-            Some(&idx) => idx,
-            None => continue,
+        let col_idx = if condition.column_name.as_ref() == SELF_COLUMN {
+            self_idx
+        } else {
+            match column_indices.get(condition.column_name.as_ref()) {
+                Some(&idx) => idx,
+                None => continue,
+            }
         };
         if col_idx >= row.len() {
             continue;
@@ -32,6 +45,11 @@ pub fn check_conditions(
         let matched = match &condition.op {
             CondOp::Equal(v) => col_value == v.as_str(),
             CondOp::NotEqual(v) => col_value != v.as_str(),
+            CondOp::IEqual(v) => eq_lowercased(col_value, v),
+            CondOp::INotEqual(v) => !eq_lowercased(col_value, v),
+            CondOp::Contains(v) => col_value.contains(v.as_str()),
+            CondOp::StartsWith(v) => col_value.starts_with(v.as_str()),
+            CondOp::EndsWith(v) => col_value.ends_with(v.as_str()),
             CondOp::ByPattern(re) => re.is_match(col_value),
         };
         if matched {
@@ -40,3 +58,12 @@ pub fn check_conditions(
     }
     false
 }
+
+/// Compare `value` against an already-lowercased `folded` string without
+/// allocating, with an ASCII fast path.
+fn eq_lowercased(value: &str, folded: &str) -> bool {
+    if value.is_ascii() && folded.is_ascii() {
+        return value.eq_ignore_ascii_case(folded);
+    }
+    value.chars().flat_map(char::to_lowercase).eq(folded.chars())
+}
@@ -0,0 +1,131 @@
+//! PostgreSQL COPY CSV-format cell quoting.
+//!
+//! In `COPY ... FROM stdin WITH (FORMAT csv)` data, a field may be wrapped
+//! in quotes, inside which the delimiter and line breaks are literal and a
+//! quote (or the escape character) is preceded by the escape character —
+//! by default both are `"`, so a quote is written twice. An unquoted field
+//! equal to the NULL marker (the empty string unless the statement says
+//! otherwise) is SQL NULL, while a quoted one is that string. There are no
+//! backslash escapes.
+
+/// The `QUOTE` and `ESCAPE` characters of a CSV COPY.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CsvQuoting {
+    pub quote: u8,
+    pub escape: u8,
+}
+
+impl Default for CsvQuoting {
+    fn default() -> Self {
+        Self {
+            quote: b'"',
+            escape: b'"',
+        }
+    }
+}
+
+impl CsvQuoting {
+    /// Scan the byte at `i`: the next position, whether a quoted section is
+    /// open after it, and the literal byte it stands for (`None` for a quote
+    /// that opens or closes a section).
+    #[inline]
+    fn step(self, bytes: &[u8], i: usize, quoted: bool) -> (usize, bool, Option<u8>) {
+        let b = bytes[i];
+        if quoted && b == self.escape {
+            if let Some(&next) = bytes.get(i + 1) {
+                if next == self.quote || next == self.escape {
+                    return (i + 2, true, Some(next));
+                }
+            }
+        }
+        if b == self.quote {
+            (i + 1, !quoted, None)
+        } else {
+            (i + 1, quoted, Some(b))
+        }
+    }
+
+    /// Split `line` on `delimiter` into `(start, end)` field spans, appended
+    /// to `spans`. Delimiters inside quotes do not split; spans keep their
+    /// quotes.
+    pub fn field_spans(self, line: &[u8], delimiter: &[u8], spans: &mut Vec<(u32, u32)>) {
+        let mut start = 0;
+        let mut quoted = false;
+        let mut i = 0;
+        while i < line.len() {
+            if !quoted && line[i..].starts_with(delimiter) {
+                spans.push((start as u32, i as u32));
+                i += delimiter.len();
+                start = i;
+            } else {
+                (i, quoted, _) = self.step(line, i, quoted);
+            }
+        }
+        spans.push((start as u32, line.len() as u32));
+    }
+
+    /// Whether `record` stops inside a quoted field, so it continues on the
+    /// next line.
+    pub fn is_open(self, record: &[u8]) -> bool {
+        let mut quoted = false;
+        let mut i = 0;
+        while i < record.len() {
+            (i, quoted, _) = self.step(record, i, quoted);
+        }
+        quoted
+    }
+
+    /// Decode a raw CSV field. Returns `None` when it has no quotes (the raw
+    /// text is already the logical value) or does not decode to UTF-8.
+    pub fn unquote(self, raw: &str) -> Option<String> {
+        let bytes = raw.as_bytes();
+        if !bytes.contains(&self.quote) {
+            return None;
+        }
+        let mut out = Vec::with_capacity(bytes.len());
+        let mut quoted = false;
+        let mut i = 0;
+        while i < bytes.len() {
+            let literal;
+            (i, quoted, literal) = self.step(bytes, i, quoted);
+            out.extend(literal);
+        }
+        String::from_utf8(out).ok()
+    }
+
+    /// Append `value` to `out` as a CSV field. `logical_null` (the marker
+    /// mutations emit for SQL NULL) is written as the table's NULL marker
+    /// `null`; any other value is quoted when it could be misread: it holds
+    /// the delimiter, the quote character or a line break, equals `null`, or
+    /// is `\.`.
+    pub fn quote_into(
+        self,
+        value: &str,
+        delimiter: &[u8],
+        null: &str,
+        logical_null: &str,
+        out: &mut Vec<u8>,
+    ) {
+        if value == logical_null {
+            out.extend_from_slice(null.as_bytes());
+            return;
+        }
+        let bytes = value.as_bytes();
+        let needs_quotes = value == null
+            || value == "\\."
+            || bytes.iter().any(|&b| b == self.quote || b == b'\n' || b == b'\r')
+            || bytes.windows(delimiter.len()).any(|w| w == delimiter);
+        if !needs_quotes {
+            out.extend_from_slice(bytes);
+            return;
+        }
+        out.push(self.quote);
+        for &b in bytes {
+            if b == self.quote || b == self.escape {
+                out.push(self.escape);
+            }
+            out.push(b);
+        }
+        out.push(self.quote);
+    }
+}
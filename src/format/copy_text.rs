@@ -0,0 +1,144 @@
+//! PostgreSQL COPY text-format cell escaping.
+//!
+//! In `COPY ... FROM stdin` data, a backslash introduces an escape: `\t`,
+//! `\n`, `\r`, `\b`, `\f`, `\v`, `\\`, octal `\ddd`, hex `\xhh`, and any
+//! other `\c` stands for `c` itself. A raw delimiter never appears inside a
+//! cell, so splitting a row on it is safe — but mutations must see the
+//! logical (decoded) value, and whatever they produce must be re-escaped
+//! before it is written back.
+
+use memchr::{memchr_iter, memmem};
+
+/// The default SQL NULL marker. A NULL marker is never decoded or escaped.
+pub const NULL_MARKER: &str = "\\N";
+
+/// Decode a raw COPY cell. Returns `None` when the cell has no escapes (the
+/// raw bytes are already the logical value) or is the NULL marker `null`, or
+/// when the decoded bytes are not valid UTF-8.
+pub fn unescape(raw: &str, null: &str) -> Option<String> {
+    if raw == null || !raw.contains('\\') {
+        return None;
+    }
+    let bytes = raw.as_bytes();
+    let mut out: Vec<u8> = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let b = bytes[i];
+        if b != b'\\' || i + 1 == bytes.len() {
+            out.push(b);
+            i += 1;
+            continue;
+        }
+        let c = bytes[i + 1];
+        i += 2;
+        match c {
+            b't' => out.push(b'\t'),
+            b'n' => out.push(b'\n'),
+            b'r' => out.push(b'\r'),
+            b'b' => out.push(0x08),
+            b'f' => out.push(0x0c),
+            b'v' => out.push(0x0b),
+            b'0'..=b'7' => {
+                let mut val = (c - b'0') as u32;
+                let mut digits = 1;
+                while digits < 3 && i < bytes.len() && (b'0'..=b'7').contains(&bytes[i]) {
+                    val = val * 8 + (bytes[i] - b'0') as u32;
+                    i += 1;
+                    digits += 1;
+                }
+                out.push(val as u8);
+            }
+            b'x' if i < bytes.len() && bytes[i].is_ascii_hexdigit() => {
+                let mut val = hex_val(bytes[i]);
+                i += 1;
+                if i < bytes.len() && bytes[i].is_ascii_hexdigit() {
+                    val = val * 16 + hex_val(bytes[i]);
+                    i += 1;
+                }
+                out.push(val);
+            }
+            other => out.push(other),
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+/// Split `line` on `delimiter` into `(start, end)` field spans, appended to
+/// `spans`.
+pub fn field_spans(line: &[u8], delimiter: &[u8], spans: &mut Vec<(u32, u32)>) {
+    let mut start = 0;
+    if let [byte] = delimiter {
+        for i in memchr_iter(*byte, line) {
+            spans.push((start as u32, i as u32));
+            start = i + 1;
+        }
+    } else {
+        for i in memmem::find_iter(line, delimiter) {
+            spans.push((start as u32, i as u32));
+            start = i + delimiter.len();
+        }
+    }
+    spans.push((start as u32, line.len() as u32));
+}
+
+/// Append `value` to `out`, escaped for a COPY text cell. The NULL marker
+/// `null` is written verbatim so mutations can still emit SQL NULL.
+///
+/// With a multi-byte delimiter, every occurrence of its first character is
+/// written as octal escapes, so no delimiter can start inside a cell.
+pub fn escape_into(value: &str, delimiter: &[u8], null: &str, out: &mut Vec<u8>) {
+    if value == null {
+        out.extend_from_slice(value.as_bytes());
+        return;
+    }
+    let (lead, single) = match delimiter {
+        [byte] => (&delimiter[..0], Some(*byte)),
+        _ => (first_char(delimiter), None),
+    };
+    let bytes = value.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if !lead.is_empty() && bytes[i..].starts_with(lead) {
+            for &b in lead {
+                out.extend_from_slice(format!("\\{:03o}", b).as_bytes());
+            }
+            i += lead.len();
+            continue;
+        }
+        let b = bytes[i];
+        i += 1;
+        match b {
+            b'\\' => out.extend_from_slice(b"\\\\"),
+            b'\t' => out.extend_from_slice(b"\\t"),
+            b'\n' => out.extend_from_slice(b"\\n"),
+            b'\r' => out.extend_from_slice(b"\\r"),
+            0x08 => out.extend_from_slice(b"\\b"),
+            0x0c => out.extend_from_slice(b"\\f"),
+            0x0b => out.extend_from_slice(b"\\v"),
+            _ if Some(b) == single => {
+                out.push(b'\\');
+                out.push(b);
+            }
+            _ => out.push(b),
+        }
+    }
+}
+
+/// Bytes of the first UTF-8 character of `delimiter` (its first byte when it
+/// is not UTF-8).
+fn first_char(delimiter: &[u8]) -> &[u8] {
+    let len = std::str::from_utf8(delimiter)
+        .ok()
+        .and_then(|s| s.chars().next())
+        .map_or(1, char::len_utf8);
+    &delimiter[..len.min(delimiter.len())]
+}
+
+#[inline]
+fn hex_val(b: u8) -> u8 {
+    match b {
+        b'0'..=b'9' => b - b'0',
+        b'a'..=b'f' => b - b'a' + 10,
+        _ => b - b'A' + 10,
+    }
+}
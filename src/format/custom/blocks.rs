@@ -1,17 +1,29 @@
 use std::io::{self, Read, Write};
 
+#[cfg(feature = "zlib")]
 use flate2::read::ZlibDecoder;
+#[cfg(feature = "zlib")]
 use flate2::write::ZlibEncoder;
+#[cfg(feature = "zlib")]
 use flate2::Compression;
 use memchr::memrchr;
+#[cfg(feature = "zstd")]
 use zstd::stream::read::Decoder as ZstdDecoder;
+#[cfg(feature = "zstd")]
 use zstd::stream::write::Encoder as ZstdEncoder;
 
 use crate::error::{PgStageError, Result};
 use crate::format::custom::header::CompressionMethod;
 use crate::format::custom::io::DumpIO;
+#[cfg(feature = "parallel")]
+use crate::format::custom::parallel::LinePool;
 use crate::processor::DataProcessor;
 
+/// Block type bytes of the custom format's data area.
+pub const BLK_DATA: u8 = 0x01;
+pub const BLK_BLOBS: u8 = 0x03;
+pub const BLK_END: u8 = 0x04;
+
 const OUTPUT_CHUNK_SIZE: usize = 1024 * 1024;
 const MAX_CHUNK_SIZE: usize = 64 * 1024 * 1024;
 const READ_BUF_SIZE: usize = 2 * 1024 * 1024;
@@ -57,15 +69,15 @@ impl<'a, R: Read> ChunkReader<'a, R> {
                 self.done = true;
                 break;
             }
-            let len = chunk_len.unsigned_abs() as usize;
-            if len > MAX_CHUNK_SIZE {
+            let len = chunk_len.unsigned_abs();
+            if len > MAX_CHUNK_SIZE as u64 {
                 return Err(io::Error::new(
                     io::ErrorKind::InvalidData,
                     format!("Chunk size {} exceeds maximum {}", len, MAX_CHUNK_SIZE),
                 ));
             }
             let start = self.buffer.len();
-            self.buffer.resize(start + len, 0);
+            self.buffer.resize(start + len as usize, 0);
             self.reader.read_exact(&mut self.buffer[start..])?;
         }
         Ok(())
@@ -91,12 +103,20 @@ impl<R: Read> Read for ChunkReader<'_, R> {
     }
 }
 
+/// Stand-in so the block loops read the same without the `parallel` feature.
+#[cfg(not(feature = "parallel"))]
+enum LinePool {}
+
 pub struct BlockProcessor<'a> {
     dio: &'a DumpIO,
     compression: CompressionMethod,
     processor: &'a mut DataProcessor,
+    #[cfg_attr(not(feature = "zstd"), allow(dead_code))]
     zstd_level: i32,
+    #[cfg_attr(not(feature = "zstd"), allow(dead_code))]
     zstd_threads: u32,
+    #[cfg_attr(not(feature = "parallel"), allow(dead_code))]
+    line_threads: usize,
 }
 
 impl<'a> BlockProcessor<'a> {
@@ -113,20 +133,41 @@ impl<'a> BlockProcessor<'a> {
             processor,
             zstd_level,
             zstd_threads,
+            line_threads: 1,
         }
     }
 
+    /// Split each window of a block's rows across `threads` workers (0 = one
+    /// per CPU) when the table's rows are independent; see
+    /// `DataProcessor::rows_independent`. Needs the `parallel` feature.
+    pub fn line_threads(mut self, threads: usize) -> Self {
+        self.line_threads = threads;
+        self
+    }
+
+    fn line_pool(&self) -> Option<LinePool> {
+        #[cfg(feature = "parallel")]
+        return LinePool::new(self.processor, self.line_threads);
+        #[cfg(not(feature = "parallel"))]
+        None
+    }
+
     pub fn process_block<R: Read, W: Write>(
         &mut self,
         reader: &mut R,
         writer: &mut W,
     ) -> Result<()> {
         match self.compression {
+            #[cfg(feature = "zlib")]
             CompressionMethod::Zlib => self.process_block_zlib(reader, writer),
+            #[cfg(feature = "zstd")]
             CompressionMethod::Zstd => self.process_block_zstd(reader, writer),
-            CompressionMethod::None | CompressionMethod::Lz4 => {
-                self.process_block_uncompressed(reader, writer)
-            }
+            CompressionMethod::Lz4 => Err(PgStageError::UnsupportedVersion(
+                "lz4-compressed data blocks cannot be mutated (lz4 is not supported)".to_string(),
+            )),
+            CompressionMethod::None => self.process_block_uncompressed(reader, writer),
+            #[cfg(not(all(feature = "zlib", feature = "zstd")))]
+            disabled => Err(disabled_backend(disabled)),
         }
     }
 
@@ -147,7 +188,7 @@ impl<'a> BlockProcessor<'a> {
             output_buf.extend_from_slice(&read_buf[..n]);
             if output_buf.len() >= OUTPUT_CHUNK_SIZE {
                 for chunk in output_buf.chunks(OUTPUT_CHUNK_SIZE) {
-                    self.dio.write_int(writer, chunk.len() as i32)?;
+                    self.dio.write_int(writer, chunk.len() as i64)?;
                     writer.write_all(chunk)?;
                 }
                 output_buf.clear();
@@ -155,7 +196,7 @@ impl<'a> BlockProcessor<'a> {
         }
 
         if !output_buf.is_empty() {
-            self.dio.write_int(writer, output_buf.len() as i32)?;
+            self.dio.write_int(writer, output_buf.len() as i64)?;
             writer.write_all(&output_buf)?;
         }
 
@@ -173,6 +214,7 @@ impl<'a> BlockProcessor<'a> {
         // Reused tail buffer: one allocation per block instead of one per chunk.
         let mut line_tail: Vec<u8> = Vec::with_capacity(64 * 1024);
         let mut output_buf: Vec<u8> = Vec::with_capacity(OUTPUT_CHUNK_SIZE * 2);
+        let mut pool = self.line_pool();
 
         loop {
             let n = chunk_reader.read(&mut read_buf)?;
@@ -191,7 +233,7 @@ impl<'a> BlockProcessor<'a> {
                     let complete_len = last_nl + 1;
                     let (complete, tail) = data_slice.split_at(complete_len);
                     let tail = tail.to_vec();
-                    process_complete_lines(self.processor, complete, &mut output_buf);
+                    mutate_lines(self.processor, &mut pool, complete, &mut output_buf)?;
                     line_tail.clear();
                     line_tail.extend_from_slice(&tail);
 
@@ -214,6 +256,7 @@ impl<'a> BlockProcessor<'a> {
             }
         }
 
+        finish_pool(self.processor, pool);
         if !output_buf.is_empty() {
             flush_uncompressed(self.dio, writer, &mut output_buf)?;
         }
@@ -222,6 +265,7 @@ impl<'a> BlockProcessor<'a> {
         Ok(())
     }
 
+    #[cfg(feature = "zlib")]
     fn process_block_zlib<R: Read, W: Write>(
         &mut self,
         reader: &mut R,
@@ -234,6 +278,7 @@ impl<'a> BlockProcessor<'a> {
 
         let mut read_buf = vec![0u8; READ_BUF_SIZE];
         let mut line_tail: Vec<u8> = Vec::with_capacity(64 * 1024);
+        let mut pool = self.line_pool();
 
         loop {
             let n = decoder
@@ -253,7 +298,7 @@ impl<'a> BlockProcessor<'a> {
                     let complete_len = last_nl + 1;
                     let (complete, tail) = data_slice.split_at(complete_len);
                     let tail = tail.to_vec();
-                    process_complete_lines_to_writer(self.processor, complete, &mut encoder)?;
+                    mutate_lines(self.processor, &mut pool, complete, &mut encoder)?;
                     line_tail.clear();
                     line_tail.extend_from_slice(&tail);
                     flush_encoder_chunks(self.dio, writer, encoder.get_mut())?;
@@ -274,12 +319,13 @@ impl<'a> BlockProcessor<'a> {
             }
         }
 
+        finish_pool(self.processor, pool);
         let remaining = encoder
             .finish()
             .map_err(|e| PgStageError::CompressionError(format!("Zlib compression finish failed: {}", e)))?;
         if !remaining.is_empty() {
             for chunk in remaining.chunks(OUTPUT_CHUNK_SIZE) {
-                self.dio.write_int(writer, chunk.len() as i32)?;
+                self.dio.write_int(writer, chunk.len() as i64)?;
                 writer.write_all(chunk)?;
             }
         }
@@ -287,6 +333,7 @@ impl<'a> BlockProcessor<'a> {
         Ok(())
     }
 
+    #[cfg(feature = "zstd")]
     fn process_block_zstd<R: Read, W: Write>(
         &mut self,
         reader: &mut R,
@@ -303,6 +350,7 @@ impl<'a> BlockProcessor<'a> {
 
         let mut read_buf = vec![0u8; READ_BUF_SIZE];
         let mut line_tail: Vec<u8> = Vec::with_capacity(64 * 1024);
+        let mut pool = self.line_pool();
 
         loop {
             let n = decoder
@@ -322,7 +370,7 @@ impl<'a> BlockProcessor<'a> {
                     let complete_len = last_nl + 1;
                     let (complete, tail) = data_slice.split_at(complete_len);
                     let tail = tail.to_vec();
-                    process_complete_lines_to_writer(self.processor, complete, &mut encoder)?;
+                    mutate_lines(self.processor, &mut pool, complete, &mut encoder)?;
                     line_tail.clear();
                     line_tail.extend_from_slice(&tail);
                     flush_encoder_chunks_zstd(self.dio, writer, encoder.get_mut())?;
@@ -343,12 +391,13 @@ impl<'a> BlockProcessor<'a> {
             }
         }
 
+        finish_pool(self.processor, pool);
         let remaining = encoder
             .finish()
             .map_err(|e| PgStageError::CompressionError(format!("Zstd compression finish failed: {}", e)))?;
         if !remaining.is_empty() {
             for chunk in remaining.chunks(OUTPUT_CHUNK_SIZE) {
-                self.dio.write_int(writer, chunk.len() as i32)?;
+                self.dio.write_int(writer, chunk.len() as i64)?;
                 writer.write_all(chunk)?;
             }
         }
@@ -357,32 +406,143 @@ impl<'a> BlockProcessor<'a> {
     }
 }
 
-fn process_complete_lines(processor: &mut DataProcessor, data: &[u8], output: &mut Vec<u8>) {
-    if processor.is_delete() {
-        return;
+/// Read one chunked block verbatim, length words and terminating zero
+/// included, so it can be processed later from memory.
+pub fn read_raw_block<R: Read>(dio: &DumpIO, reader: &mut R) -> Result<Vec<u8>> {
+    let mut raw = Vec::new();
+    loop {
+        let chunk_len = dio.read_int(reader)?;
+        dio.write_int(&mut raw, chunk_len)?;
+        if chunk_len == 0 {
+            return Ok(raw);
+        }
+        let len = chunk_len.unsigned_abs();
+        if len > MAX_CHUNK_SIZE as u64 {
+            return Err(PgStageError::InvalidFormat(format!(
+                "Chunk size {} exceeds maximum {}",
+                len, MAX_CHUNK_SIZE
+            )));
+        }
+        let start = raw.len();
+        raw.resize(start + len as usize, 0);
+        reader.read_exact(&mut raw[start..])?;
     }
-    if !processor.has_mutations() {
-        output.extend_from_slice(data);
-        return;
+}
+
+/// Copy one chunked block verbatim, chunk boundaries and terminating zero
+/// included.
+pub fn copy_raw_block<R: Read, W: Write>(
+    dio: &DumpIO,
+    reader: &mut R,
+    writer: &mut W,
+) -> Result<()> {
+    loop {
+        let chunk_len = dio.read_int_bypass(reader, writer)?;
+        if chunk_len == 0 {
+            return Ok(());
+        }
+        let len = chunk_len.unsigned_abs();
+        if len > MAX_CHUNK_SIZE as u64 {
+            return Err(PgStageError::InvalidFormat(format!(
+                "Chunk size {} exceeds maximum {}",
+                len, MAX_CHUNK_SIZE
+            )));
+        }
+        let copied = io::copy(&mut reader.take(len), writer)?;
+        if copied != len {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
     }
-    use memchr::memchr;
-    let mut start = 0;
-    while start < data.len() {
-        let end = memchr(b'\n', &data[start..])
-            .map(|p| start + p)
-            .unwrap_or(data.len());
-        let line = &data[start..end];
-        if let Some(mutated) = processor.process_line(line) {
-            output.extend_from_slice(mutated);
-            if end < data.len() {
-                output.push(b'\n');
+}
+
+/// Copy a BLOBS block (after its id) verbatim. Unlike a data block it holds
+/// one chunked stream per large object, each preceded by the object's OID,
+/// and ends with a zero OID.
+pub fn copy_blobs_block<R: Read, W: Write>(
+    dio: &DumpIO,
+    reader: &mut R,
+    writer: &mut W,
+) -> Result<()> {
+    while dio.read_int_bypass(reader, writer)? != 0 {
+        copy_raw_block(dio, reader, writer)?;
+    }
+    Ok(())
+}
+
+/// Stream decoded COPY data from `reader` through the processor line by line
+/// into `writer`. Used by the directory and tar formats, where each table's
+/// data is a standalone (optionally compressed) file rather than a chunked
+/// block.
+pub fn mutate_copy_stream<R: Read, W: Write>(
+    processor: &mut DataProcessor,
+    reader: &mut R,
+    writer: &mut W,
+) -> Result<()> {
+    let mut read_buf = vec![0u8; READ_BUF_SIZE];
+    let mut line_tail: Vec<u8> = Vec::with_capacity(64 * 1024);
+
+    loop {
+        let n = reader.read(&mut read_buf)?;
+        if n == 0 {
+            break;
+        }
+        let data_slice: &[u8] = if line_tail.is_empty() {
+            &read_buf[..n]
+        } else {
+            line_tail.extend_from_slice(&read_buf[..n]);
+            line_tail.as_slice()
+        };
+        match memrchr(b'\n', data_slice) {
+            Some(last_nl) => {
+                let complete_len = last_nl + 1;
+                let (complete, tail) = data_slice.split_at(complete_len);
+                let tail = tail.to_vec();
+                process_complete_lines_to_writer(processor, complete, writer)?;
+                line_tail.clear();
+                line_tail.extend_from_slice(&tail);
+            }
+            None => {
+                if line_tail.is_empty() {
+                    line_tail.extend_from_slice(&read_buf[..n]);
+                }
             }
         }
-        start = end + 1;
     }
+
+    if !line_tail.is_empty() {
+        if let Some(mutated) = processor.process_line(&line_tail) {
+            writer.write_all(mutated)?;
+        }
+    }
+    Ok(())
 }
 
-fn process_complete_lines_to_writer<W: Write>(
+/// Mutate a run of complete lines into `writer`, split across `pool` when
+/// there is one.
+fn mutate_lines<W: Write>(
+    processor: &mut DataProcessor,
+    pool: &mut Option<LinePool>,
+    data: &[u8],
+    writer: &mut W,
+) -> Result<()> {
+    match pool {
+        #[cfg(feature = "parallel")]
+        Some(pool) => pool.mutate(processor, data, writer),
+        _ => process_complete_lines_to_writer(processor, data, writer),
+    }
+}
+
+/// Fold a block's worker counters back into `processor`.
+fn finish_pool(processor: &mut DataProcessor, pool: Option<LinePool>) {
+    #[cfg(feature = "parallel")]
+    if let Some(pool) = pool {
+        pool.finish(processor);
+    }
+    #[cfg(not(feature = "parallel"))]
+    let _ = (processor, pool);
+}
+
+pub(super) fn process_complete_lines_to_writer<W: Write>(
     processor: &mut DataProcessor,
     data: &[u8],
     writer: &mut W,
@@ -424,13 +584,14 @@ fn flush_uncompressed<W: Write>(
     output_buf: &mut Vec<u8>,
 ) -> Result<()> {
     for chunk in output_buf.chunks(OUTPUT_CHUNK_SIZE) {
-        dio.write_int(writer, chunk.len() as i32)?;
+        dio.write_int(writer, chunk.len() as i64)?;
         writer.write_all(chunk)?;
     }
     output_buf.clear();
     Ok(())
 }
 
+#[cfg(feature = "zlib")]
 fn flush_encoder_chunks<W: Write>(
     dio: &DumpIO,
     writer: &mut W,
@@ -438,7 +599,7 @@ fn flush_encoder_chunks<W: Write>(
 ) -> Result<()> {
     if inner.len() >= OUTPUT_CHUNK_SIZE {
         for chunk in inner.chunks(OUTPUT_CHUNK_SIZE) {
-            dio.write_int(writer, chunk.len() as i32)?;
+            dio.write_int(writer, chunk.len() as i64)?;
             writer.write_all(chunk)?;
         }
         inner.clear();
@@ -446,6 +607,7 @@ fn flush_encoder_chunks<W: Write>(
     Ok(())
 }
 
+#[cfg(feature = "zstd")]
 fn flush_encoder_chunks_zstd<W: Write>(
     dio: &DumpIO,
     writer: &mut W,
@@ -453,10 +615,24 @@ fn flush_encoder_chunks_zstd<W: Write>(
 ) -> Result<()> {
     if inner.len() >= OUTPUT_CHUNK_SIZE {
         for chunk in inner.chunks(OUTPUT_CHUNK_SIZE) {
-            dio.write_int(writer, chunk.len() as i32)?;
+            dio.write_int(writer, chunk.len() as i64)?;
             writer.write_all(chunk)?;
         }
         inner.clear();
     }
     Ok(())
 }
+
+/// Error for a block whose compression backend was compiled out.
+#[cfg(not(all(feature = "zlib", feature = "zstd")))]
+fn disabled_backend(method: CompressionMethod) -> PgStageError {
+    let feature = match method {
+        CompressionMethod::Zlib => "zlib",
+        CompressionMethod::Zstd => "zstd",
+        CompressionMethod::Lz4 | CompressionMethod::None => "none",
+    };
+    PgStageError::UnsupportedVersion(format!(
+        "{:?}-compressed data requires pg_stage_rs built with the `{}` feature",
+        method, feature
+    ))
+}
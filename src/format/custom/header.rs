@@ -4,6 +4,11 @@ use crate::error::{PgStageError, Result};
 use crate::format::custom::io::DumpIO;
 use crate::format::MAGIC_HEADER;
 
+/// `ArchiveFormat` byte of the archive header (pg_backup.h).
+pub const FORMAT_CUSTOM: u8 = 1;
+pub const FORMAT_TAR: u8 = 3;
+pub const FORMAT_DIRECTORY: u8 = 5;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CompressionMethod {
     None,
@@ -38,11 +43,20 @@ pub fn parse_header<R: Read, W: Write>(
     reader: &mut R,
     writer: &mut W,
     initial_bytes: &[u8],
-    verbose: bool,
 ) -> Result<Header> {
-    if verbose {
-        eprintln!("[DEBUG] initial_bytes ({} bytes): {:02X?}", initial_bytes.len(), initial_bytes);
-    }
+    parse_archive_header(reader, writer, initial_bytes, FORMAT_CUSTOM)
+}
+
+/// Parse an archive header and require its format byte to be `expected`.
+/// The custom, directory (`toc.dat`) and tar (`toc.dat` member) formats all
+/// share this header layout.
+pub fn parse_archive_header<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    initial_bytes: &[u8],
+    expected: u8,
+) -> Result<Header> {
+    log::debug!("initial_bytes ({} bytes): {:02X?}", initial_bytes.len(), initial_bytes);
 
     // Write initial bytes (the magic we already consumed for detection)
     writer.write_all(initial_bytes)?;
@@ -77,10 +91,8 @@ pub fn parse_header<R: Read, W: Write>(
     let vrev = DumpIO::read_byte(reader)?;
     writer.write_all(&[vrev])?;
 
-    if verbose {
-        eprintln!("[DEBUG] Version bytes: [{:02X}, {:02X}, {:02X}]", vmaj, vmin, vrev);
-        eprintln!("[INFO] pg_dump format version: {}.{}.{}", vmaj, vmin, vrev);
-    }
+    log::debug!("Version bytes: [{:02X}, {:02X}, {:02X}]", vmaj, vmin, vrev);
+    log::info!("pg_dump format version: {}.{}.{}", vmaj, vmin, vrev);
 
     // custom.py validation: < 1.12 or > 1.16 is unsupported
     if vmaj < 1 || (vmaj == 1 && vmin < 12) {
@@ -104,10 +116,7 @@ pub fn parse_header<R: Read, W: Write>(
     let offset_size = DumpIO::read_byte(reader)? as usize;
     writer.write_all(&[offset_size as u8])?;
 
-    if verbose {
-        eprintln!("[DEBUG] int_size={:02X}, offset_size={:02X}", int_size, offset_size);
-        eprintln!("[INFO] int_size={}, offset_size={}", int_size, offset_size);
-    }
+    log::info!("int_size={}, offset_size={}", int_size, offset_size);
 
     // Validate sizes
     if int_size == 0 || int_size > 8 || offset_size == 0 || offset_size > 8 {
@@ -120,14 +129,17 @@ pub fn parse_header<R: Read, W: Write>(
     let format = DumpIO::read_byte(reader)?;
     writer.write_all(&[format])?;
 
-    if verbose {
-        eprintln!("[DEBUG] format byte={:02X}", format);
-    }
+    log::debug!("format byte={:02X}", format);
 
-    if format != 1 {
+    if format != expected {
+        let name = match expected {
+            FORMAT_TAR => "tar",
+            FORMAT_DIRECTORY => "directory",
+            _ => "custom",
+        };
         return Err(PgStageError::InvalidFormat(format!(
-            "Expected custom format (1), got {}",
-            format
+            "Expected {} format ({}), got {}",
+            name, expected, format
         )));
     }
 
@@ -161,7 +173,7 @@ pub fn parse_header<R: Read, W: Write>(
         // 0 = no compression
         // -1 = default zlib (level 6)
         // 1-9 = zlib with that level
-        let level = if verbose {
+        let level = if log::log_enabled!(log::Level::Debug) {
             dio.read_int_bypass_debug(reader, writer, "Compression")?
         } else {
             dio.read_int_bypass(reader, writer)?
@@ -179,9 +191,7 @@ pub fn parse_header<R: Read, W: Write>(
         }
     };
 
-    if verbose {
-        eprintln!("[INFO] Compression: {:?}", compression);
-    }
+    log::info!("Compression: {:?}", compression);
 
     // Timestamp: custom.py reads 7 integers (sec, min, hour, mday, mon, year, isdst)
     // The 7th integer is ignored in Python (_isdst), but must be read/written to maintain sync.
@@ -191,25 +201,16 @@ pub fn parse_header<R: Read, W: Write>(
 
     // Database name (string)
     let db_name = dio.read_string_bypass(reader, writer)?;
-    if verbose {
-        eprintln!("[INFO] Database: {:?}", db_name.as_deref().unwrap_or(""));
-    }
+    log::info!("Database: {:?}", db_name.as_deref().unwrap_or(""));
 
     // Server version (string)
     let server_ver = dio.read_string_bypass(reader, writer)?;
-    if verbose {
-        eprintln!("[INFO] Server version: {:?}", server_ver.as_deref().unwrap_or(""));
-    }
+    log::info!("Server version: {:?}", server_ver.as_deref().unwrap_or(""));
 
     // Dump version (string)
     let dump_ver = dio.read_string_bypass(reader, writer)?;
-    if verbose {
-        eprintln!("[INFO] pg_dump version: {:?}", dump_ver.as_deref().unwrap_or(""));
-
-        // Debug: peek next 10 bytes to see what TOC count will read
-        // Note: this is destructive for the actual reading, so we just log the position
-        eprintln!("[DEBUG] Header parsing complete. Next bytes should be TOC count.");
-    }
+    log::info!("pg_dump version: {:?}", dump_ver.as_deref().unwrap_or(""));
+    log::debug!("Header parsing complete. Next bytes should be TOC count.");
 
     Ok(Header {
         vmaj,
@@ -5,7 +5,8 @@ use crate::error::Result;
 /// Binary I/O utilities for PostgreSQL custom dump format.
 ///
 /// This implementation matches the Python `custom.py` helper:
-/// - Integers: 1 byte sign (0=pos, 1=neg) + int_size bytes magnitude (little-endian).
+/// - Integers: 1 byte sign (0=pos, 1=neg) + int_size bytes magnitude (little-endian),
+///   decoded as `i64` so 8-byte ints do not wrap.
 /// - Strings: Integer length + UTF-8 bytes.
 /// - Offsets: offset_size bytes (little-endian).
 pub struct DumpIO {
@@ -25,7 +26,7 @@ impl DumpIO {
     }
 
     /// Read a signed integer: 1 sign byte + int_size little-endian magnitude bytes.
-    pub fn read_int<R: Read>(&self, reader: &mut R) -> Result<i32> {
+    pub fn read_int<R: Read>(&self, reader: &mut R) -> Result<i64> {
         self.read_int_inner(reader, None::<&mut std::io::Sink>)
     }
 
@@ -34,25 +35,25 @@ impl DumpIO {
         &self,
         reader: &mut R,
         writer: &mut W,
-    ) -> Result<i32> {
+    ) -> Result<i64> {
         self.read_int_inner(reader, Some(writer))
     }
 
-    /// Debug version — reads, bypasses, and logs raw bytes to stderr.
+    /// Debug version — reads, bypasses, and logs raw bytes at debug level.
     pub fn read_int_bypass_debug<R: Read, W: Write>(
         &self,
         reader: &mut R,
         writer: &mut W,
         label: &str,
-    ) -> Result<i32> {
+    ) -> Result<i64> {
         // Read sign+magnitude onto a stack buffer, bypass to writer, then decode.
         let mut stack = [0u8; 9];
         let total = 1 + self.int_size;
         reader.read_exact(&mut stack[..total])?;
         writer.write_all(&stack[..total])?;
         let value = decode_int(stack[0], &stack[1..1 + self.int_size]);
-        eprintln!(
-            "[DEBUG] {} raw bytes: sign={:02X} magnitude={:02X?} -> value={}",
+        log::debug!(
+            "{} raw bytes: sign={:02X} magnitude={:02X?} -> value={}",
             label,
             stack[0],
             &stack[1..1 + self.int_size],
@@ -65,7 +66,7 @@ impl DumpIO {
         &self,
         reader: &mut R,
         writer: Option<&mut W>,
-    ) -> Result<i32> {
+    ) -> Result<i64> {
         let mut stack = [0u8; 9];
         let total = 1 + self.int_size;
         reader.read_exact(&mut stack[..total])?;
@@ -76,12 +77,9 @@ impl DumpIO {
     }
 
     /// Write a signed integer as `1 byte sign + int_size bytes`.
-    pub fn write_int<W: Write>(&self, writer: &mut W, val: i32) -> Result<()> {
-        let (sign, v_abs) = if val < 0 {
-            (1u8, val.wrapping_neg())
-        } else {
-            (0u8, val)
-        };
+    pub fn write_int<W: Write>(&self, writer: &mut W, val: i64) -> Result<()> {
+        let sign = if val < 0 { 1u8 } else { 0u8 };
+        let v_abs = val.unsigned_abs();
         let mut buf = [0u8; 9];
         buf[0] = sign;
         let mut current = v_abs;
@@ -139,6 +137,12 @@ impl DumpIO {
         Ok(decode_offset(&buf[..self.offset_size]))
     }
 
+    /// Write an offset as `offset_size` little-endian bytes.
+    pub fn write_offset<W: Write>(&self, writer: &mut W, val: u64) -> Result<()> {
+        writer.write_all(&val.to_le_bytes()[..self.offset_size])?;
+        Ok(())
+    }
+
     /// Read exactly n bytes.
     pub fn read_exact<R: Read>(reader: &mut R, n: usize) -> Result<Vec<u8>> {
         let mut buf = vec![0u8; n];
@@ -159,13 +163,43 @@ impl DumpIO {
     }
 }
 
+/// A writer that counts the bytes written through it.
+pub struct CountingWriter<W> {
+    inner: W,
+    position: u64,
+}
+
+impl<W: Write> CountingWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self { inner, position: 0 }
+    }
+
+    /// Bytes written so far.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.position += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
 #[inline]
-fn decode_int(sign: u8, magnitude: &[u8]) -> i32 {
-    let mut value: i32 = 0;
+fn decode_int(sign: u8, magnitude: &[u8]) -> i64 {
+    let mut value: u64 = 0;
     for (i, &b) in magnitude.iter().enumerate() {
-        value |= (b as i32) << (i * 8);
+        value |= (b as u64) << (i * 8);
     }
-    if sign != 0 { -value } else { value }
+    let value = value as i64;
+    if sign != 0 { value.wrapping_neg() } else { value }
 }
 
 #[inline]
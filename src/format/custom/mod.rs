@@ -1,41 +1,46 @@
 pub mod blocks;
 pub mod header;
 pub mod io;
+#[cfg(feature = "parallel")]
+mod parallel;
+mod recover;
 pub mod toc;
 
-use std::io::{BufReader, BufWriter, Read, Write};
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
 
 use crate::error::Result;
-use crate::format::custom::blocks::BlockProcessor;
-use crate::format::custom::header::parse_header;
-use crate::format::custom::io::DumpIO;
-use crate::format::custom::toc::{parse_toc, Section, TocEntry};
+use crate::format::custom::blocks::{
+    copy_blobs_block, BlockProcessor, BLK_BLOBS, BLK_DATA, BLK_END,
+};
+use crate::format::custom::header::{parse_header, CompressionMethod};
+use crate::format::custom::io::{CountingWriter, DumpIO};
+use crate::format::custom::toc::{parse_toc, DataState, Section, TocEntry};
 use crate::processor::DataProcessor;
 use crate::FastMap;
 
 /// Handler for PostgreSQL custom format dumps (-Fc).
 pub struct CustomHandler {
     processor: DataProcessor,
-    verbose: bool,
     zstd_level: i32,
     zstd_threads: u32,
+    #[cfg_attr(not(feature = "parallel"), allow(dead_code))]
+    threads: usize,
+    line_threads: usize,
+    skip_corrupt: bool,
 }
 
 impl CustomHandler {
     pub fn new(processor: DataProcessor) -> Self {
         Self {
             processor,
-            verbose: false,
             zstd_level: 1,
             zstd_threads: 0,
+            threads: 1,
+            line_threads: 1,
+            skip_corrupt: false,
         }
     }
 
-    pub fn verbose(mut self, verbose: bool) -> Self {
-        self.verbose = verbose;
-        self
-    }
-
     pub fn zstd_level(mut self, level: i32) -> Self {
         self.zstd_level = level;
         self
@@ -46,21 +51,125 @@ impl CustomHandler {
         self
     }
 
+    /// Process data blocks on `threads` worker threads (0 = one per CPU,
+    /// 1 = serially on the calling thread). Output is written in input
+    /// order and is identical to a serial run with the same seed. Dumps that
+    /// use `relations`, and builds without the `parallel` feature, are
+    /// always processed serially.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// Split the rows of each data block across `threads` workers (0 = one
+    /// per CPU, 1 = off). Only tables without `unique` or `relations` are
+    /// split; their RNG-drawn values differ from a serial run but are
+    /// reproducible under a seed. Without the `parallel` feature this is a
+    /// no-op.
+    pub fn line_threads(mut self, threads: usize) -> Self {
+        self.line_threads = threads;
+        self
+    }
+
+    /// Instead of aborting on a block whose chunk framing is broken, log it,
+    /// copy its bytes unchanged and resume at the next recognisable block
+    /// header. Blocks are then read whole into memory and processed on one
+    /// thread.
+    pub fn skip_corrupt(mut self, skip: bool) -> Self {
+        self.skip_corrupt = skip;
+        self
+    }
+
+    /// The processor, with its counters, once the dump has been processed.
+    pub fn into_processor(self) -> DataProcessor {
+        self.processor
+    }
+
+    /// Rewrite a dump to a stream. Data blocks change size, so the TOC's
+    /// data offsets are cleared, as pg_dump does when writing to a pipe;
+    /// `pg_restore` then finds each block by scanning.
     pub fn process<R: Read, W: Write>(
         &mut self,
         reader: R,
         writer: W,
         initial_bytes: &[u8],
     ) -> Result<()> {
-        let mut reader = BufReader::with_capacity(2 * 1024 * 1024, reader);
         let mut writer = BufWriter::with_capacity(2 * 1024 * 1024, writer);
+        self.rewrite(reader, &mut writer, initial_bytes)?;
+        writer.flush()?;
+        Ok(())
+    }
 
-        let header = parse_header(&mut reader, &mut writer, initial_bytes, self.verbose)?;
-        let entries = parse_toc(&mut reader, &mut writer, &header, self.verbose)?;
+    /// Rewrite a dump to a file, then seek back and fill in the TOC's data
+    /// offsets with where each block actually landed, so the output keeps
+    /// supporting random access and `pg_restore -j`. `writer` must be at the
+    /// start of the archive.
+    pub fn process_seekable<R: Read, W: Write + Seek>(
+        &mut self,
+        reader: R,
+        writer: W,
+        initial_bytes: &[u8],
+    ) -> Result<()> {
+        let mut writer = BufWriter::with_capacity(2 * 1024 * 1024, writer);
+        let base = writer.stream_position()?;
+        let offsets = self.rewrite(reader, &mut writer, initial_bytes)?;
+        offsets.write_to(&mut writer, base)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    fn rewrite<R: Read, W: Write>(
+        &mut self,
+        reader: R,
+        writer: &mut W,
+        initial_bytes: &[u8],
+    ) -> Result<DataOffsets> {
+        let mut reader = BufReader::with_capacity(2 * 1024 * 1024, reader);
+
+        // The header and TOC are held back until the stale offsets in them
+        // are cleared.
+        let mut head = Vec::new();
+        let header = parse_header(&mut reader, &mut head, initial_bytes)?;
+        let toc_start = head.len() as u64;
+        let entries = parse_toc(&mut reader, &mut head, &header)?;
 
         self.extract_comments(&entries);
+        self.processor.check_rules()?;
         let data_entries = self.build_data_map(&entries);
         let dio = DumpIO::new(header.int_size, header.offset_size);
+        let offsets = DataOffsets::clear(&mut head, toc_start, &entries, header.offset_size);
+        let mut writer = BlockWriter::new(writer);
+        writer.write_all(&head)?;
+
+        if self.skip_corrupt {
+            let block_ids = entries.iter().map(|e| e.dump_id).collect();
+            recover::process_blocks(
+                self,
+                &mut reader,
+                &mut writer,
+                &dio,
+                header.compression,
+                &data_entries,
+                block_ids,
+            )?;
+            return Ok(self.finish(offsets, writer));
+        }
+
+        #[cfg(feature = "parallel")]
+        if self.threads != 1 {
+            if !self.processor.registry.has_relations() {
+                parallel::process_blocks(
+                    self,
+                    &mut reader,
+                    &mut writer,
+                    &dio,
+                    header.compression,
+                    &data_entries,
+                )?;
+                return Ok(self.finish(offsets, writer));
+            }
+            log::info!("relations configured, processing data blocks on one thread");
+        }
 
         loop {
             let mut block_type = [0u8; 1];
@@ -70,45 +179,29 @@ impl CustomHandler {
                 Err(e) => return Err(e.into()),
             }
 
-            if block_type[0] == 0x04 {
+            if block_type[0] == BLK_END {
                 writer.write_all(&block_type)?;
-                writer.flush()?;
                 break;
             }
 
-            if block_type[0] == 0x01 {
-                let dump_id = dio.read_int(&mut reader)?;
-                if let Some(info) = data_entries.get(&dump_id) {
-                    if !info.copy_stmt.is_empty() {
-                        self.processor.setup_table(&info.copy_stmt);
-                    }
-                    if self.processor.has_mutations() || self.processor.is_delete() {
-                        writer.write_all(&block_type)?;
-                        dio.write_int(&mut writer, dump_id)?;
-                        let mut bp = BlockProcessor::new(
-                            &dio,
-                            header.compression,
-                            &mut self.processor,
-                            self.zstd_level,
-                            self.zstd_threads,
-                        );
-                        bp.process_block(&mut reader, &mut writer)?;
-                    } else {
-                        writer.write_all(&block_type)?;
-                        dio.write_int(&mut writer, dump_id)?;
-                        let bp = BlockProcessor::new(
-                            &dio,
-                            header.compression,
-                            &mut self.processor,
-                            self.zstd_level,
-                            self.zstd_threads,
-                        );
-                        bp.pass_through_block(&mut reader, &mut writer)?;
-                    }
-                    self.processor.reset_table();
-                } else {
-                    writer.write_all(&block_type)?;
-                    dio.write_int(&mut writer, dump_id)?;
+            let dump_id = dio.read_int(&mut reader)?;
+            writer.start_block(dump_id);
+            writer.write_all(&block_type)?;
+            dio.write_int(&mut writer, dump_id)?;
+            match data_entries.get(&dump_id) {
+                Some(info) if block_type[0] == BLK_DATA => {
+                    let settings = self.block_settings(&dio, header.compression);
+                    settings.copy_data_block(
+                        &mut self.processor,
+                        &info.copy_stmt,
+                        &mut reader,
+                        &mut writer,
+                    )?;
+                }
+                _ if block_type[0] == BLK_BLOBS => {
+                    copy_blobs_block(&dio, &mut reader, &mut writer)?;
+                }
+                _ => {
                     let bp = BlockProcessor::new(
                         &dio,
                         header.compression,
@@ -118,35 +211,45 @@ impl CustomHandler {
                     );
                     bp.pass_through_block(&mut reader, &mut writer)?;
                 }
-            } else {
-                writer.write_all(&block_type)?;
-                let dump_id = dio.read_int(&mut reader)?;
-                dio.write_int(&mut writer, dump_id)?;
-                let bp = BlockProcessor::new(
-                    &dio,
-                    header.compression,
-                    &mut self.processor,
-                    self.zstd_level,
-                    self.zstd_threads,
-                );
-                bp.pass_through_block(&mut reader, &mut writer)?;
             }
         }
 
-        writer.flush()?;
+        Ok(self.finish(offsets, writer))
+    }
+
+    fn finish<W: Write>(&self, mut offsets: DataOffsets, writer: BlockWriter<W>) -> DataOffsets {
         self.processor.emit_summary();
-        Ok(())
+        offsets.starts = writer.starts;
+        offsets
+    }
+
+    fn block_settings<'a>(
+        &self,
+        dio: &'a DumpIO,
+        compression: CompressionMethod,
+    ) -> BlockSettings<'a> {
+        BlockSettings {
+            dio,
+            compression,
+            zstd_level: self.zstd_level,
+            zstd_threads: self.zstd_threads,
+            line_threads: self.line_threads,
+        }
     }
 
     fn extract_comments(&mut self, entries: &[TocEntry]) {
         for entry in entries {
             if entry.desc == "COMMENT" {
                 self.processor.parse_comment(&entry.defn);
+            } else if entry.desc == "TABLE" {
+                self.processor.parse_create_table(&entry.defn);
+            } else if entry.desc == "TYPE" {
+                self.processor.parse_create_type(&entry.defn);
             }
         }
     }
 
-    fn build_data_map(&self, entries: &[TocEntry]) -> FastMap<i32, DataEntryInfo> {
+    fn build_data_map(&self, entries: &[TocEntry]) -> FastMap<i64, DataEntryInfo> {
         let mut map = FastMap::new();
         for entry in entries {
             if entry.section == Section::Data || entry.desc == "TABLE DATA" {
@@ -165,3 +268,121 @@ impl CustomHandler {
 struct DataEntryInfo {
     copy_stmt: String,
 }
+
+/// The output writer for data blocks, noting where each block starts.
+pub(super) struct BlockWriter<W> {
+    out: CountingWriter<W>,
+    /// Position of each block's type byte, counted from the start of the
+    /// archive.
+    starts: FastMap<i64, u64>,
+}
+
+impl<W: Write> BlockWriter<W> {
+    fn new(out: W) -> Self {
+        Self {
+            out: CountingWriter::new(out),
+            starts: FastMap::new(),
+        }
+    }
+
+    /// Record that the block for `dump_id` starts with the next byte.
+    pub(super) fn start_block(&mut self, dump_id: i64) {
+        self.starts.insert(dump_id, self.out.position());
+    }
+}
+
+impl<W: Write> Write for BlockWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.out.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// The TOC's data offset fields, and where the blocks they point to were
+/// written.
+struct DataOffsets {
+    /// Dump id and position of the data state byte of each entry with data.
+    slots: Vec<(i64, u64)>,
+    starts: FastMap<i64, u64>,
+    offset_size: usize,
+}
+
+impl DataOffsets {
+    /// Mark every entry with data in `head` (header and TOC, the TOC
+    /// starting at `toc_start`) as having no recorded offset.
+    fn clear(head: &mut [u8], toc_start: u64, entries: &[TocEntry], offset_size: usize) -> Self {
+        let mut slots = Vec::new();
+        for entry in entries.iter().filter(|e| e.data_state.has_data()) {
+            let pos = toc_start + entry.offset_pos;
+            let at = pos as usize;
+            head[at] = DataState::PosNotSet.as_byte();
+            head[at + 1..at + 1 + offset_size].fill(0);
+            slots.push((entry.dump_id, pos));
+        }
+        Self {
+            slots,
+            starts: FastMap::new(),
+            offset_size,
+        }
+    }
+
+    /// Fill in the offset of every entry whose block was written, then
+    /// return to the end of the output.
+    fn write_to<W: Write + Seek>(&self, writer: &mut W, base: u64) -> Result<()> {
+        let dio = DumpIO::new(0, self.offset_size);
+        for &(dump_id, pos) in &self.slots {
+            if let Some(&start) = self.starts.get(&dump_id) {
+                writer.seek(SeekFrom::Start(base + pos))?;
+                writer.write_all(&[DataState::PosSet.as_byte()])?;
+                dio.write_offset(writer, start)?;
+            }
+        }
+        writer.seek(SeekFrom::End(0))?;
+        Ok(())
+    }
+}
+
+/// What a data block needs besides the processor, so workers can share it.
+#[derive(Clone, Copy)]
+struct BlockSettings<'a> {
+    dio: &'a DumpIO,
+    compression: CompressionMethod,
+    zstd_level: i32,
+    zstd_threads: u32,
+    line_threads: usize,
+}
+
+impl BlockSettings<'_> {
+    /// Rewrite one table's data block (after its id) through `processor`, or
+    /// copy it unchanged when the table has no mutations.
+    fn copy_data_block<R: Read, W: Write>(
+        self,
+        processor: &mut DataProcessor,
+        copy_stmt: &str,
+        reader: &mut R,
+        writer: &mut W,
+    ) -> Result<()> {
+        if !copy_stmt.is_empty() {
+            processor.setup_table(copy_stmt);
+        }
+        let mutate = processor.has_mutations() || processor.is_delete();
+        let mut bp = BlockProcessor::new(
+            self.dio,
+            self.compression,
+            processor,
+            self.zstd_level,
+            self.zstd_threads,
+        )
+        .line_threads(self.line_threads);
+        if mutate {
+            bp.process_block(reader, writer)?;
+        } else {
+            bp.pass_through_block(reader, writer)?;
+        }
+        processor.reset_table();
+        Ok(())
+    }
+}
@@ -0,0 +1,311 @@
+//! Multi-threaded data block processing.
+//!
+//! Across blocks (`CustomHandler::threads`): the calling thread reads blocks
+//! in archive order. Table data blocks are handed to workers, each owning a
+//! `DataProcessor::fork`; every other block is copied as is. Finished blocks
+//! wait in a reorder buffer until all blocks before them are written, so the
+//! output keeps the input's block order.
+//!
+//! Within a block (`CustomHandler::line_threads`): each window of complete
+//! lines is cut into one share per worker, the shares are mutated
+//! concurrently and written back in order (`LinePool`).
+
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::thread;
+
+use crossbeam_channel::{bounded, unbounded, Receiver, Sender};
+use memchr::memchr;
+
+use crate::error::{PgStageError, Result};
+use crate::format::custom::blocks::{
+    copy_blobs_block, process_complete_lines_to_writer, read_raw_block, BlockProcessor,
+    BLK_BLOBS, BLK_DATA, BLK_END,
+};
+use crate::format::custom::header::CompressionMethod;
+use crate::format::custom::io::DumpIO;
+use crate::format::custom::{BlockWriter, CustomHandler, DataEntryInfo};
+use crate::processor::DataProcessor;
+use crate::FastMap;
+
+/// Windows smaller than this are mutated on the calling thread.
+const MIN_SPLIT_SIZE: usize = 64 * 1024;
+
+struct Job {
+    seq: u64,
+    copy_stmt: String,
+    /// Block type and dump id, already encoded for the output.
+    head: Vec<u8>,
+    raw: Vec<u8>,
+}
+
+type Done = (u64, Result<Vec<u8>>);
+
+pub(super) fn process_blocks<R: Read, W: Write>(
+    handler: &mut CustomHandler,
+    reader: &mut R,
+    writer: &mut BlockWriter<W>,
+    dio: &DumpIO,
+    compression: CompressionMethod,
+    data_entries: &FastMap<i64, DataEntryInfo>,
+) -> Result<()> {
+    let settings = handler.block_settings(dio, compression);
+    let threads = thread_count(handler.threads);
+    let (job_tx, job_rx) = bounded::<Job>(threads * 2);
+    let (done_tx, done_rx) = unbounded::<Done>();
+
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                let mut processor = handler.processor.fork();
+                let job_rx = job_rx.clone();
+                let done_tx = done_tx.clone();
+                scope.spawn(move || {
+                    for job in job_rx {
+                        let mut out = job.head;
+                        let result = settings
+                            .copy_data_block(
+                                &mut processor,
+                                &job.copy_stmt,
+                                &mut job.raw.as_slice(),
+                                &mut out,
+                            )
+                            .map(|_| out);
+                        if done_tx.send((job.seq, result)).is_err() {
+                            break;
+                        }
+                    }
+                    processor
+                })
+            })
+            .collect();
+        drop(job_rx);
+        drop(done_tx);
+
+        let mut order = Reorder::default();
+        let mut result = read_blocks(
+            handler,
+            reader,
+            writer,
+            dio,
+            compression,
+            data_entries,
+            job_tx,
+            &done_rx,
+            &mut order,
+        );
+
+        // The job sender is gone, so workers exit once the queue drains.
+        for done in done_rx.iter() {
+            if result.is_ok() {
+                result = order.finish(done).and_then(|_| order.flush(writer, dio));
+            }
+        }
+        for worker in workers {
+            let processor = worker
+                .join()
+                .map_err(|_| PgStageError::MutationError("block worker panicked".to_string()))?;
+            handler.processor.merge_counters(&processor);
+        }
+        result?;
+        order.flush(writer, dio)?;
+        if order.next != order.seq {
+            return Err(PgStageError::MutationError(
+                "block worker stopped before finishing its blocks".to_string(),
+            ));
+        }
+        Ok(())
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn read_blocks<R: Read, W: Write>(
+    handler: &mut CustomHandler,
+    reader: &mut R,
+    writer: &mut BlockWriter<W>,
+    dio: &DumpIO,
+    compression: CompressionMethod,
+    data_entries: &FastMap<i64, DataEntryInfo>,
+    job_tx: Sender<Job>,
+    done_rx: &Receiver<Done>,
+    order: &mut Reorder,
+) -> Result<()> {
+    loop {
+        let mut block_type = [0u8; 1];
+        match reader.read_exact(&mut block_type) {
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e.into()),
+        }
+
+        let mut head = block_type.to_vec();
+        if block_type[0] == BLK_END {
+            order.push(head);
+            break;
+        }
+        let dump_id = dio.read_int(reader)?;
+        dio.write_int(&mut head, dump_id)?;
+
+        match data_entries.get(&dump_id) {
+            Some(info) if block_type[0] == BLK_DATA => {
+                let job = Job {
+                    seq: order.reserve(),
+                    copy_stmt: info.copy_stmt.clone(),
+                    head,
+                    raw: read_raw_block(dio, reader)?,
+                };
+                job_tx.send(job).map_err(|_| {
+                    PgStageError::MutationError("block workers exited early".to_string())
+                })?;
+            }
+            _ if block_type[0] == BLK_BLOBS => {
+                copy_blobs_block(dio, reader, &mut head)?;
+                order.push(head);
+            }
+            _ => {
+                let bp = BlockProcessor::new(
+                    dio,
+                    compression,
+                    &mut handler.processor,
+                    handler.zstd_level,
+                    handler.zstd_threads,
+                );
+                bp.pass_through_block(reader, &mut head)?;
+                order.push(head);
+            }
+        }
+
+        for done in done_rx.try_iter() {
+            order.finish(done)?;
+        }
+        order.flush(writer, dio)?;
+    }
+    Ok(())
+}
+
+/// Output blocks by sequence number, written strictly in order.
+#[derive(Default)]
+struct Reorder {
+    /// Next sequence number to hand out.
+    seq: u64,
+    /// Next sequence number to write.
+    next: u64,
+    ready: BTreeMap<u64, Vec<u8>>,
+}
+
+impl Reorder {
+    fn reserve(&mut self) -> u64 {
+        self.seq += 1;
+        self.seq - 1
+    }
+
+    fn push(&mut self, block: Vec<u8>) {
+        let seq = self.reserve();
+        self.ready.insert(seq, block);
+    }
+
+    fn finish(&mut self, (seq, result): Done) -> Result<()> {
+        self.ready.insert(seq, result?);
+        Ok(())
+    }
+
+    fn flush<W: Write>(&mut self, writer: &mut BlockWriter<W>, dio: &DumpIO) -> Result<()> {
+        while let Some(block) = self.ready.remove(&self.next) {
+            if block[0] != BLK_END {
+                writer.start_block(dio.read_int(&mut &block[1..])?);
+            }
+            writer.write_all(&block)?;
+            self.next += 1;
+        }
+        Ok(())
+    }
+}
+
+/// Per-block workers for `line_threads`, each a `DataProcessor::fork_table`
+/// with its own RNG. Only built for tables whose rows are independent, so no
+/// unique or relation state has to be shared between them.
+pub(super) struct LinePool {
+    workers: Vec<DataProcessor>,
+    outputs: Vec<Vec<u8>>,
+    /// Shares handed out so far; each is reseeded from its index.
+    batch: u64,
+}
+
+impl LinePool {
+    pub(super) fn new(processor: &DataProcessor, threads: usize) -> Option<Self> {
+        let threads = thread_count(threads);
+        if threads < 2 || !processor.has_mutations() || !processor.rows_independent() {
+            return None;
+        }
+        Some(Self {
+            workers: (0..threads).map(|_| processor.fork_table()).collect(),
+            outputs: vec![Vec::new(); threads],
+            batch: 0,
+        })
+    }
+
+    /// Mutate complete lines (`data` ends with a newline) into `writer`,
+    /// keeping their order.
+    pub(super) fn mutate<W: Write>(
+        &mut self,
+        processor: &mut DataProcessor,
+        data: &[u8],
+        writer: &mut W,
+    ) -> Result<()> {
+        if data.len() < MIN_SPLIT_SIZE {
+            return process_complete_lines_to_writer(processor, data, writer);
+        }
+        let shares = split_lines(data, self.workers.len());
+        for worker in &mut self.workers[..shares.len()] {
+            worker.reseed_batch(self.batch);
+            self.batch += 1;
+        }
+        thread::scope(|scope| {
+            let handles: Vec<_> = self
+                .workers
+                .iter_mut()
+                .zip(self.outputs.iter_mut())
+                .zip(&shares)
+                .map(|((worker, out), share)| {
+                    out.clear();
+                    scope.spawn(move || process_complete_lines_to_writer(worker, share, out))
+                })
+                .collect();
+            handles.into_iter().try_for_each(|h| {
+                h.join()
+                    .map_err(|_| PgStageError::MutationError("line worker panicked".to_string()))?
+            })
+        })?;
+        for out in &self.outputs[..shares.len()] {
+            writer.write_all(out)?;
+        }
+        Ok(())
+    }
+
+    pub(super) fn finish(self, processor: &mut DataProcessor) {
+        for worker in &self.workers {
+            processor.merge_counters(worker);
+        }
+    }
+}
+
+/// Cut `data` into at most `parts` runs of whole lines of similar size.
+fn split_lines(data: &[u8], parts: usize) -> Vec<&[u8]> {
+    let target = data.len().div_ceil(parts);
+    let mut shares = Vec::with_capacity(parts);
+    let mut start = 0;
+    while start < data.len() {
+        let cut = (start + target).min(data.len() - 1);
+        let end = memchr(b'\n', &data[cut..]).map_or(data.len(), |p| cut + p + 1);
+        shares.push(&data[start..end]);
+        start = end;
+    }
+    shares
+}
+
+fn thread_count(threads: usize) -> usize {
+    match threads {
+        0 => num_cpus::get(),
+        n => n,
+    }
+}
@@ -0,0 +1,143 @@
+//! Salvaging damaged custom archives (`CustomHandler::skip_corrupt`).
+//!
+//! Each block is read whole before it is written, through a reader that
+//! keeps every byte it hands out. When a block's framing is broken (a chunk
+//! length over the limit, or the stream ending mid-block), the bytes read so
+//! far are written unchanged and the input is scanned for the next block
+//! header: a data or BLOBS type byte followed by the id of a TOC entry that
+//! has not been seen yet. Everything skipped on the way is copied as is, so
+//! the output is never shorter than the input.
+
+use std::io::{self, Read, Write};
+
+use crate::error::Result;
+use crate::format::custom::blocks::{
+    copy_blobs_block, read_raw_block, BLK_BLOBS, BLK_DATA, BLK_END,
+};
+use crate::format::custom::header::CompressionMethod;
+use crate::format::custom::io::DumpIO;
+use crate::format::custom::{BlockWriter, CustomHandler, DataEntryInfo};
+use crate::{FastMap, FastSet};
+
+/// A reader that records what it returns, so a failed block can be copied.
+struct Recorder<'a, R> {
+    inner: &'a mut R,
+    seen: Vec<u8>,
+}
+
+impl<R: Read> Read for Recorder<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.seen.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+}
+
+pub(super) fn process_blocks<R: Read, W: Write>(
+    handler: &mut CustomHandler,
+    reader: &mut R,
+    writer: &mut BlockWriter<W>,
+    dio: &DumpIO,
+    compression: CompressionMethod,
+    data_entries: &FastMap<i64, DataEntryInfo>,
+    mut block_ids: FastSet<i64>,
+) -> Result<()> {
+    let mut next = None;
+    loop {
+        let (block_type, dump_id) = match next.take() {
+            Some(head) => head,
+            None => {
+                let mut block_type = [0u8; 1];
+                match reader.read_exact(&mut block_type) {
+                    Ok(_) => {}
+                    Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                    Err(e) => return Err(e.into()),
+                }
+                if block_type[0] == BLK_END {
+                    writer.write_all(&block_type)?;
+                    break;
+                }
+                (block_type[0], dio.read_int(reader)?)
+            }
+        };
+        writer.start_block(dump_id);
+        writer.write_all(&[block_type])?;
+        dio.write_int(writer, dump_id)?;
+        block_ids.remove(&dump_id);
+
+        let mut recorder = Recorder {
+            inner: &mut *reader,
+            seen: Vec::new(),
+        };
+        let read = if block_type == BLK_BLOBS {
+            copy_blobs_block(dio, &mut recorder, &mut io::sink())
+        } else {
+            read_raw_block(dio, &mut recorder).map(drop)
+        };
+        let raw = recorder.seen;
+        if let Err(e) = read {
+            log::warn!(
+                "block {}: {}; copying {} bytes as is and resynchronizing",
+                dump_id,
+                e,
+                raw.len()
+            );
+            writer.write_all(&raw)?;
+            next = resync(reader, writer, dio, &block_ids)?;
+            if next.is_none() {
+                break;
+            }
+            continue;
+        }
+
+        match data_entries.get(&dump_id) {
+            Some(info) if block_type == BLK_DATA => {
+                let settings = handler.block_settings(dio, compression);
+                settings.copy_data_block(
+                    &mut handler.processor,
+                    &info.copy_stmt,
+                    &mut raw.as_slice(),
+                    writer,
+                )?;
+            }
+            _ => writer.write_all(&raw)?,
+        }
+    }
+    Ok(())
+}
+
+/// Copy bytes from `reader` until a plausible block header starts, and return
+/// that header's type and dump id, or `None` once the input is exhausted.
+fn resync<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    dio: &DumpIO,
+    block_ids: &FastSet<i64>,
+) -> Result<Option<(u8, i64)>> {
+    let need = 2 + dio.int_size;
+    let mut window = Vec::with_capacity(need);
+    let mut skipped = 0usize;
+    loop {
+        while window.len() < need {
+            let mut byte = [0u8; 1];
+            if reader.read(&mut byte)? == 0 {
+                // A trailing end marker is copied along with the rest.
+                writer.write_all(&window)?;
+                skipped += window.len();
+                log::warn!("no further block found; copied {} trailing bytes as is", skipped);
+                return Ok(None);
+            }
+            window.push(byte[0]);
+        }
+        if matches!(window[0], BLK_DATA | BLK_BLOBS) && window[1] == 0 {
+            let dump_id = dio.read_int(&mut &window[1..])?;
+            if block_ids.contains(&dump_id) {
+                log::warn!("resynchronized at block {} after {} bytes", dump_id, skipped);
+                return Ok(Some((window[0], dump_id)));
+            }
+        }
+        writer.write_all(&window[..1])?;
+        window.remove(0);
+        skipped += 1;
+    }
+}
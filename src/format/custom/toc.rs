@@ -1,8 +1,8 @@
 use std::io::{Read, Write};
 
 use crate::error::Result;
-use crate::format::custom::header::Header;
-use crate::format::custom::io::DumpIO;
+use crate::format::custom::header::{Header, FORMAT_CUSTOM};
+use crate::format::custom::io::{CountingWriter, DumpIO};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Section {
@@ -13,7 +13,7 @@ pub enum Section {
 }
 
 impl Section {
-    pub fn from_i32(val: i32) -> Self {
+    pub fn from_i64(val: i64) -> Self {
         match val {
             1 => Section::PreData,
             2 => Section::Data,
@@ -23,26 +23,47 @@ impl Section {
     }
 }
 
+/// Where a custom archive entry's data block is (`K_OFFSET_*` in
+/// pg_backup_custom.c).
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DataState {
     Unknown,
-    NeedData,
+    /// The entry has a data block, but its offset was not recorded (the
+    /// archive was written to a pipe).
+    PosNotSet,
+    /// The entry's data block starts at `TocEntry::offset`.
+    PosSet,
     NoData,
 }
 
 impl DataState {
     pub fn from_i32(val: i32) -> Self {
         match val {
-            1 => DataState::NeedData,
-            2 => DataState::NoData,
+            1 => DataState::PosNotSet,
+            2 => DataState::PosSet,
+            3 => DataState::NoData,
             _ => DataState::Unknown,
         }
     }
+
+    pub fn as_byte(self) -> u8 {
+        match self {
+            DataState::PosNotSet => 1,
+            DataState::PosSet => 2,
+            DataState::NoData => 3,
+            DataState::Unknown => 0,
+        }
+    }
+
+    /// Whether the entry has a data block in the archive.
+    pub fn has_data(self) -> bool {
+        matches!(self, DataState::PosNotSet | DataState::PosSet)
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct TocEntry {
-    pub dump_id: i32,
+    pub dump_id: i64,
     pub section: Section,
     pub tag: String,
     pub desc: String,
@@ -53,9 +74,15 @@ pub struct TocEntry {
     pub tablespace: String,
     pub tableam: String,
     pub owner: String,
-    pub dependencies: Vec<i32>,
+    pub dependencies: Vec<i64>,
     pub offset: i64,
     pub data_state: DataState,
+    /// Position of the data state byte, counted from the start of the TOC
+    /// (custom archives only).
+    pub offset_pos: u64,
+    /// Data file name for directory/tar archives (`<dump_id>.dat`); empty
+    /// for custom archives and for entries without data.
+    pub filename: String,
 }
 
 /// Parse all TOC entries from the dump.
@@ -64,19 +91,17 @@ pub fn parse_toc<R: Read, W: Write>(
     reader: &mut R,
     writer: &mut W,
     header: &Header,
-    verbose: bool,
 ) -> Result<Vec<TocEntry>> {
     let dio = DumpIO::new(header.int_size, header.offset_size);
+    let writer = &mut CountingWriter::new(writer);
 
     // Read TOC count
-    let toc_count = if verbose {
+    let toc_count = if log::log_enabled!(log::Level::Debug) {
         dio.read_int_bypass_debug(reader, writer, "TOC count")?
     } else {
         dio.read_int_bypass(reader, writer)?
     };
-    if verbose {
-        eprintln!("[INFO] TOC entries: {}", toc_count);
-    }
+    log::info!("TOC entries: {}", toc_count);
     let mut entries = Vec::with_capacity(toc_count.max(0) as usize);
 
     for _ in 0..toc_count {
@@ -97,7 +122,7 @@ pub fn parse_toc<R: Read, W: Write>(
 
         // Section (1=Pre, 2=Data, 3=Post, 4=None)
         let section_raw = dio.read_int_bypass(reader, writer)?;
-        let section = Section::from_i32(section_raw);
+        let section = Section::from_i64(section_raw);
 
         // defn
         let defn = dio.read_string_bypass(reader, writer)?.unwrap_or_default();
@@ -131,7 +156,7 @@ pub fn parse_toc<R: Read, W: Write>(
             let dep_str = dio.read_string_bypass(reader, writer)?;
             match dep_str {
                 Some(s) if !s.is_empty() => {
-                    if let Ok(dep_id) = s.parse::<i32>() {
+                    if let Ok(dep_id) = s.parse::<i64>() {
                         dependencies.push(dep_id);
                     }
                 }
@@ -139,13 +164,22 @@ pub fn parse_toc<R: Read, W: Write>(
             }
         }
 
-        // data_state (1 BYTE) - CRITICAL: python uses read_byte here, not read_int!
-        let data_state_byte = DumpIO::read_byte(reader)?;
-        writer.write_all(&[data_state_byte])?;
-        let data_state = DataState::from_i32(data_state_byte as i32);
-
-        // Offset
-        let offset = dio.read_offset_bypass(reader, writer)?;
+        // Format-specific trailer (_WriteExtraToc): custom archives store the
+        // data state and block offset, directory/tar archives the file name.
+        let offset_pos = writer.position();
+        let (data_state, offset, filename) = if header.format == FORMAT_CUSTOM {
+            // data_state (1 BYTE) - CRITICAL: python uses read_byte here, not read_int!
+            let data_state_byte = DumpIO::read_byte(reader)?;
+            writer.write_all(&[data_state_byte])?;
+            let data_state = DataState::from_i32(data_state_byte as i32);
+
+            // Offset
+            let offset = dio.read_offset_bypass(reader, writer)?;
+            (data_state, offset, String::new())
+        } else {
+            let filename = dio.read_string_bypass(reader, writer)?.unwrap_or_default();
+            (DataState::Unknown, 0, filename)
+        };
 
         entries.push(TocEntry {
             dump_id,
@@ -162,6 +196,8 @@ pub fn parse_toc<R: Read, W: Write>(
             dependencies,
             offset,
             data_state,
+            offset_pos,
+            filename,
         });
     }
 
@@ -0,0 +1,197 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+
+#[cfg(feature = "zlib")]
+use flate2::read::GzDecoder;
+#[cfg(feature = "zlib")]
+use flate2::write::GzEncoder;
+#[cfg(feature = "zlib")]
+use flate2::Compression;
+
+use crate::error::{PgStageError, Result};
+use crate::format::custom::blocks::mutate_copy_stream;
+use crate::format::custom::header::{parse_archive_header, FORMAT_DIRECTORY};
+use crate::format::custom::toc::{parse_toc, TocEntry};
+use crate::processor::DataProcessor;
+use crate::FastMap;
+
+/// Handler for PostgreSQL directory format dumps (-Fd).
+///
+/// A directory dump is a `toc.dat` (same header and TOC layout as a custom
+/// archive, but each entry names its data file instead of a byte offset)
+/// plus one `<dump_id>.dat[.gz|.zst|.lz4]` file per table. The TOC is copied
+/// unchanged; data files of tables with mutations are rewritten, everything
+/// else is copied verbatim.
+pub struct DirectoryHandler {
+    processor: DataProcessor,
+    #[cfg_attr(not(feature = "zstd"), allow(dead_code))]
+    zstd_level: i32,
+}
+
+impl DirectoryHandler {
+    pub fn new(processor: DataProcessor) -> Self {
+        Self {
+            processor,
+            zstd_level: 1,
+        }
+    }
+
+    pub fn zstd_level(mut self, level: i32) -> Self {
+        self.zstd_level = level;
+        self
+    }
+
+    /// The processor, with its counters, once the dump has been processed.
+    pub fn into_processor(self) -> DataProcessor {
+        self.processor
+    }
+
+    pub fn process(&mut self, input: &Path, output: &Path) -> Result<()> {
+        if !input.join("toc.dat").is_file() {
+            return Err(PgStageError::InvalidFormat(format!(
+                "'{}' is not a pg_dump directory (no toc.dat)",
+                input.display()
+            )));
+        }
+        fs::create_dir_all(output)?;
+
+        let entries = {
+            let mut reader = BufReader::new(File::open(input.join("toc.dat"))?);
+            let mut writer = BufWriter::new(File::create(output.join("toc.dat"))?);
+            let header =
+                parse_archive_header(&mut reader, &mut writer, &[], FORMAT_DIRECTORY)?;
+            let entries = parse_toc(&mut reader, &mut writer, &header)?;
+            io::copy(&mut reader, &mut writer)?;
+            writer.flush()?;
+            entries
+        };
+
+        for entry in &entries {
+            if entry.desc == "COMMENT" {
+                self.processor.parse_comment(&entry.defn);
+            } else if entry.desc == "TABLE" {
+                self.processor.parse_create_table(&entry.defn);
+            } else if entry.desc == "TYPE" {
+                self.processor.parse_create_type(&entry.defn);
+            }
+        }
+        self.processor.check_rules()?;
+        let copy_stmts = data_files(&entries);
+
+        for dir_entry in fs::read_dir(input)? {
+            let dir_entry = dir_entry?;
+            let name = dir_entry.file_name();
+            let Some(name) = name.to_str() else {
+                continue;
+            };
+            if name == "toc.dat" || !dir_entry.file_type()?.is_file() {
+                continue;
+            }
+            let (base, codec) = split_codec(name);
+            let src = dir_entry.path();
+            let dst = output.join(name);
+
+            let Some(copy_stmt) = copy_stmts.get(base) else {
+                fs::copy(&src, &dst)?;
+                continue;
+            };
+            self.processor.setup_table(copy_stmt);
+            if !self.processor.has_mutations() && !self.processor.is_delete() {
+                self.processor.reset_table();
+                fs::copy(&src, &dst)?;
+                continue;
+            }
+            log::info!("mutating data file {}", name);
+            self.rewrite_data_file(&src, &dst, codec)?;
+            self.processor.reset_table();
+        }
+
+        self.processor.emit_summary();
+        Ok(())
+    }
+
+    fn rewrite_data_file(&mut self, src: &Path, dst: &Path, codec: Codec) -> Result<()> {
+        let reader = BufReader::with_capacity(2 * 1024 * 1024, File::open(src)?);
+        let writer = BufWriter::with_capacity(2 * 1024 * 1024, File::create(dst)?);
+        match codec {
+            Codec::None => {
+                let mut reader = reader;
+                let mut writer = writer;
+                mutate_copy_stream(&mut self.processor, &mut reader, &mut writer)?;
+                writer.flush()?;
+            }
+            #[cfg(feature = "zlib")]
+            Codec::Gzip => {
+                let mut decoder = GzDecoder::new(reader);
+                let mut encoder = GzEncoder::new(writer, Compression::new(6));
+                mutate_copy_stream(&mut self.processor, &mut decoder, &mut encoder)?;
+                encoder
+                    .finish()
+                    .map_err(|e| PgStageError::CompressionError(format!("Gzip finish failed: {}", e)))?
+                    .flush()?;
+            }
+            #[cfg(feature = "zstd")]
+            Codec::Zstd => {
+                let mut decoder = zstd::stream::read::Decoder::new(reader).map_err(|e| {
+                    PgStageError::CompressionError(format!("Zstd decoder init failed: {}", e))
+                })?;
+                let mut encoder = zstd::stream::write::Encoder::new(writer, self.zstd_level)
+                    .map_err(|e| {
+                        PgStageError::CompressionError(format!("Zstd encoder init failed: {}", e))
+                    })?;
+                mutate_copy_stream(&mut self.processor, &mut decoder, &mut encoder)?;
+                encoder
+                    .finish()
+                    .map_err(|e| PgStageError::CompressionError(format!("Zstd finish failed: {}", e)))?
+                    .flush()?;
+            }
+            Codec::Lz4 => {
+                return Err(PgStageError::UnsupportedVersion(format!(
+                    "lz4-compressed data file {} cannot be mutated (lz4 is not supported)",
+                    src.display()
+                )));
+            }
+            #[cfg(not(all(feature = "zlib", feature = "zstd")))]
+            disabled => {
+                return Err(PgStageError::UnsupportedVersion(format!(
+                    "{:?}-compressed data file {} requires pg_stage_rs built with the `{}` feature",
+                    disabled,
+                    src.display(),
+                    if disabled == Codec::Gzip { "zlib" } else { "zstd" }
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Codec {
+    None,
+    Gzip,
+    Zstd,
+    Lz4,
+}
+
+/// Split `3456.dat.gz` into (`3456.dat`, Gzip).
+fn split_codec(name: &str) -> (&str, Codec) {
+    if let Some(base) = name.strip_suffix(".gz") {
+        (base, Codec::Gzip)
+    } else if let Some(base) = name.strip_suffix(".zst") {
+        (base, Codec::Zstd)
+    } else if let Some(base) = name.strip_suffix(".lz4") {
+        (base, Codec::Lz4)
+    } else {
+        (name, Codec::None)
+    }
+}
+
+/// Map each TABLE DATA entry's file name to its COPY statement.
+fn data_files(entries: &[TocEntry]) -> FastMap<&str, &str> {
+    entries
+        .iter()
+        .filter(|e| e.desc == "TABLE DATA" && !e.filename.is_empty() && !e.copy_stmt.is_empty())
+        .map(|e| (e.filename.as_str(), e.copy_stmt.as_str()))
+        .collect()
+}
@@ -0,0 +1,73 @@
+//! SQL identifier helpers for the names pg_dump writes in COPY, INSERT and
+//! COMMENT statements. Identifiers may be double-quoted (`"My Schema"`), with
+//! an embedded quote written as `""`. Names are normalized to their unquoted
+//! parts joined by `.`, so `"My Schema"."Users"` and a rules-file pattern on
+//! `My Schema.Users` refer to the same table.
+
+/// Regex fragment matching a possibly schema-qualified, possibly quoted name.
+pub const QUALIFIED_NAME: &str = r#"(?:[\w$]+|"(?:[^"]|"")*")(?:\.(?:[\w$]+|"(?:[^"]|"")*"))*"#;
+
+/// Split a qualified name into its unquoted parts:
+/// `public."Odd.Name"` -> `["public", "Odd.Name"]`.
+pub fn split_qualified(name: &str) -> Vec<String> {
+    split_outside_quotes(name, '.')
+}
+
+/// Unquoted parts of `name` joined by `.`.
+pub fn normalize_qualified(name: &str) -> String {
+    split_qualified(name).join(".")
+}
+
+/// Split a column list (`id, "first name", "a,b"`) into unquoted names.
+pub fn split_column_list(list: &str) -> Vec<String> {
+    split_outside_quotes(list, ',')
+}
+
+/// The identifier at the start of `s` (after leading whitespace), unquoted:
+/// `"first name" text NOT NULL` -> `first name`.
+pub fn leading_identifier(s: &str) -> Option<String> {
+    let s = s.trim_start();
+    if let Some(rest) = s.strip_prefix('"') {
+        let mut out = String::new();
+        let mut chars = rest.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c == '"' {
+                if chars.peek() == Some(&'"') {
+                    chars.next();
+                    out.push('"');
+                } else {
+                    return Some(out);
+                }
+            } else {
+                out.push(c);
+            }
+        }
+        return None;
+    }
+    let end = s
+        .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '$'))
+        .unwrap_or(s.len());
+    (end > 0).then(|| s[..end].to_string())
+}
+
+fn split_outside_quotes(s: &str, sep: char) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut chars = s.chars().peekable();
+    let mut in_quotes = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                chars.next();
+                current.push('"');
+            }
+            '"' => in_quotes = !in_quotes,
+            c if c == sep && !in_quotes => parts.push(std::mem::take(&mut current)),
+            // Unquoted identifiers cannot contain whitespace.
+            c if c.is_whitespace() && !in_quotes => {}
+            c => current.push(c),
+        }
+    }
+    parts.push(current);
+    parts
+}
@@ -0,0 +1,297 @@
+//! Parsing and re-rendering of `INSERT` statements written by
+//! `pg_dump --inserts` / `--column-inserts`.
+//!
+//! Only the pieces needed to mutate values are understood: the table name,
+//! the optional column list, and the literal value tuple. Each value is kept together
+//! with its raw source text so untouched values are re-emitted byte-for-byte.
+
+/// One literal inside a `VALUES (...)` tuple.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SqlValue<'a> {
+    /// Bare `NULL`.
+    Null,
+    /// A single-quoted string (optionally `E'...'`), possibly followed by a
+    /// cast such as `::jsonb`. `value` is the decoded string contents.
+    Quoted {
+        value: String,
+        suffix: &'a str,
+    },
+    /// Anything else: numbers, booleans, expressions. `value` is the raw text.
+    Bare(&'a str),
+}
+
+impl SqlValue<'_> {
+    /// Logical value in the processor's COPY conventions (`\N` for NULL).
+    pub fn logical(&self) -> &str {
+        match self {
+            SqlValue::Null => "\\N",
+            SqlValue::Quoted { value, .. } => value,
+            SqlValue::Bare(raw) => raw,
+        }
+    }
+}
+
+/// A parsed value together with its exact source text.
+#[derive(Debug, Clone)]
+pub struct TupleValue<'a> {
+    pub raw: &'a str,
+    pub value: SqlValue<'a>,
+}
+
+/// Returns true once `stmt` holds a complete statement: it ends with `;` and
+/// no string literal is left open. Used to join multi-line INSERTs whose
+/// string values contain raw newlines. A `\'` inside an `E'...'` literal
+/// does not close it.
+pub fn is_complete(stmt: &str) -> bool {
+    let bytes = stmt.as_bytes();
+    let mut pos = 0;
+    while pos < bytes.len() {
+        if bytes[pos] == b'\'' {
+            let escapes = pos > 0 && matches!(bytes[pos - 1], b'E' | b'e');
+            match skip_quoted(bytes, pos + 1, escapes) {
+                Some(end) => pos = end,
+                None => return false,
+            }
+        } else {
+            pos += 1;
+        }
+    }
+    stmt.trim_end().ends_with(';')
+}
+
+/// Parse one `( ... )` tuple starting at `s[pos]` (leading whitespace is
+/// skipped). Returns the values and the index just past the closing `)`.
+pub fn parse_tuple(s: &str, mut pos: usize) -> Option<(Vec<TupleValue<'_>>, usize)> {
+    let bytes = s.as_bytes();
+    pos = skip_ws(bytes, pos);
+    if bytes.get(pos) != Some(&b'(') {
+        return None;
+    }
+    pos += 1;
+
+    let mut values = Vec::new();
+    loop {
+        pos = skip_ws(bytes, pos);
+        let start = pos;
+        let mut depth = 0usize;
+        // Scan one value up to the `,` or `)` that ends it at depth 0.
+        loop {
+            match *bytes.get(pos)? {
+                b'\'' => {
+                    let escapes = pos > start && matches!(bytes[pos - 1], b'E' | b'e');
+                    pos = skip_quoted(bytes, pos + 1, escapes)?;
+                }
+                b'(' => {
+                    depth += 1;
+                    pos += 1;
+                }
+                b')' if depth > 0 => {
+                    depth -= 1;
+                    pos += 1;
+                }
+                b',' | b')' if depth == 0 => break,
+                _ => pos += 1,
+            }
+        }
+        let raw = s[start..pos].trim_end();
+        values.push(TupleValue {
+            raw,
+            value: classify(raw),
+        });
+        let sep = bytes[pos];
+        pos += 1;
+        if sep == b')' {
+            return Some((values, pos));
+        }
+    }
+}
+
+/// Render a mutated logical value as an SQL literal. `like` is the original
+/// value; bare numbers stay bare when the new value is numeric too, and any
+/// cast suffix on a quoted original is carried over.
+pub fn render_value(new_value: &str, like: &SqlValue<'_>, out: &mut String) {
+    if new_value == "\\N" {
+        out.push_str("NULL");
+        return;
+    }
+    if matches!(like, SqlValue::Bare(_)) && is_bare_literal(new_value) {
+        out.push_str(new_value);
+        return;
+    }
+    out.push('\'');
+    for c in new_value.chars() {
+        if c == '\'' {
+            out.push('\'');
+        }
+        out.push(c);
+    }
+    out.push('\'');
+    if let SqlValue::Quoted { suffix, .. } = like {
+        out.push_str(suffix);
+    }
+}
+
+/// True for values that can be written unquoted: plain numeric literals
+/// (`-12`, `3.5`, `1e-3`) and booleans. `NaN` and `Infinity` parse as floats
+/// but are not SQL literals, so they are quoted like any other string.
+fn is_bare_literal(v: &str) -> bool {
+    is_numeric_literal(v) || v.eq_ignore_ascii_case("true") || v.eq_ignore_ascii_case("false")
+}
+
+fn is_numeric_literal(v: &str) -> bool {
+    let bytes = v.as_bytes();
+    let mut pos = usize::from(matches!(bytes.first(), Some(b'-' | b'+')));
+    let int_start = pos;
+    while bytes.get(pos).is_some_and(u8::is_ascii_digit) {
+        pos += 1;
+    }
+    let mut digits = pos - int_start;
+    if bytes.get(pos) == Some(&b'.') {
+        pos += 1;
+        let frac_start = pos;
+        while bytes.get(pos).is_some_and(u8::is_ascii_digit) {
+            pos += 1;
+        }
+        digits += pos - frac_start;
+    }
+    if digits == 0 {
+        return false;
+    }
+    if matches!(bytes.get(pos), Some(b'e' | b'E')) {
+        pos += 1;
+        if matches!(bytes.get(pos), Some(b'-' | b'+')) {
+            pos += 1;
+        }
+        let exp_start = pos;
+        while bytes.get(pos).is_some_and(u8::is_ascii_digit) {
+            pos += 1;
+        }
+        if pos == exp_start {
+            return false;
+        }
+    }
+    pos == bytes.len()
+}
+
+fn classify(raw: &str) -> SqlValue<'_> {
+    if raw.eq_ignore_ascii_case("NULL") {
+        return SqlValue::Null;
+    }
+    let bytes = raw.as_bytes();
+    let (escapes, open) = match bytes.first() {
+        Some(b'\'') => (false, 1),
+        Some(b'E' | b'e') if bytes.get(1) == Some(&b'\'') => (true, 2),
+        _ => return SqlValue::Bare(raw),
+    };
+    match skip_quoted(bytes, open, escapes) {
+        Some(end) => SqlValue::Quoted {
+            value: decode_string(&raw[open..end - 1], escapes),
+            suffix: &raw[end..],
+        },
+        None => SqlValue::Bare(raw),
+    }
+}
+
+/// Skip past a quoted literal whose opening `'` is at `pos - 1`. Returns the
+/// index just after the closing quote. Backslash escapes are honored only for
+/// `E'...'` strings.
+fn skip_quoted(bytes: &[u8], mut pos: usize, escapes: bool) -> Option<usize> {
+    loop {
+        match *bytes.get(pos)? {
+            b'\'' if bytes.get(pos + 1) == Some(&b'\'') => pos += 2,
+            b'\'' => return Some(pos + 1),
+            b'\\' if escapes => pos += 2,
+            _ => pos += 1,
+        }
+    }
+}
+
+/// Decode the body of a string literal. With `escapes` (an `E'...'` string)
+/// the backslash escapes PostgreSQL accepts are decoded as well: the C-style
+/// letters, `\ooo` octal and `\xhh` hex bytes, and `\uXXXX` / `\UXXXXXXXX`
+/// code points. Octal and hex escapes produce raw bytes, so they are collected
+/// first and the result decoded as UTF-8.
+fn decode_string(body: &str, escapes: bool) -> String {
+    let bytes = body.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let b = bytes[pos];
+        pos += 1;
+        match b {
+            // Doubled quote; the second one is skipped here.
+            b'\'' => {
+                pos += 1;
+                out.push(b'\'');
+            }
+            b'\\' if escapes => {
+                let Some(&e) = bytes.get(pos) else {
+                    out.push(b'\\');
+                    break;
+                };
+                pos += 1;
+                match e {
+                    b'n' => out.push(b'\n'),
+                    b't' => out.push(b'\t'),
+                    b'r' => out.push(b'\r'),
+                    b'b' => out.push(0x08),
+                    b'f' => out.push(0x0c),
+                    b'0'..=b'7' => {
+                        let (value, len) = radix_prefix(&bytes[pos - 1..], 8, 3);
+                        pos += len - 1;
+                        out.push(value as u8);
+                    }
+                    b'x' => match radix_prefix(&bytes[pos..], 16, 2) {
+                        (_, 0) => out.push(b'x'),
+                        (value, len) => {
+                            pos += len;
+                            out.push(value as u8);
+                        }
+                    },
+                    b'u' | b'U' => {
+                        let width = if e == b'u' { 4 } else { 8 };
+                        let (value, len) = radix_prefix(&bytes[pos..], 16, width);
+                        match char::from_u32(value).filter(|_| len == width) {
+                            Some(c) => {
+                                pos += len;
+                                out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                            }
+                            None => out.push(e),
+                        }
+                    }
+                    other => out.push(other),
+                }
+            }
+            _ => out.push(b),
+        }
+    }
+    match String::from_utf8(out) {
+        Ok(s) => s,
+        Err(e) => String::from_utf8_lossy(e.as_bytes()).into_owned(),
+    }
+}
+
+/// Parse up to `max` leading digits of `bytes` in `radix`. Returns the value
+/// and the number of digits used.
+fn radix_prefix(bytes: &[u8], radix: u32, max: usize) -> (u32, usize) {
+    let mut value = 0u32;
+    let mut len = 0;
+    for &b in bytes.iter().take(max) {
+        match (b as char).to_digit(radix) {
+            Some(d) => {
+                value = value * radix + d;
+                len += 1;
+            }
+            None => break,
+        }
+    }
+    (value, len)
+}
+
+#[inline]
+fn skip_ws(bytes: &[u8], mut pos: usize) -> usize {
+    while pos < bytes.len() && bytes[pos].is_ascii_whitespace() {
+        pos += 1;
+    }
+    pos
+}
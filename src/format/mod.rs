@@ -1,23 +1,40 @@
+pub mod copy_csv;
+pub mod copy_text;
 pub mod custom;
+pub mod directory;
+pub mod ident;
+pub mod insert;
 pub mod plain;
+pub mod tar;
 
 use crate::error::Result;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DumpFormat {
     Plain,
+    /// Gzip-compressed plain dump (`.sql.gz`); the output is gzipped too.
+    PlainGz,
     Custom,
+    Directory,
+    Tar,
 }
 
-/// Detect format by checking the first 5 bytes for PGDMP magic.
+/// Detect format by checking the first 5 bytes for PGDMP magic, for the
+/// `toc.dat` member name that opens every pg_dump tar archive, or for the
+/// gzip magic of a compressed plain dump.
 pub fn detect_format(header: &[u8]) -> Result<DumpFormat> {
     // If we have at least 5 bytes and they match PGDMP, it's custom format
     if header.len() >= 5 && &header[..5] == b"PGDMP" {
         Ok(DumpFormat::Custom)
+    } else if header.len() >= 5 && header[..5] == TAR_FIRST_MEMBER[..5] {
+        // pg_dump always writes toc.dat as the first tar member
+        Ok(DumpFormat::Tar)
     } else if !header.is_empty() && header.starts_with(b"PGDM") {
         // If we have partial match (e.g., "PGDM"), it's likely custom format
         // but we need to read more to be sure
         Ok(DumpFormat::Custom)
+    } else if header.starts_with(GZIP_MAGIC) {
+        Ok(DumpFormat::PlainGz)
     } else {
         Ok(DumpFormat::Plain)
     }
@@ -25,3 +42,9 @@ pub fn detect_format(header: &[u8]) -> Result<DumpFormat> {
 
 /// PGDMP magic bytes
 pub const MAGIC_HEADER: &[u8; 5] = b"PGDMP";
+
+/// Gzip member magic bytes.
+pub const GZIP_MAGIC: &[u8; 2] = b"\x1f\x8b";
+
+/// Name of the first member of a pg_dump tar archive.
+pub const TAR_FIRST_MEMBER: &[u8; 7] = b"toc.dat";
@@ -1,6 +1,17 @@
+use std::collections::VecDeque;
 use std::io::{BufRead, BufReader, BufWriter, Read, Write};
 
+#[cfg(feature = "zlib")]
+use flate2::read::MultiGzDecoder;
+#[cfg(feature = "zlib")]
+use flate2::write::GzEncoder;
+#[cfg(feature = "zlib")]
+use flate2::Compression;
+
+#[cfg(feature = "zlib")]
+use crate::error::PgStageError;
 use crate::error::Result;
+use crate::format::insert;
 use crate::processor::DataProcessor;
 
 /// Handler for PostgreSQL plain text dump format (-Fp).
@@ -13,6 +24,31 @@ impl PlainHandler {
         Self { processor }
     }
 
+    /// The processor, with its counters, once the dump has been processed.
+    pub fn into_processor(self) -> DataProcessor {
+        self.processor
+    }
+
+    /// Process a gzip-compressed plain dump, writing gzip-compressed output.
+    /// `initial_bytes` are the already-read start of the compressed stream.
+    #[cfg(feature = "zlib")]
+    pub fn process_gz<R: Read, W: Write>(
+        &mut self,
+        reader: R,
+        writer: W,
+        initial_bytes: &[u8],
+    ) -> Result<()> {
+        let combined = std::io::Cursor::new(initial_bytes.to_vec()).chain(reader);
+        let decoder = MultiGzDecoder::new(combined);
+        let mut encoder = GzEncoder::new(writer, Compression::new(6));
+        self.process(decoder, &mut encoder, b"")?;
+        encoder
+            .finish()
+            .map_err(|e| PgStageError::CompressionError(format!("Gzip finish failed: {}", e)))?
+            .flush()?;
+        Ok(())
+    }
+
     /// Process a plain format dump from reader to writer.
     /// If `initial_bytes` is provided, those bytes are prepended to the stream.
     pub fn process<R: Read, W: Write>(
@@ -21,71 +57,347 @@ impl PlainHandler {
         writer: W,
         initial_bytes: &[u8],
     ) -> Result<()> {
-        let mut writer = BufWriter::with_capacity(2 * 1024 * 1024, writer);
-        let mut is_data = false;
-        let mut comment_buf: Option<String> = None;
+        let mut writer = DeferredNewline::new(BufWriter::with_capacity(2 * 1024 * 1024, writer));
+        let mut state = PlainState::default();
+        let mut buf_reader = line_reader(reader, initial_bytes);
 
-        let combined = std::io::Cursor::new(initial_bytes.to_vec()).chain(reader);
-        let buf_reader = BufReader::with_capacity(2 * 1024 * 1024, combined);
-
-        for line_result in buf_reader.lines() {
-            let line = line_result?;
-
-            if is_data {
-                if line == "\\." {
-                    if !self.processor.is_delete() {
-                        writer.write_all(b"\\.\n")?;
-                    }
-                    is_data = false;
-                    self.processor.reset_table();
-                    continue;
-                }
+        loop {
+            let mut line = String::new();
+            if buf_reader.read_line(&mut line)? == 0 {
+                break;
+            }
+            state.handle_line(&mut self.processor, line, &mut writer)?;
+        }
 
-                if let Some(mutated) = self.processor.process_line(line.as_bytes()) {
-                    writer.write_all(mutated)?;
-                    writer.write_all(b"\n")?;
-                }
-                continue;
+        state.finish(&mut writer)?;
+        writer.finish(state.ends_with_newline)?;
+        self.processor.emit_summary();
+        Ok(())
+    }
+
+    /// Like `process`, but yields the output lazily, one line per item, for
+    /// embedders that feed rows into their own sink. Each item carries its
+    /// trailing `\n` (except a last line the input did not terminate), so
+    /// the items concatenated equal `process`'s output.
+    pub fn process_iter<R: Read>(&mut self, reader: R, initial_bytes: &[u8]) -> PlainLines<'_, R> {
+        PlainLines {
+            processor: &mut self.processor,
+            reader: line_reader(reader, initial_bytes),
+            state: PlainState::default(),
+            out: Vec::new(),
+            lines: VecDeque::new(),
+            done: false,
+        }
+    }
+}
+
+type LineReader<R> = BufReader<std::io::Chain<std::io::Cursor<Vec<u8>>, R>>;
+
+fn line_reader<R: Read>(reader: R, initial_bytes: &[u8]) -> LineReader<R> {
+    let combined = std::io::Cursor::new(initial_bytes.to_vec()).chain(reader);
+    BufReader::with_capacity(2 * 1024 * 1024, combined)
+}
+
+/// Where the plain-dump parser is between input lines.
+struct PlainState {
+    ends_with_newline: bool,
+    is_data: bool,
+    comment_buf: Option<String>,
+    insert_buf: Option<String>,
+    create_buf: Option<String>,
+    /// A CSV row whose quoted field runs onto the next line.
+    record_buf: Option<String>,
+}
+
+impl Default for PlainState {
+    fn default() -> Self {
+        Self {
+            ends_with_newline: true,
+            is_data: false,
+            comment_buf: None,
+            insert_buf: None,
+            create_buf: None,
+            record_buf: None,
+        }
+    }
+}
+
+impl PlainState {
+    /// Consume one input line (as read, terminator included) and write
+    /// whatever output it completes.
+    fn handle_line<W: Write>(
+        &mut self,
+        processor: &mut DataProcessor,
+        mut line: String,
+        writer: &mut W,
+    ) -> Result<()> {
+        if line.ends_with('\n') {
+            line.pop();
+            if line.ends_with('\r') {
+                line.pop();
             }
+        } else {
+            self.ends_with_newline = false;
+        }
 
-            if let Some(ref mut buf) = comment_buf {
-                buf.push('\n');
-                buf.push_str(&line);
-                if line.ends_with("';") {
-                    let full_comment = std::mem::take(buf);
-                    comment_buf = None;
-                    self.processor.parse_comment(&full_comment);
-                    writer.write_all(full_comment.as_bytes())?;
-                    writer.write_all(b"\n")?;
+        if self.is_data {
+            if let Some(mut record) = self.record_buf.take() {
+                record.push('\n');
+                record.push_str(&line);
+                line = record;
+            } else if line == "\\." {
+                if !processor.is_delete() {
+                    writer.write_all(b"\\.\n")?;
                 }
-                continue;
+                self.is_data = false;
+                processor.reset_table();
+                return Ok(());
             }
 
-            if (line.starts_with("COMMENT ON COLUMN ") || line.starts_with("COMMENT ON TABLE "))
-                && line.contains("'anon: ")
-                && !line.ends_with("';")
-            {
-                comment_buf = Some(line);
-                continue;
+            if processor.record_is_open(line.as_bytes()) {
+                self.record_buf = Some(line);
+                return Ok(());
+            }
+            if let Some(mutated) = processor.process_line(line.as_bytes()) {
+                writer.write_all(mutated)?;
+                writer.write_all(b"\n")?;
             }
+            return Ok(());
+        }
 
-            self.processor.parse_comment(&line);
+        if let Some(ref mut buf) = self.comment_buf {
+            buf.push('\n');
+            buf.push_str(&line);
+            if comment_is_complete(buf) {
+                let full_comment = std::mem::take(buf);
+                self.comment_buf = None;
+                processor.parse_comment(&full_comment);
+                processor.check_rules()?;
+                writer.write_all(full_comment.as_bytes())?;
+                writer.write_all(b"\n")?;
+            }
+            return Ok(());
+        }
 
-            if self.processor.setup_table(&line) {
-                if !self.processor.is_delete() {
-                    writer.write_all(line.as_bytes())?;
-                    writer.write_all(b"\n")?;
+        if let Some(ref mut buf) = self.insert_buf {
+            buf.push('\n');
+            buf.push_str(&line);
+            if insert::is_complete(buf) {
+                let stmt = std::mem::take(buf);
+                self.insert_buf = None;
+                write_insert(processor, &stmt, writer)?;
+            }
+            return Ok(());
+        }
+
+        if let Some(ref mut buf) = self.create_buf {
+            buf.push('\n');
+            buf.push_str(&line);
+            if line.ends_with(';') {
+                let stmt = std::mem::take(buf);
+                self.create_buf = None;
+                if !processor.parse_create_table(&stmt) {
+                    processor.parse_create_type(&stmt);
                 }
-                is_data = true;
-                continue;
             }
+            writer.write_all(line.as_bytes())?;
+            writer.write_all(b"\n")?;
+            return Ok(());
+        }
 
+        if line.starts_with("CREATE TABLE ") || line.starts_with("CREATE UNLOGGED TABLE ") {
+            // Remember the column order for COPY statements without a
+            // column list; the statement itself is written unchanged.
+            if line.ends_with(';') {
+                processor.parse_create_table(&line);
+            } else {
+                self.create_buf = Some(line.clone());
+            }
             writer.write_all(line.as_bytes())?;
             writer.write_all(b"\n")?;
+            return Ok(());
         }
 
-        writer.flush()?;
-        self.processor.emit_summary();
+        if line.starts_with("CREATE TYPE ") && line.contains(" AS ENUM ") {
+            // Enum labels, for `enum_value`; written unchanged.
+            if line.ends_with(';') {
+                processor.parse_create_type(&line);
+            } else {
+                self.create_buf = Some(line.clone());
+            }
+            writer.write_all(line.as_bytes())?;
+            writer.write_all(b"\n")?;
+            return Ok(());
+        }
+
+        if line.starts_with("INSERT INTO ") {
+            if insert::is_complete(&line) {
+                write_insert(processor, &line, writer)?;
+            } else {
+                // A string value with a raw newline spans several lines.
+                self.insert_buf = Some(line);
+            }
+            return Ok(());
+        }
+
+        if (line.starts_with("COMMENT ON COLUMN ") || line.starts_with("COMMENT ON TABLE "))
+            && line.contains("'anon: ")
+            && !comment_is_complete(&line)
+        {
+            self.comment_buf = Some(line);
+            return Ok(());
+        }
+
+        processor.parse_search_path(&line);
+        if processor.parse_comment(&line) {
+            processor.check_rules()?;
+        }
+
+        if processor.setup_table(&line) {
+            if !processor.is_delete() {
+                writer.write_all(line.as_bytes())?;
+                writer.write_all(b"\n")?;
+            }
+            self.is_data = true;
+            return Ok(());
+        }
+
+        writer.write_all(line.as_bytes())?;
+        writer.write_all(b"\n")?;
         Ok(())
     }
+
+    /// Write out an INSERT statement left unterminated at end of input.
+    fn finish<W: Write>(&mut self, writer: &mut W) -> Result<()> {
+        for stmt in [self.insert_buf.take(), self.record_buf.take()].into_iter().flatten() {
+            writer.write_all(stmt.as_bytes())?;
+            writer.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether an `'anon: ...'` comment's string literal is closed. Doubled
+/// quotes (`''`) inside the literal do not close it.
+fn comment_is_complete(stmt: &str) -> bool {
+    let Some(start) = stmt.find("'anon: ") else {
+        return stmt.ends_with("';");
+    };
+    let mut rest = stmt[start + 1..].bytes();
+    while let Some(b) = rest.next() {
+        if b != b'\'' {
+            continue;
+        }
+        if rest.next() != Some(b'\'') {
+            return true;
+        }
+    }
+    false
+}
+
+fn write_insert<W: Write>(processor: &mut DataProcessor, stmt: &str, writer: &mut W) -> Result<()> {
+    if let Some(out) = processor.process_insert(stmt) {
+        writer.write_all(out.as_bytes())?;
+        writer.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+/// Iterator returned by `PlainHandler::process_iter`.
+pub struct PlainLines<'a, R: Read> {
+    processor: &'a mut DataProcessor,
+    reader: LineReader<R>,
+    state: PlainState,
+    /// Output of the last input line, not yet split into lines.
+    out: Vec<u8>,
+    lines: VecDeque<Vec<u8>>,
+    done: bool,
+}
+
+impl<R: Read> PlainLines<'_, R> {
+    /// Read input until at least two output lines are queued or input ends.
+    /// The last line is held back until then, as its `\n` is dropped when
+    /// the input did not end with one.
+    fn fill(&mut self) -> Result<()> {
+        while !self.done && self.lines.len() < 2 {
+            let mut line = String::new();
+            if self.reader.read_line(&mut line)? == 0 {
+                self.state.finish(&mut self.out)?;
+                self.done = true;
+                self.processor.emit_summary();
+            } else {
+                self.state.handle_line(self.processor, line, &mut self.out)?;
+            }
+            for piece in self.out.split_inclusive(|&b| b == b'\n') {
+                self.lines.push_back(piece.to_vec());
+            }
+            self.out.clear();
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Iterator for PlainLines<'_, R> {
+    type Item = Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Err(e) = self.fill() {
+            self.done = true;
+            self.lines.clear();
+            return Some(Err(e));
+        }
+        let mut line = self.lines.pop_front()?;
+        if self.done
+            && self.lines.is_empty()
+            && !self.state.ends_with_newline
+            && line.last() == Some(&b'\n')
+        {
+            line.pop();
+        }
+        Some(Ok(line))
+    }
+}
+
+/// Writer that holds back a trailing `\n` until more output follows, so the
+/// newline written after the last line can be dropped when the input did not
+/// end with one.
+struct DeferredNewline<W: Write> {
+    inner: W,
+    pending: bool,
+}
+
+impl<W: Write> DeferredNewline<W> {
+    fn new(inner: W) -> Self {
+        Self { inner, pending: false }
+    }
+
+    fn finish(mut self, keep_newline: bool) -> std::io::Result<()> {
+        if self.pending && keep_newline {
+            self.inner.write_all(b"\n")?;
+        }
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Write for DeferredNewline<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let Some((&last, head)) = buf.split_last() else {
+            return Ok(0);
+        };
+        if self.pending {
+            self.inner.write_all(b"\n")?;
+            self.pending = false;
+        }
+        if last == b'\n' {
+            self.inner.write_all(head)?;
+            self.pending = true;
+        } else {
+            self.inner.write_all(buf)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
 }
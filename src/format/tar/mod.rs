@@ -0,0 +1,231 @@
+use std::io::{self, BufReader, BufWriter, Read, Write};
+
+use crate::error::{PgStageError, Result};
+use crate::format::custom::blocks::mutate_copy_stream;
+use crate::format::custom::header::{parse_archive_header, FORMAT_TAR};
+use crate::format::custom::toc::parse_toc;
+use crate::processor::DataProcessor;
+use crate::FastMap;
+
+const BLOCK: usize = 512;
+
+/// Handler for PostgreSQL tar format dumps (-Ft).
+///
+/// A tar dump is a ustar archive whose first member is `toc.dat` (same
+/// header and TOC layout as a custom archive, with a data file name per
+/// entry), followed by one `<dump_id>.dat` member per table, `restore.sql`,
+/// and any large objects. Members are streamed through unchanged except for
+/// the data members of tables with mutations, which are rewritten with a
+/// corrected size and checksum. A rewritten member is held in memory until
+/// its new size is known.
+pub struct TarHandler {
+    processor: DataProcessor,
+}
+
+impl TarHandler {
+    pub fn new(processor: DataProcessor) -> Self {
+        Self {
+            processor,
+        }
+    }
+
+    /// The processor, with its counters, once the dump has been processed.
+    pub fn into_processor(self) -> DataProcessor {
+        self.processor
+    }
+
+    pub fn process<R: Read, W: Write>(
+        &mut self,
+        reader: R,
+        writer: W,
+        initial_bytes: &[u8],
+    ) -> Result<()> {
+        let combined = io::Cursor::new(initial_bytes.to_vec()).chain(reader);
+        let mut reader = BufReader::with_capacity(2 * 1024 * 1024, combined);
+        let mut writer = BufWriter::with_capacity(2 * 1024 * 1024, writer);
+        let mut copy_stmts: FastMap<String, String> = FastMap::new();
+
+        loop {
+            let mut header = [0u8; BLOCK];
+            if !read_block(&mut reader, &mut header)? {
+                break;
+            }
+            if header.iter().all(|&b| b == 0) {
+                // End-of-archive marker: copy it and any trailing padding.
+                writer.write_all(&header)?;
+                io::copy(&mut reader, &mut writer)?;
+                break;
+            }
+
+            let name = member_name(&header);
+            let size = member_size(&header)?;
+            let padding = padding_for(size);
+
+            if name == "toc.dat" {
+                let mut toc = vec![0u8; size as usize];
+                reader.read_exact(&mut toc)?;
+                copy_stmts = self.load_toc(&toc)?;
+                writer.write_all(&header)?;
+                writer.write_all(&toc)?;
+                copy_padding(&mut reader, &mut writer, padding)?;
+                continue;
+            }
+
+            if let Some(copy_stmt) = copy_stmts.get(&name) {
+                self.processor.setup_table(copy_stmt);
+                if self.processor.has_mutations() || self.processor.is_delete() {
+                    log::info!("mutating tar member {}", name);
+                    let mut data = Vec::with_capacity(size as usize);
+                    let mut member = (&mut reader).take(size);
+                    mutate_copy_stream(&mut self.processor, &mut member, &mut data)?;
+                    if member.limit() != 0 {
+                        return Err(PgStageError::InvalidFormat(format!(
+                            "tar member {} is truncated",
+                            name
+                        )));
+                    }
+                    skip_padding(&mut reader, padding)?;
+                    self.processor.reset_table();
+
+                    set_member_size(&mut header, data.len() as u64);
+                    writer.write_all(&header)?;
+                    writer.write_all(&data)?;
+                    writer.write_all(&vec![0u8; padding_for(data.len() as u64)])?;
+                    continue;
+                }
+                self.processor.reset_table();
+            }
+
+            writer.write_all(&header)?;
+            let copied = io::copy(&mut (&mut reader).take(size), &mut writer)?;
+            if copied != size {
+                return Err(PgStageError::InvalidFormat(format!(
+                    "tar member {} is truncated",
+                    name
+                )));
+            }
+            copy_padding(&mut reader, &mut writer, padding)?;
+        }
+
+        writer.flush()?;
+        self.processor.emit_summary();
+        Ok(())
+    }
+
+    /// Parse `toc.dat`, register its COMMENT mutations, and return the
+    /// data member name -> COPY statement map.
+    fn load_toc(&mut self, toc: &[u8]) -> Result<FastMap<String, String>> {
+        let mut cursor = toc;
+        let mut sink = io::sink();
+        let header = parse_archive_header(&mut cursor, &mut sink, &[], FORMAT_TAR)?;
+        let entries = parse_toc(&mut cursor, &mut sink, &header)?;
+        let mut map = FastMap::new();
+        for entry in entries {
+            if entry.desc == "COMMENT" {
+                self.processor.parse_comment(&entry.defn);
+            } else if entry.desc == "TABLE" {
+                self.processor.parse_create_table(&entry.defn);
+            } else if entry.desc == "TYPE" {
+                self.processor.parse_create_type(&entry.defn);
+            } else if entry.desc == "TABLE DATA"
+                && !entry.filename.is_empty()
+                && !entry.copy_stmt.is_empty()
+            {
+                map.insert(entry.filename, entry.copy_stmt);
+            }
+        }
+        self.processor.check_rules()?;
+        Ok(map)
+    }
+}
+
+/// Read one 512-byte block. Returns false on a clean EOF before the block.
+fn read_block<R: Read>(reader: &mut R, buf: &mut [u8; BLOCK]) -> Result<bool> {
+    let mut filled = 0;
+    while filled < BLOCK {
+        let n = reader.read(&mut buf[filled..])?;
+        if n == 0 {
+            if filled == 0 {
+                return Ok(false);
+            }
+            return Err(PgStageError::InvalidFormat(
+                "truncated tar header".to_string(),
+            ));
+        }
+        filled += n;
+    }
+    Ok(true)
+}
+
+fn member_name(header: &[u8; BLOCK]) -> String {
+    let field = |range: std::ops::Range<usize>| {
+        let raw = &header[range];
+        let end = raw.iter().position(|&b| b == 0).unwrap_or(raw.len());
+        String::from_utf8_lossy(&raw[..end]).into_owned()
+    };
+    let name = field(0..100);
+    let prefix = field(345..500);
+    if prefix.is_empty() {
+        name
+    } else {
+        format!("{}/{}", prefix, name)
+    }
+}
+
+fn member_size(header: &[u8; BLOCK]) -> Result<u64> {
+    let field = &header[124..136];
+    if field[0] & 0x80 != 0 {
+        // GNU base-256 encoding for members of 8 GiB and more.
+        let mut value: u64 = (field[0] & 0x7f) as u64;
+        for &b in &field[1..] {
+            value = (value << 8) | b as u64;
+        }
+        return Ok(value);
+    }
+    let text: String = field
+        .iter()
+        .take_while(|&&b| b != 0 && b != b' ')
+        .map(|&b| b as char)
+        .collect();
+    let text = text.trim_start();
+    if text.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(text, 8)
+        .map_err(|_| PgStageError::InvalidFormat(format!("invalid tar member size '{}'", text)))
+}
+
+/// Rewrite the size field and recompute the header checksum.
+fn set_member_size(header: &mut [u8; BLOCK], size: u64) {
+    if size < 0o77777777777 {
+        let text = format!("{:011o}\0", size);
+        header[124..136].copy_from_slice(text.as_bytes());
+    } else {
+        let mut field = [0u8; 12];
+        field[4..].copy_from_slice(&size.to_be_bytes());
+        field[0] = 0x80;
+        header[124..136].copy_from_slice(&field);
+    }
+    header[148..156].copy_from_slice(b"        ");
+    let sum: u32 = header.iter().map(|&b| b as u32).sum();
+    let text = format!("{:06o}\0 ", sum);
+    header[148..156].copy_from_slice(text.as_bytes());
+}
+
+#[inline]
+fn padding_for(size: u64) -> usize {
+    ((BLOCK as u64 - size % BLOCK as u64) % BLOCK as u64) as usize
+}
+
+fn copy_padding<R: Read, W: Write>(reader: &mut R, writer: &mut W, padding: usize) -> Result<()> {
+    let mut buf = [0u8; BLOCK];
+    reader.read_exact(&mut buf[..padding])?;
+    writer.write_all(&buf[..padding])?;
+    Ok(())
+}
+
+fn skip_padding<R: Read>(reader: &mut R, padding: usize) -> Result<()> {
+    let mut buf = [0u8; BLOCK];
+    reader.read_exact(&mut buf[..padding])?;
+    Ok(())
+}
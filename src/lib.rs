@@ -1,6 +1,7 @@
 pub mod conditions;
 pub mod error;
 pub mod format;
+pub mod mapping;
 pub mod mutator;
 pub mod processor;
 pub mod relations;
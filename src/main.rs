@@ -1,13 +1,19 @@
-use std::io::{self, Read};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use clap::Parser;
 use regex::Regex;
 
 use pg_stage_rs::error::{PgStageError, Result};
 use pg_stage_rs::format::custom::CustomHandler;
+use pg_stage_rs::format::directory::DirectoryHandler;
 use pg_stage_rs::format::plain::PlainHandler;
-use pg_stage_rs::format::{detect_format, DumpFormat};
-use pg_stage_rs::processor::DataProcessor;
+use pg_stage_rs::format::tar::TarHandler;
+use pg_stage_rs::format::{detect_format, DumpFormat, GZIP_MAGIC};
+use pg_stage_rs::mapping::MappingWriter;
+use pg_stage_rs::processor::{DataProcessor, ProcessorConfig};
 use pg_stage_rs::types::Locale;
 
 #[cfg(feature = "mimalloc-allocator")]
@@ -21,23 +27,53 @@ struct Args {
     #[arg(short, long, default_value = "en")]
     locale: String,
 
-    /// Column delimiter character
+    /// Column delimiter; may be several characters (e.g. `||`)
     #[arg(short, long, default_value = "\t")]
     delimiter: String,
 
-    /// Force format (plain, custom). Auto-detected if not specified.
+    /// NULL marker of COPY rows; a COPY statement's own `NULL '...'` option wins
+    #[arg(long, default_value = "\\N")]
+    null_marker: String,
+
+    /// Force format (plain, custom, directory, tar). Auto-detected if not specified.
     #[arg(short, long)]
     format: Option<String>,
 
+    /// Input dump directory (directory format, -Fd). Plain and custom dumps
+    /// are read from stdin.
+    #[arg(long)]
+    input: Option<String>,
+
+    /// Output directory for a directory-format dump, or output file for a
+    /// custom-format dump (written with real TOC data offsets instead of
+    /// cleared ones, so `pg_restore -j` can use it).
+    #[arg(long)]
+    output: Option<String>,
+
     /// Regex patterns for tables to delete (can be specified multiple times)
     #[arg(long = "delete-table-pattern")]
     delete_table_patterns: Vec<String>,
 
+    /// Only process tables matching this regex on `schema.table` (repeatable).
+    /// Other tables pass through untouched.
+    #[arg(long = "include-table")]
+    include_tables: Vec<String>,
+
+    /// Pass tables matching this regex on `schema.table` through untouched
+    /// (repeatable). Takes precedence over --include-table.
+    #[arg(long = "exclude-table")]
+    exclude_tables: Vec<String>,
+
     /// Optional rules file (JSON) with pattern-based mutations for many schemas.
     /// See README §"Pattern rules".
     #[arg(long = "rules-file")]
     rules_file: Option<String>,
 
+    /// Write a JSON-lines manifest of original -> obfuscated values to this
+    /// file. It holds the original data: keep it as protected as the source.
+    #[arg(long = "mapping-out")]
+    mapping_out: Option<String>,
+
     /// Zstd compression level for the output dump (1-22). Lower is faster.
     #[arg(long = "zstd-level", default_value_t = 1)]
     zstd_level: i32,
@@ -46,10 +82,51 @@ struct Args {
     #[arg(long = "zstd-threads", default_value_t = 0)]
     zstd_threads: u32,
 
-    /// Fail fast on invalid JSON in COMMENT mutations instead of logging a warning.
+    /// Worker threads for custom-format data blocks (0 = one per CPU).
+    /// Dumps using `relations` are always processed on one thread.
+    #[arg(long, default_value_t = 1)]
+    threads: usize,
+
+    /// Worker threads for the rows of each custom-format data block (0 = one
+    /// per CPU). Tables using `unique` or `relations` are not split.
+    #[arg(long = "line-threads", default_value_t = 1)]
+    line_threads: usize,
+
+    /// Custom format: log a block with broken chunk framing, copy it as is
+    /// and continue at the next block instead of aborting.
+    #[arg(long = "skip-corrupt")]
+    skip_corrupt: bool,
+
+    /// When a column has several anon comments, try all their specs in
+    /// order instead of keeping only the last comment.
+    #[arg(long = "merge-comments")]
+    merge_comments: bool,
+
+    /// Log a warning when a mutated value is longer than its spec's
+    /// `max_length` kwarg (once per column and table).
+    #[arg(long = "warn-length")]
+    warn_length: bool,
+
+    /// Fail before writing data on invalid JSON or unknown mutation names in
+    /// anon comments, and log COPY rows with a wrong field count as errors.
     #[arg(long)]
     strict: bool,
 
+    /// Seed the random generator so runs over the same dump are repeatable.
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Byte-reproducible output for golden-file tests: seeds the generator
+    /// (0 unless --seed is given) and pins time-dependent mutations to a
+    /// fixed date.
+    #[arg(long)]
+    deterministic: bool,
+
+    /// Validate the dump without writing it: parse every block and anon
+    /// comment, print a summary and exit nonzero on any problem.
+    #[arg(long)]
+    check: bool,
+
     /// Enable verbose output (dump version, TOC count, compression info, progress)
     #[arg(short, long)]
     verbose: bool,
@@ -65,6 +142,17 @@ fn main() {
 fn run() -> Result<()> {
     let args = Args::parse();
 
+    // Dump diagnostics go through `log`: `--verbose` shows the info level,
+    // and `RUST_LOG` (e.g. `RUST_LOG=pg_stage_rs=debug`) overrides it.
+    env_logger::Builder::new()
+        .filter_level(if args.verbose {
+            log::LevelFilter::Info
+        } else {
+            log::LevelFilter::Warn
+        })
+        .parse_default_env()
+        .init();
+
     if args.verbose
         && !matches!(args.locale.to_lowercase().as_str(), "en" | "ru" | "russian" | "english")
     {
@@ -75,51 +163,76 @@ fn run() -> Result<()> {
     }
     let locale: Locale = args.locale.parse().unwrap_or(Locale::En);
 
-    let delimiter = args.delimiter.bytes().next().ok_or_else(|| {
-        PgStageError::InvalidParameter("--delimiter must be a non-empty string".to_string())
-    })?;
+    if args.delimiter.is_empty() {
+        return Err(PgStageError::InvalidParameter(
+            "--delimiter must be a non-empty string".to_string(),
+        ));
+    }
 
-    let delete_patterns: Vec<Regex> = args
-        .delete_table_patterns
-        .iter()
-        .map(|p| {
-            Regex::new(p).map_err(|e| {
-                PgStageError::InvalidParameter(format!(
-                    "invalid --delete-table-pattern regex '{}': {}",
-                    p, e
-                ))
-            })
-        })
-        .collect::<Result<Vec<_>>>()?;
+    let delete_patterns = compile_patterns(&args.delete_table_patterns, "--delete-table-pattern")?;
+    let include_tables = compile_patterns(&args.include_tables, "--include-table")?;
+    let exclude_tables = compile_patterns(&args.exclude_tables, "--exclude-table")?;
+
+    let forced = match args.format.as_deref() {
+        None => None,
+        Some("plain" | "p") => Some(DumpFormat::Plain),
+        Some("custom" | "c") => Some(DumpFormat::Custom),
+        Some("directory" | "d") => Some(DumpFormat::Directory),
+        Some("tar" | "t") => Some(DumpFormat::Tar),
+        Some(other) => {
+            return Err(PgStageError::InvalidParameter(format!(
+                "unknown --format '{}', expected plain|p|custom|c|directory|d|tar|t",
+                other
+            )))
+        }
+    };
+
+    if args.check && args.mapping_out.is_some() {
+        return Err(PgStageError::InvalidParameter(
+            "--check writes no output; drop --mapping-out".to_string(),
+        ));
+    }
 
     let stdin = io::stdin();
     let stdout = io::stdout();
     let mut reader = stdin.lock();
-    let writer = stdout.lock();
+    let writer: Box<dyn Write> = if args.check {
+        Box::new(io::sink())
+    } else {
+        Box::new(stdout.lock())
+    };
 
-    // Peek first 5 bytes for format detection
+    // Peek first 5 bytes for format detection (stdin formats only)
     let mut peek_buf = [0u8; 5];
-    let peek_n = reader.read(&mut peek_buf)?;
+    let reads_stdin = args.input.is_none() && forced != Some(DumpFormat::Directory);
+    let peek_n = if reads_stdin { reader.read(&mut peek_buf)? } else { 0 };
     let peeked = &peek_buf[..peek_n];
 
-    let format = if let Some(ref fmt) = args.format {
-        match fmt.as_str() {
-            "plain" | "p" => DumpFormat::Plain,
-            "custom" | "c" => DumpFormat::Custom,
-            other => {
-                return Err(PgStageError::InvalidParameter(format!(
-                    "unknown --format '{}', expected plain|p|custom|c",
-                    other
-                )))
-            }
-        }
-    } else {
-        detect_format(peeked)?
+    let format = match forced {
+        Some(DumpFormat::Plain) if peeked.starts_with(GZIP_MAGIC) => DumpFormat::PlainGz,
+        Some(fmt) => fmt,
+        None if args.input.is_some() => DumpFormat::Directory,
+        None => detect_format(peeked)?,
     };
 
-    let mut processor = DataProcessor::new(locale, delimiter, delete_patterns);
-    processor.set_strict(args.strict);
-    processor.set_verbose(args.verbose);
+    let mut builder = DataProcessor::builder()
+        .locale(locale)
+        .delimiter_str(&args.delimiter)
+        .null_marker(&args.null_marker)
+        .delete_patterns(delete_patterns)
+        .include_tables(include_tables)
+        .exclude_tables(exclude_tables)
+        .secrets_from_env()
+        .config(ProcessorConfig {
+            strict: args.strict,
+            deterministic: args.deterministic,
+            merge_comments: args.merge_comments,
+            length_warnings: args.warn_length,
+        });
+    if let Some(seed) = args.seed {
+        builder = builder.seed(seed);
+    }
+    let mut processor = builder.build();
 
     if let Some(rules_path) = &args.rules_file {
         let text = std::fs::read_to_string(rules_path).map_err(|e| {
@@ -128,19 +241,123 @@ fn run() -> Result<()> {
         processor.load_rules(&text)?;
     }
 
-    match format {
+    let mapping = match &args.mapping_out {
+        Some(path) => {
+            let file = File::create(path).map_err(|e| {
+                PgStageError::InvalidParameter(format!("cannot create --mapping-out '{}': {}", path, e))
+            })?;
+            let mapping = Arc::new(MappingWriter::new(BufWriter::new(file)));
+            let sink = Arc::clone(&mapping);
+            processor.set_row_hook(move |table, columns, original, mutated, null_marker| {
+                sink.record(table, columns, original, mutated, null_marker)
+            });
+            Some(mapping)
+        }
+        None => None,
+    };
+
+    let processor = match format {
         DumpFormat::Plain => {
             let mut handler = PlainHandler::new(processor);
             handler.process(reader, writer, peeked)?;
+            handler.into_processor()
+        }
+        #[cfg(feature = "zlib")]
+        DumpFormat::PlainGz => {
+            let mut handler = PlainHandler::new(processor);
+            handler.process_gz(reader, writer, peeked)?;
+            handler.into_processor()
+        }
+        #[cfg(not(feature = "zlib"))]
+        DumpFormat::PlainGz => {
+            return Err(PgStageError::UnsupportedVersion(
+                "gzip-compressed plain dumps require pg_stage_rs built with the `zlib` feature"
+                    .to_string(),
+            ));
         }
         DumpFormat::Custom => {
             let mut handler = CustomHandler::new(processor)
-                .verbose(args.verbose)
                 .zstd_level(args.zstd_level)
-                .zstd_threads(args.zstd_threads);
+                .zstd_threads(args.zstd_threads)
+                .threads(args.threads)
+                .line_threads(args.line_threads)
+                .skip_corrupt(args.skip_corrupt);
+            match &args.output {
+                Some(path) if !args.check => {
+                    let file = File::create(path).map_err(|e| {
+                        PgStageError::InvalidParameter(format!("cannot create --output '{}': {}", path, e))
+                    })?;
+                    handler.process_seekable(reader, file, peeked)?;
+                }
+                _ => handler.process(reader, writer, peeked)?,
+            }
+            handler.into_processor()
+        }
+        DumpFormat::Tar => {
+            let mut handler = TarHandler::new(processor);
             handler.process(reader, writer, peeked)?;
+            handler.into_processor()
         }
+        DumpFormat::Directory => {
+            // A directory dump is rewritten file by file, so a check writes
+            // into a scratch directory that is removed afterwards.
+            let output = match (&args.input, &args.output) {
+                (Some(_), _) if args.check => {
+                    std::env::temp_dir().join(format!("pg_stage_rs-check-{}", std::process::id()))
+                }
+                (Some(_), Some(output)) => PathBuf::from(output),
+                _ => {
+                    return Err(PgStageError::InvalidParameter(
+                        "directory format requires --input <dir> and --output <dir>".to_string(),
+                    ))
+                }
+            };
+            let input = args.input.as_deref().unwrap_or_default();
+            let mut handler = DirectoryHandler::new(processor)
+                .zstd_level(args.zstd_level);
+            let result = handler.process(Path::new(input), &output);
+            if args.check {
+                let _ = fs::remove_dir_all(&output);
+            }
+            result?;
+            handler.into_processor()
+        }
+    };
+
+    if let Some(mapping) = mapping {
+        mapping.flush()?;
     }
+    if args.check {
+        report_check(format, &processor)?;
+    }
+    Ok(())
+}
 
+/// Print the `--check` summary; any problem becomes an error (nonzero exit).
+fn report_check(format: DumpFormat, processor: &DataProcessor) -> Result<()> {
+    let (invalid_json, unknown_mutations) = processor.parse_warnings();
+    let mismatched_rows = processor.column_mismatches();
+    eprintln!(
+        "pg_stage_rs check: {:?} dump, {} rows, {} invalid anon comments, {} unknown mutations, {} rows with a wrong field count",
+        format, processor.rows_processed, invalid_json, unknown_mutations, mismatched_rows
+    );
+    let problems = invalid_json + unknown_mutations + mismatched_rows;
+    if problems > 0 {
+        return Err(PgStageError::InvalidFormat(format!(
+            "check failed with {} problem(s)",
+            problems
+        )));
+    }
     Ok(())
 }
+
+fn compile_patterns(patterns: &[String], flag: &str) -> Result<Vec<Regex>> {
+    patterns
+        .iter()
+        .map(|p| {
+            Regex::new(p).map_err(|e| {
+                PgStageError::InvalidParameter(format!("invalid {} regex '{}': {}", flag, p, e))
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,83 @@
+use std::borrow::Cow;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use serde_json::{json, Value};
+
+use crate::error::Result;
+
+/// Streams an original-to-obfuscated manifest as JSON lines, one per
+/// replaced column value: `{"table", "column", "original", "mutated"}`, with
+/// SQL NULL (the table's active NULL marker) as `null`. Fed from `DataProcessor::set_row_hook`; nothing is
+/// kept in memory beyond the writer's buffer.
+pub struct MappingWriter {
+    out: Mutex<MappingOut>,
+}
+
+struct MappingOut {
+    writer: Box<dyn Write + Send>,
+    /// First write error, reported by `flush`: the row hook cannot fail.
+    error: Option<io::Error>,
+}
+
+impl MappingWriter {
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        Self {
+            out: Mutex::new(MappingOut {
+                writer: Box::new(writer),
+                error: None,
+            }),
+        }
+    }
+
+    /// Record the columns of one row whose value changed.
+    pub fn record(
+        &self,
+        table: &str,
+        columns: &[Arc<str>],
+        original: &[&str],
+        mutated: &[Cow<str>],
+        null_marker: &str,
+    ) {
+        let mut out = self.out.lock().unwrap_or_else(|e| e.into_inner());
+        if out.error.is_some() {
+            return;
+        }
+        for ((column, &before), after) in columns.iter().zip(original).zip(mutated) {
+            if before == after.as_ref() {
+                continue;
+            }
+            let entry = json!({
+                "table": table,
+                "column": &**column,
+                "original": sql_value(before, null_marker),
+                "mutated": sql_value(after, null_marker),
+            });
+            let written = serde_json::to_writer(&mut out.writer, &entry)
+                .map_err(io::Error::from)
+                .and_then(|()| out.writer.write_all(b"\n"));
+            if let Err(e) = written {
+                out.error = Some(e);
+                return;
+            }
+        }
+    }
+
+    /// Flush the manifest, returning the first error hit while writing it.
+    pub fn flush(&self) -> Result<()> {
+        let mut out = self.out.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(e) = out.error.take() {
+            return Err(e.into());
+        }
+        out.writer.flush()?;
+        Ok(())
+    }
+}
+
+fn sql_value(value: &str, null_marker: &str) -> Value {
+    if value == null_marker {
+        Value::Null
+    } else {
+        Value::from(value)
+    }
+}
@@ -0,0 +1,165 @@
+use crate::error::{PgStageError, Result};
+use crate::mutator::{MutationContext, NestedMutation};
+
+/// Mutates every element of a PostgreSQL array literal (`{a,b,"c,d"}`).
+/// `mutation_kwargs`: `{"element": {"mutation_name": "...", "mutation_kwargs": {...}}}`.
+///
+/// Nested (multi-dimensional) arrays are walked recursively so their shape is
+/// kept. `NULL` elements stay `NULL`, and an element mutation returning the
+/// NULL marker produces one. Output elements are double-quoted only when
+/// PostgreSQL requires it.
+pub fn array(ctx: &mut MutationContext) -> Result<String> {
+    if ctx.current_value == ctx.null_marker {
+        return Ok(ctx.current_value.to_string());
+    }
+    if !ctx.kwargs.contains_key("element") {
+        return Err(PgStageError::MissingParameter("element".to_string(), "array".to_string()));
+    }
+    let spec = ctx.nested_mutation("element", "array")?;
+
+    let source = ctx.current_value;
+    let (elements, end) = parse_array(source.as_bytes(), 0).ok_or_else(|| {
+        PgStageError::MutationError(format!("array: '{}' is not an array literal", source))
+    })?;
+    if !source[end..].trim().is_empty() {
+        return Err(PgStageError::MutationError(format!(
+            "array: trailing data after array literal '{}'",
+            source
+        )));
+    }
+
+    let mut out = String::with_capacity(source.len());
+    write_mutated(ctx, spec, &elements, &mut out)?;
+    Ok(out)
+}
+
+enum Element {
+    Null,
+    Value(String),
+    Array(Vec<Element>),
+}
+
+/// Parse `{...}` starting at `bytes[pos]`. Returns the elements and the index
+/// just past the closing `}`.
+fn parse_array(bytes: &[u8], mut pos: usize) -> Option<(Vec<Element>, usize)> {
+    pos = skip_ws(bytes, pos);
+    if bytes.get(pos) != Some(&b'{') {
+        return None;
+    }
+    pos = skip_ws(bytes, pos + 1);
+    let mut elements = Vec::new();
+    if bytes.get(pos) == Some(&b'}') {
+        return Some((elements, pos + 1));
+    }
+    loop {
+        pos = skip_ws(bytes, pos);
+        match *bytes.get(pos)? {
+            b'{' => {
+                let (nested, next) = parse_array(bytes, pos)?;
+                elements.push(Element::Array(nested));
+                pos = next;
+            }
+            b'"' => {
+                let mut value = Vec::new();
+                pos += 1;
+                loop {
+                    match *bytes.get(pos)? {
+                        b'"' => break,
+                        b'\\' => {
+                            value.push(*bytes.get(pos + 1)?);
+                            pos += 2;
+                        }
+                        b => {
+                            value.push(b);
+                            pos += 1;
+                        }
+                    }
+                }
+                pos += 1;
+                elements.push(Element::Value(String::from_utf8(value).ok()?));
+            }
+            _ => {
+                let start = pos;
+                let mut value = Vec::new();
+                while !matches!(*bytes.get(pos)?, b',' | b'}') {
+                    if bytes[pos] == b'\\' {
+                        value.push(*bytes.get(pos + 1)?);
+                        pos += 2;
+                    } else {
+                        value.push(bytes[pos]);
+                        pos += 1;
+                    }
+                }
+                let raw = std::str::from_utf8(&bytes[start..pos]).ok()?.trim_end();
+                if raw.eq_ignore_ascii_case("NULL") {
+                    elements.push(Element::Null);
+                } else {
+                    let value = String::from_utf8(value).ok()?;
+                    elements.push(Element::Value(value.trim_end().to_string()));
+                }
+            }
+        }
+        pos = skip_ws(bytes, pos);
+        match *bytes.get(pos)? {
+            b',' => pos += 1,
+            b'}' => return Some((elements, pos + 1)),
+            _ => return None,
+        }
+    }
+}
+
+fn write_mutated(
+    ctx: &mut MutationContext,
+    spec: &NestedMutation,
+    elements: &[Element],
+    out: &mut String,
+) -> Result<()> {
+    out.push('{');
+    for (i, element) in elements.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        match element {
+            Element::Null => out.push_str("NULL"),
+            Element::Array(nested) => write_mutated(ctx, spec, nested, out)?,
+            Element::Value(value) => {
+                let new_value = spec.call(&mut ctx.nested(spec, value))?;
+                if new_value == ctx.null_marker {
+                    out.push_str("NULL");
+                } else {
+                    write_element(&new_value, out);
+                }
+            }
+        }
+    }
+    out.push('}');
+    Ok(())
+}
+
+fn write_element(value: &str, out: &mut String) {
+    let needs_quotes = value.is_empty()
+        || value.eq_ignore_ascii_case("NULL")
+        || value
+            .chars()
+            .any(|c| matches!(c, '{' | '}' | ',' | '"' | '\\') || c.is_ascii_whitespace());
+    if !needs_quotes {
+        out.push_str(value);
+        return;
+    }
+    out.push('"');
+    for c in value.chars() {
+        if c == '"' || c == '\\' {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out.push('"');
+}
+
+#[inline]
+fn skip_ws(bytes: &[u8], mut pos: usize) -> usize {
+    while pos < bytes.len() && bytes[pos].is_ascii_whitespace() {
+        pos += 1;
+    }
+    pos
+}
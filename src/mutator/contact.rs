@@ -1,24 +1,42 @@
-use hmac::{Hmac, Mac};
 use rand::Rng;
-use sha2::Sha256;
 
 use crate::error::{PgStageError, Result};
-use crate::mutator::locale::{en, ru};
-use crate::mutator::MutationContext;
+use crate::mutator::locale::{en, geo_data, ru, transliterate};
+use crate::mutator::{truncate_to_max_length, MutationContext};
 use crate::types::Locale;
 
+/// Local parts kept by `email` when `preserve_role_local` is set and no
+/// `role_locals` list is supplied.
+const DEFAULT_ROLE_LOCALS: &[&str] = &["support", "admin", "noreply", "info"];
+
 pub fn email(ctx: &mut MutationContext) -> Result<String> {
     let unique = ctx.get_bool_kwarg("unique");
+    let max_length = ctx.max_length_kwarg("email")?;
     let domains: &[&str] = match ctx.locale {
         Locale::Ru => ru::EMAIL_DOMAINS,
         _ => en::EMAIL_DOMAINS,
     };
+
+    if ctx.get_bool_kwarg("preserve_role_local") {
+        if let Some(local) = role_local_part(ctx) {
+            let mut gen = || {
+                let domain = domains[ctx.rng.gen_range(0..domains.len())];
+                truncate_to_max_length(format!("{}@{}", local, domain), max_length)
+            };
+            return if unique {
+                ctx.unique_tracker.generate_unique(gen)
+            } else {
+                Ok(gen())
+            };
+        }
+    }
+
     let mut gen = || {
         let first = en::FIRST_NAMES[ctx.rng.gen_range(0..en::FIRST_NAMES.len())].to_lowercase();
         let last = en::LAST_NAMES[ctx.rng.gen_range(0..en::LAST_NAMES.len())].to_lowercase();
         let num: u32 = ctx.rng.gen_range(1..9999);
         let domain = domains[ctx.rng.gen_range(0..domains.len())];
-        format!("{}.{}{}@{}", first, last, num, domain)
+        truncate_to_max_length(format!("{}.{}{}@{}", first, last, num, domain), max_length)
     };
     if unique {
         ctx.unique_tracker.generate_unique(gen)
@@ -27,6 +45,79 @@ pub fn email(ctx: &mut MutationContext) -> Result<String> {
     }
 }
 
+/// Email built from the row's already-obfuscated name columns
+/// (`first_column`, `last_column`) as `first.last@domain`, so it matches the
+/// fake name generated for the same row. Russian names are transliterated.
+/// The domain is `domain`, or one of the locale's mail domains picked from
+/// the name. With `unique`, repeated names get a numeric suffix.
+pub fn deterministic_email(ctx: &mut MutationContext) -> Result<String> {
+    let mut parts = Vec::with_capacity(2);
+    for key in ["first_column", "last_column"] {
+        let column = ctx.get_str_kwarg(key).ok_or_else(|| {
+            PgStageError::MissingParameter(key.to_string(), "deterministic_email".to_string())
+        })?;
+        let value = ctx.obfuscated_values.get(column).ok_or_else(|| {
+            PgStageError::MutationError(format!(
+                "deterministic_email: column '{}' has no obfuscated value in this row",
+                column
+            ))
+        })?;
+        let part: String = transliterate(value)
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric() || *c == '-')
+            .map(|c| c.to_ascii_lowercase())
+            .collect();
+        if !part.is_empty() {
+            parts.push(part);
+        }
+    }
+    if parts.is_empty() {
+        return Err(PgStageError::MutationError(
+            "deterministic_email: name columns are empty".to_string(),
+        ));
+    }
+    let local = parts.join(".");
+    let domain = match ctx.get_str_kwarg("domain") {
+        Some(d) => d.to_string(),
+        None => {
+            let domains: &[&str] = match ctx.locale {
+                Locale::Ru => ru::EMAIL_DOMAINS,
+                _ => en::EMAIL_DOMAINS,
+            };
+            let hash = local.bytes().fold(0usize, |h, b| h.wrapping_mul(31).wrapping_add(b as usize));
+            domains[hash % domains.len()].to_string()
+        }
+    };
+
+    if !ctx.get_bool_kwarg("unique") {
+        return Ok(format!("{}@{}", local, domain));
+    }
+    let mut first_try = true;
+    ctx.unique_tracker.generate_unique(|| {
+        if std::mem::take(&mut first_try) {
+            format!("{}@{}", local, domain)
+        } else {
+            format!("{}{}@{}", local, ctx.rng.gen_range(1..10000), domain)
+        }
+    })
+}
+
+/// Return the source local part if it is a role account (`support@`,
+/// `noreply@`, ...). Matching is case-insensitive; the original casing is kept.
+fn role_local_part<'a>(ctx: &MutationContext<'a>) -> Option<&'a str> {
+    let (local, _) = ctx.current_value.split_once('@')?;
+    let is_role = match ctx.kwargs.get("role_locals").and_then(|v| v.as_array()) {
+        Some(list) => list
+            .iter()
+            .filter_map(|v| v.as_str())
+            .any(|r| r.eq_ignore_ascii_case(local)),
+        None => DEFAULT_ROLE_LOCALS
+            .iter()
+            .any(|r| r.eq_ignore_ascii_case(local)),
+    };
+    is_role.then_some(local)
+}
+
 pub fn phone_number(ctx: &mut MutationContext) -> Result<String> {
     let mask: &str = ctx
         .get_str_kwarg("mask")
@@ -56,21 +147,133 @@ pub fn phone_number(ctx: &mut MutationContext) -> Result<String> {
 
 pub fn address(ctx: &mut MutationContext) -> Result<String> {
     let unique = ctx.get_bool_kwarg("unique");
-    let mut gen = || match ctx.locale {
-        Locale::En => {
-            let num = ctx.rng.gen_range(1..1400);
-            let street = en::STREET_NAMES[ctx.rng.gen_range(0..en::STREET_NAMES.len())];
-            let suffix = en::STREET_SUFFIXES[ctx.rng.gen_range(0..en::STREET_SUFFIXES.len())];
-            let city = en::CITIES[ctx.rng.gen_range(0..en::CITIES.len())];
-            let state = en::STATES[ctx.rng.gen_range(0..en::STATES.len())];
-            format!("{} {} {}, {}, {}", num, street, suffix, city, state)
-        }
-        Locale::Ru => {
-            let city = ru::CITIES[ctx.rng.gen_range(0..ru::CITIES.len())];
-            let street_type = ru::STREET_TYPES[ctx.rng.gen_range(0..ru::STREET_TYPES.len())];
-            let street = ru::STREETS[ctx.rng.gen_range(0..ru::STREETS.len())];
-            let num = ctx.rng.gen_range(1..200);
-            format!("{}, {} {}, {}", city, street_type, street, num)
+    let max_length = ctx.max_length_kwarg("address")?;
+    let mut gen = || {
+        let address = match ctx.locale {
+            Locale::En => {
+                let num = ctx.rng.gen_range(1..1400);
+                let street = en::STREET_NAMES[ctx.rng.gen_range(0..en::STREET_NAMES.len())];
+                let suffix = en::STREET_SUFFIXES[ctx.rng.gen_range(0..en::STREET_SUFFIXES.len())];
+                let city = en::CITIES[ctx.rng.gen_range(0..en::CITIES.len())];
+                let state = en::STATES[ctx.rng.gen_range(0..en::STATES.len())];
+                format!("{} {} {}, {}, {}", num, street, suffix, city, state)
+            }
+            Locale::Ru => {
+                let city = ru::CITIES[ctx.rng.gen_range(0..ru::CITIES.len())];
+                let street_type = ru::STREET_TYPES[ctx.rng.gen_range(0..ru::STREET_TYPES.len())];
+                let street = ru::STREETS[ctx.rng.gen_range(0..ru::STREETS.len())];
+                let num = ctx.rng.gen_range(1..200);
+                format!("{}, {} {}, {}", city, street_type, street, num)
+            }
+        };
+        truncate_to_max_length(address, max_length)
+    };
+    if unique {
+        ctx.unique_tracker.generate_unique(gen)
+    } else {
+        Ok(gen())
+    }
+}
+
+/// Postal address laid out over as many lines as the source value has.
+///
+/// Lines are joined with `\n` (or `\r\n` when the source uses it), which
+/// the COPY writer escapes back to `\n`. Up to four lines get distinct
+/// address parts; further source lines are kept as empty lines.
+pub fn multiline_address(ctx: &mut MutationContext) -> Result<String> {
+    let unique = ctx.get_bool_kwarg("unique");
+    let line_count = ctx.current_value.matches('\n').count() + 1;
+    let separator = if ctx.current_value.contains("\r\n") { "\r\n" } else { "\n" };
+    let max_length = ctx.max_length_kwarg("multiline_address")?;
+    let mut gen = || {
+        let mut lines = match ctx.locale {
+            Locale::En => {
+                let num = ctx.rng.gen_range(1..1400);
+                let street = en::STREET_NAMES[ctx.rng.gen_range(0..en::STREET_NAMES.len())];
+                let suffix = en::STREET_SUFFIXES[ctx.rng.gen_range(0..en::STREET_SUFFIXES.len())];
+                let unit = ctx.rng.gen_range(1..500);
+                let city = en::CITIES[ctx.rng.gen_range(0..en::CITIES.len())];
+                let state = en::STATES[ctx.rng.gen_range(0..en::STATES.len())];
+                let zip = ctx.rng.gen_range(10000..100000);
+                let street = format!("{} {} {}", num, street, suffix);
+                let unit = format!("Apt {}", unit);
+                match line_count {
+                    1 => vec![format!("{}, {}, {} {}", street, city, state, zip)],
+                    2 => vec![street, format!("{}, {} {}", city, state, zip)],
+                    3 => vec![street, unit, format!("{}, {} {}", city, state, zip)],
+                    _ => vec![
+                        street,
+                        unit,
+                        format!("{}, {} {}", city, state, zip),
+                        "USA".to_string(),
+                    ],
+                }
+            }
+            Locale::Ru => {
+                let city = ru::CITIES[ctx.rng.gen_range(0..ru::CITIES.len())];
+                let street_type = ru::STREET_TYPES[ctx.rng.gen_range(0..ru::STREET_TYPES.len())];
+                let street = ru::STREETS[ctx.rng.gen_range(0..ru::STREETS.len())];
+                let num = ctx.rng.gen_range(1..200);
+                let flat = ctx.rng.gen_range(1..500);
+                let index = ctx.rng.gen_range(100000..700000);
+                let street = format!("{} {}, д. {}", street_type, street, num);
+                let flat = format!("кв. {}", flat);
+                match line_count {
+                    1 => vec![format!("{}, г. {}, {}", index, city, street)],
+                    2 => vec![street, format!("{}, г. {}", index, city)],
+                    3 => vec![street, flat, format!("{}, г. {}", index, city)],
+                    _ => vec![street, flat, format!("г. {}", city), index.to_string()],
+                }
+            }
+        };
+        lines.resize(line_count, String::new());
+        truncate_to_max_length(lines.join(separator), max_length)
+    };
+    if unique {
+        ctx.unique_tracker.generate_unique(gen)
+    } else {
+        Ok(gen())
+    }
+}
+
+/// Postal code for the locale: a 5-digit ZIP for `En`, a 6-digit index for
+/// `Ru`. A `format` mask overrides it, with `#` for a digit and `@` for an
+/// uppercase letter (`#####-####`, `@#@ #@#`).
+pub fn postal_code(ctx: &mut MutationContext) -> Result<String> {
+    let format = ctx.get_str_kwarg("format");
+    let locale = ctx.locale;
+    let unique = ctx.get_bool_kwarg("unique");
+    let mut gen = || match format {
+        Some(mask) => mask
+            .chars()
+            .map(|ch| match ch {
+                '#' => char::from(b'0' + ctx.rng.gen_range(0..10u8)),
+                '@' => char::from(b'A' + ctx.rng.gen_range(0..26u8)),
+                other => other,
+            })
+            .collect(),
+        None => match locale {
+            Locale::En => format!("{:05}", ctx.rng.gen_range(501..100000)),
+            Locale::Ru => ctx.rng.gen_range(100000..700000).to_string(),
+        },
+    };
+    if unique {
+        ctx.unique_tracker.generate_unique(gen)
+    } else {
+        Ok(gen())
+    }
+}
+
+/// Country name in the locale's language.
+pub fn country(ctx: &mut MutationContext) -> Result<String> {
+    let locale = ctx.locale;
+    let unique = ctx.get_bool_kwarg("unique");
+    let mut gen = || {
+        let (_, en_name, ru_name) =
+            geo_data::COUNTRIES[ctx.rng.gen_range(0..geo_data::COUNTRIES.len())];
+        match locale {
+            Locale::En => en_name.to_string(),
+            Locale::Ru => ru_name.to_string(),
         }
     };
     if unique {
@@ -80,6 +283,21 @@ pub fn address(ctx: &mut MutationContext) -> Result<String> {
     }
 }
 
+/// ISO 3166-1 alpha-2 country code (`DE`, `US`).
+pub fn country_code(ctx: &mut MutationContext) -> Result<String> {
+    let unique = ctx.get_bool_kwarg("unique");
+    let mut gen = || {
+        geo_data::COUNTRIES[ctx.rng.gen_range(0..geo_data::COUNTRIES.len())]
+            .0
+            .to_string()
+    };
+    if unique {
+        ctx.unique_tracker.generate_unique(gen)
+    } else {
+        Ok(gen())
+    }
+}
+
 pub fn deterministic_phone(ctx: &mut MutationContext) -> Result<String> {
     let current_value = ctx.current_value;
     let count = ctx
@@ -93,27 +311,8 @@ pub fn deterministic_phone(ctx: &mut MutationContext) -> Result<String> {
             )
         })? as usize;
 
-    let secret_key = ctx
-        .secrets
-        .get("SECRET_KEY")
-        .cloned()
-        .unwrap_or_default();
-    let nonce = ctx
-        .secrets
-        .get("SECRET_KEY_NONCE")
-        .cloned()
-        .unwrap_or_default();
-
-    if secret_key.is_empty() {
-        return Err(PgStageError::MutationError(
-            "SECRET_KEY environment variable not set".to_string(),
-        ));
-    }
-    if nonce.is_empty() {
-        return Err(PgStageError::MutationError(
-            "SECRET_KEY_NONCE environment variable not set".to_string(),
-        ));
-    }
+    // Seed: HMAC(key=nonce+secret_key, msg="digits_permutation")
+    let seed = ctx.keyed_digest(b"digits_permutation")?;
 
     // Find digit positions in the original string
     let chars: Vec<char> = current_value.chars().collect();
@@ -130,20 +329,9 @@ pub fn deterministic_phone(ctx: &mut MutationContext) -> Result<String> {
         ));
     }
 
-    // Compute seed: HMAC(key=nonce+secret_key, msg="digits_permutation")
-    type HmacSha256 = Hmac<Sha256>;
-    let hmac_key = format!("{}{}", nonce, secret_key);
-    let mut mac = HmacSha256::new_from_slice(hmac_key.as_bytes())
-        .map_err(|e| PgStageError::MutationError(e.to_string()))?;
-    mac.update(b"digits_permutation");
-    let hash_bytes = mac.finalize().into_bytes();
-
-    // Use hash as seed for deterministic RNG
     use rand::seq::SliceRandom;
     use rand::SeedableRng;
-    let mut seed_bytes = [0u8; 32];
-    seed_bytes.copy_from_slice(&hash_bytes[..32]);
-    let mut rng = rand::rngs::StdRng::from_seed(seed_bytes);
+    let mut rng = rand::rngs::StdRng::from_seed(seed);
 
     // Collect last N digits and shuffle them deterministically
     let start_idx = digit_positions.len() - count;
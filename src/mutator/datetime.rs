@@ -1,11 +1,119 @@
-use chrono::{Datelike, NaiveDate, Utc};
-use rand::Rng;
+use std::fmt::Write;
 
-use crate::error::Result;
+use chrono::{Datelike, Duration, Months, NaiveDate};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::error::{PgStageError, Result};
 use crate::mutator::MutationContext;
 
+/// Random date between `start` and `end` years (inclusive), or within the
+/// precise `start_date`/`end_date` (`YYYY-MM-DD`) window when given; a full
+/// date overrides the year on its side. Inverted bounds are swapped.
 pub fn date(ctx: &mut MutationContext) -> Result<String> {
-    let current_year = Utc::now().year();
+    let (first, span) = window(ctx)?;
+    let date_format = ctx
+        .get_str_kwarg("date_format")
+        .unwrap_or("%Y-%m-%d");
+    let unique = ctx.get_bool_kwarg("unique");
+    // Whether a format fails depends only on its specifiers, not the date,
+    // so once `first` formats the generator below cannot panic.
+    format_date(first, date_format, "date")?;
+
+    let mut gen = || {
+        let d = first + Duration::days(ctx.rng.gen_range(0..=span));
+        d.format(date_format).to_string()
+    };
+
+    if unique {
+        ctx.unique_tracker.generate_unique(gen)
+    } else {
+        Ok(gen())
+    }
+}
+
+/// Date in the same window as `date`, picked by an HMAC of the
+/// `source_column`'s original value: rows with the same source get the same
+/// date, in every table and every dump made with the same secrets (and, when
+/// the window defaults to the current year, in the same year).
+pub fn deterministic_date(ctx: &mut MutationContext) -> Result<String> {
+    let source_column = ctx.get_str_kwarg("source_column").ok_or_else(|| {
+        PgStageError::MissingParameter(
+            "source_column".to_string(),
+            "deterministic_date".to_string(),
+        )
+    })?;
+    let (first, span) = window(ctx)?;
+    let date_format = ctx
+        .get_str_kwarg("date_format")
+        .unwrap_or("%Y-%m-%d");
+
+    let source = ctx.obfuscated_values.original(source_column).ok_or_else(|| {
+        PgStageError::MutationError(format!(
+            "deterministic_date: column '{}' is not in this row",
+            source_column
+        ))
+    })?;
+    let mut rng = StdRng::from_seed(ctx.keyed_digest(source.as_bytes())?);
+    let d = first + Duration::days(rng.gen_range(0..=span));
+    format_date(d, date_format, "deterministic_date")
+}
+
+/// Date of birth for someone aged `min_age` to `max_age` (default 18 to 80,
+/// inclusive) today. Unlike `date`, the window moves with the current date,
+/// so ages derived from the column stay inside the range.
+pub fn birthdate(ctx: &mut MutationContext) -> Result<String> {
+    let age = |name: &str, default: u64| match ctx.kwargs.get(name) {
+        None => Ok(default),
+        Some(v) => v.as_u64().filter(|&a| a <= 150).ok_or_else(|| {
+            PgStageError::InvalidParameter(format!(
+                "birthdate: {} must be an integer from 0 to 150, got {}",
+                name, v
+            ))
+        }),
+    };
+    let (min_age, max_age) = (age("min_age", 18)?, age("max_age", 80)?);
+    let (min_age, max_age) = if min_age > max_age {
+        (max_age, min_age)
+    } else {
+        (min_age, max_age)
+    };
+    let date_format = ctx
+        .get_str_kwarg("date_format")
+        .unwrap_or("%Y-%m-%d");
+
+    // Born later than `max_age + 1` years ago and no later than `min_age`
+    // years ago.
+    let today = ctx.now().date_naive();
+    let years_ago = |years: u64| {
+        today
+            .checked_sub_months(Months::new(years as u32 * 12))
+            .ok_or_else(|| PgStageError::InvalidParameter("birthdate: age out of range".to_string()))
+    };
+    let first = years_ago(max_age + 1)? + Duration::days(1);
+    let last = years_ago(min_age)?;
+    let d = first + Duration::days(ctx.rng.gen_range(0..=(last - first).num_days()));
+    format_date(d, date_format, "birthdate")
+}
+
+/// `d` in the user-supplied `date_format`. An unknown specifier (`%Q`) or
+/// one a date cannot fill (`%H`) is an error instead of the panic
+/// `to_string` would raise.
+fn format_date(d: NaiveDate, date_format: &str, mutation: &str) -> Result<String> {
+    let mut out = String::new();
+    write!(out, "{}", d.format(date_format)).map_err(|_| {
+        PgStageError::InvalidParameter(format!(
+            "{}: invalid date_format '{}'",
+            mutation, date_format
+        ))
+    })?;
+    Ok(out)
+}
+
+/// First day and length in days of the window set by `start`/`end` or
+/// `start_date`/`end_date`.
+fn window(ctx: &MutationContext) -> Result<(NaiveDate, i64)> {
+    let current_year = ctx.now().year();
     let start_year = ctx
         .kwargs
         .get("start")
@@ -16,39 +124,55 @@ pub fn date(ctx: &mut MutationContext) -> Result<String> {
         .get("end")
         .and_then(|v| v.as_i64())
         .unwrap_or(current_year as i64) as i32;
-    let date_format = ctx
-        .get_str_kwarg("date_format")
-        .unwrap_or("%Y-%m-%d");
-    let unique = ctx.get_bool_kwarg("unique");
+    let (start_year, end_year) = if start_year > end_year {
+        (end_year, start_year)
+    } else {
+        (start_year, end_year)
+    };
+    let first = match date_kwarg(ctx, "start_date")? {
+        Some(d) => d,
+        None => year_bound(start_year, 1, 1)?,
+    };
+    let last = match date_kwarg(ctx, "end_date")? {
+        Some(d) => d,
+        None => year_bound(end_year, 12, 31)?,
+    };
+    let (first, last) = if first > last { (last, first) } else { (first, last) };
+    Ok((first, (last - first).num_days()))
+}
 
-    let mut gen = || {
-        let year = ctx.rng.gen_range(start_year..=end_year);
-        let month = ctx.rng.gen_range(1..=12u32);
-        let max_day = days_in_month(year, month);
-        let day = ctx.rng.gen_range(1..=max_day);
-        let d = NaiveDate::from_ymd_opt(year, month, day)
-            .unwrap_or_else(|| NaiveDate::from_ymd_opt(year, month, 1).unwrap());
-        d.format(date_format).to_string()
+fn date_kwarg(ctx: &MutationContext, key: &str) -> Result<Option<NaiveDate>> {
+    let Some(raw) = ctx.get_str_kwarg(key) else {
+        return Ok(None);
     };
+    NaiveDate::parse_from_str(raw, "%Y-%m-%d").map(Some).map_err(|e| {
+        PgStageError::InvalidParameter(format!("date: invalid {} '{}': {}", key, raw, e))
+    })
+}
 
+fn year_bound(year: i32, month: u32, day: u32) -> Result<NaiveDate> {
+    NaiveDate::from_ymd_opt(year, month, day)
+        .ok_or_else(|| PgStageError::InvalidParameter(format!("date: year {} out of range", year)))
+}
+
+/// IANA time zone names picked by `timezone`.
+pub const TIMEZONES: &[&str] = &[
+    "Europe/London", "Europe/Berlin", "Europe/Paris", "Europe/Madrid", "Europe/Rome",
+    "Europe/Warsaw", "Europe/Moscow", "Europe/Samara", "Asia/Yekaterinburg", "Asia/Omsk",
+    "Asia/Novosibirsk", "Asia/Krasnoyarsk", "Asia/Irkutsk", "Asia/Vladivostok", "Asia/Dubai",
+    "Asia/Kolkata", "Asia/Shanghai", "Asia/Tokyo", "Asia/Singapore", "Australia/Sydney",
+    "Africa/Cairo", "Africa/Johannesburg", "America/New_York", "America/Chicago",
+    "America/Denver", "America/Los_Angeles", "America/Toronto", "America/Sao_Paulo",
+    "America/Mexico_City", "Pacific/Auckland",
+];
+
+/// Random IANA time zone name such as `Europe/Moscow`.
+pub fn timezone(ctx: &mut MutationContext) -> Result<String> {
+    let unique = ctx.get_bool_kwarg("unique");
+    let mut gen = || TIMEZONES[ctx.rng.gen_range(0..TIMEZONES.len())].to_string();
     if unique {
         ctx.unique_tracker.generate_unique(gen)
     } else {
         Ok(gen())
     }
 }
-
-fn days_in_month(year: i32, month: u32) -> u32 {
-    match month {
-        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
-        4 | 6 | 9 | 11 => 30,
-        2 => {
-            if (year % 4 == 0 && year % 100 != 0) || (year % 400 == 0) {
-                29
-            } else {
-                28
-            }
-        }
-        _ => 30,
-    }
-}
@@ -0,0 +1,127 @@
+use rand::Rng;
+
+use crate::error::{PgStageError, Result};
+use crate::mutator::MutationContext;
+
+/// Money amount between `start` and `end` (default 0..10000) with exactly two
+/// decimal places, for `money` and `numeric(p, 2)` columns.
+///
+/// Optional `symbol` (e.g. `$`, `₽`) is placed before the amount, or after it
+/// separated by a space with `symbol_position: "suffix"`. Optional
+/// `thousands_separator` groups the integer part (`1,234,567.89`).
+pub fn money(ctx: &mut MutationContext) -> Result<String> {
+    let start = ctx.kwargs.get("start").and_then(|v| v.as_f64()).unwrap_or(0.0);
+    let end = ctx.kwargs.get("end").and_then(|v| v.as_f64()).unwrap_or(10000.0);
+    if start.is_nan() || end.is_nan() {
+        return Err(PgStageError::InvalidParameter(
+            "money: start/end must not be NaN".to_string(),
+        ));
+    }
+    let (start, end) = if start > end { (end, start) } else { (start, end) };
+    let (low, high) = ((start * 100.0).ceil() as i64, (end * 100.0).floor() as i64);
+    if low > high {
+        return Err(PgStageError::InvalidParameter(format!(
+            "money: no whole cent between {} and {}",
+            start, end
+        )));
+    }
+    let symbol = ctx.get_str_kwarg("symbol").unwrap_or("");
+    let suffix = match ctx.get_str_kwarg("symbol_position").unwrap_or("prefix") {
+        "prefix" => false,
+        "suffix" => true,
+        other => {
+            return Err(PgStageError::InvalidParameter(format!(
+                "money: unknown symbol_position '{}', expected prefix|suffix",
+                other
+            )))
+        }
+    };
+    let separator = ctx.get_str_kwarg("thousands_separator").unwrap_or("");
+    let unique = ctx.get_bool_kwarg("unique");
+
+    let mut gen = || {
+        let cents = ctx.rng.gen_range(low..=high);
+        let amount = format_amount(cents.unsigned_abs(), separator);
+        let sign = if cents < 0 { "-" } else { "" };
+        if symbol.is_empty() {
+            format!("{}{}", sign, amount)
+        } else if suffix {
+            format!("{}{} {}", sign, amount, symbol)
+        } else {
+            format!("{}{}{}", sign, symbol, amount)
+        }
+    };
+    if unique {
+        ctx.unique_tracker.generate_unique(gen)
+    } else {
+        Ok(gen())
+    }
+}
+
+/// Mask a card number, keeping the last four digits: every other digit
+/// becomes `mask_char` (default `*`), while spaces, dashes and other
+/// separators stay in place. Fewer than four digits are all masked.
+pub fn credit_card_mask(ctx: &mut MutationContext) -> Result<String> {
+    let mask = match ctx.get_str_kwarg("mask_char") {
+        Some(m) => {
+            let mut chars = m.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => c,
+                _ => {
+                    return Err(PgStageError::InvalidParameter(format!(
+                        "credit_card_mask: mask_char must be a single character, got '{}'",
+                        m
+                    )))
+                }
+            }
+        }
+        None => '*',
+    };
+    let value = ctx.current_value;
+    let digits = value.chars().filter(|c| c.is_ascii_digit()).count();
+    let keep_from = if digits < 4 { digits } else { digits - 4 };
+
+    let mut out = String::with_capacity(value.len());
+    let mut seen = 0;
+    for c in value.chars() {
+        if c.is_ascii_digit() {
+            out.push(if seen < keep_from { mask } else { c });
+            seen += 1;
+        } else {
+            out.push(c);
+        }
+    }
+    Ok(out)
+}
+
+fn format_amount(cents: u64, separator: &str) -> String {
+    let units = (cents / 100).to_string();
+    let mut out = String::with_capacity(units.len() * 2 + 3);
+    for (i, c) in units.chars().enumerate() {
+        if i > 0 && (units.len() - i).is_multiple_of(3) {
+            out.push_str(separator);
+        }
+        out.push(c);
+    }
+    out.push_str(&format!(".{:02}", cents % 100));
+    out
+}
+
+/// ISO 4217 codes drawn by `currency_code`.
+pub static CURRENCY_CODES: &[&str] = &[
+    "AED", "ARS", "AUD", "BRL", "BYN", "CAD", "CHF", "CLP", "CNY", "CZK",
+    "DKK", "EUR", "GBP", "GEL", "HKD", "HUF", "IDR", "ILS", "INR", "JPY",
+    "KRW", "KZT", "MXN", "NOK", "NZD", "PLN", "RON", "RUB", "SEK", "SGD",
+    "THB", "TRY", "UAH", "USD", "UZS", "ZAR",
+];
+
+/// Three-letter ISO 4217 currency code (`USD`, `EUR`, `RUB`).
+pub fn currency_code(ctx: &mut MutationContext) -> Result<String> {
+    let unique = ctx.get_bool_kwarg("unique");
+    let mut gen = || CURRENCY_CODES[ctx.rng.gen_range(0..CURRENCY_CODES.len())].to_string();
+    if unique {
+        ctx.unique_tracker.generate_unique(gen)
+    } else {
+        Ok(gen())
+    }
+}
@@ -1,13 +1,30 @@
-use chrono::Utc;
-use uuid::Uuid;
+use rand::Rng;
+use uuid::{Builder, Uuid};
 
 use crate::error::{PgStageError, Result};
+use crate::mutator::locale::en;
 use crate::mutator::MutationContext;
+use crate::types::Locale;
 
-pub fn uuid4(_ctx: &mut MutationContext) -> Result<String> {
-    Ok(Uuid::new_v4().to_string())
+pub fn uuid4(ctx: &mut MutationContext) -> Result<String> {
+    Ok(Builder::from_random_bytes(ctx.rng.gen()).into_uuid().to_string())
 }
 
+/// RFC 9562 version-7 UUID: 48-bit Unix millisecond timestamp followed by
+/// random bits, so generated keys stay roughly insertion-ordered in B-tree indexes.
+pub fn uuid7(ctx: &mut MutationContext) -> Result<String> {
+    if !ctx.deterministic {
+        return Ok(Uuid::now_v7().to_string());
+    }
+    let millis = ctx.now().timestamp_millis() as u64;
+    Ok(Builder::from_unix_timestamp_millis(millis, &ctx.rng.gen())
+        .into_uuid()
+        .to_string())
+}
+
+/// UUID v5 of the `source_column` value under `namespace`, so equal sources
+/// map to equal ids across tables and dumps. `stable: false` also hashes
+/// today's date, rotating the ids daily.
 pub fn uuid5_by_source_value(ctx: &mut MutationContext) -> Result<String> {
     let namespace_str = ctx.get_str_kwarg("namespace").ok_or_else(|| {
         PgStageError::MissingParameter("namespace".to_string(), "uuid5_by_source_value".to_string())
@@ -24,8 +41,82 @@ pub fn uuid5_by_source_value(ctx: &mut MutationContext) -> Result<String> {
     })?;
 
     let source_value = ctx.obfuscated_values.get(source_column).unwrap_or("");
-    let today = Utc::now().format("%Y-%m-%d").to_string();
-    let name = format!("{}-{}", source_value, today);
+    // Deterministic mode drops the date component even when not `stable`,
+    // so runs are byte-reproducible.
+    let stable = ctx.kwargs.get("stable").and_then(|v| v.as_bool()).unwrap_or(true);
+    let name = if stable || ctx.deterministic {
+        source_value.to_string()
+    } else {
+        format!("{}-{}", source_value, ctx.now().format("%Y-%m-%d"))
+    };
     let uuid5 = Uuid::new_v5(&namespace, name.as_bytes());
     Ok(uuid5.to_string())
 }
+
+/// Lowercase handle from a first initial, a last name and a number
+/// (`jsmith42`). `max_length` caps the result by shortening the name part.
+pub fn username(ctx: &mut MutationContext) -> Result<String> {
+    let max_length = ctx.max_length_kwarg("username")?;
+    let unique = ctx.get_bool_kwarg("unique");
+
+    let mut gen = || {
+        let first = en::FIRST_NAMES[ctx.rng.gen_range(0..en::FIRST_NAMES.len())];
+        let last = en::LAST_NAMES[ctx.rng.gen_range(0..en::LAST_NAMES.len())];
+        let mut name: String = first
+            .chars()
+            .take(1)
+            .chain(last.chars())
+            .filter(char::is_ascii_alphanumeric)
+            .map(|c| c.to_ascii_lowercase())
+            .collect();
+        let digits = ctx.rng.gen_range(1..1000).to_string();
+        if let Some(max) = max_length {
+            name.truncate(max.saturating_sub(digits.len()).max(1));
+        }
+        name.push_str(&digits);
+        if let Some(max) = max_length {
+            name.truncate(max);
+        }
+        name
+    };
+    if unique {
+        ctx.unique_tracker.generate_unique(gen)
+    } else {
+        Ok(gen())
+    }
+}
+
+/// ID document number filled from `mask`: `#` or `X` become random digits,
+/// `@` a random capital letter, anything else is kept. Without a mask the
+/// locale's passport layout is used: `#### ######` (series and number) for
+/// `ru`, nine digits for `en`.
+pub fn document_number(ctx: &mut MutationContext) -> Result<String> {
+    let mask = ctx.get_str_kwarg("mask").unwrap_or(match ctx.locale {
+        Locale::Ru => "#### ######",
+        Locale::En => "#########",
+    });
+    let unique = ctx.get_bool_kwarg("unique");
+    let mut gen = || {
+        mask.chars()
+            .map(|c| match c {
+                '#' | 'X' => char::from(b'0' + ctx.rng.gen_range(0..10u8)),
+                '@' => char::from(b'A' + ctx.rng.gen_range(0..26u8)),
+                other => other,
+            })
+            .collect::<String>()
+    };
+    if unique {
+        ctx.unique_tracker.generate_unique(gen)
+    } else {
+        Ok(gen())
+    }
+}
+
+/// Placeholder for a column that copies a mutated key through its relation.
+/// The processor answers it from the relation store or the key's own
+/// mutation; reaching this means the relation points at no mutated column.
+pub fn foreign_key(_ctx: &mut MutationContext) -> Result<String> {
+    Err(PgStageError::InvalidParameter(
+        "foreign_key: needs a relation to a column with its own mutation".to_string(),
+    ))
+}
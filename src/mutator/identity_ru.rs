@@ -0,0 +1,77 @@
+use rand::Rng;
+
+use crate::error::{PgStageError, Result};
+use crate::mutator::MutationContext;
+
+/// Control-digit weights of a 10-digit INN.
+const INN10_WEIGHTS: [u32; 9] = [2, 4, 10, 3, 5, 9, 4, 6, 8];
+/// Weights of the two control digits of a 12-digit INN.
+const INN12_WEIGHTS_11: [u32; 10] = [7, 2, 4, 10, 3, 5, 9, 4, 6, 8];
+const INN12_WEIGHTS_12: [u32; 11] = [3, 7, 2, 4, 10, 3, 5, 9, 4, 6, 8];
+
+/// Control digit over `digits`: the weighted sum mod 11, then mod 10.
+fn control_digit(digits: &[u32], weights: &[u32]) -> u32 {
+    let sum: u32 = digits.iter().zip(weights).map(|(d, w)| d * w).sum();
+    sum % 11 % 10
+}
+
+/// Russian taxpayer number (INN) with valid control digits: 10 digits for a
+/// legal entity (`kind: "legal"`, the default) or 12 for an individual
+/// (`kind: "individual"`). The first two digits are a region code 01-99.
+pub fn inn(ctx: &mut MutationContext) -> Result<String> {
+    let individual = match ctx.get_str_kwarg("kind").unwrap_or("legal") {
+        "legal" => false,
+        "individual" => true,
+        other => {
+            return Err(PgStageError::InvalidParameter(format!(
+                "inn: unknown kind '{}', expected legal|individual",
+                other
+            )))
+        }
+    };
+    let unique = ctx.get_bool_kwarg("unique");
+    let mut gen = || {
+        let region = ctx.rng.gen_range(1..=99u32);
+        let mut digits = vec![region / 10, region % 10];
+        let body = if individual { 10 } else { 9 };
+        while digits.len() < body {
+            digits.push(ctx.rng.gen_range(0..10));
+        }
+        if individual {
+            digits.push(control_digit(&digits, &INN12_WEIGHTS_11));
+            digits.push(control_digit(&digits, &INN12_WEIGHTS_12));
+        } else {
+            digits.push(control_digit(&digits, &INN10_WEIGHTS));
+        }
+        digits.iter().map(|d| char::from_digit(*d, 10).unwrap()).collect::<String>()
+    };
+    if unique {
+        ctx.unique_tracker.generate_unique(gen)
+    } else {
+        Ok(gen())
+    }
+}
+
+/// Russian insurance number (SNILS) as `XXX-XXX-XXX YY`, where `YY` is the
+/// control number: the digits weighted 9 down to 1, summed, reduced mod 101
+/// and with 100 written as `00`. Numbers start above 001-001-998, below which
+/// no control number is defined.
+pub fn snils(ctx: &mut MutationContext) -> Result<String> {
+    let unique = ctx.get_bool_kwarg("unique");
+    let mut gen = || {
+        let number: u32 = ctx.rng.gen_range(1_001_999..=999_999_999);
+        let digits = format!("{:09}", number);
+        let sum: u32 = digits
+            .bytes()
+            .zip((1..=9).rev())
+            .map(|(d, w)| (d - b'0') as u32 * w)
+            .sum();
+        let control = sum % 101 % 100;
+        format!("{}-{}-{} {:02}", &digits[..3], &digits[3..6], &digits[6..], control)
+    };
+    if unique {
+        ctx.unique_tracker.generate_unique(gen)
+    } else {
+        Ok(gen())
+    }
+}
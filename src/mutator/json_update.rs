@@ -1,8 +1,7 @@
 use serde_json::{Map, Value};
 
 use crate::error::{PgStageError, Result};
-use crate::mutator::{resolve_mutation, MutationContext};
-use crate::FastMap;
+use crate::mutator::{MutationContext, NestedMutation};
 
 /// Partially mutates a JSON object value. `mutation_kwargs` maps JSON keys to
 /// nested mutation specs: `{"mutation_name": "...", "mutation_kwargs": {...}}`.
@@ -11,10 +10,13 @@ use crate::FastMap;
 ///
 /// Missing keys: the mutation is skipped entirely (the key is NOT added).
 /// The nested mutation receives the existing JSON value (stringified) as its
-/// `current_value`; its output is inserted as a JSON string (or `null` if the
-/// mutation returns the SQL null sentinel `\N`).
+/// `current_value`. Its output keeps the source leaf's JSON type where it can:
+/// numbers stay numbers and booleans stay booleans when the output parses as
+/// one, arrays are mutated element-wise so their length is preserved, and
+/// everything else is inserted as a JSON string (or `null` if the mutation
+/// returns the NULL marker).
 pub fn json_update(ctx: &mut MutationContext) -> Result<String> {
-    let mut root: Value = if ctx.current_value == "\\N" || ctx.current_value.is_empty() {
+    let mut root: Value = if ctx.current_value == ctx.null_marker || ctx.current_value.is_empty() {
         Value::Object(Map::new())
     } else {
         serde_json::from_str(ctx.current_value).map_err(|e| {
@@ -31,67 +33,19 @@ pub fn json_update(ctx: &mut MutationContext) -> Result<String> {
     let kwargs = ctx.kwargs;
 
     for (key, spec_val) in kwargs.iter() {
-        let spec_obj = spec_val.as_object().ok_or_else(|| {
-            PgStageError::InvalidParameter(format!(
-                "json_update: expected object spec for key '{}'",
-                key
-            ))
-        })?;
-
-        let mutation_name = spec_obj
-            .get("mutation_name")
-            .and_then(|v| v.as_str())
-            .ok_or_else(|| {
-                PgStageError::InvalidParameter(format!(
-                    "json_update: missing 'mutation_name' for key '{}'",
-                    key
-                ))
-            })?;
-
         // Skip the mutation entirely if the key is not present in the JSON.
         if !obj.contains_key(key) {
             continue;
         }
 
-        if mutation_name == "delete" {
+        if spec_val.get("mutation_name").and_then(|v| v.as_str()) == Some("delete") {
             obj.insert(key.clone(), Value::String(String::new()));
             continue;
         }
 
-        let mutation_fn = resolve_mutation(mutation_name)
-            .ok_or_else(|| PgStageError::UnknownMutation(mutation_name.to_string()))?;
-
-        let mut inner_kwargs: FastMap<String, Value> = FastMap::new();
-        if let Some(kw) = spec_obj.get("mutation_kwargs").and_then(|v| v.as_object()) {
-            for (k, v) in kw.iter() {
-                inner_kwargs.insert(k.clone(), v.clone());
-            }
-        }
-
-        let cur_value_str = match obj.get(key) {
-            Some(Value::String(s)) => s.clone(),
-            Some(v) => v.to_string(),
-            None => String::new(),
-        };
-
-        let new_value = {
-            let mut inner_ctx = MutationContext {
-                kwargs: &inner_kwargs,
-                current_value: &cur_value_str,
-                rng: &mut *ctx.rng,
-                unique_tracker: &mut *ctx.unique_tracker,
-                locale: ctx.locale,
-                secrets: ctx.secrets,
-                obfuscated_values: ctx.obfuscated_values,
-            };
-            mutation_fn(&mut inner_ctx)?
-        };
-
-        let json_val = if new_value == "\\N" {
-            Value::Null
-        } else {
-            Value::String(new_value)
-        };
+        let spec = ctx.nested_mutation(key, "json_update")?;
+        let source = obj.get(key).cloned().unwrap_or(Value::Null);
+        let json_val = mutate_typed(ctx, spec, &source)?;
 
         obj.insert(key.clone(), json_val);
     }
@@ -100,3 +54,40 @@ pub fn json_update(ctx: &mut MutationContext) -> Result<String> {
         PgStageError::MutationError(format!("json_update: failed to serialize: {}", e))
     })
 }
+
+/// Run the nested mutation against one JSON leaf, keeping the leaf's type.
+pub(crate) fn mutate_typed(
+    ctx: &mut MutationContext,
+    spec: &NestedMutation,
+    source: &Value,
+) -> Result<Value> {
+    if let Value::Array(items) = source {
+        return items
+            .iter()
+            .map(|item| mutate_typed(ctx, spec, item))
+            .collect::<Result<Vec<_>>>()
+            .map(Value::Array);
+    }
+
+    let cur_value_str = match source {
+        Value::String(s) => s.clone(),
+        v => v.to_string(),
+    };
+    let new_value = spec.call(&mut ctx.nested(spec, &cur_value_str))?;
+
+    if new_value == ctx.null_marker {
+        return Ok(Value::Null);
+    }
+    let typed = match source {
+        Value::Number(_) => serde_json::from_str::<serde_json::Number>(&new_value)
+            .ok()
+            .map(Value::Number),
+        Value::Bool(_) => match new_value.as_str() {
+            "true" | "t" => Some(Value::Bool(true)),
+            "false" | "f" => Some(Value::Bool(false)),
+            _ => None,
+        },
+        _ => None,
+    };
+    Ok(typed.unwrap_or(Value::String(new_value)))
+}
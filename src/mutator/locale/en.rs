@@ -96,3 +96,47 @@ pub static URI_DOMAINS: &[&str] = &[
     "example.com", "test.org", "sample.net", "demo.io", "fake.dev",
     "placeholder.com", "mock.org", "dummy.net", "faux.io", "pseudo.dev",
 ];
+
+pub static USER_AGENT_DESKTOP_PLATFORMS: &[&str] = &[
+    "Windows NT 10.0; Win64; x64", "Macintosh; Intel Mac OS X 10_15_7",
+    "X11; Linux x86_64", "X11; Ubuntu; Linux x86_64",
+];
+
+pub static USER_AGENT_MOBILE_PLATFORMS: &[&str] = &[
+    "Linux; Android 14; Pixel 8", "Linux; Android 13; SM-S911B",
+    "Linux; Android 12; Redmi Note 11",
+];
+
+pub static DOMAIN_ADJECTIVES: &[&str] = &[
+    "bright", "calm", "clever", "swift", "silent", "golden", "rapid", "gentle",
+    "bold", "lucky", "quiet", "sunny", "brave", "fresh", "wild", "happy",
+];
+
+pub static DOMAIN_NOUNS: &[&str] = &[
+    "fox", "river", "cloud", "stone", "maple", "harbor", "falcon", "meadow",
+    "pixel", "forge", "lantern", "orbit", "summit", "willow", "comet", "anchor",
+];
+
+pub static HOST_ROLES: &[&str] = &[
+    "web", "api", "db", "cache", "mail", "app", "worker", "proxy", "auth", "files",
+];
+
+pub static HOST_SITES: &[&str] = &["dc", "az", "zone", "rack", "eu", "us", "pod"];
+
+pub static COLOR_NAMES: &[&str] = &[
+    "red", "orange", "yellow", "green", "blue", "indigo", "violet", "black",
+    "white", "gray", "brown", "pink", "purple", "teal", "navy", "olive",
+    "maroon", "beige", "turquoise", "gold", "silver", "coral", "lavender", "mint",
+];
+
+pub static PATH_DIRS: &[&str] = &[
+    "var", "data", "uploads", "files", "docs", "archive", "media", "storage",
+    "reports", "shared", "exports", "attachments", "users", "projects",
+];
+
+pub static FILE_STEMS: &[&str] = &[
+    "report", "invoice", "scan", "photo", "contract", "document", "export",
+    "backup", "statement", "receipt", "notes", "summary",
+];
+
+pub static FILE_EXTENSIONS: &[&str] = &["pdf", "docx", "xlsx", "csv", "txt", "jpg", "png", "zip"];
@@ -0,0 +1,70 @@
+/// ISO 3166-1 alpha-2 code, English name and Russian name.
+pub static COUNTRIES: &[(&str, &str, &str)] = &[
+    ("AR", "Argentina", "Аргентина"),
+    ("AM", "Armenia", "Армения"),
+    ("AU", "Australia", "Австралия"),
+    ("AT", "Austria", "Австрия"),
+    ("AZ", "Azerbaijan", "Азербайджан"),
+    ("BY", "Belarus", "Беларусь"),
+    ("BE", "Belgium", "Бельгия"),
+    ("BR", "Brazil", "Бразилия"),
+    ("BG", "Bulgaria", "Болгария"),
+    ("CA", "Canada", "Канада"),
+    ("CL", "Chile", "Чили"),
+    ("CN", "China", "Китай"),
+    ("CO", "Colombia", "Колумбия"),
+    ("HR", "Croatia", "Хорватия"),
+    ("CY", "Cyprus", "Кипр"),
+    ("CZ", "Czechia", "Чехия"),
+    ("DK", "Denmark", "Дания"),
+    ("EG", "Egypt", "Египет"),
+    ("EE", "Estonia", "Эстония"),
+    ("FI", "Finland", "Финляндия"),
+    ("FR", "France", "Франция"),
+    ("GE", "Georgia", "Грузия"),
+    ("DE", "Germany", "Германия"),
+    ("GR", "Greece", "Греция"),
+    ("HU", "Hungary", "Венгрия"),
+    ("IS", "Iceland", "Исландия"),
+    ("IN", "India", "Индия"),
+    ("ID", "Indonesia", "Индонезия"),
+    ("IE", "Ireland", "Ирландия"),
+    ("IL", "Israel", "Израиль"),
+    ("IT", "Italy", "Италия"),
+    ("JP", "Japan", "Япония"),
+    ("KZ", "Kazakhstan", "Казахстан"),
+    ("KE", "Kenya", "Кения"),
+    ("KG", "Kyrgyzstan", "Киргизия"),
+    ("LV", "Latvia", "Латвия"),
+    ("LT", "Lithuania", "Литва"),
+    ("MX", "Mexico", "Мексика"),
+    ("MD", "Moldova", "Молдова"),
+    ("MN", "Mongolia", "Монголия"),
+    ("MA", "Morocco", "Марокко"),
+    ("NL", "Netherlands", "Нидерланды"),
+    ("NZ", "New Zealand", "Новая Зеландия"),
+    ("NG", "Nigeria", "Нигерия"),
+    ("NO", "Norway", "Норвегия"),
+    ("PE", "Peru", "Перу"),
+    ("PL", "Poland", "Польша"),
+    ("PT", "Portugal", "Португалия"),
+    ("RO", "Romania", "Румыния"),
+    ("RU", "Russia", "Россия"),
+    ("RS", "Serbia", "Сербия"),
+    ("SG", "Singapore", "Сингапур"),
+    ("SK", "Slovakia", "Словакия"),
+    ("SI", "Slovenia", "Словения"),
+    ("ZA", "South Africa", "Южная Африка"),
+    ("KR", "South Korea", "Южная Корея"),
+    ("ES", "Spain", "Испания"),
+    ("SE", "Sweden", "Швеция"),
+    ("CH", "Switzerland", "Швейцария"),
+    ("TJ", "Tajikistan", "Таджикистан"),
+    ("TH", "Thailand", "Таиланд"),
+    ("TR", "Turkey", "Турция"),
+    ("AE", "United Arab Emirates", "Объединённые Арабские Эмираты"),
+    ("GB", "United Kingdom", "Великобритания"),
+    ("US", "United States", "США"),
+    ("UZ", "Uzbekistan", "Узбекистан"),
+    ("VN", "Vietnam", "Вьетнам"),
+];
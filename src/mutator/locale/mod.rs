@@ -1,12 +1,45 @@
 pub mod en;
+pub mod geo_data;
 pub mod ru;
 
 use rand::Rng;
 
-pub fn get_patronymic(rng: &mut impl Rng) -> String {
+pub fn get_patronymic(rng: &mut (impl Rng + ?Sized)) -> String {
     if rng.gen_bool(0.5) {
         ru::PATRONYMICS_MALE[rng.gen_range(0..ru::PATRONYMICS_MALE.len())].to_string()
     } else {
         ru::PATRONYMICS_FEMALE[rng.gen_range(0..ru::PATRONYMICS_FEMALE.len())].to_string()
     }
 }
+
+/// Latin transliteration of Russian letters (simplified ICAO/passport
+/// scheme); other characters are kept.
+pub fn transliterate(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        let lower = c.to_lowercase().next().unwrap_or(c);
+        let latin = match lower {
+            'а' => "a", 'б' => "b", 'в' => "v", 'г' => "g", 'д' => "d",
+            'е' => "e", 'ё' => "e", 'ж' => "zh", 'з' => "z", 'и' => "i",
+            'й' => "i", 'к' => "k", 'л' => "l", 'м' => "m", 'н' => "n",
+            'о' => "o", 'п' => "p", 'р' => "r", 'с' => "s", 'т' => "t",
+            'у' => "u", 'ф' => "f", 'х' => "kh", 'ц' => "ts", 'ч' => "ch",
+            'ш' => "sh", 'щ' => "shch", 'ъ' => "ie", 'ы' => "y", 'ь' => "",
+            'э' => "e", 'ю' => "iu", 'я' => "ia",
+            _ => {
+                out.push(c);
+                continue;
+            }
+        };
+        if c != lower {
+            let mut chars = latin.chars();
+            if let Some(first) = chars.next() {
+                out.push(first.to_ascii_uppercase());
+                out.push_str(chars.as_str());
+            }
+        } else {
+            out.push_str(latin);
+        }
+    }
+    out
+}
@@ -101,3 +101,9 @@ pub static STREETS: &[&str] = &[
 pub static STREET_TYPES: &[&str] = &[
     "ул.", "пр.", "пер.", "бульвар", "проезд", "шоссе", "наб.",
 ];
+
+pub static COLOR_NAMES: &[&str] = &[
+    "красный", "оранжевый", "жёлтый", "зелёный", "голубой", "синий", "фиолетовый", "чёрный",
+    "белый", "серый", "коричневый", "розовый", "бирюзовый", "бежевый", "бордовый", "золотой",
+    "серебристый", "лиловый", "оливковый", "мятный",
+];
@@ -1,4 +1,6 @@
+use rand::seq::SliceRandom;
 use rand::Rng;
+use regex::{Captures, Regex};
 
 use crate::error::{PgStageError, Result};
 use crate::mutator::MutationContext;
@@ -39,3 +41,129 @@ pub fn string_by_mask(ctx: &mut MutationContext) -> Result<String> {
         Ok(gen())
     }
 }
+
+/// Anagram of the current value: its characters in a random order, so the
+/// length and character counts survive. With `preserve_class`, digits only
+/// trade places with digits and letters with letters, and every other
+/// character (separators, spaces) stays where it is.
+pub fn shuffle(ctx: &mut MutationContext) -> Result<String> {
+    // COPY's NULL marker is not a value to rearrange.
+    if ctx.current_value == ctx.null_marker {
+        return Ok(ctx.current_value.to_string());
+    }
+    let mut chars: Vec<char> = ctx.current_value.chars().collect();
+    if !ctx.get_bool_kwarg("preserve_class") {
+        chars.shuffle(ctx.rng);
+        return Ok(chars.into_iter().collect());
+    }
+
+    let classes: [fn(char) -> bool; 2] = [|c| c.is_ascii_digit(), char::is_alphabetic];
+    for class in classes {
+        let positions: Vec<usize> = (0..chars.len()).filter(|&i| class(chars[i])).collect();
+        let mut picked: Vec<char> = positions.iter().map(|&i| chars[i]).collect();
+        picked.shuffle(ctx.rng);
+        for (&i, c) in positions.iter().zip(picked) {
+            chars[i] = c;
+        }
+    }
+    Ok(chars.into_iter().collect())
+}
+
+/// Piece of a `regex_replace` replacement.
+enum ReplacementPart<'r> {
+    Literal(char),
+    Digit,
+    Letter,
+    /// `$1`, `$name` or `${...}`: the text of that capture group.
+    Group(&'r str),
+}
+
+/// Split a replacement into parts. Group references follow the regex
+/// crate's syntax, but placeholders are recognized first, so `$1#` is group
+/// 1 then a digit.
+fn replacement_parts(replacement: &str) -> Vec<ReplacementPart<'_>> {
+    let mut parts = Vec::with_capacity(replacement.len());
+    let mut rest = replacement;
+    while let Some(ch) = rest.chars().next() {
+        rest = &rest[ch.len_utf8()..];
+        let part = match ch {
+            '#' => ReplacementPart::Digit,
+            '@' => ReplacementPart::Letter,
+            '$' if rest.starts_with('$') => {
+                rest = &rest[1..];
+                ReplacementPart::Literal('$')
+            }
+            '$' if rest.starts_with('{') && rest.contains('}') => {
+                let end = rest.find('}').unwrap();
+                let name = &rest[1..end];
+                rest = &rest[end + 1..];
+                ReplacementPart::Group(name)
+            }
+            '$' => {
+                let end = rest
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(rest.len());
+                if end == 0 {
+                    ReplacementPart::Literal('$')
+                } else {
+                    let name = &rest[..end];
+                    rest = &rest[end..];
+                    ReplacementPart::Group(name)
+                }
+            }
+            c => ReplacementPart::Literal(c),
+        };
+        parts.push(part);
+    }
+    parts
+}
+
+/// Replace every match of the regex `pattern` in the current value with
+/// `replacement`, where `$1`/`${name}` insert capture groups and each `#`
+/// and `@` becomes a fresh random digit or capital letter per match; `$$`
+/// is a literal `$`. Text outside the matches is kept, so
+/// `{"pattern": "ORDER-\\d+", "replacement": "ORDER-#####"}` only touches
+/// the number.
+pub fn regex_replace(ctx: &mut MutationContext) -> Result<String> {
+    let pattern = ctx.get_str_kwarg("pattern").ok_or_else(|| {
+        PgStageError::MissingParameter("pattern".to_string(), "regex_replace".to_string())
+    })?;
+    let replacement = ctx.get_str_kwarg("replacement").ok_or_else(|| {
+        PgStageError::MissingParameter("replacement".to_string(), "regex_replace".to_string())
+    })?;
+    // COPY's NULL marker is not a value to scrub.
+    if ctx.current_value == ctx.null_marker {
+        return Ok(ctx.current_value.to_string());
+    }
+    if !ctx.regexes.contains_key(pattern) {
+        let re = Regex::new(pattern).map_err(|e| {
+            PgStageError::InvalidParameter(format!(
+                "regex_replace: invalid pattern '{}': {}",
+                pattern, e
+            ))
+        })?;
+        ctx.regexes.insert(pattern.to_string(), re);
+    }
+    let re = &ctx.regexes[pattern];
+    let parts = replacement_parts(replacement);
+    let rng = &mut *ctx.rng;
+    let replaced = re.replace_all(ctx.current_value, |caps: &Captures| {
+        let mut out = String::new();
+        for part in &parts {
+            match part {
+                ReplacementPart::Literal(c) => out.push(*c),
+                ReplacementPart::Digit => out.push((b'0' + rng.gen_range(0..10u8)) as char),
+                ReplacementPart::Letter => out.push((b'A' + rng.gen_range(0..26u8)) as char),
+                ReplacementPart::Group(name) => {
+                    let group = match name.parse::<usize>() {
+                        Ok(i) => caps.get(i),
+                        Err(_) => caps.name(name),
+                    };
+                    out.push_str(group.map_or("", |m| m.as_str()));
+                }
+            }
+        }
+        out
+    });
+    Ok(replaced.into_owned())
+}
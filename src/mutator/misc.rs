@@ -0,0 +1,112 @@
+use rand::Rng;
+
+use crate::error::{PgStageError, Result};
+use crate::mutator::locale::{en, ru};
+use crate::mutator::MutationContext;
+use crate::types::Locale;
+
+/// Random color name from the locale's palette.
+pub fn color_name(ctx: &mut MutationContext) -> Result<String> {
+    let palette = match ctx.locale {
+        Locale::En => en::COLOR_NAMES,
+        Locale::Ru => ru::COLOR_NAMES,
+    };
+    Ok(palette[ctx.rng.gen_range(0..palette.len())].to_string())
+}
+
+/// Random CSS hex color: `#rrggbb`, or `#rgb` with `short`. Lowercase
+/// unless `uppercase` is set.
+pub fn color_hex(ctx: &mut MutationContext) -> Result<String> {
+    let digits = if ctx.get_bool_kwarg("short") { 3 } else { 6 };
+    let value: u32 = ctx.rng.gen_range(0..1 << (4 * digits));
+    Ok(if ctx.get_bool_kwarg("uppercase") {
+        format!("#{:0width$X}", value, width = digits)
+    } else {
+        format!("#{:0width$x}", value, width = digits)
+    })
+}
+
+/// Plausible storage path such as `/var/data/2024/report-8173.pdf`: `depth`
+/// directories (default 3, the last one a year when there are two or more)
+/// and a file named after a document kind. `extension` fixes the file
+/// extension (random otherwise; `""` for none). `style: "windows"` writes
+/// `C:\var\data\...` instead of the default `unix` form.
+pub fn file_path(ctx: &mut MutationContext) -> Result<String> {
+    let depth = match ctx.kwargs.get("depth") {
+        None => 3,
+        Some(v) => v.as_u64().ok_or_else(|| {
+            PgStageError::InvalidParameter(format!(
+                "file_path: depth must be a non-negative integer, got {}",
+                v
+            ))
+        })? as usize,
+    };
+    let (root, separator) = match ctx.get_str_kwarg("style").unwrap_or("unix") {
+        "unix" => ("", '/'),
+        "windows" => ("C:", '\\'),
+        other => {
+            return Err(PgStageError::InvalidParameter(format!(
+                "file_path: unknown style '{}', expected unix|windows",
+                other
+            )))
+        }
+    };
+    let extension = ctx.get_str_kwarg("extension").map(|e| e.trim_start_matches('.'));
+
+    let mut path = String::from(root);
+    for level in 0..depth {
+        path.push(separator);
+        if depth >= 2 && level == depth - 1 {
+            path.push_str(&ctx.rng.gen_range(2015..=2025).to_string());
+        } else {
+            path.push_str(en::PATH_DIRS[ctx.rng.gen_range(0..en::PATH_DIRS.len())]);
+        }
+    }
+    path.push(separator);
+    path.push_str(en::FILE_STEMS[ctx.rng.gen_range(0..en::FILE_STEMS.len())]);
+    path.push('-');
+    path.push_str(&ctx.rng.gen_range(1000..10000).to_string());
+    let extension = extension
+        .unwrap_or_else(|| en::FILE_EXTENSIONS[ctx.rng.gen_range(0..en::FILE_EXTENSIONS.len())]);
+    if !extension.is_empty() {
+        path.push('.');
+        path.push_str(extension);
+    }
+    Ok(path)
+}
+
+/// Pre-release labels used by `semver`.
+const PRERELEASE_LABELS: &[&str] = &["alpha", "beta", "rc"];
+
+/// Semantic version `MAJOR.MINOR.PATCH`, each part drawn from 0 up to
+/// `max_major` (default 9), `max_minor` (default 20) and `max_patch`
+/// (default 30). `prerelease` appends `-alpha.N`, `-beta.N` or `-rc.N`;
+/// `build` appends `+build.N`.
+pub fn semver(ctx: &mut MutationContext) -> Result<String> {
+    let bound = |name: &str, default: u64| match ctx.kwargs.get(name) {
+        None => Ok(default),
+        Some(v) => v.as_u64().ok_or_else(|| {
+            PgStageError::InvalidParameter(format!(
+                "semver: {} must be a non-negative integer, got {}",
+                name, v
+            ))
+        }),
+    };
+    let (max_major, max_minor, max_patch) =
+        (bound("max_major", 9)?, bound("max_minor", 20)?, bound("max_patch", 30)?);
+
+    let mut version = format!(
+        "{}.{}.{}",
+        ctx.rng.gen_range(0..=max_major),
+        ctx.rng.gen_range(0..=max_minor),
+        ctx.rng.gen_range(0..=max_patch)
+    );
+    if ctx.get_bool_kwarg("prerelease") {
+        let label = PRERELEASE_LABELS[ctx.rng.gen_range(0..PRERELEASE_LABELS.len())];
+        version.push_str(&format!("-{}.{}", label, ctx.rng.gen_range(1..10)));
+    }
+    if ctx.get_bool_kwarg("build") {
+        version.push_str(&format!("+build.{}", ctx.rng.gen_range(1..10000)));
+    }
+    Ok(version)
+}
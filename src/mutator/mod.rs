@@ -1,17 +1,30 @@
+pub mod array;
 pub mod contact;
 pub mod datetime;
+pub mod finance;
 pub mod identity;
+pub mod identity_ru;
+pub mod json;
 pub mod json_update;
 pub mod locale;
 pub mod mask;
+pub mod misc;
 pub mod names;
 pub mod network;
 pub mod numeric;
+pub mod partial;
 pub mod simple;
+pub mod text;
 
-use rand::rngs::ThreadRng;
+use std::cell::RefCell;
+use std::sync::Arc;
 
-use crate::error::Result;
+use chrono::{DateTime, TimeZone, Utc};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::Sha256;
+
+use crate::error::{PgStageError, Result};
 use crate::types::Locale;
 use crate::unique::UniqueTracker;
 use crate::FastMap;
@@ -19,21 +32,91 @@ use crate::FastMap;
 /// Monomorphic function pointer type used by the dispatch table.
 pub type MutationFn = fn(&mut MutationContext) -> Result<String>;
 
+/// User-defined mutation, registered by name with
+/// `DataProcessor::register_mutator`. Custom mutators take precedence over
+/// built-ins of the same name.
+pub trait Mutator: Send + Sync {
+    fn generate(&self, ctx: &mut MutationContext) -> Result<String>;
+}
+
+/// Custom mutators by name, consulted before the built-in dispatch table.
+pub type CustomMutators = FastMap<String, Arc<dyn Mutator>>;
+
+/// Candidate lists loaded by `random_choice` from `choices_file`, by path.
+pub type ChoiceFiles = FastMap<String, Vec<String>>;
+
+/// People generated for `name_group` kwargs in the current row, by group.
+pub type NameGroups = FastMap<String, names::Person>;
+
+/// Compiled `regex_replace` patterns, keyed by pattern text.
+pub type Regexes = FastMap<String, regex::Regex>;
+
+/// A mutation name resolved at parse time: a built-in function pointer or a
+/// registered custom mutator.
+#[derive(Clone)]
+pub enum ResolvedMutation {
+    Builtin(MutationFn),
+    Custom(Arc<dyn Mutator>),
+}
+
+impl ResolvedMutation {
+    /// Resolve `name`, preferring `custom` over the built-ins.
+    pub fn resolve(name: &str, custom: &CustomMutators) -> Option<Self> {
+        match custom.get(name) {
+            Some(m) => Some(ResolvedMutation::Custom(Arc::clone(m))),
+            None => resolve_mutation(name).map(ResolvedMutation::Builtin),
+        }
+    }
+
+    pub fn call(&self, ctx: &mut MutationContext) -> Result<String> {
+        match self {
+            ResolvedMutation::Builtin(f) => f(ctx),
+            ResolvedMutation::Custom(m) => m.generate(ctx),
+        }
+    }
+}
+
 /// Read-only accessor for already-obfuscated values in the current row.
 /// Used by mutations like `uuid5_by_source_value` that derive their output
 /// from another column's (already obfuscated) value.
 pub trait ObfuscatedLookup {
     fn get(&self, column: &str) -> Option<&str>;
+
+    /// The column's value as read from the dump, before any mutation.
+    fn original(&self, _column: &str) -> Option<&str> {
+        None
+    }
+
+    /// People generated for `name_group` kwargs earlier in the current row.
+    fn name_groups(&self) -> Option<&RefCell<NameGroups>> {
+        None
+    }
 }
 
 pub struct MutationContext<'a> {
     pub kwargs: &'a FastMap<String, serde_json::Value>,
     pub current_value: &'a str,
-    pub rng: &'a mut ThreadRng,
+    pub rng: &'a mut dyn RngCore,
     pub unique_tracker: &'a mut UniqueTracker,
     pub locale: Locale,
     pub secrets: &'a FastMap<String, String>,
     pub obfuscated_values: &'a dyn ObfuscatedLookup,
+    /// Deterministic test mode: time-dependent mutations use a fixed clock.
+    pub deterministic: bool,
+    /// `choices_file` contents, read once per processor.
+    pub choice_files: &'a mut ChoiceFiles,
+    /// `regex_replace` patterns, compiled once per processor.
+    pub regexes: &'a mut Regexes,
+    /// Labels of the column's enum type, when its `CREATE TYPE` was seen.
+    pub enum_labels: Option<&'a [String]>,
+    /// Next `sequence` value for this column (`None` before the first row).
+    pub sequence: &'a mut Option<i64>,
+    /// How SQL NULL is spelled in the current rows, both read and written:
+    /// `\N` unless a text-format COPY statement says otherwise (CSV NULLs
+    /// are always `\N` here).
+    pub null_marker: &'a str,
+    /// Specs nested in `kwargs`, resolved when the outer spec was compiled.
+    pub nested_mutations: &'a NestedMutations,
 }
 
 impl<'a> MutationContext<'a> {
@@ -47,50 +130,329 @@ impl<'a> MutationContext<'a> {
     pub fn get_str_kwarg(&self, key: &str) -> Option<&'a str> {
         self.kwargs.get(key).and_then(|v| v.as_str())
     }
+
+    /// The `max_length` kwarg of `mutation`: a cap in characters, so values
+    /// fit a `varchar(n)` column. `None` when unset.
+    pub fn max_length_kwarg(&self, mutation: &str) -> Result<Option<usize>> {
+        match self.kwargs.get("max_length") {
+            None => Ok(None),
+            Some(v) => match v.as_u64() {
+                Some(n) if n > 0 => Ok(Some(n as usize)),
+                _ => Err(PgStageError::InvalidParameter(format!(
+                    "{}: max_length must be a positive integer, got {}",
+                    mutation, v
+                ))),
+            },
+        }
+    }
+
+    /// HMAC-SHA256 of `message` keyed with `SECRET_KEY_NONCE` +
+    /// `SECRET_KEY`, for mutations that must map equal inputs to equal
+    /// outputs across dumps.
+    pub fn keyed_digest(&self, message: &[u8]) -> Result<[u8; 32]> {
+        let secret_key = self.secrets.get("SECRET_KEY").map(String::as_str).unwrap_or("");
+        let nonce = self
+            .secrets
+            .get("SECRET_KEY_NONCE")
+            .map(String::as_str)
+            .unwrap_or("");
+        if secret_key.is_empty() {
+            return Err(PgStageError::MutationError(
+                "SECRET_KEY environment variable not set".to_string(),
+            ));
+        }
+        if nonce.is_empty() {
+            return Err(PgStageError::MutationError(
+                "SECRET_KEY_NONCE environment variable not set".to_string(),
+            ));
+        }
+        let mut mac = Hmac::<Sha256>::new_from_slice(format!("{}{}", nonce, secret_key).as_bytes())
+            .map_err(|e| PgStageError::MutationError(e.to_string()))?;
+        mac.update(message);
+        Ok(mac.finalize().into_bytes().into())
+    }
+
+    /// Current time, pinned to 2024-01-01T00:00:00Z in deterministic mode.
+    pub fn now(&self) -> DateTime<Utc> {
+        if self.deterministic {
+            Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap()
+        } else {
+            Utc::now()
+        }
+    }
+
+    /// The nested spec compiled for `key` of this mutation's kwargs.
+    pub fn nested_mutation(&self, key: &str, mutation: &str) -> Result<&'a NestedMutation> {
+        self.nested_mutations.get(key).ok_or_else(|| {
+            PgStageError::MutationError(format!("{}: nested spec '{}' was not compiled", mutation, key))
+        })
+    }
+
+    /// Context for a mutation run inside this one (`partial`, `array`,
+    /// `json`, `json_update`, `template`): the same row, RNG, unique tracker
+    /// and caches, with the nested spec's kwargs and its own input value.
+    pub fn nested<'b>(&'b mut self, spec: &'b NestedMutation, current_value: &'b str) -> MutationContext<'b> {
+        MutationContext {
+            kwargs: &spec.kwargs,
+            current_value,
+            rng: &mut *self.rng,
+            unique_tracker: &mut *self.unique_tracker,
+            locale: self.locale,
+            secrets: self.secrets,
+            obfuscated_values: self.obfuscated_values,
+            deterministic: self.deterministic,
+            choice_files: &mut *self.choice_files,
+            regexes: &mut *self.regexes,
+            enum_labels: self.enum_labels,
+            sequence: &mut *self.sequence,
+            null_marker: self.null_marker,
+            nested_mutations: &spec.nested,
+        }
+    }
+}
+
+/// A `{"mutation_name": ..., "mutation_kwargs": {...}}` spec inside another
+/// mutation's kwargs, resolved (with its own nested specs) when the outer
+/// spec is compiled.
+#[derive(Clone)]
+pub struct NestedMutation {
+    pub mutation: ResolvedMutation,
+    pub kwargs: FastMap<String, serde_json::Value>,
+    pub nested: NestedMutations,
+}
+
+/// Nested specs of one mutation, keyed the way its kwargs name them:
+/// `mutation` for `partial`, `element` for `array`, the field path for
+/// `json`, the JSON key for `json_update` and the placeholder for `template`.
+pub type NestedMutations = FastMap<String, NestedMutation>;
+
+impl NestedMutation {
+    /// Compile `spec`. `what` says where it sits, for error messages, e.g.
+    /// `partial 'mutation'`.
+    fn compile(spec: &serde_json::Value, what: &str, custom: &CustomMutators) -> Result<Self> {
+        let (name, kwargs) = parse_nested_spec(spec, what)?;
+        Self::resolve(name, kwargs, custom)
+    }
+
+    fn resolve(
+        name: &str,
+        kwargs: FastMap<String, serde_json::Value>,
+        custom: &CustomMutators,
+    ) -> Result<Self> {
+        let mutation = ResolvedMutation::resolve(name, custom)
+            .ok_or_else(|| PgStageError::UnknownMutation(name.to_string()))?;
+        let nested = compile_nested(name, &kwargs, custom)?;
+        Ok(NestedMutation { mutation, kwargs, nested })
+    }
+
+    pub fn call(&self, ctx: &mut MutationContext) -> Result<String> {
+        self.mutation.call(ctx)
+    }
+}
+
+fn parse_nested_spec<'s>(
+    spec: &'s serde_json::Value,
+    what: &str,
+) -> Result<(&'s str, FastMap<String, serde_json::Value>)> {
+    let spec = spec.as_object().ok_or_else(|| {
+        PgStageError::InvalidParameter(format!("{}: expected a mutation spec object", what))
+    })?;
+    let name = spec
+        .get("mutation_name")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| PgStageError::InvalidParameter(format!("{}: missing 'mutation_name'", what)))?;
+    let kwargs = spec
+        .get("mutation_kwargs")
+        .and_then(|v| v.as_object())
+        .map(|kw| kw.iter().map(|(k, v)| (k.clone(), v.clone())).collect())
+        .unwrap_or_default();
+    Ok((name, kwargs))
+}
+
+/// Resolve the specs nested in the kwargs of mutation `name`, so unknown
+/// nested names are reported with the rules instead of failing every row.
+/// A custom mutator shadowing a built-in keeps its kwargs to itself.
+pub fn compile_nested(
+    name: &str,
+    kwargs: &FastMap<String, serde_json::Value>,
+    custom: &CustomMutators,
+) -> Result<NestedMutations> {
+    let mut nested = NestedMutations::default();
+    if custom.contains_key(name) {
+        return Ok(nested);
+    }
+    match name {
+        "partial" | "array" => {
+            let key = if name == "partial" { "mutation" } else { "element" };
+            if let Some(spec) = kwargs.get(key) {
+                let what = format!("{} '{}'", name, key);
+                nested.insert(key.to_string(), NestedMutation::compile(spec, &what, custom)?);
+            }
+        }
+        "json" => {
+            for (path, spec) in kwargs.get("fields").and_then(|v| v.as_object()).into_iter().flatten() {
+                let what = format!("json field '{}'", path);
+                nested.insert(path.clone(), NestedMutation::compile(spec, &what, custom)?);
+            }
+        }
+        "json_update" => {
+            for (key, spec) in kwargs {
+                let (inner, inner_kwargs) = parse_nested_spec(spec, &format!("json_update key '{}'", key))?;
+                // `delete` blanks the key; it is not a mutation.
+                if inner != "delete" {
+                    nested.insert(key.clone(), NestedMutation::resolve(inner, inner_kwargs, custom)?);
+                }
+            }
+        }
+        "template" => {
+            if let Some(pattern) = kwargs.get("pattern").and_then(|v| v.as_str()) {
+                let mut names = Vec::new();
+                text::fill_template(pattern, |placeholder| {
+                    names.push(placeholder.to_string());
+                    Ok(String::new())
+                })?;
+                for placeholder in names {
+                    if !nested.contains_key(&placeholder) {
+                        let spec = NestedMutation::resolve(&placeholder, FastMap::default(), custom)?;
+                        nested.insert(placeholder, spec);
+                    }
+                }
+            }
+        }
+        _ => {}
+    }
+    Ok(nested)
+}
+
+/// Built-in mutations by name.
+const BUILTIN_MUTATIONS: &[(&str, MutationFn)] = &[
+    ("first_name", names::first_name),
+    ("last_name", names::last_name),
+    ("full_name", names::full_name),
+    ("middle_name", names::middle_name),
+    ("gender", names::gender),
+
+    ("email", contact::email),
+    ("deterministic_email", contact::deterministic_email),
+    ("phone_number", contact::phone_number),
+    ("address", contact::address),
+    ("multiline_address", contact::multiline_address),
+    ("postal_code", contact::postal_code),
+    ("country", contact::country),
+    ("country_code", contact::country_code),
+    ("deterministic_phone_number", contact::deterministic_phone),
+
+    ("numeric_smallint", numeric::smallint),
+    ("numeric_integer", numeric::integer),
+    ("numeric_bigint", numeric::bigint),
+    ("numeric_decimal", numeric::decimal),
+    ("numeric_real", numeric::real),
+    ("numeric_double_precision", numeric::double_precision),
+    ("numeric_smallserial", numeric::smallserial),
+    ("numeric_serial", numeric::serial),
+    ("numeric_bigserial", numeric::bigserial),
+    ("sequence", numeric::sequence),
+    ("numeric_jitter", numeric::jitter),
+
+    ("money", finance::money),
+    ("credit_card_mask", finance::credit_card_mask),
+    ("currency_code", finance::currency_code),
+
+    ("date", datetime::date),
+    ("deterministic_date", datetime::deterministic_date),
+    ("birthdate", datetime::birthdate),
+    ("timezone", datetime::timezone),
+
+    ("uri", network::uri),
+    ("domain", network::domain),
+    ("hostname", network::hostname),
+    ("ipv4", network::ipv4),
+    ("ipv6", network::ipv6),
+    ("mac_address", network::mac_address),
+    ("user_agent", network::user_agent),
+    ("deterministic_ipv4", network::deterministic_ipv4),
+
+    ("uuid4", identity::uuid4),
+    ("uuid7", identity::uuid7),
+    ("uuid5_by_source_value", identity::uuid5_by_source_value),
+    ("username", identity::username),
+    ("document_number", identity::document_number),
+    ("foreign_key", identity::foreign_key),
+    ("inn", identity_ru::inn),
+    ("snils", identity_ru::snils),
+
+    ("null", simple::null),
+    ("empty_string", simple::empty_string),
+    ("fixed_value", simple::fixed_value),
+    ("truncate", simple::truncate),
+    ("random_choice", simple::random_choice),
+    ("enum_value", simple::enum_value),
+    ("status", simple::status),
+    ("boolean", simple::boolean),
+    ("bytea", simple::bytea),
+    ("base64", simple::base64),
+
+    ("color_name", misc::color_name),
+    ("color_hex", misc::color_hex),
+    ("file_path", misc::file_path),
+    ("semver", misc::semver),
+
+    ("slug", text::slug),
+    ("template", text::template),
+
+    ("string_by_mask", mask::string_by_mask),
+    ("shuffle", mask::shuffle),
+    ("regex_replace", mask::regex_replace),
+
+    ("json", json::json),
+    ("array", array::array),
+    ("json_update", json_update::json_update),
+
+    ("partial", partial::partial),
+];
+
+/// Cut `value` to at most `max_length` characters, on a char boundary.
+pub fn truncate_to_max_length(mut value: String, max_length: Option<usize>) -> String {
+    if let Some((at, _)) = max_length.and_then(|max| value.char_indices().nth(max)) {
+        value.truncate(at);
+    }
+    value
 }
 
 /// Resolve a mutation name to its function pointer at parse time (once).
 /// Returns `None` for unknown names — callers turn that into an error.
 pub fn resolve_mutation(name: &str) -> Option<MutationFn> {
-    Some(match name {
-        "first_name" => names::first_name,
-        "last_name" => names::last_name,
-        "full_name" => names::full_name,
-        "middle_name" => names::middle_name,
-
-        "email" => contact::email,
-        "phone_number" => contact::phone_number,
-        "address" => contact::address,
-        "deterministic_phone_number" => contact::deterministic_phone,
-
-        "numeric_smallint" => numeric::smallint,
-        "numeric_integer" => numeric::integer,
-        "numeric_bigint" => numeric::bigint,
-        "numeric_decimal" => numeric::decimal,
-        "numeric_real" => numeric::real,
-        "numeric_double_precision" => numeric::double_precision,
-        "numeric_smallserial" => numeric::smallserial,
-        "numeric_serial" => numeric::serial,
-        "numeric_bigserial" => numeric::bigserial,
-
-        "date" => datetime::date,
-
-        "uri" => network::uri,
-        "ipv4" => network::ipv4,
-        "ipv6" => network::ipv6,
-
-        "uuid4" => identity::uuid4,
-        "uuid5_by_source_value" => identity::uuid5_by_source_value,
-
-        "null" => simple::null,
-        "empty_string" => simple::empty_string,
-        "fixed_value" => simple::fixed_value,
-        "random_choice" => simple::random_choice,
-
-        "string_by_mask" => mask::string_by_mask,
-
-        "json_update" => json_update::json_update,
-
-        _ => return None,
-    })
+    BUILTIN_MUTATIONS
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|&(_, f)| f)
+}
+
+/// Names of all built-in mutations.
+pub fn mutation_names() -> impl Iterator<Item = &'static str> {
+    BUILTIN_MUTATIONS.iter().map(|&(n, _)| n)
+}
+
+/// The built-in mutation closest to `name`, if one is within two edits —
+/// used to point out typos such as `emial`.
+pub fn suggest_mutation(name: &str) -> Option<&'static str> {
+    mutation_names()
+        .map(|n| (edit_distance(name, n), n))
+        .filter(|&(d, _)| d <= 2)
+        .min_by_key(|&(d, _)| d)
+        .map(|(_, n)| n)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1; b.len() + 1];
+        for (j, &cb) in b.iter().enumerate() {
+            let cost = usize::from(ca != cb);
+            cur[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        prev = cur;
+    }
+    prev[b.len()]
 }
@@ -1,17 +1,110 @@
-use rand::Rng;
+use rand::{Rng, RngCore};
 
 use crate::error::{PgStageError, Result};
 use crate::mutator::locale::{en, ru};
 use crate::mutator::MutationContext;
 use crate::types::Locale;
 
+/// Labels `gender` treats as male / female, compared case-insensitively.
+const MALE_LABELS: &[&str] = &["male", "m", "man", "м", "муж", "мужской"];
+const FEMALE_LABELS: &[&str] = &["female", "f", "woman", "ж", "жен", "женский"];
+
+/// Random gender label from `values` (default `["male", "female"]`).
+pub fn gender(ctx: &mut MutationContext) -> Result<String> {
+    let values: Vec<&str> = match ctx.kwargs.get("values") {
+        Some(v) => v
+            .as_array()
+            .filter(|list| !list.is_empty())
+            .and_then(|list| list.iter().map(|x| x.as_str()).collect::<Option<Vec<_>>>())
+            .ok_or_else(|| {
+                PgStageError::InvalidParameter(
+                    "gender: values must be a non-empty array of strings".to_string(),
+                )
+            })?,
+        None => vec!["male", "female"],
+    };
+    Ok(values[ctx.rng.gen_range(0..values.len())].to_string())
+}
+
+/// Gender of the row, read from the already-obfuscated `gender_column`, so
+/// Russian names agree with the generated gender. `None` when the kwarg is
+/// unset or the label is not recognized.
+fn row_is_male(ctx: &MutationContext) -> Option<bool> {
+    let label = ctx.obfuscated_values.get(ctx.get_str_kwarg("gender_column")?)?;
+    let label = label.trim().to_lowercase();
+    if MALE_LABELS.contains(&label.as_str()) {
+        Some(true)
+    } else if FEMALE_LABELS.contains(&label.as_str()) {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+/// A generated person whose name parts several columns can share.
+#[derive(Debug, Clone, Copy)]
+pub struct Person {
+    pub first: &'static str,
+    pub last: &'static str,
+    /// Russian locale only.
+    pub patronymic: Option<&'static str>,
+    /// English locale only: a second given name, drawn for name groups.
+    pub middle: Option<&'static str>,
+}
+
+fn person(rng: &mut dyn RngCore, locale: Locale, is_male: Option<bool>) -> Person {
+    match locale {
+        Locale::En => Person {
+            first: en::FIRST_NAMES[rng.gen_range(0..en::FIRST_NAMES.len())],
+            last: en::LAST_NAMES[rng.gen_range(0..en::LAST_NAMES.len())],
+            patronymic: None,
+            middle: None,
+        },
+        Locale::Ru => {
+            let (first_names, last_names, patronymics) =
+                if is_male.unwrap_or_else(|| rng.gen_bool(0.5)) {
+                    (ru::FIRST_NAMES_MALE, ru::LAST_NAMES_MALE, ru::PATRONYMICS_MALE)
+                } else {
+                    (ru::FIRST_NAMES_FEMALE, ru::LAST_NAMES_FEMALE, ru::PATRONYMICS_FEMALE)
+                };
+            Person {
+                first: first_names[rng.gen_range(0..first_names.len())],
+                last: last_names[rng.gen_range(0..last_names.len())],
+                patronymic: Some(patronymics[rng.gen_range(0..patronymics.len())]),
+                middle: None,
+            }
+        }
+    }
+}
+
+/// The person shared by this row's columns with the same `name_group`,
+/// generated by the first of them. `None` without the kwarg.
+fn grouped_person(ctx: &mut MutationContext, is_male: Option<bool>) -> Option<Person> {
+    let group = ctx.get_str_kwarg("name_group")?;
+    let groups = ctx.obfuscated_values.name_groups()?;
+    if let Some(&found) = groups.borrow().get(group) {
+        return Some(found);
+    }
+    let group = group.to_string();
+    let mut generated = person(&mut *ctx.rng, ctx.locale, is_male);
+    if ctx.locale == Locale::En {
+        generated.middle = Some(en_middle_name(&mut *ctx.rng, generated.first));
+    }
+    groups.borrow_mut().insert(group, generated);
+    Some(generated)
+}
+
 pub fn first_name(ctx: &mut MutationContext) -> Result<String> {
+    let is_male = row_is_male(ctx);
+    if let Some(person) = grouped_person(ctx, is_male) {
+        return Ok(person.first.to_string());
+    }
     let unique = ctx.get_bool_kwarg("unique");
     let mut gen = || {
         match ctx.locale {
             Locale::En => en::FIRST_NAMES[ctx.rng.gen_range(0..en::FIRST_NAMES.len())].to_string(),
             Locale::Ru => {
-                if ctx.rng.gen_bool(0.5) {
+                if is_male.unwrap_or_else(|| ctx.rng.gen_bool(0.5)) {
                     ru::FIRST_NAMES_MALE[ctx.rng.gen_range(0..ru::FIRST_NAMES_MALE.len())].to_string()
                 } else {
                     ru::FIRST_NAMES_FEMALE[ctx.rng.gen_range(0..ru::FIRST_NAMES_FEMALE.len())].to_string()
@@ -27,12 +120,16 @@ pub fn first_name(ctx: &mut MutationContext) -> Result<String> {
 }
 
 pub fn last_name(ctx: &mut MutationContext) -> Result<String> {
+    let is_male = row_is_male(ctx);
+    if let Some(person) = grouped_person(ctx, is_male) {
+        return Ok(person.last.to_string());
+    }
     let unique = ctx.get_bool_kwarg("unique");
     let mut gen = || {
         match ctx.locale {
             Locale::En => en::LAST_NAMES[ctx.rng.gen_range(0..en::LAST_NAMES.len())].to_string(),
             Locale::Ru => {
-                if ctx.rng.gen_bool(0.5) {
+                if is_male.unwrap_or_else(|| ctx.rng.gen_bool(0.5)) {
                     ru::LAST_NAMES_MALE[ctx.rng.gen_range(0..ru::LAST_NAMES_MALE.len())].to_string()
                 } else {
                     ru::LAST_NAMES_FEMALE[ctx.rng.gen_range(0..ru::LAST_NAMES_FEMALE.len())].to_string()
@@ -47,32 +144,48 @@ pub fn last_name(ctx: &mut MutationContext) -> Result<String> {
     }
 }
 
+/// Name part orders `full_name` accepts as `order`.
+const FULL_NAME_ORDERS: &[&str] = &[
+    "first_last",
+    "last_first",
+    "first_patronymic_last",
+    "last_first_patronymic",
+];
+
+/// Full name in `order`: `first_last` by default for English,
+/// `last_first_patronymic` for Russian. English names have no patronymic,
+/// so the patronymic orders drop it there.
+///
+/// With `name_group`, the name parts are shared with the row's other name
+/// columns in the same group (`unique` then does not apply).
 pub fn full_name(ctx: &mut MutationContext) -> Result<String> {
     let unique = ctx.get_bool_kwarg("unique");
-    let mut gen = || {
-        match ctx.locale {
-            Locale::En => {
-                let first = en::FIRST_NAMES[ctx.rng.gen_range(0..en::FIRST_NAMES.len())];
-                let last = en::LAST_NAMES[ctx.rng.gen_range(0..en::LAST_NAMES.len())];
-                format!("{} {}", last, first)
-            }
-            Locale::Ru => {
-                let (first, last) = if ctx.rng.gen_bool(0.5) {
-                    (
-                        ru::FIRST_NAMES_MALE[ctx.rng.gen_range(0..ru::FIRST_NAMES_MALE.len())],
-                        ru::LAST_NAMES_MALE[ctx.rng.gen_range(0..ru::LAST_NAMES_MALE.len())],
-                    )
-                } else {
-                    (
-                        ru::FIRST_NAMES_FEMALE[ctx.rng.gen_range(0..ru::FIRST_NAMES_FEMALE.len())],
-                        ru::LAST_NAMES_FEMALE[ctx.rng.gen_range(0..ru::LAST_NAMES_FEMALE.len())],
-                    )
-                };
-                let patronymic = crate::mutator::locale::get_patronymic(&mut *ctx.rng);
-                format!("{} {} {}", last, first, patronymic)
-            }
+    let is_male = row_is_male(ctx);
+    let order = match ctx.get_str_kwarg("order") {
+        Some(order) if FULL_NAME_ORDERS.contains(&order) => order,
+        Some(order) => {
+            return Err(PgStageError::InvalidParameter(format!(
+                "full_name: order must be one of {}, got '{}'",
+                FULL_NAME_ORDERS.join(", "),
+                order
+            )))
         }
+        None if ctx.locale == Locale::Ru => "last_first_patronymic",
+        None => "first_last",
     };
+    let format = |p: Person| {
+        let parts = match order {
+            "first_last" => [Some(p.first), Some(p.last), None],
+            "last_first" => [Some(p.last), Some(p.first), None],
+            "first_patronymic_last" => [Some(p.first), p.patronymic, Some(p.last)],
+            _ => [Some(p.last), Some(p.first), p.patronymic],
+        };
+        parts.into_iter().flatten().collect::<Vec<_>>().join(" ")
+    };
+    if let Some(p) = grouped_person(ctx, is_male) {
+        return Ok(format(p));
+    }
+    let mut gen = || format(person(&mut *ctx.rng, ctx.locale, is_male));
     if unique {
         ctx.unique_tracker.generate_unique(gen)
     } else {
@@ -80,14 +193,49 @@ pub fn full_name(ctx: &mut MutationContext) -> Result<String> {
     }
 }
 
+/// An English given name other than `first`.
+fn en_middle_name(rng: &mut dyn RngCore, first: &str) -> &'static str {
+    loop {
+        let name = en::FIRST_NAMES[rng.gen_range(0..en::FIRST_NAMES.len())];
+        if name != first {
+            return name;
+        }
+    }
+}
+
+/// Middle name: a patronymic for Russian, a second given name for English
+/// (different from the first name within a `name_group`). `style:
+/// "initial"` shortens it to its first letter and a dot (`J.`).
 pub fn middle_name(ctx: &mut MutationContext) -> Result<String> {
-    if ctx.locale != Locale::Ru {
-        return Err(PgStageError::MutationError(
-            "middle_name mutation is only available for Russian locale".to_string(),
-        ));
+    let initial = match ctx.get_str_kwarg("style") {
+        None | Some("full") => false,
+        Some("initial") => true,
+        Some(style) => {
+            return Err(PgStageError::InvalidParameter(format!(
+                "middle_name: style must be 'full' or 'initial', got '{}'",
+                style
+            )))
+        }
+    };
+    let shorten = |name: &str| match name.chars().next() {
+        Some(c) if initial => format!("{}.", c),
+        _ => name.to_string(),
+    };
+    let is_male = row_is_male(ctx);
+    if let Some(person) = grouped_person(ctx, is_male) {
+        return Ok(shorten(person.patronymic.or(person.middle).unwrap_or_default()));
     }
     let unique = ctx.get_bool_kwarg("unique");
-    let mut gen = || crate::mutator::locale::get_patronymic(&mut *ctx.rng);
+    let mut gen = || match (ctx.locale, is_male) {
+        (Locale::En, _) => shorten(en::FIRST_NAMES[ctx.rng.gen_range(0..en::FIRST_NAMES.len())]),
+        (Locale::Ru, Some(true)) => {
+            shorten(ru::PATRONYMICS_MALE[ctx.rng.gen_range(0..ru::PATRONYMICS_MALE.len())])
+        }
+        (Locale::Ru, Some(false)) => {
+            shorten(ru::PATRONYMICS_FEMALE[ctx.rng.gen_range(0..ru::PATRONYMICS_FEMALE.len())])
+        }
+        (Locale::Ru, None) => shorten(&crate::mutator::locale::get_patronymic(&mut *ctx.rng)),
+    };
     if unique {
         ctx.unique_tracker.generate_unique(gen)
     } else {
@@ -1,15 +1,13 @@
+use std::net::{Ipv4Addr, Ipv6Addr};
+
 use rand::Rng;
 
-use crate::error::Result;
-use crate::mutator::MutationContext;
+use crate::error::{PgStageError, Result};
+use crate::mutator::{truncate_to_max_length, MutationContext};
 use crate::mutator::locale::en;
 
 pub fn uri(ctx: &mut MutationContext) -> Result<String> {
-    let max_length = ctx
-        .kwargs
-        .get("max_length")
-        .and_then(|v| v.as_u64())
-        .unwrap_or(2048) as usize;
+    let max_length = ctx.max_length_kwarg("uri")?.unwrap_or(2048);
     let unique = ctx.get_bool_kwarg("unique");
 
     let mut gen = || {
@@ -22,12 +20,50 @@ pub fn uri(ctx: &mut MutationContext) -> Result<String> {
                 chars[ctx.rng.gen_range(0..chars.len())] as char
             })
             .collect();
-        let url = format!("{}://{}/{}", scheme, domain, path);
-        if url.len() > max_length {
-            url[..max_length].to_string()
-        } else {
-            url
+        truncate_to_max_length(format!("{}://{}/{}", scheme, domain, path), Some(max_length))
+    };
+
+    if unique {
+        ctx.unique_tracker.generate_unique(gen)
+    } else {
+        Ok(gen())
+    }
+}
+
+/// Random second-level domain such as `bright-fox.net`: an adjective and a
+/// noun joined by a hyphen, under a TLD picked from `tlds` (default
+/// `["com", "net", "org"]`).
+pub fn domain(ctx: &mut MutationContext) -> Result<String> {
+    let tlds: Vec<&str> = match ctx.kwargs.get("tlds") {
+        Some(v) => {
+            let list = v.as_array().ok_or_else(|| {
+                PgStageError::InvalidParameter("domain: tlds must be an array of strings".to_string())
+            })?;
+            list.iter()
+                .map(|t| {
+                    t.as_str()
+                        .map(|t| t.trim_start_matches('.'))
+                        .filter(|t| !t.is_empty())
+                        .ok_or_else(|| {
+                            PgStageError::InvalidParameter(format!("domain: invalid tld {}", t))
+                        })
+                })
+                .collect::<Result<_>>()?
         }
+        None => vec!["com", "net", "org"],
+    };
+    if tlds.is_empty() {
+        return Err(PgStageError::InvalidParameter(
+            "domain: tlds must not be empty".to_string(),
+        ));
+    }
+    let unique = ctx.get_bool_kwarg("unique");
+
+    let mut gen = || {
+        let adjective = en::DOMAIN_ADJECTIVES[ctx.rng.gen_range(0..en::DOMAIN_ADJECTIVES.len())];
+        let noun = en::DOMAIN_NOUNS[ctx.rng.gen_range(0..en::DOMAIN_NOUNS.len())];
+        let tld = tlds[ctx.rng.gen_range(0..tlds.len())];
+        format!("{}-{}.{}", adjective, noun, tld)
     };
 
     if unique {
@@ -37,16 +73,68 @@ pub fn uri(ctx: &mut MutationContext) -> Result<String> {
     }
 }
 
-pub fn ipv4(ctx: &mut MutationContext) -> Result<String> {
+/// Random FQDN with `labels` labels (default 4, 2..=10), e.g.
+/// `web-03.dc1.maple.net`: a host label, site labels, a domain label and a
+/// `.com`/`.net`/`.org` TLD. Every label is a valid lowercase DNS label.
+pub fn hostname(ctx: &mut MutationContext) -> Result<String> {
+    let labels = match ctx.kwargs.get("labels") {
+        Some(v) => match v.as_u64() {
+            Some(n @ 2..=10) => n as usize,
+            _ => {
+                return Err(PgStageError::InvalidParameter(format!(
+                    "hostname: labels must be an integer in 2..=10, got {}",
+                    v
+                )))
+            }
+        },
+        None => 4,
+    };
     let unique = ctx.get_bool_kwarg("unique");
+
     let mut gen = || {
-        format!(
+        let mut parts = Vec::with_capacity(labels);
+        if labels > 2 {
+            let role = en::HOST_ROLES[ctx.rng.gen_range(0..en::HOST_ROLES.len())];
+            parts.push(format!("{}-{:02}", role, ctx.rng.gen_range(1..100)));
+        }
+        for _ in 3..labels {
+            let site = en::HOST_SITES[ctx.rng.gen_range(0..en::HOST_SITES.len())];
+            parts.push(format!("{}{}", site, ctx.rng.gen_range(1..10)));
+        }
+        parts.push(en::DOMAIN_NOUNS[ctx.rng.gen_range(0..en::DOMAIN_NOUNS.len())].to_string());
+        parts.push(["com", "net", "org"][ctx.rng.gen_range(0..3)].to_string());
+        parts.join(".")
+    };
+
+    if unique {
+        ctx.unique_tracker.generate_unique(gen)
+    } else {
+        Ok(gen())
+    }
+}
+
+/// Random IPv4 address, or one inside the `cidr` network (e.g. `10.0.0.0/8`)
+/// when given. Inside a CIDR the network and broadcast addresses are skipped
+/// whenever the block has room for hosts.
+pub fn ipv4(ctx: &mut MutationContext) -> Result<String> {
+    let unique = ctx.get_bool_kwarg("unique");
+    let cidr = ctx.get_str_kwarg("cidr").map(parse_ipv4_cidr).transpose()?;
+    let mut gen = || match cidr {
+        Some((network, host_bits)) => {
+            let host = match host_bits {
+                0 => 0,
+                1 => ctx.rng.gen_range(0..2u64),
+                _ => ctx.rng.gen_range(1..(1u64 << host_bits) - 1),
+            };
+            Ipv4Addr::from(network | host as u32).to_string()
+        }
+        None => format!(
             "{}.{}.{}.{}",
             ctx.rng.gen_range(1..255u8),
             ctx.rng.gen_range(0..255u8),
             ctx.rng.gen_range(0..255u8),
             ctx.rng.gen_range(1..255u8),
-        )
+        ),
     };
     if unique {
         ctx.unique_tracker.generate_unique(gen)
@@ -55,13 +143,81 @@ pub fn ipv4(ctx: &mut MutationContext) -> Result<String> {
     }
 }
 
+/// Parse `a.b.c.d/n` into the masked network address and the host bit count.
+fn parse_ipv4_cidr(cidr: &str) -> Result<(u32, u32)> {
+    let invalid = || PgStageError::InvalidParameter(format!("ipv4: invalid cidr '{}'", cidr));
+    let (addr, prefix) = cidr.split_once('/').ok_or_else(invalid)?;
+    let addr: Ipv4Addr = addr.trim().parse().map_err(|_| invalid())?;
+    let prefix: u32 = prefix.trim().parse().map_err(|_| invalid())?;
+    if prefix > 32 {
+        return Err(invalid());
+    }
+    let host_bits = 32 - prefix;
+    Ok((u32::from(addr) & ipv4_net_mask(host_bits), host_bits))
+}
+
+#[inline]
+fn ipv4_net_mask(host_bits: u32) -> u32 {
+    if host_bits == 32 {
+        0
+    } else {
+        u32::MAX << host_bits
+    }
+}
+
+/// Random IPv6 address, optionally inside `subnet` (e.g. `2001:db8::/32`).
+/// Written as eight zero-padded groups unless `compress` asks for the
+/// canonical `::` form.
 pub fn ipv6(ctx: &mut MutationContext) -> Result<String> {
     let unique = ctx.get_bool_kwarg("unique");
+    let compress = ctx.get_bool_kwarg("compress");
+    let subnet = ctx.get_str_kwarg("subnet").map(parse_ipv6_subnet).transpose()?;
     let mut gen = || {
-        let groups: Vec<String> = (0..8)
-            .map(|_| format!("{:04x}", ctx.rng.gen_range(0..0xFFFFu16)))
-            .collect();
-        groups.join(":")
+        let random: u128 = ctx.rng.gen();
+        let addr = match subnet {
+            Some((network, mask)) => Ipv6Addr::from(network | (random & !mask)),
+            None => Ipv6Addr::from(random),
+        };
+        if compress {
+            addr.to_string()
+        } else {
+            let groups: Vec<String> = addr.segments().iter().map(|g| format!("{:04x}", g)).collect();
+            groups.join(":")
+        }
+    };
+    if unique {
+        ctx.unique_tracker.generate_unique(gen)
+    } else {
+        Ok(gen())
+    }
+}
+
+/// Parse `addr/n` into the masked network address and its prefix mask.
+fn parse_ipv6_subnet(subnet: &str) -> Result<(u128, u128)> {
+    let invalid = || PgStageError::InvalidParameter(format!("ipv6: invalid subnet '{}'", subnet));
+    let (addr, prefix) = subnet.split_once('/').ok_or_else(invalid)?;
+    let addr: Ipv6Addr = addr.trim().parse().map_err(|_| invalid())?;
+    let prefix: u32 = prefix.trim().parse().map_err(|_| invalid())?;
+    if prefix > 128 {
+        return Err(invalid());
+    }
+    let mask = if prefix == 0 { 0 } else { u128::MAX << (128 - prefix) };
+    Ok((u128::from(addr) & mask, mask))
+}
+
+/// Random locally administered unicast MAC address (`02:1a:...`), so it
+/// never collides with a vendor-assigned one. `separator` defaults to `:`.
+pub fn mac_address(ctx: &mut MutationContext) -> Result<String> {
+    let separator = ctx.get_str_kwarg("separator").unwrap_or(":");
+    let unique = ctx.get_bool_kwarg("unique");
+    let mut gen = || {
+        let mut octets: [u8; 6] = ctx.rng.gen();
+        octets[0] = (octets[0] & 0xfc) | 0x02;
+        octets
+            .iter()
+            .map(|o| format!("{:02x}", o))
+            .collect::<Vec<_>>()
+            .join(separator)
     };
     if unique {
         ctx.unique_tracker.generate_unique(gen)
@@ -69,3 +225,97 @@ pub fn ipv6(ctx: &mut MutationContext) -> Result<String> {
         Ok(gen())
     }
 }
+
+/// Browser user-agent string: Chrome, Edge or Firefox on a desktop or
+/// Android platform, or Safari on macOS or iOS, with random version numbers.
+pub fn user_agent(ctx: &mut MutationContext) -> Result<String> {
+    let unique = ctx.get_bool_kwarg("unique");
+    let mut gen = || {
+        let platforms = if ctx.rng.gen_bool(0.25) {
+            en::USER_AGENT_MOBILE_PLATFORMS
+        } else {
+            en::USER_AGENT_DESKTOP_PLATFORMS
+        };
+        let platform = platforms[ctx.rng.gen_range(0..platforms.len())];
+        let major = ctx.rng.gen_range(110..=130);
+        let chrome = format!(
+            "{}.0.{}.{}",
+            major,
+            ctx.rng.gen_range(5000..7000),
+            ctx.rng.gen_range(0..200)
+        );
+        match ctx.rng.gen_range(0..4) {
+            0 => format!(
+                "Mozilla/5.0 ({}) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/{} Safari/537.36",
+                platform, chrome
+            ),
+            1 => format!(
+                "Mozilla/5.0 ({}) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/{} Safari/537.36 Edg/{}",
+                platform, chrome, chrome
+            ),
+            2 => format!(
+                "Mozilla/5.0 ({}; rv:{}.0) Gecko/20100101 Firefox/{}.0",
+                platform, major, major
+            ),
+            _ => {
+                let (version, minor) = (ctx.rng.gen_range(15..=17), ctx.rng.gen_range(0..6));
+                let platform = if ctx.rng.gen_bool(0.5) {
+                    format!("iPhone; CPU iPhone OS {}_{} like Mac OS X", version, minor)
+                } else {
+                    "Macintosh; Intel Mac OS X 10_15_7".to_string()
+                };
+                format!(
+                    "Mozilla/5.0 ({}) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/{}.{} Safari/605.1.15",
+                    platform, version, minor
+                )
+            }
+        }
+    };
+    if unique {
+        ctx.unique_tracker.generate_unique(gen)
+    } else {
+        Ok(gen())
+    }
+}
+
+/// Pseudonymize an IPv4 address while keeping its network segment.
+///
+/// The leading `prefix_length` bits (default 24) are copied from the source
+/// address; the host bits are derived from HMAC-SHA256 of the source address
+/// keyed with `SECRET_KEY`/`SECRET_KEY_NONCE`, so the same source IP always
+/// maps to the same fake IP and per-subnet aggregates survive.
+pub fn deterministic_ipv4(ctx: &mut MutationContext) -> Result<String> {
+    let prefix_len = ctx
+        .kwargs
+        .get("prefix_length")
+        .and_then(|v| v.as_u64())
+        .unwrap_or(24);
+    if prefix_len > 32 {
+        return Err(PgStageError::InvalidParameter(format!(
+            "deterministic_ipv4: prefix_length must be 0..=32, got {}",
+            prefix_len
+        )));
+    }
+
+    let source: Ipv4Addr = ctx.current_value.parse().map_err(|_| {
+        PgStageError::MutationError(format!(
+            "deterministic_ipv4: '{}' is not an IPv4 address",
+            ctx.current_value
+        ))
+    })?;
+
+    let digest = ctx.keyed_digest(&source.octets())?;
+    let hashed = u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]]);
+
+    let host_bits = 32 - prefix_len as u32;
+    let net_mask = ipv4_net_mask(host_bits);
+    let network = u32::from(source) & net_mask;
+    let mut host = hashed & !net_mask;
+    // Keep clear of the network and broadcast addresses when the subnet has
+    // room for real hosts.
+    if host_bits >= 2 {
+        let usable = (1u64 << host_bits) - 2;
+        host = (hashed as u64 % usable) as u32 + 1;
+    }
+    Ok(Ipv4Addr::from(network | host).to_string())
+}
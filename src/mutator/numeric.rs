@@ -1,8 +1,10 @@
 use rand::Rng;
 
-use crate::error::Result;
+use crate::error::{PgStageError, Result};
 use crate::mutator::MutationContext;
 
+/// `start`/`end` kwargs clamped to the column type's range; inverted bounds
+/// are swapped.
 fn get_range_i64(ctx: &MutationContext, min: i64, max: i64) -> (i64, i64) {
     let start = ctx
         .kwargs
@@ -14,13 +16,80 @@ fn get_range_i64(ctx: &MutationContext, min: i64, max: i64) -> (i64, i64) {
         .get("end")
         .and_then(|v| v.as_i64())
         .unwrap_or(max);
-    (start.max(min), end.min(max))
+    let (start, end) = (start.clamp(min, max), end.clamp(min, max));
+    if start > end {
+        (end, start)
+    } else {
+        (start, end)
+    }
+}
+
+/// `start`/`end` float kwargs; inverted bounds are swapped, NaN is rejected.
+fn get_range_f64(ctx: &MutationContext, name: &str, default: f64) -> Result<(f64, f64)> {
+    let start = ctx
+        .kwargs
+        .get("start")
+        .and_then(|v| v.as_f64())
+        .unwrap_or(-default);
+    let end = ctx
+        .kwargs
+        .get("end")
+        .and_then(|v| v.as_f64())
+        .unwrap_or(default);
+    if start.is_nan() || end.is_nan() {
+        return Err(PgStageError::InvalidParameter(format!(
+            "{}: start/end must not be NaN",
+            name
+        )));
+    }
+    Ok(if start > end { (end, start) } else { (start, end) })
+}
+
+/// Sample from `[start, end)`, or return `start` when the range is empty.
+fn sample_f64(rng: &mut (impl Rng + ?Sized), start: f64, end: f64) -> f64 {
+    if start < end {
+        rng.gen_range(start..end)
+    } else {
+        start
+    }
+}
+
+/// Digit count to zero-pad to under `match_format`: the source's length when
+/// it is a zero-padded run of digits (`007`), otherwise none.
+fn zero_pad_width(ctx: &MutationContext) -> usize {
+    let source = ctx.current_value;
+    if !ctx.get_bool_kwarg("match_format")
+        || source.len() < 2
+        || !source.starts_with('0')
+        || !source.bytes().all(|b| b.is_ascii_digit())
+    {
+        return 0;
+    }
+    source.len()
+}
+
+/// Uniform integer in `start..=end`. The full `i64` range (the `bigint`
+/// default) is drawn as a plain `i64`, as its span does not fit in one.
+fn sample_i64(rng: &mut (impl Rng + ?Sized), start: i64, end: i64) -> i64 {
+    if (start, end) == (i64::MIN, i64::MAX) {
+        rng.gen()
+    } else {
+        rng.gen_range(start..=end)
+    }
 }
 
 fn gen_int(ctx: &mut MutationContext, min: i64, max: i64) -> Result<String> {
     let (start, end) = get_range_i64(ctx, min, max);
     let unique = ctx.get_bool_kwarg("unique");
-    let mut gen = || ctx.rng.gen_range(start..=end).to_string();
+    let width = zero_pad_width(ctx);
+    let mut gen = || {
+        let n = sample_i64(ctx.rng, start, end);
+        if n < 0 {
+            format!("-{:0width$}", n.unsigned_abs())
+        } else {
+            format!("{:0width$}", n)
+        }
+    };
     if unique {
         ctx.unique_tracker.generate_unique(gen)
     } else {
@@ -52,17 +121,21 @@ pub fn bigserial(ctx: &mut MutationContext) -> Result<String> {
     gen_int(ctx, 1, 9223372036854775807)
 }
 
+/// Consecutive integers per table column, from `start` (default 1) in
+/// steps of `step` (default 1), so reassigned keys stay unique and ordered.
+pub fn sequence(ctx: &mut MutationContext) -> Result<String> {
+    let start = ctx.kwargs.get("start").and_then(|v| v.as_i64()).unwrap_or(1);
+    let step = ctx.kwargs.get("step").and_then(|v| v.as_i64()).unwrap_or(1);
+    let value = ctx.sequence.unwrap_or(start);
+    let next = value.checked_add(step).ok_or_else(|| {
+        PgStageError::InvalidParameter(format!("sequence: overflow after {}", value))
+    })?;
+    *ctx.sequence = Some(next);
+    Ok(value.to_string())
+}
+
 pub fn decimal(ctx: &mut MutationContext) -> Result<String> {
-    let start = ctx
-        .kwargs
-        .get("start")
-        .and_then(|v| v.as_f64())
-        .unwrap_or(-999999.0);
-    let end = ctx
-        .kwargs
-        .get("end")
-        .and_then(|v| v.as_f64())
-        .unwrap_or(999999.0);
+    let (start, end) = get_range_f64(ctx, "numeric_decimal", 999999.0)?;
     let precision = ctx
         .kwargs
         .get("precision")
@@ -70,7 +143,7 @@ pub fn decimal(ctx: &mut MutationContext) -> Result<String> {
         .unwrap_or(2) as usize;
     let unique = ctx.get_bool_kwarg("unique");
     let mut gen = || {
-        let val: f64 = ctx.rng.gen_range(start..end);
+        let val = sample_f64(&mut *ctx.rng, start, end);
         format!("{:.prec$}", val, prec = precision)
     };
     if unique {
@@ -81,19 +154,10 @@ pub fn decimal(ctx: &mut MutationContext) -> Result<String> {
 }
 
 pub fn real(ctx: &mut MutationContext) -> Result<String> {
-    let start = ctx
-        .kwargs
-        .get("start")
-        .and_then(|v| v.as_f64())
-        .unwrap_or(-999999.0);
-    let end = ctx
-        .kwargs
-        .get("end")
-        .and_then(|v| v.as_f64())
-        .unwrap_or(999999.0);
+    let (start, end) = get_range_f64(ctx, "numeric_real", 999999.0)?;
     let unique = ctx.get_bool_kwarg("unique");
     let mut gen = || {
-        let val: f64 = ctx.rng.gen_range(start..end);
+        let val = sample_f64(&mut *ctx.rng, start, end);
         format!("{:.6}", val)
     };
     if unique {
@@ -104,19 +168,10 @@ pub fn real(ctx: &mut MutationContext) -> Result<String> {
 }
 
 pub fn double_precision(ctx: &mut MutationContext) -> Result<String> {
-    let start = ctx
-        .kwargs
-        .get("start")
-        .and_then(|v| v.as_f64())
-        .unwrap_or(-999999999.0);
-    let end = ctx
-        .kwargs
-        .get("end")
-        .and_then(|v| v.as_f64())
-        .unwrap_or(999999999.0);
+    let (start, end) = get_range_f64(ctx, "numeric_double_precision", 999999999.0)?;
     let unique = ctx.get_bool_kwarg("unique");
     let mut gen = || {
-        let val: f64 = ctx.rng.gen_range(start..end);
+        let val = sample_f64(&mut *ctx.rng, start, end);
         format!("{:.15}", val)
     };
     if unique {
@@ -125,3 +180,38 @@ pub fn double_precision(ctx: &mut MutationContext) -> Result<String> {
         Ok(gen())
     }
 }
+
+/// Perturb the current number by up to ±`percent` of its value (default 10),
+/// or by up to ±`absolute` when given. Integers stay integers; decimals keep
+/// the source's number of decimal places. Non-numeric values (and NULL) are
+/// returned unchanged.
+pub fn jitter(ctx: &mut MutationContext) -> Result<String> {
+    let source = ctx.current_value.trim();
+    let absolute = ctx.kwargs.get("absolute").and_then(|v| v.as_f64());
+    let percent = ctx.kwargs.get("percent").and_then(|v| v.as_f64()).unwrap_or(10.0);
+    if absolute.is_some_and(|a| a.is_nan() || a < 0.0) || percent.is_nan() || percent < 0.0 {
+        return Err(PgStageError::InvalidParameter(
+            "numeric_jitter: percent/absolute must be non-negative".to_string(),
+        ));
+    }
+
+    if let Ok(value) = source.parse::<i64>() {
+        let window = absolute.unwrap_or(value.unsigned_abs() as f64 * percent / 100.0);
+        let window = window.floor().min(i64::MAX as f64) as i64;
+        let delta = ctx.rng.gen_range(-window..=window);
+        return Ok(value.saturating_add(delta).to_string());
+    }
+    let Ok(value) = source.parse::<f64>() else {
+        return Ok(ctx.current_value.to_string());
+    };
+    if !value.is_finite() {
+        return Ok(ctx.current_value.to_string());
+    }
+    let decimals = source
+        .split_once('.')
+        .map(|(_, frac)| frac.bytes().take_while(u8::is_ascii_digit).count())
+        .unwrap_or(0);
+    let window = absolute.unwrap_or(value.abs() * percent / 100.0);
+    let jittered = value + sample_f64(&mut *ctx.rng, -window, window);
+    Ok(format!("{:.prec$}", jittered, prec = decimals))
+}
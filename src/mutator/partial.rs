@@ -0,0 +1,36 @@
+use rand::Rng;
+
+use crate::error::{PgStageError, Result};
+use crate::mutator::MutationContext;
+
+/// Applies a nested mutation to a random fraction of rows and keeps the
+/// original value for the rest. `mutation_kwargs`:
+/// `{"probability": 0.3, "mutation": {"mutation_name": "...", "mutation_kwargs": {...}}}`.
+///
+/// Unlike conditions, the gate is random per row, not driven by row data.
+pub fn partial(ctx: &mut MutationContext) -> Result<String> {
+    let probability = ctx
+        .kwargs
+        .get("probability")
+        .and_then(|v| v.as_f64())
+        .ok_or_else(|| {
+            PgStageError::MissingParameter("probability".to_string(), "partial".to_string())
+        })?;
+    if !(0.0..=1.0).contains(&probability) {
+        return Err(PgStageError::InvalidParameter(format!(
+            "partial: probability must be within 0..=1, got {}",
+            probability
+        )));
+    }
+    if !ctx.kwargs.contains_key("mutation") {
+        return Err(PgStageError::MissingParameter("mutation".to_string(), "partial".to_string()));
+    }
+    let spec = ctx.nested_mutation("mutation", "partial")?;
+
+    if !ctx.rng.gen_bool(probability) {
+        return Ok(ctx.current_value.to_string());
+    }
+
+    let current_value = ctx.current_value;
+    spec.call(&mut ctx.nested(spec, current_value))
+}
@@ -1,10 +1,11 @@
+use rand::distributions::{Distribution, WeightedIndex};
 use rand::Rng;
 
 use crate::error::{PgStageError, Result};
 use crate::mutator::MutationContext;
 
-pub fn null(_ctx: &mut MutationContext) -> Result<String> {
-    Ok("\\N".to_string())
+pub fn null(ctx: &mut MutationContext) -> Result<String> {
+    Ok(ctx.null_marker.to_string())
 }
 
 pub fn empty_string(_ctx: &mut MutationContext) -> Result<String> {
@@ -17,12 +18,69 @@ pub fn fixed_value(ctx: &mut MutationContext) -> Result<String> {
     })?;
     match value {
         serde_json::Value::String(s) => Ok(s.clone()),
-        serde_json::Value::Null => Ok("\\N".to_string()),
+        serde_json::Value::Null => Ok(ctx.null_marker.to_string()),
         other => Ok(other.to_string()),
     }
 }
 
+/// Cut the current value to `length` characters (not bytes), or pad it on
+/// the right with the `pad` character up to `length` when it is shorter and
+/// `pad` is given. NULL is left as is.
+pub fn truncate(ctx: &mut MutationContext) -> Result<String> {
+    let length = match ctx.kwargs.get("length") {
+        None => {
+            return Err(PgStageError::MissingParameter(
+                "length".to_string(),
+                "truncate".to_string(),
+            ))
+        }
+        Some(v) => v.as_u64().ok_or_else(|| {
+            PgStageError::InvalidParameter(format!(
+                "truncate: length must be a non-negative integer, got {}",
+                v
+            ))
+        })? as usize,
+    };
+    let pad = match ctx.get_str_kwarg("pad") {
+        None => None,
+        Some(p) => {
+            let mut chars = p.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Some(c),
+                _ => {
+                    return Err(PgStageError::InvalidParameter(format!(
+                        "truncate: pad must be a single character, got '{}'",
+                        p
+                    )))
+                }
+            }
+        }
+    };
+
+    let value = ctx.current_value;
+    if value == ctx.null_marker {
+        return Ok(value.to_string());
+    }
+    let mut result: String = value.chars().take(length).collect();
+    if let Some(pad) = pad {
+        let missing = length - result.chars().count();
+        result.extend(std::iter::repeat_n(pad, missing));
+    }
+    Ok(result)
+}
+
+/// Random pick from the `choices` array, or from the non-empty lines of
+/// `choices_file` (read once, then cached on the processor).
 pub fn random_choice(ctx: &mut MutationContext) -> Result<String> {
+    if let Some(path) = ctx.get_str_kwarg("choices_file") {
+        if !ctx.choice_files.contains_key(path) {
+            let lines = load_choices(path)?;
+            ctx.choice_files.insert(path.to_string(), lines);
+        }
+        let lines = &ctx.choice_files[path];
+        return Ok(lines[ctx.rng.gen_range(0..lines.len())].clone());
+    }
+
     let choices = ctx
         .kwargs
         .get("choices")
@@ -41,7 +99,203 @@ pub fn random_choice(ctx: &mut MutationContext) -> Result<String> {
     let idx = ctx.rng.gen_range(0..choices.len());
     match &choices[idx] {
         serde_json::Value::String(s) => Ok(s.clone()),
-        serde_json::Value::Null => Ok("\\N".to_string()),
+        serde_json::Value::Null => Ok(ctx.null_marker.to_string()),
+        other => Ok(other.to_string()),
+    }
+}
+
+/// Random label of the column's enum type: from `values`, or else from the
+/// `CREATE TYPE ... AS ENUM` seen earlier in the dump for the column's type.
+pub fn enum_value(ctx: &mut MutationContext) -> Result<String> {
+    let explicit: Option<Vec<String>> = ctx
+        .kwargs
+        .get("values")
+        .and_then(|v| v.as_array())
+        .map(|a| {
+            a.iter()
+                .map(|v| v.as_str().map_or_else(|| v.to_string(), str::to_string))
+                .collect()
+        });
+    let labels = match &explicit {
+        Some(labels) => labels.as_slice(),
+        None => ctx.enum_labels.ok_or_else(|| {
+            PgStageError::MissingParameter("values".to_string(), "enum_value".to_string())
+        })?,
+    };
+    if labels.is_empty() {
+        return Err(PgStageError::InvalidParameter(
+            "enum_value: no enum labels".to_string(),
+        ));
+    }
+    Ok(labels[ctx.rng.gen_range(0..labels.len())].clone())
+}
+
+fn load_choices(path: &str) -> Result<Vec<String>> {
+    let content = std::fs::read_to_string(path).map_err(|e| {
+        PgStageError::InvalidParameter(format!(
+            "random_choice: cannot read choices_file {}: {}",
+            path, e
+        ))
+    })?;
+    let lines: Vec<String> = content
+        .lines()
+        .map(|l| l.trim_end_matches('\r'))
+        .filter(|l| !l.is_empty())
+        .map(str::to_string)
+        .collect();
+    if lines.is_empty() {
+        return Err(PgStageError::InvalidParameter(format!(
+            "random_choice: choices_file {} is empty",
+            path
+        )));
+    }
+    Ok(lines)
+}
+
+/// PostgreSQL boolean literal: `t`/`f` (`style: "short"`, the default) or
+/// `true`/`false` (`style: "long"`), true with `true_probability`
+/// (default 0.5).
+pub fn boolean(ctx: &mut MutationContext) -> Result<String> {
+    let probability = ctx
+        .kwargs
+        .get("true_probability")
+        .and_then(|v| v.as_f64())
+        .unwrap_or(0.5);
+    if !(0.0..=1.0).contains(&probability) {
+        return Err(PgStageError::InvalidParameter(format!(
+            "boolean: true_probability must be within 0..=1, got {}",
+            probability
+        )));
+    }
+    let (t, f) = match ctx.get_str_kwarg("style").unwrap_or("short") {
+        "short" => ("t", "f"),
+        "long" => ("true", "false"),
+        other => {
+            return Err(PgStageError::InvalidParameter(format!(
+                "boolean: unknown style '{}', expected short|long",
+                other
+            )))
+        }
+    };
+    Ok(if ctx.rng.gen_bool(probability) { t } else { f }.to_string())
+}
+
+/// Random bytes as a PostgreSQL hex-format `bytea` literal (`\x` followed
+/// by two hex digits per byte), `length` bytes long (default 16).
+pub fn bytea(ctx: &mut MutationContext) -> Result<String> {
+    let length = match ctx.kwargs.get("length") {
+        None => 16,
+        Some(v) => v.as_u64().ok_or_else(|| {
+            PgStageError::InvalidParameter(format!(
+                "bytea: length must be a non-negative integer, got {}",
+                v
+            ))
+        })? as usize,
+    };
+    let mut out = String::with_capacity(2 + 2 * length);
+    out.push_str("\\x");
+    for _ in 0..length {
+        let byte: u8 = ctx.rng.gen();
+        out.push_str(&format!("{:02x}", byte));
+    }
+    Ok(out)
+}
+
+const BASE64_STANDARD: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const BASE64_URLSAFE: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// Random token of `bytes` bytes (default 16), base64-encoded: the standard
+/// alphabet with `=` padding, or with `urlsafe` the `-`/`_` alphabet
+/// without padding, as used in URLs and cookies.
+pub fn base64(ctx: &mut MutationContext) -> Result<String> {
+    let bytes = match ctx.kwargs.get("bytes") {
+        None => 16,
+        Some(v) => v.as_u64().ok_or_else(|| {
+            PgStageError::InvalidParameter(format!(
+                "base64: bytes must be a non-negative integer, got {}",
+                v
+            ))
+        })? as usize,
+    };
+    let urlsafe = ctx.get_bool_kwarg("urlsafe");
+    let alphabet = if urlsafe { BASE64_URLSAFE } else { BASE64_STANDARD };
+
+    let mut raw = vec![0u8; bytes];
+    ctx.rng.fill_bytes(&mut raw);
+    let mut out = String::with_capacity(bytes.div_ceil(3) * 4);
+    for chunk in raw.chunks(3) {
+        let n = chunk.iter().fold(0u32, |n, &b| (n << 8) | b as u32) << (8 * (3 - chunk.len()));
+        for i in 0..=chunk.len() {
+            out.push(alphabet[((n >> (18 - 6 * i)) & 0x3f) as usize] as char);
+        }
+        if !urlsafe {
+            for _ in chunk.len()..3 {
+                out.push('=');
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Terminal states kept by `status` when `preserve_terminal` is set and no
+/// `terminal_states` list is supplied.
+const DEFAULT_TERMINAL_STATES: &[&str] = &["cancelled", "completed"];
+
+/// Workflow status: a (optionally weighted) pick from `choices`. With
+/// `preserve_terminal`, values listed in `terminal_states` are left as-is so
+/// finished workflows stay finished.
+pub fn status(ctx: &mut MutationContext) -> Result<String> {
+    if ctx.get_bool_kwarg("preserve_terminal") {
+        let current = ctx.current_value;
+        let is_terminal = match ctx.kwargs.get("terminal_states").and_then(|v| v.as_array()) {
+            Some(list) => list.iter().any(|v| v.as_str() == Some(current)),
+            None => DEFAULT_TERMINAL_STATES.contains(&current),
+        };
+        if is_terminal {
+            return Ok(current.to_string());
+        }
+    }
+
+    let choices = ctx
+        .kwargs
+        .get("choices")
+        .and_then(|v| v.as_array())
+        .ok_or_else(|| PgStageError::MissingParameter("choices".to_string(), "status".to_string()))?;
+    if choices.is_empty() {
+        return Err(PgStageError::InvalidParameter(
+            "choices list is empty".to_string(),
+        ));
+    }
+
+    let idx = match ctx.kwargs.get("weights").and_then(|v| v.as_array()) {
+        Some(weights) => {
+            if weights.len() != choices.len() {
+                return Err(PgStageError::InvalidParameter(format!(
+                    "status: {} weights given for {} choices",
+                    weights.len(),
+                    choices.len()
+                )));
+            }
+            let weights: Vec<f64> = weights
+                .iter()
+                .map(|w| {
+                    w.as_f64().ok_or_else(|| {
+                        PgStageError::InvalidParameter(format!("status: weight {} is not a number", w))
+                    })
+                })
+                .collect::<Result<_>>()?;
+            let dist = WeightedIndex::new(&weights)
+                .map_err(|e| PgStageError::InvalidParameter(format!("status: invalid weights: {}", e)))?;
+            dist.sample(&mut *ctx.rng)
+        }
+        None => ctx.rng.gen_range(0..choices.len()),
+    };
+
+    match &choices[idx] {
+        serde_json::Value::String(s) => Ok(s.clone()),
+        serde_json::Value::Null => Ok(ctx.null_marker.to_string()),
         other => Ok(other.to_string()),
     }
 }
@@ -0,0 +1,103 @@
+use rand::Rng;
+
+use crate::error::{PgStageError, Result};
+use crate::mutator::locale::en;
+use crate::mutator::{truncate_to_max_length, MutationContext};
+
+/// URL slug of `words` random lowercase words (default 3) joined by `-`,
+/// an adjective then nouns (`bright-fox-river`). `max_length` drops words
+/// from the end to fit, cutting the last one only when a single word is
+/// still too long.
+pub fn slug(ctx: &mut MutationContext) -> Result<String> {
+    let words = match ctx.kwargs.get("words") {
+        None => 3,
+        Some(v) => match v.as_u64() {
+            Some(n) if n > 0 => n as usize,
+            _ => {
+                return Err(PgStageError::InvalidParameter(format!(
+                    "slug: words must be a positive integer, got {}",
+                    v
+                )))
+            }
+        },
+    };
+    let max_length = ctx.max_length_kwarg("slug")?;
+    let unique = ctx.get_bool_kwarg("unique");
+
+    let mut gen = || {
+        let mut slug = String::new();
+        for i in 0..words {
+            let list = if i == 0 { en::DOMAIN_ADJECTIVES } else { en::DOMAIN_NOUNS };
+            let word = list[ctx.rng.gen_range(0..list.len())];
+            if let Some(max) = max_length {
+                if i > 0 && slug.len() + 1 + word.len() > max {
+                    break;
+                }
+            }
+            if i > 0 {
+                slug.push('-');
+            }
+            slug.push_str(word);
+        }
+        if let Some(max) = max_length {
+            slug.truncate(max);
+        }
+        slug
+    };
+    if unique {
+        ctx.unique_tracker.generate_unique(gen)
+    } else {
+        Ok(gen())
+    }
+}
+
+/// Fill a `pattern` such as `"{first_name} {last_name} <{email}>"`: each
+/// `{name}` is replaced by the mutation of that name (a registered custom
+/// mutator or a built-in), run with its default kwargs, and the text around
+/// placeholders is kept. `{{` and `}}` stand for literal braces.
+/// `max_length` cuts the filled-in result.
+pub fn template(ctx: &mut MutationContext) -> Result<String> {
+    let pattern = ctx
+        .get_str_kwarg("pattern")
+        .ok_or_else(|| PgStageError::MissingParameter("pattern".to_string(), "template".to_string()))?;
+    let max_length = ctx.max_length_kwarg("template")?;
+    let out = fill_template(pattern, |name| {
+        let spec = ctx.nested_mutation(name, "template")?;
+        let current_value = ctx.current_value;
+        spec.call(&mut ctx.nested(spec, current_value))
+    })?;
+    Ok(truncate_to_max_length(out, max_length))
+}
+
+/// Copy `pattern` with each `{name}` placeholder replaced by `fill(name)`
+/// and `{{` / `}}` unescaped.
+pub(crate) fn fill_template(
+    pattern: &str,
+    mut fill: impl FnMut(&str) -> Result<String>,
+) -> Result<String> {
+    let mut out = String::with_capacity(pattern.len());
+    let mut rest = pattern;
+    while let Some(i) = rest.find(['{', '}']) {
+        out.push_str(&rest[..i]);
+        let brace = rest.as_bytes()[i];
+        rest = &rest[i + 1..];
+        if rest.as_bytes().first() == Some(&brace) {
+            out.push(brace as char);
+            rest = &rest[1..];
+            continue;
+        }
+        let end = match (brace, rest.find('}')) {
+            (b'{', Some(end)) => end,
+            _ => {
+                return Err(PgStageError::InvalidParameter(format!(
+                    "template: unbalanced brace in pattern '{}'",
+                    pattern
+                )))
+            }
+        };
+        out.push_str(&fill(rest[..end].trim())?);
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
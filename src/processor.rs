@@ -1,29 +1,50 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
 use std::sync::Arc;
 
-use rand::rngs::ThreadRng;
-use rand::thread_rng;
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
 use regex::Regex;
 
 use crate::conditions::{check_conditions, RowRead};
 use crate::error::{PgStageError, Result};
-use crate::mutator::{MutationContext, ObfuscatedLookup};
+use crate::format::ident::{self, QUALIFIED_NAME};
+use crate::format::copy_csv::CsvQuoting;
+use crate::format::{copy_text, insert};
+use crate::mutator::{
+    suggest_mutation, ChoiceFiles, CustomMutators, MutationContext, Mutator, NameGroups,
+    ObfuscatedLookup, Regexes,
+};
 use crate::relations::RelationTracker;
 use crate::types::{
-    ColumnPatternRule, CompiledMutationSpec, Locale, MutationMap, MutationSpec, RulesFile,
+    ColumnPatternRule, CompiledMutationSpec, CompiledRelation, Locale, MutationMap, MutationSpec, RulesFile,
     TableMutationMap, TableMutationSpec, TablePatternRule,
 };
 use crate::unique::UniqueTracker;
-use crate::FastMap;
+use crate::{FastMap, FastSet};
+
+/// Callback run after each mutated row with the table name, its columns, the
+/// row's original and final values (decoded, one per column), and the marker
+/// that stands for SQL NULL among those values.
+pub type RowHook = Arc<dyn Fn(&str, &[Arc<str>], &[&str], &[Cow<str>], &str) + Send + Sync>;
 
 /// Compiled mutation registry, filled during parse-time.
-/// Separated from per-row runtime state so it can, in the future, be shared
-/// read-only between worker threads.
-#[derive(Default)]
+/// Separated from per-row runtime state so it can be copied to worker
+/// threads (see `DataProcessor::fork`).
+#[derive(Clone, Default)]
 pub struct MutationRegistry {
     pub mutation_map: MutationMap,
     pub table_mutations: TableMutationMap,
     pub table_pattern_rules: Vec<(Regex, TableMutationSpec)>,
-    pub column_pattern_rules: Vec<(Regex, Regex, Vec<CompiledMutationSpec>)>,
+    pub column_pattern_rules: Vec<(Regex, Regex, Arc<[CompiledMutationSpec]>)>,
+    pub custom_mutators: CustomMutators,
+    /// Column order from `CREATE TABLE`, used for `COPY table FROM stdin;`
+    /// statements that carry no column list.
+    pub table_columns: FastMap<Arc<str>, Vec<Arc<str>>>,
+    /// Declared column types from `CREATE TABLE`, by table then column.
+    pub column_types: FastMap<Arc<str>, FastMap<Arc<str>, Arc<str>>>,
+    /// Labels of `CREATE TYPE ... AS ENUM` types, for `enum_value`.
+    pub enum_types: FastMap<Arc<str>, Arc<[String]>>,
 }
 
 impl MutationRegistry {
@@ -49,102 +70,528 @@ impl MutationRegistry {
         }
         false
     }
+
+    /// The mutation a `foreign_key` spec borrows on a relation miss: the
+    /// first non-`foreign_key` spec of the related column, with its table.
+    fn primary_key_spec(
+        &self,
+        default_schema: &str,
+        rel: &CompiledRelation,
+    ) -> Option<(Arc<str>, &CompiledMutationSpec)> {
+        let table = qualify_in(default_schema, ident::split_qualified(&rel.table_name));
+        let (table, columns) = self.mutation_map.get_key_value(table.as_str())?;
+        let spec = columns
+            .get(&rel.column_name)?
+            .iter()
+            .find(|s| &*s.mutation_name != "foreign_key")?;
+        Some((Arc::clone(table), spec))
+    }
+
+    /// Whether any column mutation carries `relations`. Relation values are
+    /// shared across tables, so such dumps must be processed serially.
+    pub fn has_relations(&self) -> bool {
+        self.mutation_map
+            .values()
+            .flat_map(|cols| cols.values())
+            .chain(self.column_pattern_rules.iter().map(|(_, _, specs)| specs))
+            .flat_map(|specs| specs.iter())
+            .any(|spec| !spec.relations.is_empty())
+    }
 }
 
 pub struct DataProcessor {
     pub registry: MutationRegistry,
     pub locale: Locale,
-    pub delimiter: u8,
+    /// COPY field separator; one byte or a whole UTF-8 sequence.
+    pub delimiter: Box<[u8]>,
+    /// COPY NULL marker. A COPY statement's own `NULL '...'` option
+    /// overrides it for that table.
+    pub null_marker: Box<str>,
     pub delete_patterns: Vec<Regex>,
+    include_tables: Vec<Regex>,
+    exclude_tables: Vec<Regex>,
 
     strict: bool,
-    verbose: bool,
+    /// Append the specs of a repeated `COMMENT ON COLUMN` instead of
+    /// replacing the earlier ones.
+    merge_comments: bool,
+    /// Log a warning when a mutated value is longer than its spec's
+    /// `max_length` kwarg.
+    length_warnings: bool,
+    /// Columns of the current table already warned about by
+    /// `length_warnings`.
+    length_warned: FastSet<usize>,
 
     pub rows_processed: u64,
     pub mutations_applied: u64,
 
     current_table: Arc<str>,
+    /// Delimiter, NULL marker and format of the current table's rows, from
+    /// the settings or the COPY statement's options.
+    current_delimiter: Box<[u8]>,
+    current_null: Box<str>,
+    /// Quoting of the current table's CSV rows; `None` for text rows.
+    current_csv: Option<CsvQuoting>,
     current_columns: Vec<Arc<str>>,
     column_indices: FastMap<Arc<str>, usize>,
-    current_mutations: FastMap<Arc<str>, Vec<CompiledMutationSpec>>,
+    current_mutations: FastMap<Arc<str>, Arc<[CompiledMutationSpec]>>,
+    /// Enum labels of each current column whose type is a known enum.
+    current_enum_labels: Vec<Option<Arc<[String]>>>,
+    /// Next `sequence` value of each current column, once one was drawn.
+    current_sequences: Vec<Option<i64>>,
+    /// `current_sequences` of tables configured before, by table and column.
+    sequences: FastMap<(Arc<str>, Arc<str>), i64>,
     sorted_col_indices: Vec<usize>,
     is_delete_table: bool,
 
     // Per-row scratch — cleared, not reallocated, each row.
     scratch_spans: Vec<(u32, u32)>,
-    scratch_replacements: Vec<Option<Box<str>>>,
+    /// Logical value of each cell that has COPY escapes or CSV quotes.
+    scratch_decoded: Vec<Option<Cow<'static, str>>>,
+    /// New value of each mutated cell, moved in from the mutation's result.
+    scratch_replacements: Vec<Option<String>>,
+    scratch_name_groups: RefCell<NameGroups>,
     scratch_output: Vec<u8>,
 
-    rng: ThreadRng,
+    rng: Box<dyn RngCore + Send>,
+    seed: Option<u64>,
+    deterministic: bool,
     unique_tracker: UniqueTracker,
     relation_tracker: RelationTracker,
     secrets: FastMap<String, String>,
+    choice_files: ChoiceFiles,
+    regexes: Regexes,
+    row_hook: Option<RowHook>,
 
     comment_column_re: Regex,
     comment_table_re: Regex,
     copy_re: Regex,
+    copy_all_re: Regex,
+    create_table_re: Regex,
+    create_enum_re: Regex,
+    column_type_re: Regex,
+    insert_re: Regex,
+    search_path_re: Regex,
+    /// Schema that unqualified table names resolve to: the first schema of
+    /// the active `search_path`, `public` by default.
+    default_schema: String,
 
     json_errors: u64,
     unknown_mutation_errors: u64,
+    /// First invalid anon comment or mutation compile error, returned by
+    /// `check_rules` in strict mode.
+    rule_error: Option<PgStageError>,
+    /// Rows whose field count did not match the COPY column list.
+    column_mismatches: u64,
+    /// 1-based row number within the current table, for diagnostics.
+    table_row: u64,
+    /// Rows were mutated by forked workers (`merge_counters`), whose
+    /// unique/relation state is not carried back.
+    merged_workers: bool,
 }
 
-impl DataProcessor {
-    pub fn new(locale: Locale, delimiter: u8, delete_patterns: Vec<Regex>) -> Self {
-        let mut secrets = FastMap::new();
-        if let Ok(v) = std::env::var("SECRET_KEY") {
-            secrets.insert("SECRET_KEY".to_string(), v);
+/// Processor-wide switches set through `DataProcessorBuilder::config`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProcessorConfig {
+    pub strict: bool,
+    pub deterministic: bool,
+    pub merge_comments: bool,
+    pub length_warnings: bool,
+}
+
+/// Builder for `DataProcessor`. Unlike `DataProcessor::new`, secrets are not
+/// read from the environment unless `secrets_from_env` is called.
+#[derive(Default)]
+pub struct DataProcessorBuilder {
+    locale: Locale,
+    delimiter: Option<Box<[u8]>>,
+    null_marker: Option<Box<str>>,
+    delete_patterns: Vec<Regex>,
+    include_tables: Vec<Regex>,
+    exclude_tables: Vec<Regex>,
+    secrets: FastMap<String, String>,
+    seed: Option<u64>,
+    rng: Option<Box<dyn RngCore + Send>>,
+    config: ProcessorConfig,
+}
+
+impl DataProcessorBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn locale(mut self, locale: Locale) -> Self {
+        self.locale = locale;
+        self
+    }
+
+    pub fn delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = Some(Box::new([delimiter]));
+        self
+    }
+
+    /// Multi-character (or non-ASCII) delimiter such as `||` or `¦`. Cells
+    /// are split on the whole sequence. An empty string keeps the default.
+    pub fn delimiter_str(mut self, delimiter: &str) -> Self {
+        if !delimiter.is_empty() {
+            self.delimiter = Some(delimiter.as_bytes().into());
         }
-        if let Ok(v) = std::env::var("SECRET_KEY_NONCE") {
-            secrets.insert("SECRET_KEY_NONCE".to_string(), v);
+        self
+    }
+
+    /// NULL marker of COPY rows, for dumps written with `COPY ... WITH
+    /// (NULL '...')`. Defaults to `\N`.
+    pub fn null_marker(mut self, marker: &str) -> Self {
+        self.null_marker = Some(marker.into());
+        self
+    }
+
+    pub fn delete_patterns(mut self, patterns: Vec<Regex>) -> Self {
+        self.delete_patterns = patterns;
+        self
+    }
+
+    /// Only process tables whose `schema.table` matches one of `patterns`
+    /// (all tables when empty). Other tables pass through untouched.
+    pub fn include_tables(mut self, patterns: Vec<Regex>) -> Self {
+        self.include_tables = patterns;
+        self
+    }
+
+    /// Pass tables whose `schema.table` matches one of `patterns` through
+    /// untouched: no mutations and no deletion.
+    pub fn exclude_tables(mut self, patterns: Vec<Regex>) -> Self {
+        self.exclude_tables = patterns;
+        self
+    }
+
+    /// Set a secret such as `SECRET_KEY` or `SECRET_KEY_NONCE`.
+    pub fn secret(mut self, name: &str, value: &str) -> Self {
+        self.secrets.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    /// Read `SECRET_KEY` and `SECRET_KEY_NONCE` from the environment.
+    pub fn secrets_from_env(mut self) -> Self {
+        for name in ["SECRET_KEY", "SECRET_KEY_NONCE"] {
+            if let Ok(v) = std::env::var(name) {
+                self.secrets.insert(name.to_string(), v);
+            }
         }
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Use `rng` instead of an entropy-seeded `StdRng`, e.g. a seeded one in
+    /// tests. A `seed` (or deterministic mode) replaces it.
+    pub fn rng(mut self, rng: impl RngCore + Send + 'static) -> Self {
+        self.rng = Some(Box::new(rng));
+        self
+    }
+
+    pub fn config(mut self, config: ProcessorConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn build(self) -> DataProcessor {
+        let mut processor = DataProcessor::from_parts(
+            self.locale,
+            self.delimiter.unwrap_or_else(|| Box::new([b'\t'])),
+            self.delete_patterns,
+            self.secrets,
+            self.rng.unwrap_or_else(|| Box::new(StdRng::from_entropy())),
+        );
+        if let Some(marker) = self.null_marker {
+            processor.null_marker = marker;
+        }
+        processor.include_tables = self.include_tables;
+        processor.exclude_tables = self.exclude_tables;
+        processor.set_strict(self.config.strict);
+        processor.set_merge_comments(self.config.merge_comments);
+        processor.set_length_warnings(self.config.length_warnings);
+        if let Some(seed) = self.seed {
+            processor.set_seed(seed);
+        }
+        processor.set_deterministic(self.config.deterministic);
+        processor
+    }
+}
+
+impl DataProcessor {
+    /// Shorthand for the builder with secrets read from the environment.
+    pub fn new(locale: Locale, delimiter: u8, delete_patterns: Vec<Regex>) -> Self {
+        DataProcessorBuilder::new()
+            .locale(locale)
+            .delimiter(delimiter)
+            .delete_patterns(delete_patterns)
+            .secrets_from_env()
+            .build()
+    }
+
+    /// Like `new`, but mutations draw from `rng`.
+    pub fn with_rng(
+        locale: Locale,
+        delimiter: u8,
+        delete_patterns: Vec<Regex>,
+        rng: impl RngCore + Send + 'static,
+    ) -> Self {
+        DataProcessorBuilder::new()
+            .locale(locale)
+            .delimiter(delimiter)
+            .delete_patterns(delete_patterns)
+            .secrets_from_env()
+            .rng(rng)
+            .build()
+    }
+
+    pub fn builder() -> DataProcessorBuilder {
+        DataProcessorBuilder::new()
+    }
+
+    fn from_parts(
+        locale: Locale,
+        delimiter: Box<[u8]>,
+        delete_patterns: Vec<Regex>,
+        secrets: FastMap<String, String>,
+        rng: Box<dyn RngCore + Send>,
+    ) -> Self {
         Self {
             registry: MutationRegistry::new(),
             locale,
             delimiter,
+            null_marker: copy_text::NULL_MARKER.into(),
             delete_patterns,
+            include_tables: Vec::new(),
+            exclude_tables: Vec::new(),
             strict: false,
-            verbose: false,
+            merge_comments: false,
+            length_warnings: false,
+            length_warned: FastSet::default(),
             rows_processed: 0,
             mutations_applied: 0,
             current_table: Arc::from(""),
+            current_delimiter: Box::new([b'\t']),
+            current_null: copy_text::NULL_MARKER.into(),
+            current_csv: None,
             current_columns: Vec::new(),
             column_indices: FastMap::new(),
             current_mutations: FastMap::new(),
+            current_enum_labels: Vec::new(),
+            current_sequences: Vec::new(),
+            sequences: FastMap::new(),
             sorted_col_indices: Vec::new(),
             is_delete_table: false,
             scratch_spans: Vec::new(),
+            scratch_decoded: Vec::new(),
             scratch_replacements: Vec::new(),
+            scratch_name_groups: RefCell::new(NameGroups::new()),
             scratch_output: Vec::new(),
-            rng: thread_rng(),
+            rng,
+            seed: None,
+            deterministic: false,
             unique_tracker: UniqueTracker::new(),
             relation_tracker: RelationTracker::new(),
             secrets,
-            comment_column_re: Regex::new(
-                r"COMMENT ON COLUMN ([\d\w_\.]+) IS 'anon: ([\s\S]*)';",
-            )
+            choice_files: ChoiceFiles::new(),
+            regexes: Regexes::new(),
+            row_hook: None,
+            comment_column_re: Regex::new(&format!(
+                r"COMMENT ON COLUMN ({}) IS 'anon: ((?:[^']|'')*)';",
+                QUALIFIED_NAME
+            ))
+            .unwrap(),
+            comment_table_re: Regex::new(&format!(
+                r"COMMENT ON TABLE ({}) IS 'anon: ((?:[^']|'')*)';",
+                QUALIFIED_NAME
+            ))
             .unwrap(),
-            comment_table_re: Regex::new(
-                r"COMMENT ON TABLE ([\d\w_\.]*) IS 'anon: ([\s\S]*)';",
+            copy_re: Regex::new(&format!(
+                r"COPY ({}) \(([#\w\W]+)\) FROM stdin\b(.*);",
+                QUALIFIED_NAME
+            ))
+            .unwrap(),
+            copy_all_re: Regex::new(&format!(r"^COPY ({}) FROM stdin\b(.*);", QUALIFIED_NAME))
+                .unwrap(),
+            create_table_re: Regex::new(&format!(
+                r"^CREATE (?:UNLOGGED )?TABLE ({}) \(",
+                QUALIFIED_NAME
+            ))
+            .unwrap(),
+            create_enum_re: Regex::new(&format!(r"^CREATE TYPE ({}) AS ENUM \(", QUALIFIED_NAME))
+                .unwrap(),
+            column_type_re: Regex::new(&format!(r"^({0})\s+({0})", QUALIFIED_NAME)).unwrap(),
+            insert_re: Regex::new(&format!(
+                r#"^INSERT INTO ({}) (?:\(((?:[^)"]|"(?:[^"]|"")*")*)\) )?VALUES\s*"#,
+                QUALIFIED_NAME
+            ))
+            .unwrap(),
+            search_path_re: Regex::new(
+                r"^(?:SET search_path (?:=|TO) (.*);|SELECT pg_catalog\.set_config\('search_path', '(.*)', (?:false|true)\);)",
             )
             .unwrap(),
-            copy_re: Regex::new(r"COPY ([\d\w_\.]+) \(([#\w\W]+)\) FROM stdin;").unwrap(),
+            default_schema: "public".to_string(),
             json_errors: 0,
             unknown_mutation_errors: 0,
+            rule_error: None,
+            column_mismatches: 0,
+            table_row: 0,
+            merged_workers: false,
+        }
+    }
+
+    /// An independent processor with the same rules and settings, for a
+    /// worker thread. Counters and unique/relation state start empty; with a
+    /// seed every table is still reseeded from its name, so a worker produces
+    /// the same rows as a serial run.
+    pub fn fork(&self) -> DataProcessor {
+        let mut forked = DataProcessor::from_parts(
+            self.locale,
+            self.delimiter.clone(),
+            self.delete_patterns.clone(),
+            self.secrets.clone(),
+            Box::new(StdRng::from_entropy()),
+        );
+        forked.registry = self.registry.clone();
+        forked.null_marker = self.null_marker.clone();
+        forked.include_tables = self.include_tables.clone();
+        forked.exclude_tables = self.exclude_tables.clone();
+        forked.default_schema = self.default_schema.clone();
+        forked.strict = self.strict;
+        forked.length_warnings = self.length_warnings;
+        if let Some(seed) = self.seed {
+            forked.set_seed(seed);
+        }
+        forked.deterministic = self.deterministic;
+        forked.choice_files = self.choice_files.clone();
+        forked.regexes = self.regexes.clone();
+        forked.row_hook = self.row_hook.clone();
+        forked
+    }
+
+    /// `fork` configured for the current table, to mutate a share of its
+    /// rows on another thread.
+    pub fn fork_table(&self) -> DataProcessor {
+        let mut forked = self.fork();
+        forked.configure_table(&self.current_table, self.current_columns.iter().map(|c| &**c));
+        forked.current_delimiter = self.current_delimiter.clone();
+        forked.current_null = self.current_null.clone();
+        forked.current_csv = self.current_csv;
+        forked
+    }
+
+    /// Whether the current table's rows can be mutated independently, in any
+    /// order: no `unique` values or `sequence` mutations (at any nesting
+    /// level) and no `relations`.
+    pub fn rows_independent(&self) -> bool {
+        !self.is_delete_table
+            && self.current_mutations.values().flat_map(|specs| specs.iter()).all(|spec| {
+                spec.relations.is_empty()
+                    && &*spec.mutation_name != "sequence"
+                    && !spec.mutation_kwargs.iter().any(depends_on_order)
+            })
+    }
+
+    /// Reseed for the `batch`-th share of the current table's rows, so split
+    /// tables stay reproducible under a seed. Unseeded runs are unaffected.
+    pub fn reseed_batch(&mut self, batch: u64) {
+        if let Some(seed) = self.seed {
+            let mixed = seed
+                ^ fnv1a(self.current_table.as_bytes())
+                ^ (batch + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+            self.rng = Box::new(StdRng::seed_from_u64(mixed));
         }
     }
 
+    /// Add a forked worker's row counters to this processor's, for the
+    /// end-of-run summary. Unique and relation state stay with the worker,
+    /// so the summary leaves their sizes out afterwards.
+    pub fn merge_counters(&mut self, worker: &DataProcessor) {
+        self.rows_processed += worker.rows_processed;
+        self.mutations_applied += worker.mutations_applied;
+        self.column_mismatches += worker.column_mismatches;
+        self.merged_workers = true;
+    }
+
     pub fn set_strict(&mut self, strict: bool) {
         self.strict = strict;
     }
 
-    pub fn set_verbose(&mut self, verbose: bool) {
-        self.verbose = verbose;
+    /// Diagnostics go through the `log` facade, so their verbosity is the
+    /// logger's level; this setting no longer has any effect.
+    #[deprecated(note = "set the `log` level instead")]
+    pub fn set_verbose(&mut self, _verbose: bool) {}
+
+    /// When a column gets a second anon comment (e.g. from two migration
+    /// files), append its specs after the earlier ones instead of replacing
+    /// them. The first spec whose conditions match a row still wins.
+    pub fn set_merge_comments(&mut self, merge: bool) {
+        self.merge_comments = merge;
+    }
+
+    /// Warn (through `log`) when a mutated value has more characters than
+    /// its spec's `max_length` kwarg, e.g. a `fixed_value` or
+    /// `random_choice` too long for a `varchar(n)` column. Values are left
+    /// as generated; each column is reported once per table.
+    pub fn set_length_warnings(&mut self, warn: bool) {
+        self.length_warnings = warn;
+    }
+
+    /// Seed the RNG. Each table is reseeded from this seed and its name, so
+    /// a table's output does not depend on which tables precede it.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = Some(seed);
+        self.rng = Box::new(StdRng::seed_from_u64(seed));
+    }
+
+    /// Deterministic test mode: seeds the RNG (with 0 unless `set_seed` was
+    /// called) and pins time-dependent mutations (`date` defaults, `uuid7`,
+    /// the date component of `uuid5_by_source_value`) to a fixed clock, so a
+    /// run is byte-reproducible.
+    pub fn set_deterministic(&mut self, deterministic: bool) {
+        self.deterministic = deterministic;
+        if deterministic && self.seed.is_none() {
+            self.set_seed(0);
+        }
+    }
+
+    /// Register a custom mutator under `name`. It shadows a built-in of the
+    /// same name and must be registered before the comments or rules that
+    /// use it are parsed.
+    pub fn register_mutator(&mut self, name: &str, mutator: impl Mutator + 'static) {
+        self.registry
+            .custom_mutators
+            .insert(name.to_string(), Arc::new(mutator));
+    }
+
+    /// Call `hook` after each row of a table with mutations, e.g. to audit
+    /// changes or build an original-to-fake mapping. Rows of other tables,
+    /// and rows left unmutated for a field-count mismatch, are not reported.
+    /// With `--threads`, workers call the hook concurrently.
+    ///
+    /// The last argument is the marker that stands for SQL NULL among the
+    /// values: the table's active NULL marker, or `\N` for CSV rows.
+    pub fn set_row_hook(
+        &mut self,
+        hook: impl Fn(&str, &[Arc<str>], &[&str], &[Cow<str>], &str) + Send + Sync + 'static,
+    ) {
+        self.row_hook = Some(Arc::new(hook));
     }
 
     pub fn load_rules(&mut self, text: &str) -> Result<()> {
         let file: RulesFile = serde_json::from_str(text)
             .map_err(|e| PgStageError::InvalidParameter(format!("invalid rules file: {}", e)))?;
         for TablePatternRule { table, mutation } in file.table_patterns {
+            // Like a COMMENT ON TABLE, a non-`delete` mutation is reported
+            // (fatal under `--strict`) and the rule ignored.
+            if let Err(e) = check_table_mutation(&mutation) {
+                self.report_rule_error(&table, e);
+                continue;
+            }
             let re = Regex::new(&table).map_err(|e| {
                 PgStageError::InvalidParameter(format!("invalid table pattern '{}': {}", table, e))
             })?;
@@ -163,10 +610,10 @@ impl DataProcessor {
                     column, e
                 ))
             })?;
-            let compiled: Vec<CompiledMutationSpec> = mutations
+            let compiled: Arc<[CompiledMutationSpec]> = mutations
                 .into_iter()
-                .map(CompiledMutationSpec::compile)
-                .collect::<Result<Vec<_>>>()?;
+                .map(|spec| CompiledMutationSpec::compile(spec, &self.registry.custom_mutators))
+                .collect::<Result<_>>()?;
             self.registry
                 .column_pattern_rules
                 .push((table_re, col_re, compiled));
@@ -178,88 +625,70 @@ impl DataProcessor {
         (self.json_errors, self.unknown_mutation_errors)
     }
 
+    /// Number of rows passed through unmutated because their field count did
+    /// not match the table's column list.
+    pub fn column_mismatches(&self) -> u64 {
+        self.column_mismatches
+    }
+
     /// Parse a COMMENT ON COLUMN / COMMENT ON TABLE line. Returns true if a
-    /// comment was recognized (even if its JSON failed to parse — that error
-    /// is reported via stderr based on `strict`/`verbose`).
+    /// comment was recognized, even if its JSON failed to parse: that error
+    /// is reported on stderr, counted in `parse_warnings`, and returned by
+    /// `check_rules` in strict mode.
     pub fn parse_comment(&mut self, line: &str) -> bool {
         if let Some(caps) = self.comment_column_re.captures(line) {
             let full_name = caps.get(1).unwrap().as_str();
-            let json_str = caps.get(2).unwrap().as_str();
+            let json_str = unquote_literal(caps.get(2).unwrap().as_str());
 
-            let parts: Vec<&str> = full_name.rsplitn(2, '.').collect();
+            let mut parts = ident::split_qualified(full_name);
             if parts.len() < 2 {
                 return false;
             }
-            let column_name: Arc<str> = Arc::from(parts[0]);
-            let table_name: Arc<str> = Arc::from(parts[1]);
+            let column_name: Arc<str> = Arc::from(parts.pop().unwrap());
+            let table_name: Arc<str> = Arc::from(self.qualify_parts(parts));
 
-            match serde_json::from_str::<Vec<MutationSpec>>(json_str) {
+            match serde_json::from_str::<Vec<MutationSpec>>(&json_str) {
                 Ok(specs) => {
                     let mut compiled = Vec::with_capacity(specs.len());
                     for spec in specs {
-                        match CompiledMutationSpec::compile(spec) {
+                        match CompiledMutationSpec::compile(spec, &self.registry.custom_mutators) {
                             Ok(c) => compiled.push(c),
-                            Err(e) => {
-                                self.unknown_mutation_errors += 1;
-                                if self.strict {
-                                    eprintln!(
-                                        "pg_stage_rs error: compile failed for {}: {}",
-                                        full_name, e
-                                    );
-                                } else if self.verbose {
-                                    eprintln!(
-                                        "pg_stage_rs warning: compile failed for {}: {}",
-                                        full_name, e
-                                    );
-                                }
-                            }
+                            Err(e) => self.report_rule_error(full_name, e),
                         }
                     }
-                    self.registry
-                        .mutation_map
-                        .entry(table_name)
-                        .or_default()
-                        .insert(column_name, compiled);
-                }
-                Err(e) => {
-                    self.json_errors += 1;
-                    if self.strict {
-                        eprintln!(
-                            "pg_stage_rs error: invalid JSON in COMMENT ON COLUMN {}: {}",
-                            full_name, e
-                        );
-                    } else if self.verbose {
-                        eprintln!(
-                            "pg_stage_rs warning: invalid JSON in COMMENT ON COLUMN {}: {}",
-                            full_name, e
-                        );
+                    let columns = self.registry.mutation_map.entry(table_name).or_default();
+                    match columns.get_mut(&column_name) {
+                        Some(existing) if self.merge_comments => {
+                            *existing = existing.iter().cloned().chain(compiled).collect();
+                        }
+                        Some(existing) => {
+                            log::warn!(
+                                "COMMENT ON COLUMN {} replaces an earlier anon comment (--merge-comments keeps both)",
+                                full_name
+                            );
+                            *existing = compiled.into();
+                        }
+                        None => {
+                            columns.insert(column_name, compiled.into());
+                        }
                     }
                 }
+                Err(e) => self.report_json_error(&format!("COMMENT ON COLUMN {}", full_name), e),
             }
             return true;
         }
 
         if let Some(caps) = self.comment_table_re.captures(line) {
-            let table_name: Arc<str> = Arc::from(caps.get(1).unwrap().as_str());
-            let json_str = caps.get(2).unwrap().as_str();
-            match serde_json::from_str::<TableMutationSpec>(json_str) {
-                Ok(spec) => {
-                    self.registry.table_mutations.insert(table_name, spec);
-                }
-                Err(e) => {
-                    self.json_errors += 1;
-                    if self.strict {
-                        eprintln!(
-                            "pg_stage_rs error: invalid JSON in COMMENT ON TABLE {}: {}",
-                            table_name, e
-                        );
-                    } else if self.verbose {
-                        eprintln!(
-                            "pg_stage_rs warning: invalid JSON in COMMENT ON TABLE {}: {}",
-                            table_name, e
-                        );
+            let table_name: Arc<str> = Arc::from(self.qualified_table(caps.get(1).unwrap().as_str()));
+            let json_str = unquote_literal(caps.get(2).unwrap().as_str());
+            match serde_json::from_str::<TableMutationSpec>(&json_str) {
+                Ok(spec) => match check_table_mutation(&spec) {
+                    Ok(()) => {
+                        self.registry.table_mutations.insert(table_name, spec);
                     }
-                }
+                    Err(e) => self.report_rule_error(&table_name, e),
+                },
+                Err(e) => self.report_json_error(&format!("COMMENT ON TABLE {}", table_name), e),
             }
             return true;
         }
@@ -267,18 +696,250 @@ impl DataProcessor {
         false
     }
 
+    /// Record an anon comment whose JSON does not parse. Like a compile
+    /// error it is always reported: a typo would otherwise drop the whole
+    /// comment and leave its columns unmasked.
+    fn report_json_error(&mut self, comment: &str, e: serde_json::Error) {
+        self.json_errors += 1;
+        let e = PgStageError::InvalidFormat(format!("invalid JSON in {}: {}", comment, e));
+        log::log!(self.rule_error_level(), "{}", e);
+        if self.rule_error.is_none() {
+            self.rule_error = Some(e);
+        }
+    }
+
+    /// Record a mutation that failed to compile. Unlike invalid JSON this is
+    /// always reported: a misspelled mutation name would otherwise leave the
+    /// column silently unmasked.
+    fn report_rule_error(&mut self, name: &str, e: PgStageError) {
+        self.unknown_mutation_errors += 1;
+        let hint = match &e {
+            PgStageError::UnknownMutation(m) => suggest_mutation(m)
+                .map(|s| format!(" (did you mean '{}'?)", s))
+                .unwrap_or_default(),
+            _ => String::new(),
+        };
+        log::log!(self.rule_error_level(), "compile failed for {}: {}{}", name, e, hint);
+        if self.rule_error.is_none() {
+            self.rule_error = Some(e);
+        }
+    }
+
+    /// Rule problems abort the run under `--strict` and are only warned
+    /// about otherwise.
+    fn rule_error_level(&self) -> log::Level {
+        if self.strict {
+            log::Level::Error
+        } else {
+            log::Level::Warn
+        }
+    }
+
+    /// In strict mode, fail with the first invalid anon comment or mutation
+    /// compile error seen so far.
+    /// Format handlers call this after reading the schema, before any data.
+    pub fn check_rules(&mut self) -> Result<()> {
+        match self.rule_error.take() {
+            Some(e) if self.strict => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Track `SET search_path` (and pg_dump's `set_config('search_path', ...)`)
+    /// so unqualified table names resolve to the right schema. Returns true if
+    /// the line set the search path.
+    pub fn parse_search_path(&mut self, line: &str) -> bool {
+        let Some(caps) = self.search_path_re.captures(line) else {
+            return false;
+        };
+        let list = caps.get(1).or_else(|| caps.get(2)).unwrap().as_str();
+        self.default_schema = ident::split_column_list(list)
+            .into_iter()
+            .map(|s| s.trim_matches('\'').to_string())
+            .find(|s| !s.is_empty() && s != "pg_catalog" && s != "$user")
+            .unwrap_or_else(|| "public".to_string());
+        true
+    }
+
+    /// Normalize a table name and qualify a bare `users` with the default
+    /// schema, so comments and COPY statements match either way.
+    fn qualified_table(&self, raw: &str) -> String {
+        self.qualify_parts(ident::split_qualified(raw))
+    }
+
+    fn qualify_parts(&self, parts: Vec<String>) -> String {
+        qualify_in(&self.default_schema, parts)
+    }
+
+    /// Record the column order of a `CREATE TABLE` statement (one column per
+    /// line, as pg_dump writes it). Stored generated columns are skipped since
+    /// COPY does not carry them. Returns true if the statement was recognized.
+    pub fn parse_create_table(&mut self, stmt: &str) -> bool {
+        let Some(caps) = self.create_table_re.captures(stmt) else {
+            return false;
+        };
+        let table_name: Arc<str> = Arc::from(self.qualified_table(caps.get(1).unwrap().as_str()));
+        let body = &stmt[caps.get(0).unwrap().end()..];
+        let mut columns = Vec::new();
+        let mut types = FastMap::new();
+        for line in body.lines() {
+            let line = line.trim();
+            if line.starts_with(')') {
+                break;
+            }
+            let first_word = line.split_whitespace().next().unwrap_or("");
+            if matches!(
+                first_word,
+                "" | "CONSTRAINT" | "CHECK" | "UNIQUE" | "PRIMARY" | "FOREIGN" | "EXCLUDE" | "LIKE"
+            ) || line.contains("GENERATED ALWAYS AS (")
+            {
+                continue;
+            }
+            if let Some(col) = ident::leading_identifier(line) {
+                let col: Arc<str> = Arc::from(col);
+                if let Some(caps) = self.column_type_re.captures(line) {
+                    let type_name = self.qualified_table(caps.get(2).unwrap().as_str());
+                    types.insert(Arc::clone(&col), Arc::from(type_name));
+                }
+                columns.push(col);
+            }
+        }
+        self.registry.column_types.insert(Arc::clone(&table_name), types);
+        self.registry.table_columns.insert(table_name, columns);
+        true
+    }
+
+    /// Record the labels of a `CREATE TYPE ... AS ENUM (...)` statement for
+    /// `enum_value`. Returns true if the statement was recognized.
+    pub fn parse_create_type(&mut self, stmt: &str) -> bool {
+        let Some(caps) = self.create_enum_re.captures(stmt) else {
+            return false;
+        };
+        let type_name: Arc<str> = Arc::from(self.qualified_table(caps.get(1).unwrap().as_str()));
+        let labels = string_literals(&stmt[caps.get(0).unwrap().end()..]);
+        self.registry.enum_types.insert(type_name, labels.into());
+        true
+    }
+
     pub fn setup_table(&mut self, line: &str) -> bool {
-        let Some(caps) = self.copy_re.captures(line) else {
+        if let Some(caps) = self.copy_re.captures(line) {
+            let table_name = self.qualified_table(caps.get(1).unwrap().as_str());
+            let columns = ident::split_column_list(caps.get(2).unwrap().as_str());
+            self.configure_table(&table_name, columns.iter().map(String::as_str));
+            self.set_copy_options(caps.get(3).unwrap().as_str());
+            self.log_table_setup();
+            return true;
+        }
+        let Some(caps) = self.copy_all_re.captures(line) else {
             return false;
         };
-        let table_name_str = caps.get(1).unwrap().as_str();
-        let columns_str = caps.get(2).unwrap().as_str();
+        // No column list: all columns, in CREATE TABLE order. Without a known
+        // definition the rows can only pass through unmutated.
+        let table_name = self.qualified_table(caps.get(1).unwrap().as_str());
+        let columns = self
+            .registry
+            .table_columns
+            .get(table_name.as_str())
+            .cloned()
+            .unwrap_or_default();
+        self.configure_table(&table_name, columns.iter().map(|c| c.as_ref()));
+        if columns.is_empty() && !self.is_delete_table && !self.current_mutations.is_empty() {
+            log::warn!(
+                "COPY {} has no column list and no CREATE TABLE was seen; rows pass through",
+                table_name
+            );
+        }
+        self.set_copy_options(caps.get(2).unwrap().as_str());
+        self.log_table_setup();
+        true
+    }
+
+    /// Debug-log what the rules do to the COPY just set up.
+    fn log_table_setup(&self) {
+        if self.is_delete_table {
+            log::debug!("COPY {}: table deleted", self.current_table);
+        } else if self.current_mutations.is_empty() {
+            log::debug!("COPY {}: no mutations", self.current_table);
+        } else {
+            let mut columns: Vec<&str> = self.current_mutations.keys().map(|c| c.as_ref()).collect();
+            columns.sort_unstable();
+            log::debug!("COPY {}: mutating {}", self.current_table, columns.join(", "));
+        }
+    }
+
+    /// Apply the options after `FROM stdin` to the current table: `FORMAT
+    /// csv`, `DELIMITER`, `NULL`, `QUOTE` and `ESCAPE`, in the `WITH (...)`
+    /// list form or the older `WITH DELIMITER AS ';' NULL AS '' CSV` one. CSV
+    /// defaults to a `,` delimiter and an empty NULL marker.
+    fn set_copy_options(&mut self, options: &str) {
+        let mut csv = false;
+        let (mut delimiter, mut null, mut quote, mut escape) = (None, None, None, None);
+        let mut tokens = copy_option_tokens(options).into_iter().peekable();
+        while let Some(token) = tokens.next() {
+            let CopyOptionToken::Word(word) = token else {
+                continue;
+            };
+            let target = match word.to_ascii_uppercase().as_str() {
+                "FORMAT" => {
+                    csv = tokens.peek().is_some_and(|f| f.text().eq_ignore_ascii_case("csv"));
+                    continue;
+                }
+                "CSV" => {
+                    csv = true;
+                    continue;
+                }
+                "DELIMITER" => &mut delimiter,
+                "NULL" => &mut null,
+                "QUOTE" => &mut quote,
+                "ESCAPE" => &mut escape,
+                _ => continue,
+            };
+            let is_as = |t: &CopyOptionToken| {
+                matches!(t, CopyOptionToken::Word(w) if w.eq_ignore_ascii_case("AS"))
+            };
+            if tokens.peek().is_some_and(is_as) {
+                tokens.next();
+            }
+            if let Some(CopyOptionToken::Literal(value)) = tokens.next() {
+                *target = Some(value);
+            }
+        }
 
+        let single_byte = |s: Option<String>| s.filter(|s| s.len() == 1).map(|s| s.as_bytes()[0]);
+        self.current_csv = csv.then(|| {
+            let quote = single_byte(quote).unwrap_or(b'"');
+            CsvQuoting {
+                quote,
+                escape: single_byte(escape).unwrap_or(quote),
+            }
+        });
+        match delimiter.filter(|d| !d.is_empty()) {
+            Some(d) => self.current_delimiter = d.into_bytes().into(),
+            None if csv => self.current_delimiter = Box::new([b',']),
+            None => {}
+        }
+        match null {
+            Some(n) => self.current_null = n.into(),
+            None if csv => self.current_null = "".into(),
+            None => {}
+        }
+    }
+
+    /// Whether `record` is an unfinished CSV row: a quoted field holds a line
+    /// break, so the row continues on the next line.
+    pub fn record_is_open(&self, record: &[u8]) -> bool {
+        self.current_csv.is_some_and(|csv| csv.is_open(record))
+    }
+
+    /// Load the mutations for `table_name` with the given column order.
+    /// Shared by COPY and INSERT statements.
+    fn configure_table<'c>(&mut self, table_name_str: &str, columns: impl Iterator<Item = &'c str>) {
+        self.save_sequences();
         self.current_columns.clear();
         self.column_indices.clear();
         self.current_mutations.clear();
 
-        for (i, raw) in columns_str.split(", ").enumerate() {
+        for (i, raw) in columns.enumerate() {
             let col: Arc<str> = Arc::from(raw.trim());
             self.column_indices.insert(Arc::clone(&col), i);
             self.current_columns.push(col);
@@ -286,17 +947,20 @@ impl DataProcessor {
 
         let table_name: Arc<str> = Arc::from(table_name_str);
         self.current_table = Arc::clone(&table_name);
+        self.current_delimiter = self.delimiter.clone();
+        self.current_null = self.null_marker.clone();
+        self.current_csv = None;
 
         self.is_delete_table = self
             .registry
             .table_delete(&table_name, &self.delete_patterns);
 
+        // The spec lists are shared with the registry, not cloned per COPY;
+        // only a column that both a COMMENT and a rules-file pattern cover
+        // gets a merged list of its own.
         if let Some(cols) = self.registry.mutation_map.get(&table_name) {
             for (col, specs) in cols.iter() {
-                self.current_mutations
-                    .entry(Arc::clone(col))
-                    .or_default()
-                    .extend(specs.iter().cloned());
+                self.current_mutations.insert(Arc::clone(col), Arc::clone(specs));
             }
         }
 
@@ -305,18 +969,189 @@ impl DataProcessor {
                 continue;
             }
             for col in self.current_columns.iter() {
-                if col_re.is_match(col) {
-                    self.current_mutations
-                        .entry(Arc::clone(col))
-                        .or_default()
-                        .extend(specs.iter().cloned());
+                if !col_re.is_match(col) {
+                    continue;
+                }
+                match self.current_mutations.get_mut(col) {
+                    Some(current) => *current = current.iter().chain(specs.iter()).cloned().collect(),
+                    None => {
+                        self.current_mutations.insert(Arc::clone(col), Arc::clone(specs));
+                    }
                 }
             }
         }
 
+        if !self.is_table_selected(&table_name) {
+            self.is_delete_table = false;
+            self.current_mutations.clear();
+        }
+
+        self.current_enum_labels.clear();
+        let types = self.registry.column_types.get(&table_name);
+        for col in &self.current_columns {
+            let labels = types
+                .and_then(|t| t.get(col))
+                .and_then(|type_name| self.registry.enum_types.get(type_name));
+            self.current_enum_labels.push(labels.cloned());
+        }
+        self.current_sequences = self
+            .current_columns
+            .iter()
+            .map(|col| self.sequences.get(&(Arc::clone(&table_name), Arc::clone(col))).copied())
+            .collect();
+
         self.build_sorted_indices();
+        self.table_row = 0;
+        self.length_warned.clear();
         self.unique_tracker.clear();
-        true
+        if let Some(seed) = self.seed {
+            self.rng = Box::new(StdRng::seed_from_u64(seed ^ fnv1a(table_name_str.as_bytes())));
+        }
+    }
+
+    /// Process one `INSERT INTO table [(cols)] VALUES (...);` statement from a
+    /// `pg_dump --inserts` plain dump. The values go through the same column
+    /// mutations as COPY rows. Without a column list (`--inserts` rather than
+    /// `--column-inserts`) the columns are taken from the table's CREATE
+    /// TABLE, as for `COPY` without one. Returns `None` if the table is being
+    /// deleted; statements that cannot be parsed are returned unchanged.
+    pub fn process_insert(&mut self, stmt: &str) -> Option<String> {
+        let Some(caps) = self.insert_re.captures(stmt) else {
+            return Some(stmt.to_string());
+        };
+        let table_name = self.qualified_table(caps.get(1).unwrap().as_str());
+        let columns = match caps.get(2) {
+            Some(list) => ident::split_column_list(list.as_str()),
+            None => self
+                .registry
+                .table_columns
+                .get(table_name.as_str())
+                .map(|cols| cols.iter().map(|c| c.to_string()).collect())
+                .unwrap_or_default(),
+        };
+        let values_start = caps.get(0).unwrap().end();
+
+        // pg_dump emits all INSERTs of a table back to back; only reload the
+        // mutations (and reset unique tracking) when the target changes.
+        let same_target = *self.current_table == *table_name
+            && self.current_columns.len() == columns.len()
+            && self
+                .current_columns
+                .iter()
+                .zip(&columns)
+                .all(|(a, b)| **a == **b);
+        if !same_target {
+            self.configure_table(&table_name, columns.iter().map(String::as_str));
+            // Tuples are mutated as COPY rows in the default encoding.
+            self.current_null = copy_text::NULL_MARKER.into();
+            if columns.is_empty() && !self.is_delete_table && !self.current_mutations.is_empty() {
+                log::warn!(
+                    "INSERT INTO {} has no column list and no CREATE TABLE was seen; statements pass through unmutated",
+                    table_name
+                );
+            }
+        }
+        if self.is_delete_table {
+            return None;
+        }
+        if self.current_mutations.is_empty() {
+            return Some(stmt.to_string());
+        }
+
+        // `--rows-per-insert` packs several `(...)` tuples into one statement,
+        // separated by commas and (usually) newlines. Separators are copied
+        // through verbatim so the statement layout is unchanged.
+        let mut out = String::with_capacity(stmt.len() + 16);
+        out.push_str(&stmt[..values_start]);
+        let mut pos = values_start;
+        loop {
+            let Some((values, end)) = insert::parse_tuple(stmt, pos) else {
+                return Some(stmt.to_string());
+            };
+            if values.len() != self.current_columns.len() {
+                return Some(stmt.to_string());
+            }
+            let open = pos + (stmt[pos..].len() - stmt[pos..].trim_start().len());
+            out.push_str(&stmt[pos..open]);
+            self.mutate_tuple(&values, &mut out);
+
+            let after = stmt[end..].trim_start();
+            if !after.starts_with(',') {
+                out.push_str(&stmt[end..]);
+                return Some(out);
+            }
+            let comma = stmt.len() - after.len();
+            out.push_str(&stmt[end..=comma]);
+            pos = comma + 1;
+        }
+    }
+
+    /// Run the mutations configured for `table.column` on a single value,
+    /// with the same condition and relation handling as COPY rows
+    /// (conditions only see `column` itself). Returns `None` when the column
+    /// has no mutations or the table is deleted. Like `process_insert`, this
+    /// reconfigures the current table when the target changes.
+    pub fn mutate_value(&mut self, table: &str, column: &str, value: &str) -> Option<String> {
+        let table = self.qualified_table(table);
+        let same_target = *self.current_table == *table
+            && self.current_columns.len() == 1
+            && *self.current_columns[0] == *column;
+        if !same_target {
+            self.configure_table(&table, std::iter::once(column));
+        }
+        if self.is_delete_table || !self.current_mutations.contains_key(column) {
+            return None;
+        }
+        let mut row = Vec::with_capacity(value.len() + 2);
+        self.encode_cell(value, &mut row);
+        self.process_line(&row)?;
+        Some(match self.scratch_replacements.first() {
+            Some(Some(new_value)) => new_value.to_string(),
+            _ => value.to_string(),
+        })
+    }
+
+    /// Mutate one INSERT value tuple and append it, parenthesized, to `out`.
+    /// Values the mutations leave untouched are copied verbatim.
+    fn mutate_tuple(&mut self, values: &[insert::TupleValue<'_>], out: &mut String) {
+        let delim = self.current_delimiter.clone();
+        let mut row: Vec<u8> = Vec::with_capacity(values.iter().map(|v| v.raw.len() + 1).sum());
+        for (i, v) in values.iter().enumerate() {
+            if i > 0 {
+                row.extend_from_slice(&delim);
+            }
+            match v.value.logical() {
+                copy_text::NULL_MARKER if v.value != insert::SqlValue::Null => {
+                    row.extend_from_slice(b"\\\\N")
+                }
+                logical => copy_text::escape_into(logical, &delim, copy_text::NULL_MARKER, &mut row),
+            }
+        }
+        let mutated = match self.process_line(&row) {
+            Some(m) => m.to_vec(),
+            None => row.clone(),
+        };
+
+        out.push('(');
+        let (mut new_spans, mut old_spans) = (Vec::new(), Vec::new());
+        copy_text::field_spans(&mutated, &delim, &mut new_spans);
+        copy_text::field_spans(&row, &delim, &mut old_spans);
+        let new_cells = new_spans.iter().map(|&(s, e)| &mutated[s as usize..e as usize]);
+        let old_cells = old_spans.iter().map(|&(s, e)| &row[s as usize..e as usize]);
+        for (i, ((new_cell, old_cell), v)) in new_cells.zip(old_cells).zip(values).enumerate() {
+            if i > 0 {
+                out.push_str(", ");
+            }
+            if new_cell == old_cell {
+                out.push_str(v.raw);
+                continue;
+            }
+            let cell = String::from_utf8_lossy(new_cell);
+            let logical = copy_text::unescape(&cell, copy_text::NULL_MARKER)
+                .unwrap_or_else(|| cell.into_owned());
+            insert::render_value(&logical, &v.value, out);
+        }
+        out.push(')');
     }
 
     fn build_sorted_indices(&mut self) {
@@ -336,6 +1171,7 @@ impl DataProcessor {
     }
 
     pub fn reset_table(&mut self) {
+        self.save_sequences();
         self.current_table = Arc::from("");
         self.current_columns.clear();
         self.column_indices.clear();
@@ -344,6 +1180,24 @@ impl DataProcessor {
         self.is_delete_table = false;
     }
 
+    /// Keep the current table's `sequence` positions for when it comes back
+    /// (e.g. INSERTs interleaved with other statements).
+    fn save_sequences(&mut self) {
+        for (col, next) in self.current_columns.iter().zip(self.current_sequences.drain(..)) {
+            if let Some(next) = next {
+                self.sequences
+                    .insert((Arc::clone(&self.current_table), Arc::clone(col)), next);
+            }
+        }
+    }
+
+    /// Whether `--include-table`/`--exclude-table` let `table` be processed:
+    /// it matches an include pattern (if any are set) and no exclude pattern.
+    pub fn is_table_selected(&self, table: &str) -> bool {
+        (self.include_tables.is_empty() || self.include_tables.iter().any(|re| re.is_match(table)))
+            && !self.exclude_tables.iter().any(|re| re.is_match(table))
+    }
+
     pub fn has_mutations(&self) -> bool {
         !self.current_mutations.is_empty()
     }
@@ -368,6 +1222,7 @@ impl DataProcessor {
             return None;
         }
         self.rows_processed = self.rows_processed.wrapping_add(1);
+        self.table_row += 1;
 
         if self.current_mutations.is_empty() {
             return Some(line);
@@ -381,44 +1236,110 @@ impl DataProcessor {
         self.scratch_replacements.clear();
         self.scratch_replacements
             .resize_with(self.current_columns.len(), || None);
+        self.scratch_name_groups.get_mut().clear();
 
-        let delim = self.delimiter;
-        let mut start: u32 = 0;
-        for (i, &b) in line.iter().enumerate() {
-            if b == delim {
-                self.scratch_spans.push((start, i as u32));
-                start = i as u32 + 1;
-            }
+        if let Some(csv) = self.current_csv {
+            csv.field_spans(line, &self.current_delimiter, &mut self.scratch_spans);
+        } else {
+            copy_text::field_spans(line, &self.current_delimiter, &mut self.scratch_spans);
         }
-        self.scratch_spans.push((start, line.len() as u32));
 
         if self.scratch_spans.len() != self.current_columns.len() {
+            // Usually an embedded delimiter or schema drift; the row is kept
+            // as is. Row contents are not logged since they may hold PII.
+            self.column_mismatches += 1;
+            // One line per row, so it stays at the info level unless
+            // `--strict` asks for such rows to be treated as errors.
+            let level = if self.strict { log::Level::Error } else { log::Level::Info };
+            log::log!(
+                level,
+                "{} row {} has {} fields, expected {}; row left unmutated",
+                self.current_table,
+                self.table_row,
+                self.scratch_spans.len(),
+                self.current_columns.len()
+            );
             return Some(line);
         }
 
+        // Mutations and conditions see logical cell values, so decode any
+        // COPY escapes (or CSV quotes) up front. Most cells have none and
+        // stay borrowed. An unquoted CSV NULL reads as `\N`, as in text rows.
+        self.scratch_decoded.clear();
+        for &(s, e) in &self.scratch_spans {
+            // SAFETY: the whole line was validated as UTF-8 above and the
+            // delimiter is ASCII or whole UTF-8 characters, so each span is a
+            // valid UTF-8 slice.
+            let raw = unsafe { std::str::from_utf8_unchecked(&line[s as usize..e as usize]) };
+            let decoded = match self.current_csv {
+                None => copy_text::unescape(raw, &self.current_null).map(Cow::Owned),
+                Some(_) if raw == &*self.current_null => Some(Cow::Borrowed(copy_text::NULL_MARKER)),
+                Some(csv) => csv.unquote(raw).map(Cow::Owned),
+            };
+            self.scratch_decoded.push(decoded);
+        }
+
         self.run_mutations(line);
+        self.call_row_hook(line);
         self.build_output(line);
         Some(&self.scratch_output)
     }
 
+    fn call_row_hook(&self, line: &[u8]) {
+        let Some(hook) = &self.row_hook else {
+            return;
+        };
+        let columns = 0..self.current_columns.len();
+        let original: Vec<&str> = columns
+            .clone()
+            .map(|i| original_value(line, &self.scratch_spans, &self.scratch_decoded, i))
+            .collect();
+        let mutated: Vec<Cow<str>> = columns
+            .map(|i| match &self.scratch_replacements[i] {
+                Some(b) => Cow::Borrowed(b.as_str()),
+                None => Cow::Borrowed(original[i]),
+            })
+            .collect();
+        let null_marker = match self.current_csv {
+            Some(_) => copy_text::NULL_MARKER,
+            None => &self.current_null,
+        };
+        hook(&self.current_table, &self.current_columns, &original, &mutated, null_marker);
+    }
+
     fn run_mutations(&mut self, line: &[u8]) {
         let Self {
             current_columns,
             column_indices,
             current_mutations,
+            current_enum_labels,
+            current_sequences,
+            sequences,
+            current_table,
+            current_null,
+            current_csv,
+            registry,
+            default_schema,
             sorted_col_indices,
             scratch_spans,
+            scratch_decoded,
             scratch_replacements,
+            scratch_name_groups,
             rng,
             unique_tracker,
             relation_tracker,
             secrets,
+            choice_files,
+            regexes,
             locale,
             mutations_applied,
-            verbose,
+            deterministic,
+            length_warnings,
+            length_warned,
+            table_row,
             ..
         } = self;
-        let verbose = *verbose;
+        let deterministic = *deterministic;
 
         for &col_idx in sorted_col_indices.iter() {
             let col_name: &Arc<str> = &current_columns[col_idx];
@@ -430,9 +1351,10 @@ impl DataProcessor {
                 let row = ScratchRow {
                     line,
                     spans: scratch_spans,
+                    decoded: scratch_decoded,
                     replacements: scratch_replacements,
                 };
-                if !check_conditions(&spec.conditions, &row, column_indices) {
+                if !check_conditions(&spec.conditions, &row, column_indices, col_idx) {
                     continue;
                 }
 
@@ -443,50 +1365,122 @@ impl DataProcessor {
                             Some(&i) => i,
                             None => continue,
                         };
-                        let fk_view = current_value(line, scratch_spans, scratch_replacements, from_idx);
+                        // Key on the FK as read: the FK column may itself be
+                        // mutated earlier in this row.
+                        let fk_view = original_value(line, scratch_spans, scratch_decoded, from_idx);
                         if let Some(existing) =
-                            relation_tracker.lookup(&rel.table_name, &rel.to_column_name, fk_view)
+                            relation_tracker.lookup(
+                                &rel.table_name,
+                                &rel.column_name,
+                                &rel.to_column_name,
+                                fk_view,
+                            )
                         {
                             found = Some(existing.to_string());
                             break;
                         }
                     }
                     if let Some(val) = found {
-                        scratch_replacements[col_idx] = Some(Box::from(val.as_str()));
+                        scratch_replacements[col_idx] = Some(val);
                         break;
                     }
                 }
 
-                let cur = current_value(line, scratch_spans, scratch_replacements, col_idx);
+                // A `foreign_key` whose target was not seen yet draws the
+                // key's new value from the key column's own mutation (and its
+                // `sequence` position), so both tables agree whichever comes
+                // first.
+                let mut call_spec = spec;
+                let mut key_sequence: Option<(KeySequence, Option<i64>)> = None;
+                if &*spec.mutation_name == "foreign_key" {
+                    if let Some((key_table, key_spec)) = spec
+                        .relations
+                        .first()
+                        .and_then(|rel| registry.primary_key_spec(default_schema, rel))
+                    {
+                        let key_column = &spec.relations[0].column_name;
+                        call_spec = key_spec;
+                        key_sequence = Some(if key_table == *current_table {
+                            let idx = column_indices.get(key_column).copied();
+                            let next = idx.and_then(|i| current_sequences[i]);
+                            (KeySequence::Current(idx), next)
+                        } else {
+                            let key = (key_table, Arc::clone(key_column));
+                            let next = sequences.get(&key).copied();
+                            (KeySequence::Saved(key), next)
+                        });
+                    }
+                }
+
+                let cur = current_value(
+                    line,
+                    scratch_spans,
+                    scratch_decoded,
+                    scratch_replacements,
+                    col_idx,
+                );
                 let lookup = ScratchLookup {
                     column_indices,
-                    replacements: scratch_replacements,
+                    name_groups: scratch_name_groups,
+                    row: ScratchRow {
+                        line,
+                        spans: scratch_spans,
+                        decoded: scratch_decoded,
+                        replacements: scratch_replacements,
+                    },
                 };
+                let mut borrowed_sequence = key_sequence.as_ref().and_then(|(_, next)| *next);
                 let mut ctx = MutationContext {
-                    kwargs: spec.mutation_kwargs.as_ref(),
+                    kwargs: call_spec.mutation_kwargs.as_ref(),
                     current_value: cur,
                     rng,
                     unique_tracker,
                     locale: *locale,
                     secrets,
                     obfuscated_values: &lookup,
+                    deterministic,
+                    choice_files,
+                    regexes,
+                    enum_labels: current_enum_labels[col_idx].as_deref(),
+                    sequence: match &key_sequence {
+                        Some(_) => &mut borrowed_sequence,
+                        None => &mut current_sequences[col_idx],
+                    },
+                    null_marker: match current_csv {
+                        Some(_) => copy_text::NULL_MARKER,
+                        None => current_null,
+                    },
+                    nested_mutations: &call_spec.nested,
                 };
 
-                match spec.call(&mut ctx) {
+                let result = call_spec.call(&mut ctx);
+                match key_sequence {
+                    Some((KeySequence::Current(Some(idx)), _)) => {
+                        current_sequences[idx] = borrowed_sequence
+                    }
+                    Some((KeySequence::Saved(key), _)) => {
+                        if let Some(next) = borrowed_sequence {
+                            sequences.insert(key, next);
+                        }
+                    }
+                    _ => {}
+                }
+                match result {
                     Ok(new_val) => {
                         if !spec.relations.is_empty() {
                             for rel in &spec.relations {
                                 if let Some(&from_idx) =
                                     column_indices.get(rel.from_column_name.as_ref())
                                 {
-                                    let fk_view = current_value(
+                                    let fk_view = original_value(
                                         line,
                                         scratch_spans,
-                                        scratch_replacements,
+                                        scratch_decoded,
                                         from_idx,
                                     );
                                     relation_tracker.store(
                                         &rel.table_name,
+                                        &rel.column_name,
                                         &rel.to_column_name,
                                         fk_view,
                                         &new_val,
@@ -494,17 +1488,23 @@ impl DataProcessor {
                                 }
                             }
                         }
-                        scratch_replacements[col_idx] = Some(Box::from(new_val.as_str()));
+                        if *length_warnings
+                            && !length_warned.contains(&col_idx)
+                            && warn_if_too_long(spec, &new_val, current_table, col_name, *table_row)
+                        {
+                            length_warned.insert(col_idx);
+                        }
+                        scratch_replacements[col_idx] = Some(new_val);
                         *mutations_applied = mutations_applied.wrapping_add(1);
                         break;
                     }
                     Err(e) => {
-                        if verbose {
-                            eprintln!(
-                                "pg_stage_rs warning: mutation '{}' failed for column '{}': {}",
-                                spec.mutation_name, col_name, e
-                            );
-                        }
+                        log::warn!(
+                            "mutation '{}' failed for column '{}': {}",
+                            spec.mutation_name,
+                            col_name,
+                            e
+                        );
                         continue;
                     }
                 }
@@ -512,39 +1512,74 @@ impl DataProcessor {
         }
     }
 
+    /// Append a logical value to `out` as a cell of the current table.
+    fn encode_cell(&self, value: &str, out: &mut Vec<u8>) {
+        if let Some(csv) = self.current_csv {
+            csv.quote_into(
+                value,
+                &self.current_delimiter,
+                &self.current_null,
+                copy_text::NULL_MARKER,
+                out,
+            );
+        } else {
+            copy_text::escape_into(value, &self.current_delimiter, &self.current_null, out);
+        }
+    }
+
     fn build_output(&mut self, line: &[u8]) {
-        self.scratch_output.clear();
-        self.scratch_output.reserve(line.len() + 16);
+        let mut output = std::mem::take(&mut self.scratch_output);
+        output.clear();
+        output.reserve(line.len() + 16);
         for (i, span) in self.scratch_spans.iter().enumerate() {
             if i > 0 {
-                self.scratch_output.push(self.delimiter);
+                output.extend_from_slice(&self.current_delimiter);
             }
             match &self.scratch_replacements[i] {
-                Some(s) => self.scratch_output.extend_from_slice(s.as_bytes()),
-                None => self
-                    .scratch_output
-                    .extend_from_slice(&line[span.0 as usize..span.1 as usize]),
+                Some(s) => self.encode_cell(s, &mut output),
+                None => output.extend_from_slice(&line[span.0 as usize..span.1 as usize]),
             }
         }
+        self.scratch_output = output;
     }
 
     pub fn emit_summary(&self) {
-        if !self.verbose {
-            return;
+        if self.merged_workers {
+            log::info!(
+                "processed rows: {}, mutations applied: {}",
+                self.rows_processed,
+                self.mutations_applied
+            );
+        } else {
+            log::info!(
+                "processed rows: {}, mutations applied: {}, unique values tracked: {}, relations tracked: {}",
+                self.rows_processed,
+                self.mutations_applied,
+                self.unique_tracker.len(),
+                self.relation_tracker.len(),
+            );
         }
-        eprintln!(
-            "[INFO] processed rows: {}, mutations applied: {}, unique values tracked: {}, relations tracked: {}",
-            self.rows_processed,
-            self.mutations_applied,
-            self.unique_tracker.len(),
-            self.relation_tracker.len(),
-        );
         if self.json_errors > 0 || self.unknown_mutation_errors > 0 {
-            eprintln!(
-                "[WARN] parse warnings: {} invalid JSON comments, {} unknown mutations",
+            log::warn!(
+                "parse warnings: {} invalid JSON comments, {} unknown mutations",
                 self.json_errors, self.unknown_mutation_errors
             );
         }
+        if self.column_mismatches > 0 {
+            log::warn!(
+                "{} rows left unmutated: field count did not match the column list",
+                self.column_mismatches
+            );
+        }
+    }
+}
+
+/// Table-level mutations only support `delete`.
+fn check_table_mutation(spec: &TableMutationSpec) -> Result<()> {
+    if spec.mutation_name == "delete" {
+        Ok(())
+    } else {
+        Err(PgStageError::UnknownMutation(spec.mutation_name.clone()))
     }
 }
 
@@ -552,15 +1587,145 @@ impl DataProcessor {
 fn current_value<'a>(
     line: &'a [u8],
     spans: &[(u32, u32)],
-    replacements: &'a [Option<Box<str>>],
+    decoded: &'a [Option<Cow<'static, str>>],
+    replacements: &'a [Option<String>],
+    idx: usize,
+) -> &'a str {
+    if let Some(b) = &replacements[idx] {
+        return b;
+    }
+    original_value(line, spans, decoded, idx)
+}
+
+/// Where a `foreign_key` fallback keeps the key column's `sequence` position:
+/// in the live per-column slots, or in the saved map for another table.
+enum KeySequence {
+    Current(Option<usize>),
+    Saved((Arc<str>, Arc<str>)),
+}
+
+/// A word or a single-quoted literal in a COPY statement's options.
+enum CopyOptionToken<'a> {
+    Word(&'a str),
+    Literal(String),
+}
+
+impl CopyOptionToken<'_> {
+    fn text(&self) -> &str {
+        match self {
+            CopyOptionToken::Word(w) => w,
+            CopyOptionToken::Literal(l) => l,
+        }
+    }
+}
+
+/// Split COPY options into words and literals (`'...'` or `E'...'`),
+/// dropping `(`, `)` and `,`.
+fn copy_option_tokens(options: &str) -> Vec<CopyOptionToken<'_>> {
+    let mut tokens = Vec::new();
+    let mut rest = options;
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || matches!(c, '(' | ')' | ','));
+        if rest.is_empty() {
+            return tokens;
+        }
+        let escaped = rest.starts_with("E'") || rest.starts_with("e'");
+        let Some(body) = rest.strip_prefix('\'').or_else(|| rest.get(2..).filter(|_| escaped)) else {
+            let end = rest
+                .find(|c: char| c.is_whitespace() || matches!(c, '(' | ')' | ',' | '\''))
+                .unwrap_or(rest.len());
+            tokens.push(CopyOptionToken::Word(&rest[..end]));
+            rest = &rest[end..];
+            continue;
+        };
+        let mut value = String::new();
+        let mut chars = body.char_indices();
+        rest = "";
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\'' if body[i + 1..].starts_with('\'') => {
+                    value.push('\'');
+                    chars.next();
+                }
+                '\'' => {
+                    rest = &body[i + 1..];
+                    break;
+                }
+                '\\' if escaped => match chars.next().map(|(_, e)| e) {
+                    Some('t') => value.push('\t'),
+                    Some('n') => value.push('\n'),
+                    Some('r') => value.push('\r'),
+                    Some(other) => value.push(other),
+                    None => {}
+                },
+                _ => value.push(c),
+            }
+        }
+        tokens.push(CopyOptionToken::Literal(value));
+    }
+}
+
+/// Join a split name, putting a lone table name in `default_schema`.
+fn qualify_in(default_schema: &str, parts: Vec<String>) -> String {
+    if parts.len() == 1 {
+        format!("{}.{}", default_schema, parts[0])
+    } else {
+        parts.join(".")
+    }
+}
+
+/// The single-quoted SQL string literals in `s`, unquoted, up to the first
+/// `)` outside a literal.
+fn string_literals(s: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            ')' => break,
+            '\'' => {
+                let mut literal = String::new();
+                while let Some(c) = chars.next() {
+                    if c != '\'' {
+                        literal.push(c);
+                    } else if chars.peek() == Some(&'\'') {
+                        chars.next();
+                        literal.push('\'');
+                    } else {
+                        break;
+                    }
+                }
+                out.push(literal);
+            }
+            _ => {}
+        }
+    }
+    out
+}
+
+/// Body of a SQL string literal with its doubled quotes (`''`) undone.
+fn unquote_literal(body: &str) -> Cow<'_, str> {
+    if body.contains('\'') {
+        Cow::Owned(body.replace("''", "'"))
+    } else {
+        Cow::Borrowed(body)
+    }
+}
+
+/// A field as it was read, ignoring any replacement.
+#[inline]
+fn original_value<'a>(
+    line: &'a [u8],
+    spans: &[(u32, u32)],
+    decoded: &'a [Option<Cow<'static, str>>],
     idx: usize,
 ) -> &'a str {
-    match &replacements[idx] {
+    match &decoded[idx] {
         Some(b) => b.as_ref(),
         None => {
             let (s, e) = spans[idx];
             // SAFETY: caller verified the whole line is valid UTF-8 and the
-            // delimiter byte is ASCII, so each span is a valid UTF-8 slice.
+            // delimiter is ASCII or whole UTF-8 characters, so each span is a
+            // valid UTF-8 slice.
             unsafe { std::str::from_utf8_unchecked(&line[s as usize..e as usize]) }
         }
     }
@@ -569,7 +1734,8 @@ fn current_value<'a>(
 struct ScratchRow<'a> {
     line: &'a [u8],
     spans: &'a [(u32, u32)],
-    replacements: &'a [Option<Box<str>>],
+    decoded: &'a [Option<Cow<'static, str>>],
+    replacements: &'a [Option<String>],
 }
 
 impl<'a> RowRead for ScratchRow<'a> {
@@ -577,18 +1743,89 @@ impl<'a> RowRead for ScratchRow<'a> {
         self.spans.len()
     }
     fn value_at(&self, idx: usize) -> &str {
-        current_value(self.line, self.spans, self.replacements, idx)
+        current_value(self.line, self.spans, self.decoded, self.replacements, idx)
     }
 }
 
 struct ScratchLookup<'a> {
     column_indices: &'a FastMap<Arc<str>, usize>,
-    replacements: &'a [Option<Box<str>>],
+    name_groups: &'a RefCell<NameGroups>,
+    row: ScratchRow<'a>,
 }
 
 impl<'a> ObfuscatedLookup for ScratchLookup<'a> {
     fn get(&self, column: &str) -> Option<&str> {
         let idx = *self.column_indices.get(column)?;
-        self.replacements.get(idx)?.as_deref()
+        self.row.replacements.get(idx)?.as_deref()
+    }
+
+    fn original(&self, column: &str) -> Option<&str> {
+        let idx = *self.column_indices.get(column)?;
+        Some(original_value(self.row.line, self.row.spans, self.row.decoded, idx))
+    }
+
+    fn name_groups(&self) -> Option<&RefCell<NameGroups>> {
+        Some(self.name_groups)
+    }
+}
+
+/// Whether a kwarg is `"unique": true`, a `template` pattern with a
+/// `{sequence}` placeholder, or holds a nested mutation spec (`json_update`,
+/// `array`, `partial`) that sets it or is a `sequence`.
+fn depends_on_order((key, value): (&String, &serde_json::Value)) -> bool {
+    if key == "unique" && value.as_bool() == Some(true) {
+        return true;
+    }
+    if key == "mutation_name" && value.as_str() == Some("sequence") {
+        return true;
+    }
+    if key == "pattern" && value.as_str().is_some_and(|p| p.contains("{sequence}")) {
+        return true;
+    }
+    match value {
+        serde_json::Value::Object(map) => map.iter().any(depends_on_order),
+        serde_json::Value::Array(items) => items
+            .iter()
+            .any(|item| item.as_object().is_some_and(|m| m.iter().any(depends_on_order))),
+        _ => false,
+    }
+}
+
+/// Warn when `value` has more characters than `spec`'s `max_length` kwarg.
+/// Only lengths are logged, not values. Returns whether it warned.
+fn warn_if_too_long(
+    spec: &CompiledMutationSpec,
+    value: &str,
+    table: &str,
+    column: &str,
+    row: u64,
+) -> bool {
+    let Some(max) = spec.mutation_kwargs.get("max_length").and_then(|v| v.as_u64()) else {
+        return false;
+    };
+    let len = value.chars().count() as u64;
+    if len <= max {
+        return false;
+    }
+    log::warn!(
+        "{}.{} row {}: {} produced {} characters, over max_length {}; later values in this column are not reported",
+        table,
+        column,
+        row,
+        spec.mutation_name,
+        len,
+        max
+    );
+    true
+}
+
+/// FNV-1a, used to derive a stable per-table seed (std's hashers are
+/// randomly keyed per process).
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for &b in bytes {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
     }
+    hash
 }
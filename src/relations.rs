@@ -2,11 +2,20 @@ use std::sync::Arc;
 
 use crate::FastMap;
 
+/// `key_column -> fk_value -> obfuscated_value`.
+type KeyValues = FastMap<Arc<str>, FastMap<Box<str>, Box<str>>>;
+/// `column -> KeyValues`.
+type ColumnValues = FastMap<Arc<str>, KeyValues>;
+
 /// Tracks FK relationships to ensure consistent obfuscation across tables.
 ///
-/// Layout: `by_table[table][column][fk_value] = obfuscated_value`.
+/// Layout: `by_table[table][column][key_column][fk_value] = obfuscated_value`,
+/// where `column` is the related table's column the value stands for and
+/// `key_column` is the column the FK points at. Keying on both keeps two
+/// relations into the same table (e.g. `users.email` and `users.first_name`,
+/// both via `users.id`) from sharing values.
 ///
-/// `table` and `column` keys are `Arc<str>`, shared with `CompiledRelation` and
+/// `table` and column keys are `Arc<str>`, shared with `CompiledRelation` and
 /// `MutationRegistry`, so a name that appears in thousands of schemas is stored
 /// in memory exactly once per unique string.
 ///
@@ -14,7 +23,7 @@ use crate::FastMap;
 /// capacity overhead).
 #[derive(Debug, Default)]
 pub struct RelationTracker {
-    by_table: FastMap<Arc<str>, FastMap<Arc<str>, FastMap<Box<str>, Box<str>>>>,
+    by_table: FastMap<Arc<str>, ColumnValues>,
     count: usize,
 }
 
@@ -31,10 +40,17 @@ impl RelationTracker {
         self.count == 0
     }
 
-    pub fn lookup(&self, table: &Arc<str>, column: &Arc<str>, fk_value: &str) -> Option<&str> {
+    pub fn lookup(
+        &self,
+        table: &Arc<str>,
+        column: &Arc<str>,
+        key_column: &Arc<str>,
+        fk_value: &str,
+    ) -> Option<&str> {
         self.by_table
             .get(table.as_ref())?
             .get(column.as_ref())?
+            .get(key_column.as_ref())?
             .get(fk_value)
             .map(|v| v.as_ref())
     }
@@ -43,12 +59,14 @@ impl RelationTracker {
         &mut self,
         table: &Arc<str>,
         column: &Arc<str>,
+        key_column: &Arc<str>,
         fk_value: &str,
         obfuscated: &str,
     ) {
         let outer = self.by_table.entry(Arc::clone(table)).or_default();
         let mid = outer.entry(Arc::clone(column)).or_default();
-        let inserted = mid.insert(Box::from(fk_value), Box::from(obfuscated));
+        let inner = mid.entry(Arc::clone(key_column)).or_default();
+        let inserted = inner.insert(Box::from(fk_value), Box::from(obfuscated));
         if inserted.is_none() {
             self.count += 1;
         }
@@ -4,11 +4,12 @@ use std::str::FromStr;
 use std::sync::Arc;
 
 use crate::error::{PgStageError, Result};
-use crate::mutator::{resolve_mutation, MutationFn, MutationContext};
+use crate::mutator::{compile_nested, CustomMutators, MutationContext, NestedMutations, ResolvedMutation};
 use crate::FastMap;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum Locale {
+    #[default]
     En,
     Ru,
 }
@@ -60,6 +61,13 @@ pub struct TableMutationSpec {
 pub enum CondOp {
     Equal(String),
     NotEqual(String),
+    /// Case-insensitive equality; the value is stored lowercased.
+    IEqual(String),
+    INotEqual(String),
+    Contains(String),
+    StartsWith(String),
+    EndsWith(String),
+    /// Compiled once when the spec is parsed and shared by every row.
     ByPattern(Regex),
 }
 
@@ -72,6 +80,7 @@ pub struct CompiledCondition {
 #[derive(Debug, Clone)]
 pub struct CompiledRelation {
     pub table_name: Arc<str>,
+    pub column_name: Arc<str>,
     pub to_column_name: Arc<str>,
     pub from_column_name: Arc<str>,
 }
@@ -80,8 +89,11 @@ pub struct CompiledRelation {
 #[derive(Clone)]
 pub struct CompiledMutationSpec {
     pub mutation_name: Arc<str>,
-    pub mutation_fn: MutationFn,
+    pub mutation: ResolvedMutation,
     pub mutation_kwargs: Arc<FastMap<String, serde_json::Value>>,
+    /// Specs nested in the kwargs (`partial`, `array`, `json`,
+    /// `json_update`, `template`), resolved once here.
+    pub nested: Arc<NestedMutations>,
     pub conditions: Vec<CompiledCondition>,
     pub relations: Vec<CompiledRelation>,
 }
@@ -97,9 +109,10 @@ impl std::fmt::Debug for CompiledMutationSpec {
 }
 
 impl CompiledMutationSpec {
-    pub fn compile(spec: MutationSpec) -> Result<Self> {
-        let mutation_fn: MutationFn = resolve_mutation(&spec.mutation_name)
+    pub fn compile(spec: MutationSpec, custom: &CustomMutators) -> Result<Self> {
+        let mutation = ResolvedMutation::resolve(&spec.mutation_name, custom)
             .ok_or_else(|| PgStageError::UnknownMutation(spec.mutation_name.clone()))?;
+        let nested = compile_nested(&spec.mutation_name, &spec.mutation_kwargs, custom)?;
         let conditions = spec
             .conditions
             .into_iter()
@@ -107,6 +120,11 @@ impl CompiledMutationSpec {
                 let op = match c.operation.as_str() {
                     "equal" => CondOp::Equal(c.value),
                     "not_equal" => CondOp::NotEqual(c.value),
+                    "iequal" => CondOp::IEqual(c.value.to_lowercase()),
+                    "inot_equal" => CondOp::INotEqual(c.value.to_lowercase()),
+                    "contains" => CondOp::Contains(c.value),
+                    "starts_with" => CondOp::StartsWith(c.value),
+                    "ends_with" => CondOp::EndsWith(c.value),
                     "by_pattern" => {
                         let re = Regex::new(&c.value).map_err(|e| {
                             PgStageError::InvalidParameter(format!(
@@ -134,31 +152,41 @@ impl CompiledMutationSpec {
             .into_iter()
             .map(|r| CompiledRelation {
                 table_name: Arc::from(r.table_name.as_str()),
+                column_name: Arc::from(r.column_name.as_str()),
                 to_column_name: Arc::from(r.to_column_name.as_str()),
                 from_column_name: Arc::from(r.from_column_name.as_str()),
             })
             .collect();
         Ok(Self {
             mutation_name: Arc::from(spec.mutation_name.as_str()),
-            mutation_fn,
+            mutation,
             mutation_kwargs: Arc::new(spec.mutation_kwargs),
+            nested: Arc::new(nested),
             conditions,
             relations,
         })
     }
 
+    /// Whether the mutation reads another column's obfuscated value, so its
+    /// column must run after the others in the row.
     pub fn has_source_column(&self) -> bool {
-        self.mutation_kwargs.contains_key("source_column")
+        SOURCE_COLUMN_KWARGS
+            .iter()
+            .any(|k| self.mutation_kwargs.contains_key(*k))
     }
 
     /// Run the compiled mutation.
     pub fn call(&self, ctx: &mut MutationContext) -> Result<String> {
-        (self.mutation_fn)(ctx)
+        self.mutation.call(ctx)
     }
 }
 
+/// Kwargs naming a column whose obfuscated value the mutation reads.
+const SOURCE_COLUMN_KWARGS: &[&str] =
+    &["source_column", "gender_column", "first_column", "last_column"];
+
 /// Maps table_name -> column_name -> Vec<CompiledMutationSpec>
-pub type MutationMap = FastMap<Arc<str>, FastMap<Arc<str>, Vec<CompiledMutationSpec>>>;
+pub type MutationMap = FastMap<Arc<str>, FastMap<Arc<str>, Arc<[CompiledMutationSpec]>>>;
 
 /// Maps table_name -> TableMutationSpec (e.g., delete)
 pub type TableMutationMap = FastMap<Arc<str>, TableMutationSpec>;
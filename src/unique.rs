@@ -19,6 +19,10 @@ impl UniqueTracker {
         self.values.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
     /// Try to insert. Returns true if the value was new.
     /// Avoids allocating a `String` when the value is already present.
     pub fn try_insert(&mut self, value: &str) -> bool {
@@ -1,3 +1,5 @@
+#![allow(clippy::manual_range_contains, clippy::iter_nth_zero, clippy::manual_split_once)]
+
 use std::io::Cursor;
 
 use pg_stage_rs::format::plain::PlainHandler;
@@ -116,6 +118,27 @@ fn test_plain_mutation_email() {
     assert!(parts[2].contains('@'));
 }
 
+#[test]
+fn test_plain_mutation_email_preserve_role_local() {
+    let input = concat!(
+        "COMMENT ON COLUMN public.users.email IS 'anon: [{\"mutation_name\": \"email\", \"mutation_kwargs\": {\"preserve_role_local\": true}}]';\n",
+        "COPY public.users (id, email) FROM stdin;\n",
+        "1\tsupport@x.com\n",
+        "2\tjohn@x.com\n",
+        "\\.\n",
+    );
+    let mut output = Vec::new();
+    let mut handler = PlainHandler::new(make_processor());
+    handler.process(Cursor::new(b""), &mut output, input.as_bytes()).unwrap();
+    let result = String::from_utf8(output).unwrap();
+    let role = result.lines().find(|l| l.starts_with("1\t")).unwrap();
+    let (local, domain) = role.split('\t').nth(1).unwrap().split_once('@').unwrap();
+    assert_eq!(local, "support");
+    assert_ne!(domain, "x.com");
+    let user = result.lines().find(|l| l.starts_with("2\t")).unwrap();
+    assert!(!user.split('\t').nth(1).unwrap().starts_with("john@"));
+}
+
 #[test]
 fn test_plain_mutation_first_name() {
     let input = concat!(
@@ -190,6 +213,29 @@ fn test_plain_mutation_uuid4() {
     assert_eq!(parts[1].chars().filter(|c| *c == '-').count(), 4);
 }
 
+#[test]
+fn test_plain_mutation_uuid7() {
+    let input = concat!(
+        "COMMENT ON COLUMN public.users.uid IS 'anon: [{\"mutation_name\": \"uuid7\"}]';\n",
+        "COPY public.users (id, uid) FROM stdin;\n",
+        "1\told-uuid\n",
+        "\\.\n",
+    );
+    let mut output = Vec::new();
+    let mut handler = PlainHandler::new(make_processor());
+    handler.process(Cursor::new(b""), &mut output, input.as_bytes()).unwrap();
+    let result = String::from_utf8(output).unwrap();
+    let lines: Vec<&str> = result.lines().collect();
+    let data_line = lines.iter().find(|l| l.starts_with("1\t")).unwrap();
+    let parts: Vec<&str> = data_line.split('\t').collect();
+    let uid = parts[1];
+    assert_eq!(uid.len(), 36);
+    // Version nibble is the first hex digit of the third group.
+    assert_eq!(uid.as_bytes()[14], b'7');
+    // RFC 9562 variant: top two bits of the fourth group are `10`.
+    assert!(matches!(uid.as_bytes()[19], b'8' | b'9' | b'a' | b'b'));
+}
+
 #[test]
 fn test_plain_mutation_numeric_integer() {
     let input = concat!(
@@ -249,6 +295,36 @@ fn test_plain_mutation_ipv4() {
     assert_eq!(octets.len(), 4);
 }
 
+#[test]
+fn test_plain_mutation_deterministic_ipv4_preserves_subnet() {
+    std::env::set_var("SECRET_KEY", "test-secret");
+    std::env::set_var("SECRET_KEY_NONCE", "test-nonce");
+    let input = concat!(
+        "COMMENT ON COLUMN public.logs.ip IS 'anon: [{\"mutation_name\": \"deterministic_ipv4\", \"mutation_kwargs\": {\"prefix_length\": 24}}]';\n",
+        "COPY public.logs (id, ip) FROM stdin;\n",
+        "1\t10.20.30.40\n",
+        "2\t10.20.30.41\n",
+        "3\t10.20.30.40\n",
+        "\\.\n",
+    );
+    let mut output = Vec::new();
+    let mut handler = PlainHandler::new(make_processor());
+    handler.process(Cursor::new(b""), &mut output, input.as_bytes()).unwrap();
+    let result = String::from_utf8(output).unwrap();
+    let ips: Vec<&str> = result
+        .lines()
+        .filter(|l| l.starts_with(|c: char| c.is_ascii_digit()))
+        .map(|l| l.split('\t').nth(1).unwrap())
+        .collect();
+    assert_eq!(ips.len(), 3);
+    for ip in &ips {
+        assert!(ip.starts_with("10.20.30."), "left the /24: {}", ip);
+        let host: u8 = ip.rsplit('.').next().unwrap().parse().unwrap();
+        assert!(host != 0 && host != 255);
+    }
+    assert_eq!(ips[0], ips[2]);
+}
+
 #[test]
 fn test_plain_mutation_string_by_mask() {
     let input = concat!(
@@ -292,6 +368,55 @@ fn test_plain_mutation_random_choice() {
     assert!(parts[1] == "active" || parts[1] == "inactive");
 }
 
+#[test]
+fn test_plain_mutation_partial_changes_about_half() {
+    let mut input = String::from(
+        "COMMENT ON COLUMN public.users.email IS 'anon: [{\"mutation_name\": \"partial\", \"mutation_kwargs\": {\"probability\": 0.5, \"mutation\": {\"mutation_name\": \"fixed_value\", \"mutation_kwargs\": {\"value\": \"CHANGED\"}}}}]';\nCOPY public.users (id, email) FROM stdin;\n",
+    );
+    for i in 0..2000 {
+        input.push_str(&format!("{}\toriginal\n", i));
+    }
+    input.push_str("\\.\n");
+    let mut output = Vec::new();
+    let mut handler = PlainHandler::new(make_processor());
+    handler.process(Cursor::new(b""), &mut output, input.as_bytes()).unwrap();
+    let result = String::from_utf8(output).unwrap();
+    let changed = result.lines().filter(|l| l.ends_with("\tCHANGED")).count();
+    let kept = result.lines().filter(|l| l.ends_with("\toriginal")).count();
+    assert_eq!(changed + kept, 2000);
+    // 2000 fair coin flips: the 6-sigma band is roughly 866..1134.
+    assert!((850..=1150).contains(&changed), "changed = {}", changed);
+}
+
+#[test]
+fn test_plain_mutation_status_preserves_terminal_states() {
+    let mut input = String::from(
+        "COMMENT ON COLUMN public.orders.status IS 'anon: [{\"mutation_name\": \"status\", \"mutation_kwargs\": {\"choices\": [\"new\", \"paid\", \"shipped\"], \"weights\": [1, 0, 3], \"preserve_terminal\": true}}]';\nCOPY public.orders (id, status) FROM stdin;\n",
+    );
+    for i in 0..200 {
+        let status = match i % 3 {
+            0 => "cancelled",
+            1 => "completed",
+            _ => "pending",
+        };
+        input.push_str(&format!("{}\t{}\n", i, status));
+    }
+    input.push_str("\\.\n");
+    let mut output = Vec::new();
+    let mut handler = PlainHandler::new(make_processor());
+    handler.process(Cursor::new(b""), &mut output, input.as_bytes()).unwrap();
+    let result = String::from_utf8(output).unwrap();
+    for line in result.lines().filter(|l| l.starts_with(|c: char| c.is_ascii_digit())) {
+        let (id, status) = line.split_once('\t').unwrap();
+        match id.parse::<u32>().unwrap() % 3 {
+            0 => assert_eq!(status, "cancelled"),
+            1 => assert_eq!(status, "completed"),
+            // Zero weight: "paid" must never be picked.
+            _ => assert!(status == "new" || status == "shipped", "got {}", status),
+        }
+    }
+}
+
 #[test]
 fn test_plain_condition_equal() {
     let input = concat!(
@@ -330,6 +455,51 @@ fn test_plain_condition_not_equal() {
     assert!(result.contains("2\tuser\t\\N\n"));
 }
 
+#[test]
+fn test_plain_escaped_tab_in_neighbor_survives_mutation() {
+    let input = concat!(
+        "COMMENT ON COLUMN public.users.email IS 'anon: [{\"mutation_name\": \"fixed_value\", \"mutation_kwargs\": {\"value\": \"REDACTED\"}}]';\n",
+        "COPY public.users (id, note, email) FROM stdin;\n",
+        "1\tleft\\tright\\nnext \\\\ end\tjohn@example.com\n",
+        "\\.\n",
+    );
+    let mut output = Vec::new();
+    let mut handler = PlainHandler::new(make_processor());
+    handler.process(Cursor::new(b""), &mut output, input.as_bytes()).unwrap();
+    let result = String::from_utf8(output).unwrap();
+    assert!(result.contains("1\tleft\\tright\\nnext \\\\ end\tREDACTED\n"));
+}
+
+#[test]
+fn test_plain_mutation_output_is_copy_escaped() {
+    let input = concat!(
+        "COMMENT ON COLUMN public.users.note IS 'anon: [{\"mutation_name\": \"fixed_value\", \"mutation_kwargs\": {\"value\": \"a\\tb\\nc\\\\d\"}}]';\n",
+        "COPY public.users (id, note) FROM stdin;\n",
+        "1\told\n",
+        "\\.\n",
+    );
+    let mut output = Vec::new();
+    let mut handler = PlainHandler::new(make_processor());
+    handler.process(Cursor::new(b""), &mut output, input.as_bytes()).unwrap();
+    let result = String::from_utf8(output).unwrap();
+    assert!(result.contains("1\ta\\tb\\nc\\\\d\n"));
+}
+
+#[test]
+fn test_plain_condition_sees_unescaped_value() {
+    let input = concat!(
+        "COMMENT ON COLUMN public.users.email IS 'anon: [{\"mutation_name\": \"null\", \"conditions\": [{\"column_name\": \"role\", \"operation\": \"equal\", \"value\": \"a\\tb\"}]}]';\n",
+        "COPY public.users (id, role, email) FROM stdin;\n",
+        "1\ta\\tb\tx@example.com\n",
+        "\\.\n",
+    );
+    let mut output = Vec::new();
+    let mut handler = PlainHandler::new(make_processor());
+    handler.process(Cursor::new(b""), &mut output, input.as_bytes()).unwrap();
+    let result = String::from_utf8(output).unwrap();
+    assert!(result.contains("1\ta\\tb\t\\N\n"));
+}
+
 #[test]
 fn test_plain_delete_table() {
     let input = concat!(
@@ -351,6 +521,137 @@ fn test_plain_delete_table() {
     assert!(result.contains("SELECT 1;"));
 }
 
+#[test]
+fn test_plain_insert_statement_mutated() {
+    let input = concat!(
+        "COMMENT ON COLUMN public.users.email IS 'anon: [{\"mutation_name\": \"email\"}]';\n",
+        "INSERT INTO public.users (id, name, email) VALUES (1, 'O''Brien, Pat', 'john@example.com');\n",
+        "INSERT INTO public.users (id, name, email) VALUES (2, NULL, 'jane@example.com');\n",
+    );
+    let mut output = Vec::new();
+    let mut handler = PlainHandler::new(make_processor());
+    handler.process(Cursor::new(b""), &mut output, input.as_bytes()).unwrap();
+    let result = String::from_utf8(output).unwrap();
+    assert!(!result.contains("john@example.com"));
+    assert!(!result.contains("jane@example.com"));
+    let first = result.lines().find(|l| l.contains("VALUES (1,")).unwrap();
+    assert!(first.starts_with("INSERT INTO public.users (id, name, email) VALUES (1, 'O''Brien, Pat', '"));
+    assert!(first.ends_with("');"));
+    assert!(first.contains('@'));
+    let second = result.lines().find(|l| l.contains("VALUES (2,")).unwrap();
+    assert!(second.contains("VALUES (2, NULL, '"));
+}
+
+#[test]
+fn test_plain_insert_multiline_string_value() {
+    let input = concat!(
+        "COMMENT ON COLUMN public.notes.body IS 'anon: [{\"mutation_name\": \"fixed_value\", \"mutation_kwargs\": {\"value\": \"REDACTED\"}}]';\n",
+        "INSERT INTO public.notes (id, body) VALUES (7, 'line one\n",
+        "line two;');\n",
+        "SELECT 1;\n",
+    );
+    let mut output = Vec::new();
+    let mut handler = PlainHandler::new(make_processor());
+    handler.process(Cursor::new(b""), &mut output, input.as_bytes()).unwrap();
+    let result = String::from_utf8(output).unwrap();
+    assert!(result.contains("INSERT INTO public.notes (id, body) VALUES (7, 'REDACTED');\nSELECT 1;\n"));
+}
+
+#[test]
+fn test_plain_insert_multi_row_each_tuple_mutated() {
+    let input = concat!(
+        "COMMENT ON COLUMN public.users.email IS 'anon: [{\"mutation_name\": \"email\"}]';\n",
+        "INSERT INTO public.users (id, note, email) VALUES (1, 'a, (b)', 'john@example.com'), (2, 'c);d', 'jane@example.com');\n",
+    );
+    let mut output = Vec::new();
+    let mut handler = PlainHandler::new(make_processor());
+    handler.process(Cursor::new(b""), &mut output, input.as_bytes()).unwrap();
+    let result = String::from_utf8(output).unwrap();
+    assert!(!result.contains("john@example.com"));
+    assert!(!result.contains("jane@example.com"));
+    assert!(result.contains("VALUES (1, 'a, (b)', '"));
+    assert!(result.contains("), (2, 'c);d', '"));
+    assert!(result.trim_end().ends_with("');"));
+    assert_eq!(result.matches('@').count(), 2);
+}
+
+#[test]
+fn test_plain_insert_rows_per_insert_layout_preserved() {
+    let input = concat!(
+        "COMMENT ON COLUMN public.users.email IS 'anon: [{\"mutation_name\": \"fixed_value\", \"mutation_kwargs\": {\"value\": \"x@y.z\"}}]';\n",
+        "INSERT INTO public.users (id, email) VALUES\n",
+        "\t(1, 'john@example.com'),\n",
+        "\t(2, 'jane@example.com');\n",
+    );
+    let mut output = Vec::new();
+    let mut handler = PlainHandler::new(make_processor());
+    handler.process(Cursor::new(b""), &mut output, input.as_bytes()).unwrap();
+    let result = String::from_utf8(output).unwrap();
+    assert!(result.ends_with(concat!(
+        "INSERT INTO public.users (id, email) VALUES\n",
+        "\t(1, 'x@y.z'),\n",
+        "\t(2, 'x@y.z');\n",
+    )));
+}
+
+#[test]
+fn test_plain_insert_delete_table() {
+    let input = concat!(
+        "COMMENT ON TABLE public.logs IS 'anon: {\"mutation_name\": \"delete\"}';\n",
+        "INSERT INTO public.logs (id, msg) VALUES (1, 'secret');\n",
+        "SELECT 1;\n",
+    );
+    let mut output = Vec::new();
+    let mut handler = PlainHandler::new(make_processor());
+    handler.process(Cursor::new(b""), &mut output, input.as_bytes()).unwrap();
+    let result = String::from_utf8(output).unwrap();
+    assert!(!result.contains("INSERT INTO public.logs"));
+    assert!(result.contains("SELECT 1;"));
+}
+
+#[test]
+fn test_plain_insert_without_column_list_uses_create_table() {
+    drop(captured_logs());
+    let input = concat!(
+        "CREATE TABLE public.users (\n",
+        "    id integer NOT NULL,\n",
+        "    email text\n",
+        ");\n",
+        "COMMENT ON COLUMN public.users.email IS 'anon: [{\"mutation_name\": \"fixed_value\", \"mutation_kwargs\": {\"value\": \"x@y.z\"}}]';\n",
+        "COMMENT ON COLUMN public.orders.note IS 'anon: [{\"mutation_name\": \"fixed_value\", \"mutation_kwargs\": {\"value\": \"-\"}}]';\n",
+        "INSERT INTO public.users VALUES (1, 'secret@corp.com');\n",
+        "INSERT INTO public.orders VALUES (1, 'secret');\n",
+    );
+    let mut output = Vec::new();
+    let mut handler = PlainHandler::new(make_processor());
+    handler.process(Cursor::new(b""), &mut output, input.as_bytes()).unwrap();
+    let result = String::from_utf8(output).unwrap();
+    assert!(result.contains("INSERT INTO public.users VALUES (1, 'x@y.z');\n"));
+    // No CREATE TABLE for orders: the columns are unknown, so it passes through.
+    assert!(result.contains("INSERT INTO public.orders VALUES (1, 'secret');\n"));
+    assert!(captured_logs()
+        .iter()
+        .any(|l| l.starts_with("WARN INSERT INTO public.orders has no column list")));
+}
+
+#[test]
+fn test_plain_insert_quotes_non_numeric_and_decodes_escapes() {
+    let input = concat!(
+        "COMMENT ON COLUMN public.metrics.score IS 'anon: [{\"mutation_name\": \"fixed_value\", \"mutation_kwargs\": {\"value\": \"NaN\"}}]';\n",
+        "COMMENT ON COLUMN public.metrics.note IS 'anon: [{\"mutation_name\": \"fixed_value\", \"conditions\": [{\"column_name\": \"note\", \"operation\": \"equal\", \"value\": \"it''s AA\\u00e9\"}], \"mutation_kwargs\": {\"value\": \"ok\"}}]';\n",
+        "INSERT INTO public.metrics (score, note) VALUES (1.5, E'it\\'s \\x41\\101\\u00e9');\n",
+        "INSERT INTO public.metrics (score, note) VALUES (2, E'a\\');\n",
+        "b');\n",
+    );
+    let mut output = Vec::new();
+    let mut handler = PlainHandler::new(make_processor());
+    handler.process(Cursor::new(b""), &mut output, input.as_bytes()).unwrap();
+    let result = String::from_utf8(output).unwrap();
+    assert!(result.contains("VALUES ('NaN', 'ok');\n"));
+    // `\'` does not end the literal, so the statement spans both lines.
+    assert!(result.contains("VALUES ('NaN', E'a\\');\nb');\n"));
+}
+
 #[test]
 fn test_plain_multiple_tables() {
     let input = concat!(
@@ -407,6 +708,47 @@ fn test_plain_mutation_address_en() {
     assert!(!result.contains("123 Old Street"));
 }
 
+#[test]
+fn test_plain_mutation_multiline_address() {
+    let input = concat!(
+        "COMMENT ON COLUMN public.users.addr IS 'anon: [{\"mutation_name\": \"multiline_address\"}]';\n",
+        "COPY public.users (id, addr) FROM stdin;\n",
+        "1\t12 Oak St\\nApt 3\\nBoston, MA 02101\n",
+        "2\tsingle line\n",
+        "\\.\n",
+    );
+    let mut output = Vec::new();
+    let mut handler = PlainHandler::new(make_processor());
+    handler.process(Cursor::new(b""), &mut output, input.as_bytes()).unwrap();
+    let result = String::from_utf8(output).unwrap();
+    assert!(!result.contains("Boston, MA 02101"));
+    let row = result.lines().find(|l| l.starts_with("1\t")).unwrap();
+    let addr = row.split('\t').nth(1).unwrap();
+    let lines: Vec<&str> = addr.split("\\n").collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[1].starts_with("Apt "));
+    let state_zip = lines[2].rsplit(", ").next().unwrap();
+    assert!(state_zip.len() == 8 && state_zip[..2].chars().all(|c| c.is_ascii_uppercase()));
+    let row = result.lines().find(|l| l.starts_with("2\t")).unwrap();
+    assert!(!row.contains("\\n"));
+
+    let input = concat!(
+        "COMMENT ON COLUMN public.users.addr IS 'anon: [{\"mutation_name\": \"multiline_address\"}]';\n",
+        "COPY public.users (id, addr) FROM stdin;\n",
+        "1\tул. Мира, д. 1\\nг. Москва\n",
+        "\\.\n",
+    );
+    let mut output = Vec::new();
+    let mut handler = PlainHandler::new(make_ru_processor());
+    handler.process(Cursor::new(b""), &mut output, input.as_bytes()).unwrap();
+    let result = String::from_utf8(output).unwrap();
+    let row = result.lines().find(|l| l.starts_with("1\t")).unwrap();
+    let lines: Vec<&str> = row.split('\t').nth(1).unwrap().split("\\n").collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].contains(", д. "));
+    assert!(lines[1].contains(", г. "));
+}
+
 #[test]
 fn test_plain_mutation_uri() {
     let input = concat!(
@@ -578,6 +920,42 @@ fn test_plain_mutation_json_update_empty_object_skips_all() {
 
 // ---------- --rules-file (RulesFile / load_rules) ----------
 
+#[test]
+fn test_plain_mutation_json_update_numeric_leaf_stays_numeric() {
+    let meta = run_json_update(
+        r#"{"mutation_name": "json_update", "mutation_kwargs": {"age": {"mutation_name": "numeric_integer", "mutation_kwargs": {"start": 18, "end": 99}}}}"#,
+        r#"{"age":42,"name":"x"}"#,
+    );
+    let parsed: serde_json::Value = serde_json::from_str(&meta).unwrap();
+    let age = parsed["age"].as_i64().unwrap_or_else(|| panic!("age not numeric: {}", meta));
+    assert!((18..=99).contains(&age));
+}
+
+#[test]
+fn test_plain_mutation_json_update_boolean_leaf_stays_boolean() {
+    let meta = run_json_update(
+        r#"{"mutation_name": "json_update", "mutation_kwargs": {"active": {"mutation_name": "random_choice", "mutation_kwargs": {"choices": ["true", "false"]}}}}"#,
+        r#"{"active":true}"#,
+    );
+    let parsed: serde_json::Value = serde_json::from_str(&meta).unwrap();
+    assert!(parsed["active"].is_boolean(), "got: {}", meta);
+}
+
+#[test]
+fn test_plain_mutation_json_update_array_length_preserved() {
+    let meta = run_json_update(
+        r#"{"mutation_name": "json_update", "mutation_kwargs": {"scores": {"mutation_name": "numeric_integer", "mutation_kwargs": {"start": 1, "end": 5}}, "tags": {"mutation_name": "first_name"}}}"#,
+        r#"{"scores":[10,20,30],"tags":["a","b"]}"#,
+    );
+    let parsed: serde_json::Value = serde_json::from_str(&meta).unwrap();
+    let scores = parsed["scores"].as_array().unwrap();
+    assert_eq!(scores.len(), 3);
+    assert!(scores.iter().all(|v| v.is_i64()));
+    let tags = parsed["tags"].as_array().unwrap();
+    assert_eq!(tags.len(), 2);
+    assert!(tags.iter().all(|v| v.is_string()));
+}
+
 fn run_with_rules(rules_json: &str, dump: &str) -> String {
     let mut proc = make_processor();
     proc.load_rules(rules_json).expect("rules should load");
@@ -635,6 +1013,24 @@ fn test_rules_file_table_pattern_non_delete_does_not_drop_table() {
     assert!(result.contains("COPY public.users"));
 }
 
+#[test]
+fn test_rules_file_table_pattern_non_delete_fails_in_strict_mode() {
+    let rules = r#"{
+        "table_patterns": [
+            { "table": "^public\\.users$", "mutation": { "mutation_name": "noop" } }
+        ]
+    }"#;
+    let mut proc = make_processor();
+    proc.load_rules(rules).unwrap();
+    assert_eq!(proc.parse_warnings(), (0, 1));
+    assert!(proc.check_rules().is_ok());
+
+    let mut strict = make_processor();
+    strict.set_strict(true);
+    strict.load_rules(rules).unwrap();
+    assert!(strict.check_rules().is_err());
+}
+
 #[test]
 fn test_rules_file_column_pattern_applies_mutation() {
     let rules = r#"{
@@ -790,3 +1186,3047 @@ fn test_rules_file_unknown_mutation_in_column_pattern_errors() {
     let mut proc = make_processor();
     assert!(proc.load_rules(rules).is_err());
 }
+
+#[test]
+fn test_dump_io_round_trips_8_byte_int() {
+    use pg_stage_rs::format::custom::io::DumpIO;
+
+    let dio = DumpIO::new(8, 8);
+    for value in [i32::MAX as i64 + 1, 5_000_000_000, -5_000_000_000, 0, 42] {
+        let mut buf = Vec::new();
+        dio.write_int(&mut buf, value).unwrap();
+        assert_eq!(buf.len(), 9);
+        assert_eq!(dio.read_int(&mut buf.as_slice()).unwrap(), value);
+        let mut bypass = Vec::new();
+        assert_eq!(dio.read_int_bypass(&mut buf.as_slice(), &mut bypass).unwrap(), value);
+        assert_eq!(bypass, buf);
+    }
+}
+
+#[cfg(not(feature = "zstd"))]
+#[test]
+fn test_custom_block_with_disabled_backend_errors() {
+    use pg_stage_rs::error::PgStageError;
+    use pg_stage_rs::format::custom::blocks::BlockProcessor;
+    use pg_stage_rs::format::custom::header::CompressionMethod;
+    use pg_stage_rs::format::custom::io::DumpIO;
+
+    let dio = DumpIO::new(4, 8);
+    let mut block = Vec::new();
+    dio.write_int(&mut block, 0).unwrap();
+    let mut proc = make_processor();
+    let mut bp = BlockProcessor::new(&dio, CompressionMethod::Zstd, &mut proc, 1, 0);
+    let mut out = Vec::new();
+    let err = bp.process_block(&mut block.as_slice(), &mut out).unwrap_err();
+    assert!(matches!(err, PgStageError::UnsupportedVersion(_)));
+    assert!(out.is_empty());
+}
+
+#[test]
+fn test_deterministic_mode_is_byte_reproducible() {
+    let input = concat!(
+        "COMMENT ON COLUMN public.users.name IS 'anon: [{\"mutation_name\": \"full_name\"}]';\n",
+        "COMMENT ON COLUMN public.users.email IS 'anon: [{\"mutation_name\": \"email\", \"mutation_kwargs\": {\"unique\": true}}]';\n",
+        "COMMENT ON COLUMN public.users.born IS 'anon: [{\"mutation_name\": \"date\"}]';\n",
+        "COMMENT ON COLUMN public.users.token IS 'anon: [{\"mutation_name\": \"uuid4\"}]';\n",
+        "COMMENT ON COLUMN public.users.ext_id IS 'anon: [{\"mutation_name\": \"uuid5_by_source_value\", \"mutation_kwargs\": {\"namespace\": \"6ba7b810-9dad-11d1-80b4-00c04fd430c8\", \"source_column\": \"email\"}}]';\n",
+        "COMMENT ON COLUMN public.orders.id IS 'anon: [{\"mutation_name\": \"uuid7\"}]';\n",
+        "COMMENT ON COLUMN public.orders.total IS 'anon: [{\"mutation_name\": \"numeric_integer\", \"mutation_kwargs\": {\"start\": 1, \"end\": 1000000}}]';\n",
+        "COPY public.users (id, name, email, born, token, ext_id) FROM stdin;\n",
+        "1\tAlice Smith\talice@example.com\t1990-01-01\tx\tx\n",
+        "2\tBob Jones\tbob@example.com\t1985-05-05\tx\tx\n",
+        "\\.\n",
+        "COPY public.orders (id, total) FROM stdin;\n",
+        "x\t10\n",
+        "y\t20\n",
+        "\\.\n",
+    );
+    let run = |seed: Option<u64>| {
+        let mut processor = make_processor();
+        if let Some(seed) = seed {
+            processor.set_seed(seed);
+        }
+        processor.set_deterministic(true);
+        let mut output = Vec::new();
+        let mut handler = PlainHandler::new(processor);
+        handler.process(Cursor::new(b""), &mut output, input.as_bytes()).unwrap();
+        output
+    };
+    let first = run(None);
+    assert_eq!(first, run(None));
+    assert_eq!(run(Some(7)), run(Some(7)));
+    assert_ne!(first, run(Some(7)));
+    let result = String::from_utf8(first).unwrap();
+    assert!(!result.contains("alice@example.com"));
+    assert!(result.contains("\t2024-") || result.contains("\t2023-"));
+}
+
+#[test]
+fn test_numeric_degenerate_ranges() {
+    let input = concat!(
+        "COMMENT ON COLUMN public.t.same IS 'anon: [{\"mutation_name\": \"numeric_integer\", \"mutation_kwargs\": {\"start\": 7, \"end\": 7}}]';\n",
+        "COMMENT ON COLUMN public.t.inverted IS 'anon: [{\"mutation_name\": \"numeric_smallint\", \"mutation_kwargs\": {\"start\": 50, \"end\": 10}}]';\n",
+        "COMMENT ON COLUMN public.t.dec_same IS 'anon: [{\"mutation_name\": \"numeric_decimal\", \"mutation_kwargs\": {\"start\": 5, \"end\": 5}}]';\n",
+        "COMMENT ON COLUMN public.t.real_inverted IS 'anon: [{\"mutation_name\": \"numeric_real\", \"mutation_kwargs\": {\"start\": 2.5, \"end\": 1.5}}]';\n",
+        "COMMENT ON COLUMN public.t.dbl_same IS 'anon: [{\"mutation_name\": \"numeric_double_precision\", \"mutation_kwargs\": {\"start\": -1, \"end\": -1}}]';\n",
+        "COPY public.t (id, same, inverted, dec_same, real_inverted, dbl_same) FROM stdin;\n",
+        "1\t0\t0\t0\t0\t0\n",
+        "2\t0\t0\t0\t0\t0\n",
+        "\\.\n",
+    );
+    let mut output = Vec::new();
+    let mut handler = PlainHandler::new(make_processor());
+    handler.process(Cursor::new(b""), &mut output, input.as_bytes()).unwrap();
+    let result = String::from_utf8(output).unwrap();
+    for id in ["1", "2"] {
+        let row = result.lines().find(|l| l.starts_with(&format!("{}\t", id))).unwrap();
+        let cols: Vec<&str> = row.split('\t').collect();
+        assert_eq!(cols[1], "7");
+        let inverted: i64 = cols[2].parse().unwrap();
+        assert!((10..=50).contains(&inverted));
+        assert_eq!(cols[3], "5.00");
+        let real: f64 = cols[4].parse().unwrap();
+        assert!((1.5..2.5).contains(&real));
+        assert_eq!(cols[5], "-1.000000000000000");
+    }
+}
+
+#[test]
+fn test_date_inverted_years_and_narrow_window() {
+    let input = concat!(
+        "COMMENT ON COLUMN public.t.inverted IS 'anon: [{\"mutation_name\": \"date\", \"mutation_kwargs\": {\"start\": 2010, \"end\": 2005}}]';\n",
+        "COMMENT ON COLUMN public.t.window IS 'anon: [{\"mutation_name\": \"date\", \"mutation_kwargs\": {\"start_date\": \"2021-02-27\", \"end_date\": \"2021-03-01\"}}]';\n",
+        "COPY public.t (id, inverted, window) FROM stdin;\n",
+        "1\t2000-01-01\t2000-01-01\n",
+        "2\t2000-01-01\t2000-01-01\n",
+        "3\t2000-01-01\t2000-01-01\n",
+        "\\.\n",
+    );
+    let mut output = Vec::new();
+    let mut handler = PlainHandler::new(make_processor());
+    handler.process(Cursor::new(b""), &mut output, input.as_bytes()).unwrap();
+    let result = String::from_utf8(output).unwrap();
+    for id in ["1", "2", "3"] {
+        let row = result.lines().find(|l| l.starts_with(&format!("{}\t", id))).unwrap();
+        let cols: Vec<&str> = row.split('\t').collect();
+        let year: i32 = cols[1][..4].parse().unwrap();
+        assert!((2005..=2010).contains(&year));
+        assert!(["2021-02-27", "2021-02-28", "2021-03-01"].contains(&cols[2]));
+    }
+}
+
+#[test]
+fn test_ipv4_within_cidr() {
+    use std::net::Ipv4Addr;
+
+    let input = concat!(
+        "COMMENT ON COLUMN public.hosts.a IS 'anon: [{\"mutation_name\": \"ipv4\", \"mutation_kwargs\": {\"cidr\": \"10.0.0.0/8\"}}]';\n",
+        "COMMENT ON COLUMN public.hosts.b IS 'anon: [{\"mutation_name\": \"ipv4\", \"mutation_kwargs\": {\"cidr\": \"192.168.7.99/24\"}}]';\n",
+        "COMMENT ON COLUMN public.hosts.c IS 'anon: [{\"mutation_name\": \"ipv4\", \"mutation_kwargs\": {\"cidr\": \"172.16.0.4/30\"}}]';\n",
+        "COMMENT ON COLUMN public.hosts.d IS 'anon: [{\"mutation_name\": \"ipv4\", \"mutation_kwargs\": {\"cidr\": \"8.8.8.8/32\"}}]';\n",
+        "COMMENT ON COLUMN public.hosts.bad IS 'anon: [{\"mutation_name\": \"ipv4\", \"mutation_kwargs\": {\"cidr\": \"10.0.0.0/33\"}}]';\n",
+        "COPY public.hosts (id, a, b, c, d, bad) FROM stdin;\n",
+        "1\t1.1.1.1\t1.1.1.1\t1.1.1.1\t1.1.1.1\t1.1.1.1\n",
+        "2\t1.1.1.1\t1.1.1.1\t1.1.1.1\t1.1.1.1\t1.1.1.1\n",
+        "3\t1.1.1.1\t1.1.1.1\t1.1.1.1\t1.1.1.1\t1.1.1.1\n",
+        "\\.\n",
+    );
+    let mut output = Vec::new();
+    let mut handler = PlainHandler::new(make_processor());
+    handler.process(Cursor::new(b""), &mut output, input.as_bytes()).unwrap();
+    let result = String::from_utf8(output).unwrap();
+    for id in ["1", "2", "3"] {
+        let row = result.lines().find(|l| l.starts_with(&format!("{}\t", id))).unwrap();
+        let ips: Vec<Ipv4Addr> = row.split('\t').skip(1).take(4).map(|c| c.parse().unwrap()).collect();
+        assert_eq!(ips[0].octets()[0], 10);
+        assert_eq!(ips[1].octets()[..3], [192, 168, 7]);
+        assert!(!matches!(ips[1].octets()[3], 0 | 255));
+        assert!([Ipv4Addr::new(172, 16, 0, 5), Ipv4Addr::new(172, 16, 0, 6)].contains(&ips[2]));
+        assert_eq!(ips[3], Ipv4Addr::new(8, 8, 8, 8));
+        assert!(row.ends_with("\t1.1.1.1"), "malformed cidr leaves the value unchanged");
+    }
+}
+
+#[test]
+fn test_ipv6_subnet_and_compression() {
+    use std::net::Ipv6Addr;
+
+    let input = concat!(
+        "COMMENT ON COLUMN public.hosts.full IS 'anon: [{\"mutation_name\": \"ipv6\", \"mutation_kwargs\": {\"subnet\": \"2001:db8::/32\"}}]';\n",
+        "COMMENT ON COLUMN public.hosts.short IS 'anon: [{\"mutation_name\": \"ipv6\", \"mutation_kwargs\": {\"subnet\": \"fd00:1:2:3::/120\", \"compress\": true}}]';\n",
+        "COPY public.hosts (id, full, short) FROM stdin;\n",
+        "1\t::1\t::1\n",
+        "2\t::1\t::1\n",
+        "3\t::1\t::1\n",
+        "\\.\n",
+    );
+    let mut output = Vec::new();
+    let mut handler = PlainHandler::new(make_processor());
+    handler.process(Cursor::new(b""), &mut output, input.as_bytes()).unwrap();
+    let result = String::from_utf8(output).unwrap();
+    for id in ["1", "2", "3"] {
+        let row = result.lines().find(|l| l.starts_with(&format!("{}\t", id))).unwrap();
+        let cols: Vec<&str> = row.split('\t').collect();
+
+        assert_eq!(cols[1].len(), 39);
+        assert!(cols[1].starts_with("2001:0db8:"));
+        let full: Ipv6Addr = cols[1].parse().unwrap();
+        assert_eq!(full.segments()[..2], [0x2001, 0x0db8]);
+
+        let short: Ipv6Addr = cols[2].parse().unwrap();
+        assert_eq!(short.to_string(), cols[2]);
+        assert_eq!(short.segments()[..7], [0xfd00, 1, 2, 3, 0, 0, 0]);
+        assert!(short.segments()[7] <= 0xff);
+    }
+}
+
+#[test]
+fn test_boolean_literals_and_probability() {
+    let mut input = String::from(concat!(
+        "COMMENT ON COLUMN public.flags.short IS 'anon: [{\"mutation_name\": \"boolean\"}]';\n",
+        "COMMENT ON COLUMN public.flags.long IS 'anon: [{\"mutation_name\": \"boolean\", \"mutation_kwargs\": {\"style\": \"long\", \"true_probability\": 0.9}}]';\n",
+        "COPY public.flags (id, short, long) FROM stdin;\n",
+    ));
+    for i in 0..1000 {
+        input.push_str(&format!("{}\tx\tx\n", i));
+    }
+    input.push_str("\\.\n");
+    let mut output = Vec::new();
+    let mut handler = PlainHandler::new(make_processor());
+    handler.process(Cursor::new(b""), &mut output, input.as_bytes()).unwrap();
+    let result = String::from_utf8(output).unwrap();
+    let rows: Vec<Vec<&str>> = result
+        .lines()
+        .filter(|l| l.as_bytes().first().is_some_and(u8::is_ascii_digit))
+        .map(|l| l.split('\t').collect())
+        .collect();
+    assert_eq!(rows.len(), 1000);
+    assert!(rows.iter().all(|r| r[1] == "t" || r[1] == "f"));
+    assert!(rows.iter().all(|r| r[2] == "true" || r[2] == "false"));
+    let trues = rows.iter().filter(|r| r[2] == "true").count();
+    assert!(trues > 800, "expected ~900 trues, got {}", trues);
+}
+
+#[test]
+fn test_json_field_mutation() {
+    let input = concat!(
+        "COMMENT ON COLUMN public.users.profile IS 'anon: [{\"mutation_name\": \"json\", \"mutation_kwargs\": {\"fields\": {\"$.email\": {\"mutation_name\": \"email\"}, \"/contacts/1\": {\"mutation_name\": \"fixed_value\", \"mutation_kwargs\": {\"value\": \"hidden\"}}, \"$.missing\": {\"mutation_name\": \"email\"}}}}]';\n",
+        "COPY public.users (id, profile) FROM stdin;\n",
+        "1\t{\"age\": 30, \"contacts\": [\"a\", \"b\"], \"email\": \"john@example.com\", \"note\": \"line1\\\\nline2\"}\n",
+        "2\t\\N\n",
+        "\\.\n",
+    );
+    let mut output = Vec::new();
+    let mut handler = PlainHandler::new(make_processor());
+    handler.process(Cursor::new(b""), &mut output, input.as_bytes()).unwrap();
+    let result = String::from_utf8(output).unwrap();
+    let row = result.lines().find(|l| l.starts_with("1\t")).unwrap();
+    // Re-read the COPY cell: `\\` is the only escape in this row.
+    let cell = row.split('\t').nth(1).unwrap().replace("\\\\", "\\");
+    let json: serde_json::Value = serde_json::from_str(&cell).unwrap();
+    let email = json["email"].as_str().unwrap();
+    assert_ne!(email, "john@example.com");
+    assert!(email.contains('@'));
+    assert_eq!(json["age"], 30);
+    assert_eq!(json["contacts"], serde_json::json!(["a", "hidden"]));
+    assert_eq!(json["note"], "line1\nline2");
+    assert!(json.get("missing").is_none());
+    assert!(result.contains("2\t\\N\n"));
+}
+
+#[test]
+fn test_array_element_mutation() {
+    let input = concat!(
+        "COMMENT ON COLUMN public.users.tags IS 'anon: [{\"mutation_name\": \"array\", \"mutation_kwargs\": {\"element\": {\"mutation_name\": \"fixed_value\", \"mutation_kwargs\": {\"value\": \"a, b\"}}}}]';\n",
+        "COMMENT ON COLUMN public.users.names IS 'anon: [{\"mutation_name\": \"array\", \"mutation_kwargs\": {\"element\": {\"mutation_name\": \"first_name\"}}}]';\n",
+        "COPY public.users (id, tags, names) FROM stdin;\n",
+        "1\t{x,y}\t{x,y}\n",
+        "2\t{}\t{\"x,1\",NULL,{y}}\n",
+        "\\.\n",
+    );
+    let mut output = Vec::new();
+    let mut handler = PlainHandler::new(make_processor());
+    handler.process(Cursor::new(b""), &mut output, input.as_bytes()).unwrap();
+    let result = String::from_utf8(output).unwrap();
+
+    let row = result.lines().find(|l| l.starts_with("1\t")).unwrap();
+    let cols: Vec<&str> = row.split('\t').collect();
+    assert_eq!(cols[1], r#"{"a, b","a, b"}"#);
+    let names: Vec<&str> = cols[2].trim_matches(|c| c == '{' || c == '}').split(',').collect();
+    assert_eq!(names.len(), 2);
+    assert!(names.iter().all(|n| *n != "x" && !n.is_empty()));
+
+    let row = result.lines().find(|l| l.starts_with("2\t")).unwrap();
+    let cols: Vec<&str> = row.split('\t').collect();
+    assert_eq!(cols[1], "{}");
+    assert!(cols[2].starts_with('{') && cols[2].contains(",NULL,{") && cols[2].ends_with("}}"));
+    assert!(!cols[2].contains("x,1"));
+}
+
+#[test]
+fn test_money_format() {
+    let input = concat!(
+        "COMMENT ON COLUMN public.orders.usd IS 'anon: [{\"mutation_name\": \"money\", \"mutation_kwargs\": {\"start\": 1000, \"end\": 999999, \"symbol\": \"$\", \"thousands_separator\": \",\"}}]';\n",
+        "COMMENT ON COLUMN public.orders.rub IS 'anon: [{\"mutation_name\": \"money\", \"mutation_kwargs\": {\"start\": 10, \"end\": 20, \"symbol\": \"₽\", \"symbol_position\": \"suffix\", \"unique\": true}}]';\n",
+        "COMMENT ON COLUMN public.orders.plain IS 'anon: [{\"mutation_name\": \"money\", \"mutation_kwargs\": {\"start\": 5, \"end\": 5}}]';\n",
+        "COPY public.orders (id, usd, rub, plain) FROM stdin;\n",
+        "1\t0\t0\t0\n",
+        "2\t0\t0\t0\n",
+        "3\t0\t0\t0\n",
+        "\\.\n",
+    );
+    let mut output = Vec::new();
+    let mut handler = PlainHandler::new(make_processor());
+    handler.process(Cursor::new(b""), &mut output, input.as_bytes()).unwrap();
+    let result = String::from_utf8(output).unwrap();
+    let mut rubs = Vec::new();
+    for id in ["1", "2", "3"] {
+        let row = result.lines().find(|l| l.starts_with(&format!("{}\t", id))).unwrap();
+        let cols: Vec<&str> = row.split('\t').collect();
+
+        let usd = cols[1].strip_prefix('$').unwrap();
+        let (units, cents) = usd.split_once('.').unwrap();
+        assert_eq!(cents.len(), 2);
+        assert!(units.contains(','));
+        let value: f64 = format!("{}.{}", units.replace(',', ""), cents).parse().unwrap();
+        assert!((1000.0..=999999.0).contains(&value));
+
+        let rub = cols[2].strip_suffix(" ₽").unwrap();
+        assert_eq!(rub.split_once('.').unwrap().1.len(), 2);
+        let value: f64 = rub.parse().unwrap();
+        assert!((10.0..=20.0).contains(&value));
+        rubs.push(rub.to_string());
+
+        assert_eq!(cols[3], "5.00");
+    }
+    rubs.sort();
+    rubs.dedup();
+    assert_eq!(rubs.len(), 3);
+}
+
+#[test]
+fn test_numeric_jitter_window() {
+    let input = concat!(
+        "COMMENT ON COLUMN public.orders.qty IS 'anon: [{\"mutation_name\": \"numeric_jitter\"}]';\n",
+        "COMMENT ON COLUMN public.orders.price IS 'anon: [{\"mutation_name\": \"numeric_jitter\", \"mutation_kwargs\": {\"absolute\": 0.5}}]';\n",
+        "COPY public.orders (id, qty, price) FROM stdin;\n",
+        "1\t1000\t19.990\n",
+        "2\t-200\t5.5\n",
+        "3\tabc\t\\N\n",
+        "\\.\n",
+    );
+    let mut output = Vec::new();
+    let mut handler = PlainHandler::new(make_processor());
+    handler.process(Cursor::new(b""), &mut output, input.as_bytes()).unwrap();
+    let result = String::from_utf8(output).unwrap();
+    let cols = |id: &str| -> Vec<String> {
+        let row = result.lines().find(|l| l.starts_with(&format!("{}\t", id))).unwrap();
+        row.split('\t').map(str::to_string).collect()
+    };
+
+    let row = cols("1");
+    let qty: i64 = row[1].parse().unwrap();
+    assert!((900..=1100).contains(&qty));
+    let price: f64 = row[2].parse().unwrap();
+    assert!((19.49..=20.49).contains(&price));
+    assert_eq!(row[2].split_once('.').unwrap().1.len(), 3);
+
+    let row = cols("2");
+    let qty: i64 = row[1].parse().unwrap();
+    assert!((-220..=-180).contains(&qty));
+    let price: f64 = row[2].parse().unwrap();
+    assert!((5.0..=6.0).contains(&price));
+
+    assert_eq!(cols("3"), ["3", "abc", "\\N"]);
+}
+
+#[test]
+fn test_custom_mutator_registration() {
+    use pg_stage_rs::error::Result;
+    use pg_stage_rs::mutator::{MutationContext, Mutator};
+
+    struct Shout;
+    impl Mutator for Shout {
+        fn generate(&self, ctx: &mut MutationContext) -> Result<String> {
+            Ok(ctx.current_value.to_uppercase())
+        }
+    }
+
+    let mut proc = make_processor();
+    proc.register_mutator("shout", Shout);
+    // Custom mutators shadow built-ins of the same name.
+    proc.register_mutator("email", Shout);
+    proc.parse_comment(
+        "COMMENT ON COLUMN public.users.name IS 'anon: [{\"mutation_name\": \"shout\"}]';",
+    );
+    proc.parse_comment(
+        "COMMENT ON COLUMN public.users.email IS 'anon: [{\"mutation_name\": \"email\"}]';",
+    );
+    assert_eq!(proc.parse_warnings(), (0, 0));
+    assert!(proc.setup_table("COPY public.users (id, name, email) FROM stdin;"));
+    let out = proc.process_line(b"1\talice\ta@example.com").unwrap();
+    assert_eq!(out, b"1\tALICE\tA@EXAMPLE.COM");
+}
+
+#[test]
+fn test_custom_mutator_in_nested_specs() {
+    use pg_stage_rs::error::Result;
+    use pg_stage_rs::mutator::{MutationContext, Mutator};
+
+    struct Shout;
+    impl Mutator for Shout {
+        fn generate(&self, ctx: &mut MutationContext) -> Result<String> {
+            Ok(ctx.current_value.to_uppercase())
+        }
+    }
+
+    let mut proc = make_processor();
+    proc.register_mutator("shout", Shout);
+    for comment in [
+        "COMMENT ON COLUMN public.t.a IS 'anon: [{\"mutation_name\": \"partial\", \"mutation_kwargs\": {\"probability\": 1.0, \"mutation\": {\"mutation_name\": \"shout\"}}}]';",
+        "COMMENT ON COLUMN public.t.b IS 'anon: [{\"mutation_name\": \"array\", \"mutation_kwargs\": {\"element\": {\"mutation_name\": \"shout\"}}}]';",
+        "COMMENT ON COLUMN public.t.c IS 'anon: [{\"mutation_name\": \"json_update\", \"mutation_kwargs\": {\"k\": {\"mutation_name\": \"shout\"}}}]';",
+        "COMMENT ON COLUMN public.t.d IS 'anon: [{\"mutation_name\": \"template\", \"mutation_kwargs\": {\"pattern\": \"<{shout}>\"}}]';",
+    ] {
+        assert!(proc.parse_comment(comment));
+    }
+    assert!(proc.setup_table("COPY public.t (a, b, c, d) FROM stdin;"));
+    let out = proc.process_line(b"x\t{y,z}\t{\"k\": \"v\"}\tw").unwrap();
+    assert_eq!(out, b"X\t{Y,Z}\t{\"k\":\"V\"}\t<W>");
+}
+
+#[test]
+fn test_processor_builder_with_injected_secrets() {
+    use pg_stage_rs::processor::ProcessorConfig;
+
+    let build = || {
+        DataProcessor::builder()
+            .locale(Locale::Ru)
+            .delimiter(b'|')
+            .delete_patterns(vec![regex::Regex::new(r"^public\.logs$").unwrap()])
+            .secret("SECRET_KEY", "builder-key")
+            .secret("SECRET_KEY_NONCE", "builder-nonce")
+            .seed(11)
+            .config(ProcessorConfig {
+                strict: true,
+                ..Default::default()
+            })
+            .build()
+    };
+    let run = |mut proc: DataProcessor| {
+        proc.parse_comment(
+            "COMMENT ON COLUMN public.users.ip IS 'anon: [{\"mutation_name\": \"deterministic_ipv4\"}]';",
+        );
+        proc.parse_comment(
+            "COMMENT ON COLUMN public.users.name IS 'anon: [{\"mutation_name\": \"first_name\"}]';",
+        );
+        assert!(proc.setup_table("COPY public.users (id, ip, name) FROM stdin;"));
+        let out = String::from_utf8(proc.process_line(b"1|10.1.2.3|Bob").unwrap().to_vec()).unwrap();
+        proc.setup_table("COPY public.logs (id) FROM stdin;");
+        assert!(proc.is_delete());
+        out
+    };
+
+    let first = run(build());
+    let cols: Vec<&str> = first.split('|').collect();
+    assert_eq!(cols.len(), 3);
+    assert!(cols[1].starts_with("10.1.2."));
+    assert_ne!(cols[1], "10.1.2.3");
+    assert!(cols[2].chars().all(|c| !c.is_ascii_alphabetic()), "Russian locale name");
+    assert_eq!(first, run(build()));
+}
+
+#[test]
+fn test_injected_seeded_rng_is_reproducible() {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    let run = || {
+        let mut proc = DataProcessor::with_rng(Locale::En, b'\t', vec![], StdRng::seed_from_u64(99));
+        proc.parse_comment(
+            "COMMENT ON COLUMN public.users.email IS 'anon: [{\"mutation_name\": \"email\"}]';",
+        );
+        proc.setup_table("COPY public.users (id, email) FROM stdin;");
+        let mut out = Vec::new();
+        for line in [&b"1\ta@example.com"[..], b"2\tb@example.com"] {
+            out.extend_from_slice(proc.process_line(line).unwrap());
+            out.push(b'\n');
+        }
+        String::from_utf8(out).unwrap()
+    };
+    let first = run();
+    assert!(!first.contains("a@example.com"));
+    assert_eq!(first, run());
+}
+
+#[test]
+fn test_mutate_value_api() {
+    let mut proc = make_processor();
+    proc.parse_comment(
+        "COMMENT ON COLUMN public.users.email IS 'anon: [{\"mutation_name\": \"fixed_value\", \"mutation_kwargs\": {\"value\": \"support@example.com\"}, \"conditions\": [{\"column_name\": \"email\", \"operation\": \"equal\", \"value\": \"support@corp.com\"}]}, {\"mutation_name\": \"email\"}]';",
+    );
+
+    let mutated = proc.mutate_value("public.users", "email", "john@corp.com").unwrap();
+    assert_ne!(mutated, "john@corp.com");
+    assert!(mutated.contains('@'));
+    assert_eq!(
+        proc.mutate_value("public.users", "email", "support@corp.com").as_deref(),
+        Some("support@example.com")
+    );
+    assert_eq!(proc.mutate_value("public.users", "name", "John"), None);
+    assert_eq!(proc.mutate_value("public.orders", "email", "x@y.z"), None);
+}
+
+#[test]
+fn test_relations_into_same_table_keep_columns_apart() {
+    let rel = |column: &str| {
+        format!(
+            "\"relations\": [{{\"table_name\": \"users\", \"column_name\": \"{}\", \"from_column_name\": \"user_id\", \"to_column_name\": \"id\"}}]",
+            column
+        )
+    };
+    let input = format!(
+        concat!(
+            "COMMENT ON COLUMN public.orders.customer_email IS 'anon: [{{\"mutation_name\": \"email\", {}}}]';\n",
+            "COMMENT ON COLUMN public.orders.customer_name IS 'anon: [{{\"mutation_name\": \"first_name\", {}}}]';\n",
+            "COPY public.orders (id, user_id, customer_email, customer_name) FROM stdin;\n",
+            "1\t7\ta@example.com\tAlice\n",
+            "2\t7\ta@example.com\tAlice\n",
+            "\\.\n",
+        ),
+        rel("email"),
+        rel("first_name"),
+    );
+    let mut output = Vec::new();
+    let mut handler = PlainHandler::new(make_processor());
+    handler.process(Cursor::new(b""), &mut output, input.as_bytes()).unwrap();
+    let result = String::from_utf8(output).unwrap();
+    let rows: Vec<Vec<&str>> = result
+        .lines()
+        .filter(|l| l.starts_with("1\t") || l.starts_with("2\t"))
+        .map(|l| l.split('\t').collect())
+        .collect();
+    assert_eq!(rows.len(), 2);
+    assert!(rows[0][2].contains('@'));
+    assert!(!rows[0][3].contains('@'), "name took the email relation: {}", rows[0][3]);
+    assert_eq!(rows[0][2], rows[1][2]);
+    assert_eq!(rows[0][3], rows[1][3]);
+}
+
+#[test]
+fn test_plain_condition_iequal_matches_mixed_case() {
+    let input = concat!(
+        "COMMENT ON COLUMN public.users.email IS 'anon: [{\"mutation_name\": \"null\", \"conditions\": [{\"column_name\": \"role\", \"operation\": \"iequal\", \"value\": \"Admin\"}]}]';\n",
+        "COPY public.users (id, role, email) FROM stdin;\n",
+        "1\tadmin\ta@example.com\n",
+        "2\tADMIN\tb@example.com\n",
+        "3\tÄdmin\tc@example.com\n",
+        "4\tuser\td@example.com\n",
+        "\\.\n",
+    );
+    let mut output = Vec::new();
+    let mut handler = PlainHandler::new(make_processor());
+    handler.process(Cursor::new(b""), &mut output, input.as_bytes()).unwrap();
+    let result = String::from_utf8(output).unwrap();
+    assert!(result.contains("1\tadmin\t\\N\n"));
+    assert!(result.contains("2\tADMIN\t\\N\n"));
+    assert!(result.contains("3\tÄdmin\tc@example.com\n"));
+    assert!(result.contains("4\tuser\td@example.com\n"));
+}
+
+#[test]
+fn test_plain_condition_inot_equal_unicode_case_folding() {
+    let input = concat!(
+        "COMMENT ON COLUMN public.users.email IS 'anon: [{\"mutation_name\": \"null\", \"conditions\": [{\"column_name\": \"role\", \"operation\": \"inot_equal\", \"value\": \"Админ\"}]}]';\n",
+        "COPY public.users (id, role, email) FROM stdin;\n",
+        "1\tАДМИН\ta@example.com\n",
+        "2\tадмин\tb@example.com\n",
+        "3\tuser\tc@example.com\n",
+        "\\.\n",
+    );
+    let mut output = Vec::new();
+    let mut handler = PlainHandler::new(make_processor());
+    handler.process(Cursor::new(b""), &mut output, input.as_bytes()).unwrap();
+    let result = String::from_utf8(output).unwrap();
+    assert!(result.contains("1\tАДМИН\ta@example.com\n"));
+    assert!(result.contains("2\tадмин\tb@example.com\n"));
+    assert!(result.contains("3\tuser\t\\N\n"));
+}
+
+fn run_condition(operation: &str, value: &str, emails: &[&str]) -> Vec<bool> {
+    let mut input = format!(
+        "COMMENT ON COLUMN public.users.name IS 'anon: [{{\"mutation_name\": \"null\", \"conditions\": [{{\"column_name\": \"email\", \"operation\": \"{}\", \"value\": \"{}\"}}]}}]';\nCOPY public.users (email, name) FROM stdin;\n",
+        operation, value
+    );
+    for email in emails {
+        input.push_str(&format!("{}\tkeep\n", email));
+    }
+    input.push_str("\\.\n");
+    let mut output = Vec::new();
+    let mut handler = PlainHandler::new(make_processor());
+    handler.process(Cursor::new(b""), &mut output, input.as_bytes()).unwrap();
+    let result = String::from_utf8(output).unwrap();
+    emails
+        .iter()
+        .map(|email| result.contains(&format!("{}\t\\N\n", email)))
+        .collect()
+}
+
+#[test]
+fn test_plain_condition_contains() {
+    assert_eq!(
+        run_condition("contains", "qa", &["qa.bot@x.com", "team-qa@x.com", "QA@x.com", "dev@x.com"]),
+        vec![true, true, false, false]
+    );
+}
+
+#[test]
+fn test_plain_condition_starts_with() {
+    assert_eq!(
+        run_condition("starts_with", "test_", &["test_1@x.com", "a_test_@x.com", "Test_2@x.com"]),
+        vec![true, false, false]
+    );
+}
+
+#[test]
+fn test_plain_condition_ends_with() {
+    assert_eq!(
+        run_condition(
+            "ends_with",
+            "@internal.com",
+            &["a@internal.com", "b@internal.com.evil", "c@INTERNAL.COM", "d@example.com"]
+        ),
+        vec![true, false, false, false]
+    );
+}
+
+#[test]
+fn test_plain_condition_by_pattern_reused_across_rows() {
+    let emails: Vec<String> = (0..500)
+        .map(|i| {
+            if i % 3 == 0 {
+                format!("user{}@internal.com", i)
+            } else {
+                format!("user{}@example.com", i)
+            }
+        })
+        .collect();
+    let refs: Vec<&str> = emails.iter().map(String::as_str).collect();
+    let matched = run_condition("by_pattern", "^user[0-9]+@internal\\\\.com$", &refs);
+    for (i, m) in matched.iter().enumerate() {
+        assert_eq!(*m, i % 3 == 0, "row {}", i);
+    }
+}
+
+#[test]
+fn test_invalid_by_pattern_rejected_at_parse_time() {
+    let rules = r#"{
+        "column_patterns": [
+            { "table": "^public\\.users$", "column": "^name$",
+              "mutations": [{ "mutation_name": "null", "mutation_kwargs": {}, "relations": [],
+                              "conditions": [{ "column_name": "email", "operation": "by_pattern", "value": "(unclosed" }] }] }
+        ]
+    }"#;
+    let mut proc = make_processor();
+    assert!(proc.load_rules(rules).is_err());
+}
+
+#[test]
+fn test_credit_card_mask_keeps_last_four() {
+    let mut proc = make_processor();
+    proc.parse_comment(
+        "COMMENT ON COLUMN public.payments.card IS 'anon: [{\"mutation_name\": \"credit_card_mask\"}]';",
+    );
+    proc.parse_comment(
+        "COMMENT ON COLUMN public.payments.pan IS 'anon: [{\"mutation_name\": \"credit_card_mask\", \"mutation_kwargs\": {\"mask_char\": \"X\"}}]';",
+    );
+    let mask = |proc: &mut DataProcessor, column: &str, value: &str| {
+        proc.mutate_value("public.payments", column, value).unwrap()
+    };
+    assert_eq!(mask(&mut proc, "card", "4111 1111 1111 1234"), "**** **** **** 1234");
+    assert_eq!(mask(&mut proc, "card", "5500-0000-0000-0004"), "****-****-****-0004");
+    assert_eq!(mask(&mut proc, "card", "4111111111111234"), "************1234");
+    assert_eq!(mask(&mut proc, "card", "12-3"), "**-*");
+    assert_eq!(mask(&mut proc, "pan", "4111 1111 1111 1234"), "XXXX XXXX XXXX 1234");
+}
+
+#[test]
+fn test_postal_code_per_locale_and_format() {
+    let comments = [
+        "COMMENT ON COLUMN public.addresses.zip IS 'anon: [{\"mutation_name\": \"postal_code\"}]';",
+        "COMMENT ON COLUMN public.addresses.zip4 IS 'anon: [{\"mutation_name\": \"postal_code\", \"mutation_kwargs\": {\"format\": \"#####-####\"}}]';",
+        "COMMENT ON COLUMN public.addresses.ca IS 'anon: [{\"mutation_name\": \"postal_code\", \"mutation_kwargs\": {\"format\": \"@#@ #@#\"}}]';",
+    ];
+    let mut en = make_processor();
+    let mut ru = make_ru_processor();
+    for comment in comments {
+        en.parse_comment(comment);
+        ru.parse_comment(comment);
+    }
+    for _ in 0..20 {
+        let zip = en.mutate_value("public.addresses", "zip", "90210").unwrap();
+        assert_eq!(zip.len(), 5);
+        assert!(zip.bytes().all(|b| b.is_ascii_digit()));
+
+        let index = ru.mutate_value("public.addresses", "zip", "101000").unwrap();
+        assert_eq!(index.len(), 6);
+        assert!(index.bytes().all(|b| b.is_ascii_digit()));
+
+        let zip4 = en.mutate_value("public.addresses", "zip4", "x").unwrap();
+        let (head, tail) = zip4.split_once('-').unwrap();
+        assert_eq!((head.len(), tail.len()), (5, 4));
+        assert!(head.bytes().chain(tail.bytes()).all(|b| b.is_ascii_digit()));
+
+        let ca: Vec<char> = ru.mutate_value("public.addresses", "ca", "x").unwrap().chars().collect();
+        assert_eq!(ca.len(), 7);
+        assert_eq!(ca[3], ' ');
+        for i in [0, 2, 5] {
+            assert!(ca[i].is_ascii_uppercase());
+        }
+        for i in [1, 4, 6] {
+            assert!(ca[i].is_ascii_digit());
+        }
+    }
+}
+
+#[test]
+fn test_username_lowercase_alphanumeric_within_cap() {
+    let mut proc = make_processor();
+    proc.parse_comment(
+        "COMMENT ON COLUMN public.users.login IS 'anon: [{\"mutation_name\": \"username\", \"mutation_kwargs\": {\"unique\": true}}]';",
+    );
+    proc.parse_comment(
+        "COMMENT ON COLUMN public.users.short IS 'anon: [{\"mutation_name\": \"username\", \"mutation_kwargs\": {\"max_length\": 6}}]';",
+    );
+    let mut seen = std::collections::HashSet::new();
+    for _ in 0..50 {
+        let login = proc.mutate_value("public.users", "login", "john_doe").unwrap();
+        assert_ne!(login, "john_doe");
+        assert!(login.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit()), "{}", login);
+        assert!(login.as_bytes()[0].is_ascii_lowercase());
+        assert!(login.as_bytes()[login.len() - 1].is_ascii_digit());
+        assert!(seen.insert(login));
+
+        let short = proc.mutate_value("public.users", "short", "john_doe").unwrap();
+        assert!(!short.is_empty() && short.len() <= 6, "{}", short);
+        assert!(short.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit()));
+    }
+}
+
+#[test]
+fn test_domain_uses_allowed_tlds() {
+    let mut proc = make_processor();
+    proc.parse_comment(
+        "COMMENT ON COLUMN public.sites.host IS 'anon: [{\"mutation_name\": \"domain\"}]';",
+    );
+    proc.parse_comment(
+        "COMMENT ON COLUMN public.sites.custom IS 'anon: [{\"mutation_name\": \"domain\", \"mutation_kwargs\": {\"tlds\": [\"io\", \".dev\"], \"unique\": true}}]';",
+    );
+    for (column, allowed) in [("host", &["com", "net", "org"][..]), ("custom", &["io", "dev"][..])] {
+        let mut seen = std::collections::HashSet::new();
+        for _ in 0..40 {
+            let domain = proc.mutate_value("public.sites", column, "corp.example").unwrap();
+            let (label, tld) = domain.split_once('.').unwrap();
+            assert!(!tld.contains('.'), "{}", domain);
+            assert!(allowed.contains(&tld), "{}", domain);
+            assert!(!label.is_empty());
+            assert!(label.bytes().all(|b| b.is_ascii_lowercase() || b == b'-'));
+            if column == "custom" {
+                assert!(seen.insert(domain));
+            }
+        }
+    }
+}
+
+#[test]
+fn test_hostname_labels_are_valid_dns_labels() {
+    for labels in [2usize, 4, 7] {
+        let mut proc = make_processor();
+        proc.parse_comment(&format!(
+            "COMMENT ON COLUMN public.servers.fqdn IS 'anon: [{{\"mutation_name\": \"hostname\", \"mutation_kwargs\": {{\"labels\": {}}}}}]';",
+            labels
+        ));
+        for _ in 0..20 {
+            let host = proc.mutate_value("public.servers", "fqdn", "db.corp.local").unwrap();
+            let parts: Vec<&str> = host.split('.').collect();
+            assert_eq!(parts.len(), labels, "{}", host);
+            for label in parts {
+                assert!(!label.is_empty() && label.len() <= 63, "{}", host);
+                assert!(label.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-'));
+                assert!(!label.starts_with('-') && !label.ends_with('-'), "{}", host);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_plain_copy_with_quoted_identifiers() {
+    let input = concat!(
+        "COMMENT ON COLUMN \"My Schema\".\"Users\".\"first name\" IS 'anon: [{\"mutation_name\": \"fixed_value\", \"mutation_kwargs\": {\"value\": \"Anon\"}}]';\n",
+        "COMMENT ON COLUMN \"My Schema\".\"Users\".\"say \"\"hi\"\", ok\" IS 'anon: [{\"mutation_name\": \"null\"}]';\n",
+        "COPY \"My Schema\".\"Users\" (id, \"first name\", \"say \"\"hi\"\", ok\") FROM stdin;\n",
+        "1\tAlice\thello\n",
+        "\\.\n",
+        "INSERT INTO \"My Schema\".\"Users\" (id, \"first name\", \"say \"\"hi\"\", ok\") VALUES (2, 'Bob', 'hey');\n",
+    );
+    let mut output = Vec::new();
+    let mut handler = PlainHandler::new(make_processor());
+    handler.process(Cursor::new(b""), &mut output, input.as_bytes()).unwrap();
+    let result = String::from_utf8(output).unwrap();
+    assert!(result.contains("1\tAnon\t\\N\n"), "{}", result);
+    assert!(result.contains("VALUES (2, 'Anon', NULL);"), "{}", result);
+
+    let mut proc = make_processor();
+    proc.parse_comment("COMMENT ON TABLE \"Audit\".\"Log Entries\" IS 'anon: {\"mutation_name\": \"delete\"}';");
+    proc.setup_table("COPY \"Audit\".\"Log Entries\" (id) FROM stdin;");
+    assert!(proc.is_delete());
+}
+
+#[test]
+fn test_plain_copy_without_column_list_uses_create_table() {
+    let input = concat!(
+        "CREATE TABLE public.users (\n",
+        "    id integer NOT NULL,\n",
+        "    \"full name\" text,\n",
+        "    name_upper text GENERATED ALWAYS AS (upper(\"full name\")) STORED,\n",
+        "    email text,\n",
+        "    CONSTRAINT email_check CHECK ((email <> ''::text))\n",
+        ");\n",
+        "COMMENT ON COLUMN public.users.email IS 'anon: [{\"mutation_name\": \"null\"}]';\n",
+        "COMMENT ON COLUMN public.orders.note IS 'anon: [{\"mutation_name\": \"null\"}]';\n",
+        "COPY public.users FROM stdin;\n",
+        "1\tAlice\talice@example.com\n",
+        "\\.\n",
+        "COPY public.orders FROM stdin;\n",
+        "7\tleave me\n",
+        "\\.\n",
+    );
+    let mut output = Vec::new();
+    let mut handler = PlainHandler::new(make_processor());
+    handler.process(Cursor::new(b""), &mut output, input.as_bytes()).unwrap();
+    let result = String::from_utf8(output).unwrap();
+    assert!(result.contains("CREATE TABLE public.users (\n    id integer NOT NULL,\n"));
+    assert!(result.contains("COPY public.users FROM stdin;\n1\tAlice\t\\N\n\\.\n"), "{}", result);
+    // No CREATE TABLE for orders: its rows pass through unchanged.
+    assert!(result.contains("COPY public.orders FROM stdin;\n7\tleave me\n\\.\n"), "{}", result);
+}
+
+#[test]
+fn test_plain_qualified_comment_matches_unqualified_copy() {
+    let input = concat!(
+        "COMMENT ON COLUMN public.users.email IS 'anon: [{\"mutation_name\": \"null\"}]';\n",
+        "COMMENT ON COLUMN accounts.token IS 'anon: [{\"mutation_name\": \"null\"}]';\n",
+        "COMMENT ON COLUMN app.profiles.bio IS 'anon: [{\"mutation_name\": \"null\"}]';\n",
+        "COPY users (id, email) FROM stdin;\n",
+        "1\ta@example.com\n",
+        "\\.\n",
+        "COPY public.accounts (id, token) FROM stdin;\n",
+        "2\tsecret\n",
+        "\\.\n",
+        "SET search_path = app, pg_catalog;\n",
+        "COPY profiles (id, bio) FROM stdin;\n",
+        "3\thello\n",
+        "\\.\n",
+        "COPY users (id, email) FROM stdin;\n",
+        "4\tapp@example.com\n",
+        "\\.\n",
+    );
+    let mut output = Vec::new();
+    let mut handler = PlainHandler::new(make_processor());
+    handler.process(Cursor::new(b""), &mut output, input.as_bytes()).unwrap();
+    let result = String::from_utf8(output).unwrap();
+    assert!(result.contains("1\t\\N\n"), "{}", result);
+    assert!(result.contains("2\t\\N\n"), "{}", result);
+    assert!(result.contains("3\t\\N\n"), "{}", result);
+    // After the search_path change, bare `users` is app.users, not public.users.
+    assert!(result.contains("4\tapp@example.com\n"), "{}", result);
+}
+
+#[test]
+fn test_include_and_exclude_table_filters() {
+    let input = concat!(
+        "COMMENT ON COLUMN billing.invoices.email IS 'anon: [{\"mutation_name\": \"null\"}]';\n",
+        "COMMENT ON COLUMN billing.country_lookup.name IS 'anon: [{\"mutation_name\": \"null\"}]';\n",
+        "COMMENT ON COLUMN public.users.email IS 'anon: [{\"mutation_name\": \"null\"}]';\n",
+        "COMMENT ON TABLE billing.audit IS 'anon: {\"mutation_name\": \"delete\"}';\n",
+        "COPY billing.invoices (id, email) FROM stdin;\n",
+        "1\ta@example.com\n",
+        "\\.\n",
+        "COPY billing.country_lookup (id, name) FROM stdin;\n",
+        "2\tGermany\n",
+        "\\.\n",
+        "COPY public.users (id, email) FROM stdin;\n",
+        "3\tu@example.com\n",
+        "\\.\n",
+        "COPY billing.audit (id) FROM stdin;\n",
+        "4\n",
+        "\\.\n",
+    );
+    let processor = DataProcessor::builder()
+        .include_tables(vec![regex::Regex::new(r"^billing\.").unwrap()])
+        .exclude_tables(vec![
+            regex::Regex::new(r"_lookup$").unwrap(),
+            regex::Regex::new(r"^billing\.audit$").unwrap(),
+        ])
+        .build();
+    let mut output = Vec::new();
+    let mut handler = PlainHandler::new(processor);
+    handler.process(Cursor::new(b""), &mut output, input.as_bytes()).unwrap();
+    let result = String::from_utf8(output).unwrap();
+    assert!(result.contains("1\t\\N\n"));
+    // Excluded, or not included: copied untouched, and never deleted.
+    assert!(result.contains("2\tGermany\n"));
+    assert!(result.contains("3\tu@example.com\n"));
+    assert!(result.contains("COPY billing.audit (id) FROM stdin;\n4\n\\.\n"));
+}
+
+#[test]
+fn test_gender_labels_and_name_coherence() {
+    let input = concat!(
+        "COMMENT ON COLUMN public.people.sex IS 'anon: [{\"mutation_name\": \"gender\", \"mutation_kwargs\": {\"values\": [\"М\", \"Ж\"]}}]';\n",
+        "COMMENT ON COLUMN public.people.first IS 'anon: [{\"mutation_name\": \"first_name\", \"mutation_kwargs\": {\"gender_column\": \"sex\"}}]';\n",
+        "COMMENT ON COLUMN public.people.fio IS 'anon: [{\"mutation_name\": \"full_name\", \"mutation_kwargs\": {\"gender_column\": \"sex\"}}]';\n",
+        "COPY public.people (first, fio, sex) FROM stdin;\n",
+    );
+    let mut input = input.to_string();
+    for _ in 0..30 {
+        input.push_str("Иван\tИванов Иван Иванович\tМ\n");
+    }
+    input.push_str("\\.\n");
+    let mut output = Vec::new();
+    let mut handler = PlainHandler::new(make_ru_processor());
+    handler.process(Cursor::new(b""), &mut output, input.as_bytes()).unwrap();
+    let result = String::from_utf8(output).unwrap();
+    let rows: Vec<Vec<&str>> = result
+        .lines()
+        .skip_while(|l| !l.starts_with("COPY "))
+        .skip(1)
+        .take_while(|l| *l != "\\.")
+        .map(|l| l.split('\t').collect())
+        .collect();
+    assert_eq!(rows.len(), 30);
+    let mut genders = std::collections::HashSet::new();
+    for row in rows {
+        let (first, fio, sex) = (row[0], row[1], row[2]);
+        assert!(sex == "М" || sex == "Ж", "{}", sex);
+        genders.insert(sex);
+        let patronymic = fio.rsplit(' ').next().unwrap();
+        if sex == "М" {
+            assert!(pg_stage_rs::mutator::locale::ru::FIRST_NAMES_MALE.contains(&first), "{}", first);
+            assert!(patronymic.ends_with("ич"), "{}", fio);
+        } else {
+            assert!(pg_stage_rs::mutator::locale::ru::FIRST_NAMES_FEMALE.contains(&first), "{}", first);
+            assert!(patronymic.ends_with("на"), "{}", fio);
+        }
+    }
+    assert_eq!(genders.len(), 2);
+}
+
+#[test]
+fn test_deterministic_email_matches_generated_name() {
+    let input = concat!(
+        "COMMENT ON COLUMN public.users.email IS 'anon: [{\"mutation_name\": \"deterministic_email\", \"mutation_kwargs\": {\"first_column\": \"first\", \"last_column\": \"last\", \"domain\": \"example.org\"}}]';\n",
+        "COMMENT ON COLUMN public.users.first IS 'anon: [{\"mutation_name\": \"first_name\"}]';\n",
+        "COMMENT ON COLUMN public.users.last IS 'anon: [{\"mutation_name\": \"last_name\"}]';\n",
+        "COPY public.users (email, first, last) FROM stdin;\n",
+        "john.smith@corp.com\tJohn\tSmith\n",
+        "jane.doe@corp.com\tJane\tDoe\n",
+        "\\.\n",
+    );
+    let mut output = Vec::new();
+    let mut handler = PlainHandler::new(make_processor());
+    handler.process(Cursor::new(b""), &mut output, input.as_bytes()).unwrap();
+    let result = String::from_utf8(output).unwrap();
+    let rows: Vec<Vec<&str>> = result
+        .lines()
+        .filter(|l| l.contains("@example.org"))
+        .map(|l| l.split('\t').collect())
+        .collect();
+    assert_eq!(rows.len(), 2, "{}", result);
+    for row in rows {
+        let clean = |s: &str| -> String {
+            s.chars().filter(|c| c.is_ascii_alphanumeric() || *c == '-').collect::<String>().to_lowercase()
+        };
+        assert_eq!(row[0], format!("{}.{}@example.org", clean(row[1]), clean(row[2])));
+    }
+
+    let mut proc = make_ru_processor();
+    proc.parse_comment(
+        "COMMENT ON COLUMN public.users.email IS 'anon: [{\"mutation_name\": \"deterministic_email\", \"mutation_kwargs\": {\"first_column\": \"first\", \"last_column\": \"last\"}}]';",
+    );
+    proc.parse_comment("COMMENT ON COLUMN public.users.first IS 'anon: [{\"mutation_name\": \"first_name\"}]';");
+    proc.parse_comment("COMMENT ON COLUMN public.users.last IS 'anon: [{\"mutation_name\": \"last_name\"}]';");
+    proc.setup_table("COPY public.users (email, first, last) FROM stdin;");
+    let line = proc.process_line("a@b.c\tИван\tИванов".as_bytes()).unwrap().to_vec();
+    let line = String::from_utf8(line).unwrap();
+    let email = line.split('\t').next().unwrap();
+    let (local, _) = email.split_once('@').unwrap();
+    assert!(local.contains('.') && local.is_ascii(), "{}", email);
+}
+
+#[test]
+fn test_plain_preserves_missing_final_newline() {
+    let run = |input: &str| {
+        let mut output = Vec::new();
+        let mut handler = PlainHandler::new(make_processor());
+        handler.process(Cursor::new(b""), &mut output, input.as_bytes()).unwrap();
+        String::from_utf8(output).unwrap()
+    };
+    let body = concat!(
+        "COMMENT ON COLUMN public.users.email IS 'anon: [{\"mutation_name\": \"null\"}]';\n",
+        "COPY public.users (id, email) FROM stdin;\n",
+        "1\ta@example.com\n",
+        "\\.",
+    );
+    let expected = body.replace("a@example.com", "\\N");
+    assert_eq!(run(body), expected);
+    assert_eq!(run(&format!("{}\n", body)), format!("{}\n", expected));
+    assert_eq!(run("SELECT 1;"), "SELECT 1;");
+    assert_eq!(run(""), "");
+}
+
+#[test]
+fn test_column_count_mismatch_is_counted_and_passed_through() {
+    use pg_stage_rs::processor::ProcessorConfig;
+
+    let mut proc = DataProcessor::builder()
+        .config(ProcessorConfig {
+            strict: true,
+            ..Default::default()
+        })
+        .build();
+    proc.parse_comment("COMMENT ON COLUMN public.users.email IS 'anon: [{\"mutation_name\": \"null\"}]';");
+    proc.setup_table("COPY public.users (id, email) FROM stdin;");
+    assert_eq!(proc.process_line(b"1\ta@example.com").unwrap(), b"1\t\\N");
+    assert_eq!(
+        proc.process_line(b"2\tb@example.com\textra").unwrap(),
+        b"2\tb@example.com\textra"
+    );
+    assert_eq!(proc.process_line(b"3").unwrap(), b"3");
+    assert_eq!(proc.column_mismatches(), 2);
+}
+
+#[test]
+fn test_typo_mutation_is_reported() {
+    use pg_stage_rs::mutator::suggest_mutation;
+
+    assert_eq!(suggest_mutation("emial"), Some("email"));
+    assert_eq!(suggest_mutation("completely_unrelated"), None);
+
+    let input = concat!(
+        "COMMENT ON COLUMN public.users.email IS 'anon: [{\"mutation_name\": \"emial\"}]';\n",
+        "COMMENT ON TABLE public.logs IS 'anon: {\"mutation_name\": \"delet\"}';\n",
+        "COPY public.users (id, email) FROM stdin;\n",
+        "1\ta@example.com\n",
+        "\\.\n",
+    );
+
+    // Lenient: counted, and the dump is still written.
+    let mut proc = make_processor();
+    for line in input.lines().take(2) {
+        proc.parse_comment(line);
+    }
+    assert_eq!(proc.parse_warnings(), (0, 2));
+    assert!(proc.check_rules().is_ok());
+    let mut output = Vec::new();
+    PlainHandler::new(make_processor())
+        .process(Cursor::new(b""), &mut output, input.as_bytes())
+        .unwrap();
+    assert!(String::from_utf8(output).unwrap().contains("1\ta@example.com"));
+
+    // Strict: aborts before any data row is written.
+    let mut strict = make_processor();
+    strict.set_strict(true);
+    let mut output = Vec::new();
+    let err = PlainHandler::new(strict)
+        .process(Cursor::new(b""), &mut output, input.as_bytes())
+        .unwrap_err();
+    assert!(err.to_string().contains("emial"));
+    assert!(!String::from_utf8(output).unwrap().contains("a@example.com"));
+}
+
+#[test]
+fn test_nested_typo_mutation_is_reported() {
+    let input = concat!(
+        "COMMENT ON COLUMN public.users.email IS 'anon: [{\"mutation_name\": \"partial\", ",
+        "\"mutation_kwargs\": {\"probability\": 1.0, \"mutation\": {\"mutation_name\": \"emial\"}}}]';\n",
+        "COPY public.users (id, email) FROM stdin;\n",
+        "1\ta@example.com\n",
+        "\\.\n",
+    );
+
+    let mut proc = make_processor();
+    proc.parse_comment(input.lines().next().unwrap());
+    assert_eq!(proc.parse_warnings(), (0, 1));
+    assert!(proc.check_rules().is_ok());
+
+    let mut strict = make_processor();
+    strict.set_strict(true);
+    let mut output = Vec::new();
+    let err = PlainHandler::new(strict)
+        .process(Cursor::new(b""), &mut output, input.as_bytes())
+        .unwrap_err();
+    assert!(err.to_string().contains("emial"));
+    assert!(!String::from_utf8(output).unwrap().contains("a@example.com"));
+}
+
+#[test]
+fn test_deterministic_date_follows_source_value() {
+    let run = || {
+        let mut proc = DataProcessor::builder()
+            .secret("SECRET_KEY", "date-key")
+            .secret("SECRET_KEY_NONCE", "date-nonce")
+            .build();
+        proc.parse_comment(
+            "COMMENT ON COLUMN public.visits.seen_on IS 'anon: [{\"mutation_name\": \"deterministic_date\", \"mutation_kwargs\": {\"source_column\": \"user_id\", \"start\": 2000, \"end\": 2010}}]';",
+        );
+        assert!(proc.setup_table("COPY public.visits (id, user_id, seen_on) FROM stdin;"));
+        ["1\t42\t2024-05-01", "2\t7\t2024-05-01", "3\t42\t2023-01-15"]
+            .iter()
+            .map(|row| {
+                let out = proc.process_line(row.as_bytes()).unwrap();
+                String::from_utf8(out.to_vec()).unwrap()
+            })
+            .collect::<Vec<_>>()
+    };
+    let rows = run();
+    let dates: Vec<&str> = rows.iter().map(|r| r.split('\t').nth(2).unwrap()).collect();
+    assert_eq!(dates[0], dates[2]);
+    for (row, date) in rows.iter().zip(&dates) {
+        // The source column itself is left as is.
+        assert!(row.split('\t').nth(1).unwrap() == "42" || row.contains("\t7\t"));
+        let year: i32 = date[..4].parse().unwrap();
+        assert!((2000..=2010).contains(&year), "outside window: {}", date);
+    }
+    // Another dump with the same secrets maps the same sources the same way.
+    assert_eq!(run(), rows);
+
+    // Without the source column the mutation fails and the value is kept.
+    let mut proc = DataProcessor::builder()
+        .secret("SECRET_KEY", "date-key")
+        .secret("SECRET_KEY_NONCE", "date-nonce")
+        .build();
+    proc.parse_comment(
+        "COMMENT ON COLUMN public.visits.seen_on IS 'anon: [{\"mutation_name\": \"deterministic_date\", \"mutation_kwargs\": {\"source_column\": \"user_id\", \"start\": 2000, \"end\": 2010}}]';",
+    );
+    assert_eq!(proc.mutate_value("public.visits", "seen_on", "2024-05-01").as_deref(), Some("2024-05-01"));
+}
+
+#[test]
+fn test_shuffle_keeps_characters() {
+    let sorted = |s: &str| {
+        let mut v: Vec<char> = s.chars().collect();
+        v.sort_unstable();
+        v
+    };
+    let mut proc = make_processor();
+    proc.parse_comment(
+        "COMMENT ON COLUMN public.t.code IS 'anon: [{\"mutation_name\": \"shuffle\"}]';",
+    );
+    proc.parse_comment(
+        "COMMENT ON COLUMN public.t.plate IS 'anon: [{\"mutation_name\": \"shuffle\", \"mutation_kwargs\": {\"preserve_class\": true}}]';",
+    );
+    assert!(proc.setup_table("COPY public.t (code, plate) FROM stdin;"));
+
+    let (code, plate) = ("Correct-Horse-42", "AB-123-CD-456-EF");
+    let row = format!("{code}\t{plate}");
+    let mut reordered = 0;
+    for _ in 0..20 {
+        let out = proc.process_line(row.as_bytes()).unwrap();
+        let out = String::from_utf8(out.to_vec()).unwrap();
+        let (new_code, new_plate) = out.split_once('\t').unwrap();
+        assert_eq!(sorted(new_code), sorted(code));
+        assert_eq!(sorted(new_plate), sorted(plate));
+        for (a, b) in plate.chars().zip(new_plate.chars()) {
+            assert_eq!(a.is_ascii_digit(), b.is_ascii_digit());
+            assert_eq!(a.is_alphabetic(), b.is_alphabetic());
+            if a == '-' {
+                assert_eq!(b, '-');
+            }
+        }
+        if new_code != code && new_plate != plate {
+            reordered += 1;
+        }
+    }
+    assert!(reordered > 15);
+    assert_eq!(proc.process_line(b"\\N\t\\N").unwrap(), b"\\N\t\\N");
+}
+
+#[test]
+fn test_truncate_cuts_on_char_boundaries() {
+    let mut proc = make_processor();
+    proc.parse_comment(
+        "COMMENT ON COLUMN public.t.code IS 'anon: [{\"mutation_name\": \"truncate\", \"mutation_kwargs\": {\"length\": 4, \"pad\": \"·\"}}]';",
+    );
+    assert!(proc.setup_table("COPY public.t (id, code) FROM stdin;"));
+    let mut run = |row: &str| String::from_utf8(proc.process_line(row.as_bytes()).unwrap().to_vec()).unwrap();
+    // Cyrillic and emoji are 2 and 4 bytes wide; a byte cut would split them.
+    assert_eq!(run("1\tПривет"), "1\tПрив");
+    assert_eq!(run("2\t🙂🙃🙂🙃🙂"), "2\t🙂🙃🙂🙃");
+    assert_eq!(run("3\tåb"), "3\tåb··");
+    assert_eq!(run("4\t\\N"), "4\t\\N");
+
+    let mut proc = make_processor();
+    proc.parse_comment(
+        "COMMENT ON COLUMN public.t.code IS 'anon: [{\"mutation_name\": \"truncate\", \"mutation_kwargs\": {\"pad\": \"x\"}}]';",
+    );
+    assert!(proc.setup_table("COPY public.t (id, code) FROM stdin;"));
+    // Missing `length` fails the mutation, which leaves the value unchanged.
+    assert_eq!(proc.process_line(b"1\tabc").unwrap(), b"1\tabc");
+}
+
+#[test]
+fn test_country_and_country_code() {
+    use pg_stage_rs::mutator::locale::geo_data::COUNTRIES;
+
+    for (mut proc, names) in [
+        (make_processor(), COUNTRIES.iter().map(|c| c.1).collect::<Vec<_>>()),
+        (make_ru_processor(), COUNTRIES.iter().map(|c| c.2).collect::<Vec<_>>()),
+    ] {
+        proc.parse_comment(
+            "COMMENT ON COLUMN public.t.country IS 'anon: [{\"mutation_name\": \"country\"}]';",
+        );
+        for _ in 0..50 {
+            let name = proc.mutate_value("public.t", "country", "").unwrap();
+            assert!(names.contains(&name.as_str()), "unexpected country {}", name);
+        }
+    }
+
+    let mut proc = make_processor();
+    proc.parse_comment(
+        "COMMENT ON COLUMN public.t.code IS 'anon: [{\"mutation_name\": \"country_code\", \"mutation_kwargs\": {\"unique\": true}}]';",
+    );
+    assert!(proc.setup_table("COPY public.t (code) FROM stdin;"));
+    let mut seen = std::collections::HashSet::new();
+    for _ in 0..20 {
+        let code = String::from_utf8(proc.process_line(b"XX").unwrap().to_vec()).unwrap();
+        assert_eq!(code.len(), 2);
+        assert!(code.bytes().all(|b| b.is_ascii_uppercase()), "bad code {}", code);
+        assert!(seen.insert(code));
+    }
+}
+
+#[test]
+fn test_currency_code_from_known_set() {
+    use pg_stage_rs::mutator::finance::CURRENCY_CODES;
+
+    let mut proc = make_processor();
+    proc.parse_comment(
+        "COMMENT ON COLUMN public.prices.currency IS 'anon: [{\"mutation_name\": \"currency_code\", \"mutation_kwargs\": {\"unique\": true}}]';",
+    );
+    let mut seen = std::collections::HashSet::new();
+    for _ in 0..CURRENCY_CODES.len() {
+        let code = proc.mutate_value("public.prices", "currency", "USD").unwrap();
+        assert_eq!(code.len(), 3);
+        assert!(code.bytes().all(|b| b.is_ascii_uppercase()), "bad code {}", code);
+        assert!(CURRENCY_CODES.contains(&code.as_str()));
+        assert!(seen.insert(code));
+    }
+}
+
+#[test]
+fn test_relation_keeps_network_values_per_mutated_device_id() {
+    let rel = |column: &str| {
+        format!(
+            "\"relations\": [{{\"table_name\": \"devices\", \"column_name\": \"{}\", \"from_column_name\": \"device_id\", \"to_column_name\": \"id\"}}]",
+            column
+        )
+    };
+    let input = format!(
+        concat!(
+            "COMMENT ON COLUMN public.device_events.device_id IS 'anon: [{{\"mutation_name\": \"numeric_integer\"}}]';\n",
+            "COMMENT ON COLUMN public.device_events.ip IS 'anon: [{{\"mutation_name\": \"ipv4\", {}}}]';\n",
+            "COMMENT ON COLUMN public.device_events.mac IS 'anon: [{{\"mutation_name\": \"mac_address\", {}}}]';\n",
+            "COPY public.device_events (id, device_id, ip, mac) FROM stdin;\n",
+            "1\t7\t10.0.0.1\taa:bb:cc:dd:ee:01\n",
+            "2\t7\t10.0.0.1\taa:bb:cc:dd:ee:01\n",
+            "3\t8\t10.0.0.2\taa:bb:cc:dd:ee:02\n",
+            "\\.\n",
+        ),
+        rel("ip"),
+        rel("mac"),
+    );
+    let mut output = Vec::new();
+    let mut handler = PlainHandler::new(make_processor());
+    handler.process(Cursor::new(b""), &mut output, input.as_bytes()).unwrap();
+    let result = String::from_utf8(output).unwrap();
+    let rows: Vec<Vec<&str>> = result
+        .lines()
+        .filter(|l| l.starts_with(|c: char| c.is_ascii_digit()))
+        .map(|l| l.split('\t').collect())
+        .collect();
+    assert_eq!(rows.len(), 3);
+    assert_ne!(rows[0][1], "7");
+    assert_eq!(rows[0][2], rows[1][2]);
+    assert_eq!(rows[0][3], rows[1][3]);
+    assert_ne!(rows[0][2], rows[2][2]);
+    assert_ne!(rows[0][3], rows[2][3]);
+    assert_eq!(rows[0][3].split(':').count(), 6);
+}
+
+#[test]
+fn test_random_choice_from_file() {
+    let dir = temp_dir("choices");
+    let path = dir.join("cities.txt");
+    std::fs::write(&path, "Oslo\nLima\r\n\nKyiv\n").unwrap();
+    let mut proc = make_processor();
+    proc.parse_comment(&format!(
+        "COMMENT ON COLUMN public.users.city IS 'anon: [{{\"mutation_name\": \"random_choice\", \"mutation_kwargs\": {{\"choices_file\": \"{}\"}}}}]';",
+        path.display()
+    ));
+    for _ in 0..20 {
+        let city = proc.mutate_value("public.users", "city", "Paris").unwrap();
+        assert!(["Oslo", "Lima", "Kyiv"].contains(&city.as_str()), "unexpected {}", city);
+    }
+
+    std::fs::write(dir.join("empty.txt"), "\n").unwrap();
+    let mut proc = make_processor();
+    proc.parse_comment(&format!(
+        "COMMENT ON COLUMN public.users.city IS 'anon: [{{\"mutation_name\": \"random_choice\", \"mutation_kwargs\": {{\"choices_file\": \"{}\"}}}}]';",
+        dir.join("empty.txt").display()
+    ));
+    // InvalidParameter: the value is left as is.
+    assert_eq!(proc.mutate_value("public.users", "city", "Paris").as_deref(), Some("Paris"));
+}
+
+#[test]
+fn test_numeric_match_format_keeps_leading_zeros() {
+    let mut proc = make_processor();
+    proc.parse_comment(
+        "COMMENT ON COLUMN public.agents.code IS 'anon: [{\"mutation_name\": \"numeric_integer\", \"mutation_kwargs\": {\"start\": 0, \"end\": 999, \"match_format\": true}}]';",
+    );
+    for _ in 0..50 {
+        let code = proc.mutate_value("public.agents", "code", "007").unwrap();
+        assert_eq!(code.len(), 3, "bad code {}", code);
+        assert!(code.bytes().all(|b| b.is_ascii_digit()));
+    }
+    // Unpadded sources keep the plain form.
+    let value = proc.mutate_value("public.agents", "code", "7").unwrap();
+    assert_eq!(value, value.parse::<u32>().unwrap().to_string());
+}
+
+#[cfg(feature = "zlib")]
+#[test]
+fn test_gzipped_plain_dump_is_mutated() {
+    use flate2::read::GzDecoder;
+    use flate2::write::GzEncoder;
+    use std::io::{Read, Write};
+
+    let input = concat!(
+        "COMMENT ON COLUMN public.users.email IS 'anon: [{\"mutation_name\": \"fixed_value\", \"mutation_kwargs\": {\"value\": \"REDACTED\"}}]';\n",
+        "COPY public.users (id, email) FROM stdin;\n",
+        "1\tjohn@example.com\n",
+        "\\.\n",
+    );
+    let mut gz = GzEncoder::new(Vec::new(), flate2::Compression::default());
+    gz.write_all(input.as_bytes()).unwrap();
+    let dump = gz.finish().unwrap();
+    assert_eq!(detect_format(&dump[..5]).unwrap(), DumpFormat::PlainGz);
+
+    let mut output = Vec::new();
+    let mut handler = PlainHandler::new(make_processor());
+    handler
+        .process_gz(Cursor::new(&dump[5..]), &mut output, &dump[..5])
+        .unwrap();
+    let mut result = String::new();
+    GzDecoder::new(&output[..]).read_to_string(&mut result).unwrap();
+    assert!(result.contains("1\tREDACTED\n"), "{}", result);
+    assert!(!result.contains("john@example.com"));
+}
+
+#[test]
+fn test_plain_process_iter_matches_writer_output() {
+    let input = concat!(
+        "SET statement_timeout = 0;\n",
+        "COMMENT ON COLUMN public.users.email IS 'anon: [{\"mutation_name\": \"fixed_value\", \"mutation_kwargs\": {\"value\": \"REDACTED\"}}]';\n",
+        "COPY public.users (id, email) FROM stdin;\n",
+        "1\tjohn@example.com\n",
+        "2\tjane@example.com\n",
+        "\\.\n",
+        "INSERT INTO public.users (id, email) VALUES (3, 'a@b.c'), (4, 'line\nbreak');\n",
+        "SELECT 1;",
+    );
+    let mut expected = Vec::new();
+    PlainHandler::new(make_processor())
+        .process(Cursor::new(b""), &mut expected, input.as_bytes())
+        .unwrap();
+
+    let mut handler = PlainHandler::new(make_processor());
+    let lines: Vec<Vec<u8>> = handler
+        .process_iter(&input.as_bytes()[4..], &input.as_bytes()[..4])
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert!(lines.len() > 5);
+    assert!(lines.contains(&b"1\tREDACTED\n".to_vec()));
+    assert_eq!(lines.concat(), expected);
+}
+
+#[test]
+fn test_row_hook_sees_original_and_mutated_rows() {
+    use std::sync::{Arc, Mutex};
+
+    let seen: Arc<Mutex<Vec<String>>> = Arc::default();
+    let mut proc = make_processor();
+    let sink = Arc::clone(&seen);
+    proc.set_row_hook(move |table, _columns, original, mutated, _null_marker| {
+        let mutated: Vec<&str> = mutated.iter().map(|v| v.as_ref()).collect();
+        sink.lock()
+            .unwrap()
+            .push(format!("{} {:?} -> {:?}", table, original, mutated));
+    });
+    let input = concat!(
+        "COMMENT ON COLUMN public.users.email IS 'anon: [{\"mutation_name\": \"fixed_value\", \"mutation_kwargs\": {\"value\": \"REDACTED\"}}]';\n",
+        "COPY public.users (id, email) FROM stdin;\n",
+        "1\tjohn@example.com\n",
+        "2\tjane\\tdoe@example.com\n",
+        "\\.\n",
+        "COPY public.orders (id, note) FROM stdin;\n",
+        "1\tuntouched\n",
+        "\\.\n",
+    );
+    let mut output = Vec::new();
+    PlainHandler::new(proc)
+        .process(Cursor::new(b""), &mut output, input.as_bytes())
+        .unwrap();
+
+    assert_eq!(
+        *seen.lock().unwrap(),
+        vec![
+            r#"public.users ["1", "john@example.com"] -> ["1", "REDACTED"]"#,
+            r#"public.users ["2", "jane\tdoe@example.com"] -> ["2", "REDACTED"]"#,
+        ]
+    );
+}
+
+#[test]
+fn test_mapping_manifest_records_replaced_email() {
+    use pg_stage_rs::mapping::MappingWriter;
+    use std::sync::Arc;
+
+    let path = temp_dir("mapping").join("mapping.jsonl");
+    let mapping = Arc::new(MappingWriter::new(std::fs::File::create(&path).unwrap()));
+    let sink = Arc::clone(&mapping);
+    let mut proc = make_processor();
+    proc.set_row_hook(move |table, columns, original, mutated, null_marker| {
+        sink.record(table, columns, original, mutated, null_marker)
+    });
+    let input = concat!(
+        "COMMENT ON COLUMN public.users.email IS 'anon: [{\"mutation_name\": \"fixed_value\", \"mutation_kwargs\": {\"value\": \"REDACTED\"}}]';\n",
+        "COPY public.users (id, email) FROM stdin;\n",
+        "1\tjohn@example.com\n",
+        "2\t\\N\n",
+        "\\.\n",
+    );
+    let mut output = Vec::new();
+    PlainHandler::new(proc)
+        .process(Cursor::new(b""), &mut output, input.as_bytes())
+        .unwrap();
+    mapping.flush().unwrap();
+
+    let entries: Vec<serde_json::Value> = std::fs::read_to_string(&path)
+        .unwrap()
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(
+        entries,
+        vec![
+            serde_json::json!({"table": "public.users", "column": "email", "original": "john@example.com", "mutated": "REDACTED"}),
+            serde_json::json!({"table": "public.users", "column": "email", "original": null, "mutated": "REDACTED"}),
+        ]
+    );
+}
+
+#[test]
+fn test_mapping_manifest_uses_copy_null_marker() {
+    use pg_stage_rs::mapping::MappingWriter;
+    use std::sync::Arc;
+
+    let path = temp_dir("mapping_null").join("mapping.jsonl");
+    let mapping = Arc::new(MappingWriter::new(std::fs::File::create(&path).unwrap()));
+    let sink = Arc::clone(&mapping);
+    let mut proc = make_processor();
+    proc.set_row_hook(move |table, columns, original, mutated, null_marker| {
+        sink.record(table, columns, original, mutated, null_marker)
+    });
+    let input = concat!(
+        "COMMENT ON COLUMN public.users.email IS 'anon: [{\"mutation_name\": \"fixed_value\", \"mutation_kwargs\": {\"value\": \"REDACTED\"}}]';\n",
+        "COMMENT ON COLUMN public.users.note IS 'anon: [{\"mutation_name\": \"null\"}]';\n",
+        "COPY public.users (id, email, note) FROM stdin WITH (NULL 'NULL');\n",
+        "1\tNULL\tkept\n",
+        "\\.\n",
+    );
+    let mut output = Vec::new();
+    PlainHandler::new(proc)
+        .process(Cursor::new(b""), &mut output, input.as_bytes())
+        .unwrap();
+    mapping.flush().unwrap();
+
+    let entries: Vec<serde_json::Value> = std::fs::read_to_string(&path)
+        .unwrap()
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(
+        entries,
+        vec![
+            serde_json::json!({"table": "public.users", "column": "email", "original": null, "mutated": "REDACTED"}),
+            serde_json::json!({"table": "public.users", "column": "note", "original": "kept", "mutated": null}),
+        ]
+    );
+}
+
+#[test]
+fn test_bigint_full_range_does_not_panic() {
+    let mut proc = make_processor();
+    proc.parse_comment(
+        "COMMENT ON COLUMN public.events.id IS 'anon: [{\"mutation_name\": \"numeric_bigint\", \"mutation_kwargs\": {\"unique\": true}}]';",
+    );
+    let mut seen = std::collections::HashSet::new();
+    for _ in 0..10_000 {
+        let value = proc.mutate_value("public.events", "id", "1").unwrap();
+        value.parse::<i64>().unwrap();
+        assert!(seen.insert(value));
+    }
+
+    proc.parse_comment(
+        "COMMENT ON COLUMN public.events.top IS 'anon: [{\"mutation_name\": \"numeric_bigint\", \"mutation_kwargs\": {\"start\": 9223372036854775806}}]';",
+    );
+    for _ in 0..100 {
+        let value: i64 = proc.mutate_value("public.events", "top", "1").unwrap().parse().unwrap();
+        assert!(value >= i64::MAX - 1);
+    }
+}
+
+#[test]
+fn test_condition_on_self_checks_the_mutated_cell() {
+    let input = concat!(
+        "COMMENT ON COLUMN public.users.email IS 'anon: [{\"mutation_name\": \"fixed_value\", \"mutation_kwargs\": {\"value\": \"REDACTED\"}, \"conditions\": [{\"column_name\": \"self\", \"operation\": \"ends_with\", \"value\": \"@test.com\"}]}]';\n",
+        "COPY public.users (id, email) FROM stdin;\n",
+        "1\tqa@test.com\n",
+        "2\tjohn@example.com\n",
+        "\\.\n",
+    );
+    let mut output = Vec::new();
+    let mut handler = PlainHandler::new(make_processor());
+    handler.process(Cursor::new(b""), &mut output, input.as_bytes()).unwrap();
+    let result = String::from_utf8(output).unwrap();
+    assert!(result.contains("1\tREDACTED\n"));
+    assert!(result.contains("2\tjohn@example.com\n"));
+}
+
+#[test]
+fn test_comment_with_escaped_quote_in_value() {
+    let input = concat!(
+        "COMMENT ON COLUMN public.users.note IS 'anon: [{\"mutation_name\": \"fixed_value\", \"mutation_kwargs\": {\"value\": \"it''s;\"}}]';\n",
+        "COMMENT ON COLUMN public.users.bio IS 'anon: [{\"mutation_name\": \"fixed_value\",\n",
+        "  \"mutation_kwargs\": {\"value\": \"x'';\"}}]';\n",
+        "COPY public.users (id, note, bio) FROM stdin;\n",
+        "1\tsecret\tsecret\n",
+        "\\.\n",
+    );
+    let mut output = Vec::new();
+    let mut handler = PlainHandler::new(make_processor());
+    handler.process(Cursor::new(b""), &mut output, input.as_bytes()).unwrap();
+    let result = String::from_utf8(output).unwrap();
+    assert!(result.contains("1\tit's;\tx';\n"), "{}", result);
+}
+
+#[test]
+fn test_multi_char_delimiter() {
+    let mut proc = DataProcessor::builder().delimiter_str("||").build();
+    proc.parse_comment(
+        "COMMENT ON COLUMN public.users.note IS 'anon: [{\"mutation_name\": \"fixed_value\", \"mutation_kwargs\": {\"value\": \"a|b\"}}]';",
+    );
+    assert!(proc.setup_table("COPY public.users (id, note, city) FROM stdin;"));
+    let out = proc.process_line(b"1||secret||Oslo|Lima").unwrap().to_vec();
+    assert_eq!(out, b"1||a\\174b||Oslo|Lima");
+    // The escaped pipe does not split the cell when read back.
+    assert_eq!(proc.process_line(&out).unwrap(), &out[..]);
+    assert_eq!(proc.column_mismatches(), 0);
+}
+
+#[test]
+fn test_enum_value_explicit_and_discovered_labels() {
+    let mut proc = make_processor();
+    proc.parse_comment(
+        "COMMENT ON COLUMN public.users.role IS 'anon: [{\"mutation_name\": \"enum_value\", \"mutation_kwargs\": {\"values\": [\"guest\", \"member\"]}}]';",
+    );
+    for _ in 0..20 {
+        let role = proc.mutate_value("public.users", "role", "admin").unwrap();
+        assert!(role == "guest" || role == "member", "unexpected {}", role);
+    }
+
+    let input = concat!(
+        "CREATE TYPE public.mood AS ENUM (\n",
+        "    'sad',\n",
+        "    'it''s ok'\n",
+        ");\n",
+        "CREATE TABLE public.people (\n",
+        "    id integer NOT NULL,\n",
+        "    mood public.mood\n",
+        ");\n",
+        "COMMENT ON COLUMN public.people.mood IS 'anon: [{\"mutation_name\": \"enum_value\"}]';\n",
+        "COPY public.people (id, mood) FROM stdin;\n",
+        "1\thappy\n",
+        "2\thappy\n",
+        "3\thappy\n",
+        "\\.\n",
+    );
+    let mut output = Vec::new();
+    let mut handler = PlainHandler::new(make_processor());
+    handler.process(Cursor::new(b""), &mut output, input.as_bytes()).unwrap();
+    let result = String::from_utf8(output).unwrap();
+    let moods: Vec<&str> = result
+        .lines()
+        .filter(|l| l.starts_with(|c: char| c.is_ascii_digit()))
+        .map(|l| l.split('\t').nth(1).unwrap())
+        .collect();
+    assert_eq!(moods.len(), 3);
+    assert!(moods.iter().all(|m| *m == "sad" || *m == "it's ok"), "{:?}", moods);
+}
+
+#[test]
+fn test_sequence_assigns_consecutive_ids() {
+    let input = concat!(
+        "COMMENT ON COLUMN public.users.id IS 'anon: [{\"mutation_name\": \"sequence\", \"mutation_kwargs\": {\"start\": 100}}]';\n",
+        "COPY public.users (id, name) FROM stdin;\n",
+        "42\ta\n",
+        "7\tb\n",
+        "9000\tc\n",
+        "\\.\n",
+    );
+    let mut output = Vec::new();
+    let mut handler = PlainHandler::new(make_processor());
+    handler.process(Cursor::new(b""), &mut output, input.as_bytes()).unwrap();
+    let result = String::from_utf8(output).unwrap();
+    assert!(result.contains("100\ta\n101\tb\n102\tc\n"), "{}", result);
+}
+
+#[test]
+fn test_foreign_key_follows_mutated_primary_key() {
+    // orders is dumped before users, so the first sighting of each key is
+    // through the foreign key.
+    let input = concat!(
+        "COMMENT ON COLUMN public.users.id IS 'anon: [{\"mutation_name\": \"sequence\", \"mutation_kwargs\": {\"start\": 1000}, \"relations\": [{\"table_name\": \"users\", \"column_name\": \"id\", \"from_column_name\": \"id\", \"to_column_name\": \"id\"}]}]';\n",
+        "COMMENT ON COLUMN public.orders.user_id IS 'anon: [{\"mutation_name\": \"foreign_key\", \"relations\": [{\"table_name\": \"users\", \"column_name\": \"id\", \"from_column_name\": \"user_id\", \"to_column_name\": \"id\"}]}]';\n",
+        "COPY public.orders (id, user_id) FROM stdin;\n",
+        "1\t7\n",
+        "2\t42\n",
+        "3\t7\n",
+        "\\.\n",
+        "COPY public.users (id, name) FROM stdin;\n",
+        "42\talice\n",
+        "7\tbob\n",
+        "5\tcarol\n",
+        "\\.\n",
+    );
+    let mut output = Vec::new();
+    let mut handler = PlainHandler::new(make_processor());
+    handler.process(Cursor::new(b""), &mut output, input.as_bytes()).unwrap();
+    let result = String::from_utf8(output).unwrap();
+    assert!(result.contains("1\t1000\n2\t1001\n3\t1000\n"), "{}", result);
+    assert!(result.contains("1001\talice\n1000\tbob\n1002\tcarol\n"), "{}", result);
+}
+
+#[test]
+fn test_copy_null_option_sets_null_marker() {
+    let input = concat!(
+        "COMMENT ON COLUMN public.users.email IS 'anon: [{\"mutation_name\": \"null\"}]';\n",
+        "COMMENT ON COLUMN public.users.note IS 'anon: [{\"mutation_name\": \"fixed_value\", \"mutation_kwargs\": {\"value\": \"x\"}, \"conditions\": [{\"column_name\": \"self\", \"operation\": \"not_equal\", \"value\": \"NULL\"}]}]';\n",
+        "COPY public.users (id, email, note) FROM stdin WITH (NULL 'NULL');\n",
+        "1\ta@example.com\tNULL\n",
+        "2\tb@example.com\t\\N\n",
+        "\\.\n",
+    );
+    let mut output = Vec::new();
+    let mut handler = PlainHandler::new(make_processor());
+    handler.process(Cursor::new(b""), &mut output, input.as_bytes()).unwrap();
+    let result = String::from_utf8(output).unwrap();
+    // Under this marker `\N` is an ordinary value, so only row 2's note changes.
+    assert!(result.contains("1\tNULL\tNULL\n2\tNULL\tx\n"), "{}", result);
+
+    let mut proc = DataProcessor::builder().null_marker("").build();
+    proc.parse_comment(
+        "COMMENT ON COLUMN public.users.email IS 'anon: [{\"mutation_name\": \"null\"}]';",
+    );
+    assert_eq!(proc.mutate_value("users", "email", "a@example.com").as_deref(), Some(""));
+}
+
+#[test]
+fn test_copy_options_csv_semicolon() {
+    let input = concat!(
+        "COMMENT ON COLUMN public.users.name IS 'anon: [{\"mutation_name\": \"fixed_value\", \"mutation_kwargs\": {\"value\": \"O\\\"Hara; Jr\"}}]';\n",
+        "COMMENT ON COLUMN public.users.email IS 'anon: [{\"mutation_name\": \"null\"}]';\n",
+        "COMMENT ON COLUMN public.users.note IS 'anon: [{\"mutation_name\": \"fixed_value\", \"mutation_kwargs\": {\"value\": \"none\"}, \"conditions\": [{\"column_name\": \"self\", \"operation\": \"equal\", \"value\": \"\\\\N\"}]}]';\n",
+        "COMMENT ON COLUMN public.tags.label IS 'anon: [{\"mutation_name\": \"null\"}]';\n",
+        "COPY public.users (id, name, email, note) FROM stdin WITH (FORMAT csv, DELIMITER ';', NULL 'NULL');\n",
+        "1;\"Doe; John\";a@example.com;NULL\n",
+        "2;Roe;b@example.com;\"two\n",
+        "lines; kept\"\n",
+        "\\.\n",
+        "COPY public.tags (id, label) FROM stdin WITH DELIMITER AS '|' NULL AS 'nil';\n",
+        "1|red\n",
+        "\\.\n",
+    );
+    let mut output = Vec::new();
+    let mut handler = PlainHandler::new(make_processor());
+    handler.process(Cursor::new(b""), &mut output, input.as_bytes()).unwrap();
+    let result = String::from_utf8(output).unwrap();
+    assert!(result.contains("\n1;\"O\"\"Hara; Jr\";NULL;none\n"), "{}", result);
+    assert!(
+        result.contains("\n2;\"O\"\"Hara; Jr\";NULL;\"two\nlines; kept\"\n"),
+        "{}",
+        result
+    );
+    assert!(result.contains("\n1|nil\n"), "{}", result);
+}
+
+#[test]
+fn test_csv_copy_keeps_quoted_commas() {
+    let input = concat!(
+        "COMMENT ON COLUMN public.people.name IS 'anon: [{\"mutation_name\": \"fixed_value\", \"mutation_kwargs\": {\"value\": \"Doe, Jane\"}, \"conditions\": [{\"column_name\": \"self\", \"operation\": \"not_equal\", \"value\": \"\\\\N\"}]}]';\n",
+        "COMMENT ON COLUMN public.notes.body IS 'anon: [{\"mutation_name\": \"fixed_value\", \"mutation_kwargs\": {\"value\": \"a ''b'', c\"}, \"conditions\": [{\"column_name\": \"self\", \"operation\": \"equal\", \"value\": \"it''s, fine\"}]}]';\n",
+        "COPY public.people (id, name, city) FROM stdin WITH (FORMAT csv);\n",
+        "1,\"Smith, John\",\"Paris\"\n",
+        "2,,\"Rome, \"\"Italy\"\"\"\n",
+        "\\.\n",
+        "COPY public.notes (id, body) FROM stdin WITH (FORMAT csv, QUOTE '''', ESCAPE '\\');\n",
+        "1,'it\\'s, fine'\n",
+        "2,'other'\n",
+        "\\.\n",
+    );
+    let mut output = Vec::new();
+    let mut handler = PlainHandler::new(make_processor());
+    handler.process(Cursor::new(b""), &mut output, input.as_bytes()).unwrap();
+    let result = String::from_utf8(output).unwrap();
+    assert!(
+        result.contains("\n1,\"Doe, Jane\",\"Paris\"\n2,,\"Rome, \"\"Italy\"\"\"\n"),
+        "{}",
+        result
+    );
+    assert!(result.contains("\n1,'a \\'b\\', c'\n2,'other'\n"), "{}", result);
+}
+
+/// Run the binary with `args`, feeding `stdin`.
+fn run_cli(args: &[&str], stdin: &str) -> std::process::Output {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_pg_stage_rs"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn test_check_mode_fails_on_malformed_comment() {
+    let dump = concat!(
+        "COMMENT ON COLUMN public.users.email IS 'anon: [{\"mutation_name\": \"email\"}]';\n",
+        "COPY public.users (id, email) FROM stdin;\n",
+        "1\ta@example.com\n",
+        "\\.\n",
+    );
+    let ok = run_cli(&["--check"], dump);
+    assert!(ok.status.success(), "{}", String::from_utf8_lossy(&ok.stderr));
+    assert!(ok.stdout.is_empty());
+
+    let broken = dump.replace("\"email\"}]", "\"email\"");
+    let failed = run_cli(&["--check"], &broken);
+    assert!(!failed.status.success());
+    assert!(failed.stdout.is_empty());
+    let stderr = String::from_utf8_lossy(&failed.stderr);
+    assert!(stderr.contains("1 invalid anon comments"), "{}", stderr);
+}
+
+#[test]
+fn test_bytea_hex_literal() {
+    let input = concat!(
+        "COMMENT ON COLUMN public.files.digest IS 'anon: [{\"mutation_name\": \"bytea\", \"mutation_kwargs\": {\"length\": 4}}]';\n",
+        "COMMENT ON COLUMN public.files.blob IS 'anon: [{\"mutation_name\": \"bytea\"}]';\n",
+        "COPY public.files (id, digest, blob) FROM stdin;\n",
+        "1\t\\\\xdeadbeef\t\\\\x00\n",
+        "\\.\n",
+    );
+    let mut output = Vec::new();
+    let mut handler = PlainHandler::new(make_processor());
+    handler.process(Cursor::new(b""), &mut output, input.as_bytes()).unwrap();
+    let result = String::from_utf8(output).unwrap();
+    let row: Vec<&str> = result.lines().find(|l| l.starts_with("1\t")).unwrap().split('\t').collect();
+    // COPY text doubles the backslash of the `\x` prefix.
+    for (cell, bytes) in [(row[1], 4), (row[2], 16)] {
+        let hex = cell.strip_prefix("\\\\x").unwrap_or_else(|| panic!("{}", cell));
+        assert_eq!(hex.len(), 2 * bytes, "{}", cell);
+        assert!(hex.bytes().all(|b| b.is_ascii_hexdigit()), "{}", cell);
+    }
+}
+
+#[test]
+fn test_base64_token_decodes_to_requested_bytes() {
+    fn decode(token: &str, alphabet: &str) -> Vec<u8> {
+        let (mut bits, mut pending, mut out) = (0u32, 0, Vec::new());
+        for c in token.trim_end_matches('=').chars() {
+            let v = alphabet.find(c).unwrap_or_else(|| panic!("{}", token));
+            bits = (bits << 6) | v as u32;
+            pending += 6;
+            if pending >= 8 {
+                pending -= 8;
+                out.push((bits >> pending) as u8);
+            }
+        }
+        out
+    }
+    const STANDARD: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    const URLSAFE: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+    let mut proc = make_processor();
+    proc.parse_comment(
+        "COMMENT ON COLUMN public.keys.api_key IS 'anon: [{\"mutation_name\": \"base64\"}]';",
+    );
+    proc.parse_comment(
+        "COMMENT ON COLUMN public.keys.session IS 'anon: [{\"mutation_name\": \"base64\", \"mutation_kwargs\": {\"bytes\": 20, \"urlsafe\": true}}]';",
+    );
+    for _ in 0..50 {
+        let api_key = proc.mutate_value("keys", "api_key", "x").unwrap();
+        assert_eq!(api_key.len(), 24);
+        assert!(api_key.ends_with("=="), "{}", api_key);
+        assert_eq!(decode(&api_key, STANDARD).len(), 16);
+
+        let session = proc.mutate_value("keys", "session", "x").unwrap();
+        assert_eq!(session.len(), 27);
+        assert!(!session.contains('='), "{}", session);
+        assert_eq!(decode(&session, URLSAFE).len(), 20);
+    }
+}
+
+#[test]
+fn test_inn_control_digits() {
+    fn check(digits: &[u32], weights: &[u32]) -> u32 {
+        digits.iter().zip(weights).map(|(d, w)| d * w).sum::<u32>() % 11 % 10
+    }
+    let mut proc = make_ru_processor();
+    proc.parse_comment(
+        "COMMENT ON COLUMN public.companies.inn IS 'anon: [{\"mutation_name\": \"inn\"}]';",
+    );
+    proc.parse_comment(
+        "COMMENT ON COLUMN public.people.inn IS 'anon: [{\"mutation_name\": \"inn\", \"mutation_kwargs\": {\"kind\": \"individual\"}}]';",
+    );
+    for _ in 0..200 {
+        let legal = proc.mutate_value("companies", "inn", "x").unwrap();
+        let d: Vec<u32> = legal.chars().map(|c| c.to_digit(10).unwrap()).collect();
+        assert_eq!(d.len(), 10, "{}", legal);
+        assert_eq!(d[9], check(&d[..9], &[2, 4, 10, 3, 5, 9, 4, 6, 8]), "{}", legal);
+
+        let person = proc.mutate_value("people", "inn", "x").unwrap();
+        let d: Vec<u32> = person.chars().map(|c| c.to_digit(10).unwrap()).collect();
+        assert_eq!(d.len(), 12, "{}", person);
+        assert_eq!(d[10], check(&d[..10], &[7, 2, 4, 10, 3, 5, 9, 4, 6, 8]), "{}", person);
+        assert_eq!(d[11], check(&d[..11], &[3, 7, 2, 4, 10, 3, 5, 9, 4, 6, 8]), "{}", person);
+    }
+}
+
+#[test]
+fn test_snils_layout_and_control_number() {
+    let mut proc = make_ru_processor();
+    proc.parse_comment(
+        "COMMENT ON COLUMN public.people.snils IS 'anon: [{\"mutation_name\": \"snils\", \"mutation_kwargs\": {\"unique\": true}}]';",
+    );
+    let mut seen = std::collections::HashSet::new();
+    for _ in 0..200 {
+        let snils = proc.mutate_value("people", "snils", "x").unwrap();
+        let b = snils.as_bytes();
+        assert_eq!(b.len(), 14, "{}", snils);
+        assert_eq!((b[3], b[7], b[11]), (b'-', b'-', b' '), "{}", snils);
+        let digits: Vec<u32> = snils.chars().filter_map(|c| c.to_digit(10)).collect();
+        assert_eq!(digits.len(), 11, "{}", snils);
+        let sum: u32 = digits[..9].iter().zip((1..=9).rev()).map(|(d, w)| d * w).sum();
+        let expected = match sum {
+            0..=99 => sum,
+            100 | 101 => 0,
+            _ => sum % 101 % 100,
+        };
+        assert_eq!(digits[9] * 10 + digits[10], expected, "{}", snils);
+        assert!(seen.insert(snils));
+    }
+}
+
+#[test]
+fn test_document_number_mask_and_locale_default() {
+    let mut proc = make_ru_processor();
+    proc.parse_comment(
+        "COMMENT ON COLUMN public.people.passport IS 'anon: [{\"mutation_name\": \"document_number\", \"mutation_kwargs\": {\"unique\": true}}]';",
+    );
+    proc.parse_comment(
+        "COMMENT ON COLUMN public.people.license IS 'anon: [{\"mutation_name\": \"document_number\", \"mutation_kwargs\": {\"mask\": \"@@-####\"}}]';",
+    );
+    let mut passports = std::collections::HashSet::new();
+    for _ in 0..100 {
+        let passport = proc.mutate_value("people", "passport", "4500 123456").unwrap();
+        let (series, number) = passport.split_once(' ').unwrap();
+        assert!(series.len() == 4 && series.bytes().all(|b| b.is_ascii_digit()), "{}", passport);
+        assert!(number.len() == 6 && number.bytes().all(|b| b.is_ascii_digit()), "{}", passport);
+        passports.insert(passport);
+
+        let license = proc.mutate_value("people", "license", "AB-1234").unwrap();
+        let b = license.as_bytes();
+        assert_eq!(b.len(), 7, "{}", license);
+        assert!(b[..2].iter().all(u8::is_ascii_uppercase), "{}", license);
+        assert_eq!(b[2], b'-');
+        assert!(b[3..].iter().all(u8::is_ascii_digit), "{}", license);
+    }
+    assert_eq!(passports.len(), 100);
+}
+
+#[test]
+fn test_color_hex_and_name() {
+    use pg_stage_rs::mutator::locale::{en, ru};
+
+    let mut proc = make_processor();
+    proc.parse_comment("COMMENT ON COLUMN public.themes.name IS 'anon: [{\"mutation_name\": \"color_name\"}]';");
+    proc.parse_comment("COMMENT ON COLUMN public.themes.fg IS 'anon: [{\"mutation_name\": \"color_hex\"}]';");
+    proc.parse_comment(
+        "COMMENT ON COLUMN public.themes.bg IS 'anon: [{\"mutation_name\": \"color_hex\", \"mutation_kwargs\": {\"short\": true, \"uppercase\": true}}]';",
+    );
+    for _ in 0..100 {
+        let name = proc.mutate_value("themes", "name", "x").unwrap();
+        assert!(en::COLOR_NAMES.contains(&name.as_str()), "{}", name);
+
+        let fg = proc.mutate_value("themes", "fg", "x").unwrap();
+        let hex = fg.strip_prefix('#').unwrap();
+        assert_eq!(hex.len(), 6, "{}", fg);
+        assert!(hex.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f')), "{}", fg);
+
+        let bg = proc.mutate_value("themes", "bg", "x").unwrap();
+        let hex = bg.strip_prefix('#').unwrap();
+        assert_eq!(hex.len(), 3, "{}", bg);
+        assert!(hex.bytes().all(|b| matches!(b, b'0'..=b'9' | b'A'..=b'F')), "{}", bg);
+    }
+
+    let mut proc = make_ru_processor();
+    proc.parse_comment("COMMENT ON COLUMN public.themes.name IS 'anon: [{\"mutation_name\": \"color_name\"}]';");
+    let name = proc.mutate_value("themes", "name", "x").unwrap();
+    assert!(ru::COLOR_NAMES.contains(&name.as_str()), "{}", name);
+}
+
+#[test]
+fn test_slug_words_and_charset() {
+    let mut proc = make_processor();
+    proc.parse_comment("COMMENT ON COLUMN public.posts.slug IS 'anon: [{\"mutation_name\": \"slug\"}]';");
+    proc.parse_comment(
+        "COMMENT ON COLUMN public.posts.short IS 'anon: [{\"mutation_name\": \"slug\", \"mutation_kwargs\": {\"words\": 5, \"max_length\": 12}}]';",
+    );
+    for _ in 0..100 {
+        let slug = proc.mutate_value("posts", "slug", "x").unwrap();
+        assert!(slug.bytes().all(|b| matches!(b, b'a'..=b'z' | b'0'..=b'9' | b'-')), "{}", slug);
+        assert_eq!(slug.split('-').count(), 3, "{}", slug);
+        assert!(slug.split('-').all(|w| !w.is_empty()), "{}", slug);
+
+        let short = proc.mutate_value("posts", "short", "x").unwrap();
+        assert!(short.len() <= 12 && !short.ends_with('-'), "{}", short);
+    }
+}
+
+#[test]
+fn test_file_path_style_and_extension() {
+    let mut proc = make_processor();
+    proc.parse_comment(
+        "COMMENT ON COLUMN public.files.path IS 'anon: [{\"mutation_name\": \"file_path\", \"mutation_kwargs\": {\"extension\": \"pdf\"}}]';",
+    );
+    proc.parse_comment(
+        "COMMENT ON COLUMN public.files.win IS 'anon: [{\"mutation_name\": \"file_path\", \"mutation_kwargs\": {\"style\": \"windows\", \"depth\": 2, \"extension\": \".txt\"}}]';",
+    );
+    for _ in 0..50 {
+        let path = proc.mutate_value("files", "path", "x").unwrap();
+        assert!(path.starts_with('/') && !path.contains('\\'), "{}", path);
+        assert!(path.ends_with(".pdf"), "{}", path);
+        assert_eq!(path.matches('/').count(), 4, "{}", path);
+
+        let win = proc.mutate_value("files", "win", "x").unwrap();
+        assert!(win.starts_with("C:\\") && !win.contains('/'), "{}", win);
+        assert!(win.ends_with(".txt") && !win.ends_with("..txt"), "{}", win);
+        assert_eq!(win.matches('\\').count(), 3, "{}", win);
+    }
+}
+
+#[test]
+fn test_semver_components_and_suffixes() {
+    let mut proc = make_processor();
+    proc.parse_comment(
+        "COMMENT ON COLUMN public.releases.version IS 'anon: [{\"mutation_name\": \"semver\", \"mutation_kwargs\": {\"max_major\": 2}}]';",
+    );
+    proc.parse_comment(
+        "COMMENT ON COLUMN public.releases.nightly IS 'anon: [{\"mutation_name\": \"semver\", \"mutation_kwargs\": {\"prerelease\": true, \"build\": true}}]';",
+    );
+    let parts = |core: &str| -> Vec<u64> { core.split('.').map(|p| p.parse().unwrap()).collect() };
+    for _ in 0..100 {
+        let version = proc.mutate_value("releases", "version", "x").unwrap();
+        let numbers = parts(&version);
+        assert_eq!(numbers.len(), 3, "{}", version);
+        assert!(numbers[0] <= 2 && numbers[1] <= 20 && numbers[2] <= 30, "{}", version);
+
+        let nightly = proc.mutate_value("releases", "nightly", "x").unwrap();
+        let (rest, build) = nightly.split_once('+').unwrap();
+        let (core, pre) = rest.split_once('-').unwrap();
+        assert_eq!(parts(core).len(), 3, "{}", nightly);
+        let (label, n) = pre.split_once('.').unwrap();
+        assert!(["alpha", "beta", "rc"].contains(&label), "{}", nightly);
+        assert!(n.parse::<u32>().is_ok(), "{}", nightly);
+        let build = build.strip_prefix("build.").unwrap();
+        assert!(build.parse::<u32>().is_ok(), "{}", nightly);
+    }
+}
+
+#[test]
+fn test_user_agent_looks_like_browser() {
+    let mut proc = make_processor();
+    proc.parse_comment(
+        "COMMENT ON COLUMN public.visits.agent IS 'anon: [{\"mutation_name\": \"user_agent\", \"mutation_kwargs\": {\"unique\": true}}]';",
+    );
+    let mut seen = std::collections::HashSet::new();
+    for _ in 0..200 {
+        let agent = proc.mutate_value("visits", "agent", "curl/8.0").unwrap();
+        assert!(agent.starts_with("Mozilla/5.0 ("), "{}", agent);
+        assert!(
+            ["Chrome/", "Firefox/", "Safari/"].iter().any(|t| agent.contains(t)),
+            "{}",
+            agent
+        );
+        assert!(seen.insert(agent));
+    }
+}
+
+#[test]
+fn test_timezone_from_known_set() {
+    let mut proc = make_processor();
+    proc.parse_comment(
+        "COMMENT ON COLUMN public.profiles.tz IS 'anon: [{\"mutation_name\": \"timezone\"}]';",
+    );
+    for _ in 0..100 {
+        let tz = proc.mutate_value("profiles", "tz", "UTC").unwrap();
+        assert!(tz.contains('/'), "{}", tz);
+        assert!(pg_stage_rs::mutator::datetime::TIMEZONES.contains(&tz.as_str()), "{}", tz);
+    }
+}
+
+#[test]
+fn test_birthdate_age_within_window() {
+    use chrono::{Datelike, NaiveDate, Utc};
+
+    let mut proc = make_processor();
+    proc.parse_comment(
+        "COMMENT ON COLUMN public.people.born IS 'anon: [{\"mutation_name\": \"birthdate\", \"mutation_kwargs\": {\"min_age\": 21, \"max_age\": 25}}]';",
+    );
+    let today = Utc::now().date_naive();
+    for _ in 0..200 {
+        let born = proc.mutate_value("people", "born", "1990-01-01").unwrap();
+        let born = NaiveDate::parse_from_str(&born, "%Y-%m-%d").unwrap();
+        let mut age = today.year() - born.year();
+        if (today.month(), today.day()) < (born.month(), born.day()) {
+            age -= 1;
+        }
+        assert!((21..=25).contains(&age), "{} is {} years old", born, age);
+    }
+}
+
+#[test]
+fn test_invalid_date_format_keeps_original_value() {
+    // An unknown strftime specifier fails the mutation instead of panicking.
+    let input = concat!(
+        "COMMENT ON COLUMN public.people.a IS 'anon: [{\"mutation_name\": \"date\", \"mutation_kwargs\": {\"date_format\": \"%Q\"}}]';\n",
+        "COMMENT ON COLUMN public.people.b IS 'anon: [{\"mutation_name\": \"deterministic_date\", \"mutation_kwargs\": {\"source_column\": \"id\", \"date_format\": \"%Q\"}}]';\n",
+        "COMMENT ON COLUMN public.people.c IS 'anon: [{\"mutation_name\": \"birthdate\", \"mutation_kwargs\": {\"date_format\": \"%Q\"}}]';\n",
+        "COPY public.people (id, a, b, c) FROM stdin;\n",
+        "1\t2001-01-01\t2002-02-02\t2003-03-03\n",
+        "\\.\n",
+    );
+    let mut output = Vec::new();
+    PlainHandler::new(make_processor())
+        .process(Cursor::new(b""), &mut output, input.as_bytes())
+        .unwrap();
+    let result = String::from_utf8(output).unwrap();
+    assert!(result.contains("1\t2001-01-01\t2002-02-02\t2003-03-03\n"));
+}
+
+#[test]
+fn test_template_fills_placeholders() {
+    let mut proc = make_processor();
+    proc.parse_comment(
+        "COMMENT ON COLUMN public.contacts.display IS 'anon: [{\"mutation_name\": \"template\", \"mutation_kwargs\": {\"pattern\": \"{first_name} {last_name} <{email}> {{id}}\"}}]';",
+    );
+    for _ in 0..20 {
+        let display = proc.mutate_value("contacts", "display", "x").unwrap();
+        let (name, rest) = display.split_once(" <").unwrap();
+        let (email, tail) = rest.split_once("> ").unwrap();
+        assert_eq!(name.split(' ').count(), 2, "{}", display);
+        assert!(!name.contains('{'), "{}", display);
+        assert!(email.contains('@'), "{}", display);
+        assert_eq!(tail, "{id}");
+    }
+
+    proc.parse_comment(
+        "COMMENT ON COLUMN public.contacts.bad IS 'anon: [{\"mutation_name\": \"template\", \"mutation_kwargs\": {\"pattern\": \"{no_such_mutation}\"}}]';",
+    );
+    // An unknown placeholder fails the mutation, so the value is kept.
+    assert_eq!(proc.mutate_value("contacts", "bad", "x").as_deref(), Some("x"));
+}
+
+#[test]
+fn test_regex_replace_keeps_prefix_and_groups() {
+    let mut proc = make_processor();
+    proc.parse_comment(
+        r#"COMMENT ON COLUMN public.orders.code IS 'anon: [{"mutation_name": "regex_replace", "mutation_kwargs": {"pattern": "(ORDER)-\\d+", "replacement": "$1-#####"}}]';"#,
+    );
+    proc.parse_comment(
+        r#"COMMENT ON COLUMN public.orders.ref IS 'anon: [{"mutation_name": "regex_replace", "mutation_kwargs": {"pattern": "(?P<year>\\d{4})/\\d+", "replacement": "${year}/@@#"}}]';"#,
+    );
+    for _ in 0..20 {
+        let code = proc.mutate_value("orders", "code", "ORDER-12345 shipped").unwrap();
+        let digits = code.strip_prefix("ORDER-").unwrap().strip_suffix(" shipped").unwrap();
+        assert_eq!(digits.len(), 5, "{}", code);
+        assert!(digits.bytes().all(|b| b.is_ascii_digit()), "{}", code);
+
+        let reference = proc.mutate_value("orders", "ref", "ref 2024/991").unwrap();
+        let tail = reference.strip_prefix("ref 2024/").unwrap();
+        let bytes = tail.as_bytes();
+        assert_eq!(bytes.len(), 3, "{}", reference);
+        assert!(bytes[..2].iter().all(u8::is_ascii_uppercase), "{}", reference);
+        assert!(bytes[2].is_ascii_digit(), "{}", reference);
+    }
+    assert_eq!(
+        proc.mutate_value("orders", "code", "no order here").as_deref(),
+        Some("no order here")
+    );
+}
+
+#[test]
+fn test_full_name_order() {
+    use pg_stage_rs::mutator::locale::{en, ru};
+
+    let (first_names, last_names) = (en::FIRST_NAMES, en::LAST_NAMES);
+    let mut proc = make_processor();
+    proc.parse_comment(
+        "COMMENT ON COLUMN public.users.name IS 'anon: [{\"mutation_name\": \"full_name\"}]';",
+    );
+    proc.parse_comment(
+        "COMMENT ON COLUMN public.users.formal IS 'anon: [{\"mutation_name\": \"full_name\", \"mutation_kwargs\": {\"order\": \"last_first\"}}]';",
+    );
+    for _ in 0..20 {
+        let name = proc.mutate_value("users", "name", "x").unwrap();
+        let (first, last) = name.split_once(' ').unwrap();
+        assert!(first_names.contains(&first) && last_names.contains(&last), "{}", name);
+
+        let formal = proc.mutate_value("users", "formal", "x").unwrap();
+        let (last, first) = formal.split_once(' ').unwrap();
+        assert!(first_names.contains(&first) && last_names.contains(&last), "{}", formal);
+    }
+
+    let mut ru = make_ru_processor();
+    ru.parse_comment(
+        "COMMENT ON COLUMN public.users.fio IS 'anon: [{\"mutation_name\": \"full_name\", \"mutation_kwargs\": {\"order\": \"first_patronymic_last\"}}]';",
+    );
+    let fio = ru.mutate_value("users", "fio", "x").unwrap();
+    let parts: Vec<&str> = fio.split(' ').collect();
+    assert_eq!(parts.len(), 3, "{}", fio);
+    let patronymics = [ru::PATRONYMICS_MALE, ru::PATRONYMICS_FEMALE].concat();
+    assert!(patronymics.contains(&parts[1]), "{}", fio);
+}
+
+#[test]
+fn test_name_group_shares_one_person() {
+    let input = concat!(
+        "COMMENT ON COLUMN public.people.first IS 'anon: [{\"mutation_name\": \"first_name\", \"mutation_kwargs\": {\"name_group\": \"person\"}}]';\n",
+        "COMMENT ON COLUMN public.people.last IS 'anon: [{\"mutation_name\": \"last_name\", \"mutation_kwargs\": {\"name_group\": \"person\"}}]';\n",
+        "COMMENT ON COLUMN public.people.full IS 'anon: [{\"mutation_name\": \"full_name\", \"mutation_kwargs\": {\"name_group\": \"person\"}}]';\n",
+        "COPY public.people (id, first, last, full) FROM stdin;\n",
+        "1\tAlice\tSmith\tAlice Smith\n",
+        "2\tBob\tJones\tBob Jones\n",
+        "3\tCarol\tWhite\tCarol White\n",
+        "\\.\n",
+    );
+    let mut output = Vec::new();
+    PlainHandler::new(make_processor())
+        .process(Cursor::new(b""), &mut output, input.as_bytes())
+        .unwrap();
+    let output = String::from_utf8(output).unwrap();
+    let rows: Vec<&str> = output
+        .lines()
+        .skip_while(|l| !l.starts_with("COPY"))
+        .skip(1)
+        .take_while(|l| *l != "\\.")
+        .collect();
+    assert_eq!(rows.len(), 3);
+    for row in rows {
+        let cols: Vec<&str> = row.split('\t').collect();
+        assert_eq!(cols[3], format!("{} {}", cols[1], cols[2]), "{}", row);
+    }
+}
+
+#[test]
+fn test_invalid_comment_json_is_reported() {
+    let input = concat!(
+        "COMMENT ON COLUMN public.users.email IS 'anon: [{\"mutation_name\": \"email\"';\n",
+        "COPY public.users (id, email) FROM stdin;\n",
+        "1\ta@example.com\n",
+        "\\.\n",
+    );
+
+    // Lenient: counted and kept for `check_rules`, which only fails in strict mode.
+    let mut proc = make_processor();
+    assert!(proc.parse_comment(input.lines().next().unwrap()));
+    assert_eq!(proc.parse_warnings(), (1, 0));
+    assert!(proc.check_rules().is_ok());
+
+    let mut strict = make_processor();
+    strict.set_strict(true);
+    let mut output = Vec::new();
+    let err = PlainHandler::new(strict)
+        .process(Cursor::new(b""), &mut output, input.as_bytes())
+        .unwrap_err();
+    let message = err.to_string();
+    assert!(message.contains("invalid JSON in COMMENT ON COLUMN public.users.email"), "{}", message);
+    assert!(!String::from_utf8(output).unwrap().contains("a@example.com"));
+}
+
+/// Log records (`"LEVEL message"`) of every test in this binary, captured
+/// at debug level; a logger can only be installed once per process. Call it
+/// before the code under test to install the logger.
+fn captured_logs() -> std::sync::MutexGuard<'static, Vec<String>> {
+    use std::sync::{Mutex, Once};
+
+    struct CaptureLogger(Mutex<Vec<String>>);
+    impl log::Log for CaptureLogger {
+        fn enabled(&self, _: &log::Metadata) -> bool {
+            true
+        }
+        fn log(&self, record: &log::Record) {
+            let line = format!("{} {}", record.level(), record.args());
+            self.0.lock().unwrap().push(line);
+        }
+        fn flush(&self) {}
+    }
+    static LOGGER: CaptureLogger = CaptureLogger(Mutex::new(Vec::new()));
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        log::set_logger(&LOGGER).unwrap();
+        log::set_max_level(log::LevelFilter::Debug);
+    });
+    LOGGER.0.lock().unwrap()
+}
+
+#[test]
+fn test_table_setup_emits_debug_record() {
+    drop(captured_logs());
+    let input = concat!(
+        "COMMENT ON COLUMN public.logged_users.email IS 'anon: [{\"mutation_name\": \"email\"}]';\n",
+        "COPY public.logged_users (id, email) FROM stdin;\n",
+        "1\ta@example.com\n",
+        "\\.\n",
+    );
+    let mut output = Vec::new();
+    PlainHandler::new(make_processor())
+        .process(Cursor::new(b""), &mut output, input.as_bytes())
+        .unwrap();
+    let records = captured_logs();
+    assert!(
+        records.iter().any(|r| r == "DEBUG COPY public.logged_users: mutating email"),
+        "{:?}",
+        records
+    );
+}
+
+#[test]
+fn test_row_rewrite_output_is_stable() {
+    // Deterministic mutations only, so the expected dump is fixed: escaped
+    // and CSV cells, NULLs, conditions, lookups of other columns and nested
+    // mutations all go through the per-row scratch buffers.
+    let input = concat!(
+        "COMMENT ON COLUMN public.accounts.login IS 'anon: [{\"mutation_name\": \"regex_replace\", \"mutation_kwargs\": {\"pattern\": \"^(\\\\w)\\\\w*\", \"replacement\": \"$1-user\"}}]';\n",
+        "COMMENT ON COLUMN public.accounts.note IS 'anon: [{\"mutation_name\": \"truncate\", \"mutation_kwargs\": {\"length\": 6}}]';\n",
+        "COMMENT ON COLUMN public.accounts.secret IS 'anon: [{\"mutation_name\": \"fixed_value\", \"mutation_kwargs\": {\"value\": \"a\\tb\\\\c\"}}]';\n",
+        "COMMENT ON COLUMN public.accounts.ref IS 'anon: [{\"mutation_name\": \"uuid5_by_source_value\", \"mutation_kwargs\": {\"namespace\": \"6ba7b810-9dad-11d1-80b4-00c04fd430c8\", \"source_column\": \"login\"}}]';\n",
+        "COMMENT ON COLUMN public.accounts.tags IS 'anon: [{\"mutation_name\": \"array\", \"mutation_kwargs\": {\"element\": {\"mutation_name\": \"fixed_value\", \"mutation_kwargs\": {\"value\": \"x,y\"}}}}]';\n",
+        "COMMENT ON COLUMN public.accounts.phone IS 'anon: [{\"mutation_name\": \"null\", \"conditions\": [{\"column_name\": \"id\", \"operation\": \"equal\", \"value\": \"2\"}]}, {\"mutation_name\": \"empty_string\"}]';\n",
+        "COMMENT ON COLUMN public.contacts.email IS 'anon: [{\"mutation_name\": \"fixed_value\", \"mutation_kwargs\": {\"value\": \"a,\\\"b\\\"\"}}]';\n",
+        "COPY public.accounts (id, login, note, secret, ref, tags, phone) FROM stdin;\n",
+        "1\talice\tline\\none\\ttwo\tpw\tr\t{a,b}\t555\n",
+        "2\tbob\tshort\t\\N\tr\t{\"c d\",NULL}\t\\N\n",
+        "\\.\n",
+        "COPY public.contacts (id, email) FROM stdin WITH (FORMAT csv);\n",
+        "1,\"x,y\"\n",
+        "2,\n",
+        "\\.\n",
+    );
+    let mut output = Vec::new();
+    PlainHandler::new(make_processor())
+        .process(Cursor::new(b""), &mut output, input.as_bytes())
+        .unwrap();
+    let output = String::from_utf8(output).unwrap();
+    let data: Vec<&str> = output.lines().filter(|l| !l.starts_with("COMMENT")).collect();
+    assert_eq!(
+        data,
+        [
+            "COPY public.accounts (id, login, note, secret, ref, tags, phone) FROM stdin;",
+            "1\ta-user\tline\\no\ta\\tb\\\\c\ta1fad12c-1f0a-5f68-876d-bd54e85d8efa\t{\"x,y\",\"x,y\"}\t",
+            "2\tb-user\tshort\ta\\tb\\\\c\ta5ee2f80-7557-5997-8b0b-95a5202391da\t{\"x,y\",NULL}\t\\N",
+            "\\.",
+            "COPY public.contacts (id, email) FROM stdin WITH (FORMAT csv);",
+            "1,\"a,\"\"b\"\"\"",
+            "2,\"a,\"\"b\"\"\"",
+            "\\.",
+        ]
+    );
+}
+
+/// Throughput check for the COPY row path; run with
+/// `cargo test --release -- --ignored --nocapture`.
+#[test]
+#[ignore]
+fn bench_copy_row_throughput() {
+    const ROWS: usize = 200_000;
+    let mut input = String::from(concat!(
+        "COMMENT ON COLUMN public.events.email IS 'anon: [{\"mutation_name\": \"email\"}]';\n",
+        "COMMENT ON COLUMN public.events.note IS 'anon: [{\"mutation_name\": \"truncate\", \"mutation_kwargs\": {\"length\": 4}}]';\n",
+        "COPY public.events (id, email, note, payload) FROM stdin;\n",
+    ));
+    for i in 0..ROWS {
+        input.push_str(&format!("{}\tuser{}@example.com\tline\\tone\t{{\"k\": {}}}\n", i, i, i));
+    }
+    input.push_str("\\.\n");
+
+    let started = std::time::Instant::now();
+    let mut output = Vec::with_capacity(input.len());
+    PlainHandler::new(make_processor())
+        .process(Cursor::new(b""), &mut output, input.as_bytes())
+        .unwrap();
+    let elapsed = started.elapsed();
+    println!(
+        "{} rows in {:?} ({:.0} rows/s)",
+        ROWS,
+        elapsed,
+        ROWS as f64 / elapsed.as_secs_f64()
+    );
+    let output = String::from_utf8(output).unwrap();
+    assert!(!output.contains("@example.com\t"));
+    assert!(elapsed < std::time::Duration::from_secs(30), "{:?}", elapsed);
+}
+
+#[test]
+fn test_comment_and_pattern_specs_merge_per_copy() {
+    let rules = r#"{
+        "column_patterns": [{
+            "table": "^public\\.users$",
+            "column": "^email$",
+            "mutations": [{"mutation_name": "fixed_value", "mutation_kwargs": {"value": "RULE"}}]
+        }]
+    }"#;
+    let dump = concat!(
+        "COMMENT ON COLUMN public.users.email IS 'anon: [{\"mutation_name\": \"fixed_value\", \"mutation_kwargs\": {\"value\": \"COMMENT\"}, \"conditions\": [{\"column_name\": \"id\", \"operation\": \"equal\", \"value\": \"1\"}]}]';\n",
+        "COPY public.users (id, email) FROM stdin;\n",
+        "1\ta@example.com\n",
+        "2\tb@example.com\n",
+        "\\.\n",
+        "COPY public.users (id, email) FROM stdin;\n",
+        "1\tc@example.com\n",
+        "2\td@example.com\n",
+        "\\.\n",
+    );
+    let mut proc = make_processor();
+    proc.load_rules(rules).unwrap();
+    let mut output = Vec::new();
+    let mut handler = PlainHandler::new(proc);
+    handler.process(Cursor::new(b""), &mut output, dump.as_bytes()).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    assert_eq!(output.matches("1\tCOMMENT\n").count(), 2, "{}", output);
+    assert_eq!(output.matches("2\tRULE\n").count(), 2, "{}", output);
+
+    // Merging for the COPY leaves the registry's own lists untouched.
+    let proc = handler.into_processor();
+    assert_eq!(proc.registry.mutation_map["public.users"]["email"].len(), 1);
+    assert_eq!(proc.registry.column_pattern_rules[0].2.len(), 1);
+}
+
+/// Throughput check for wide tables, where every mutated cell looks up its
+/// column and its `source_column`; run with
+/// `cargo test --release -- --ignored --nocapture`.
+#[test]
+#[ignore]
+fn bench_wide_table_throughput() {
+    const COLUMNS: usize = 60;
+    const ROWS: usize = 20_000;
+    let mut input = String::new();
+    for c in (0..COLUMNS).step_by(2) {
+        input.push_str(&format!(
+            "COMMENT ON COLUMN public.wide.c{} IS 'anon: [{{\"mutation_name\": \"uuid5_by_source_value\", \"mutation_kwargs\": {{\"namespace\": \"6ba7b810-9dad-11d1-80b4-00c04fd430c8\", \"source_column\": \"c{}\"}}}}]';\n",
+            c,
+            c + 1
+        ));
+    }
+    let names: Vec<String> = (0..COLUMNS).map(|c| format!("c{}", c)).collect();
+    input.push_str(&format!("COPY public.wide ({}) FROM stdin;\n", names.join(", ")));
+    for r in 0..ROWS {
+        let cells: Vec<String> = (0..COLUMNS).map(|c| format!("v{}_{}", r, c)).collect();
+        input.push_str(&cells.join("\t"));
+        input.push('\n');
+    }
+    input.push_str("\\.\n");
+
+    let started = std::time::Instant::now();
+    let mut output = Vec::with_capacity(input.len() * 2);
+    PlainHandler::new(make_processor())
+        .process(Cursor::new(b""), &mut output, input.as_bytes())
+        .unwrap();
+    let elapsed = started.elapsed();
+    println!(
+        "{} rows x {} columns in {:?} ({:.0} cells/s)",
+        ROWS,
+        COLUMNS,
+        elapsed,
+        (ROWS * COLUMNS) as f64 / elapsed.as_secs_f64()
+    );
+    let output = String::from_utf8(output).unwrap();
+    let first_row = output.lines().skip_while(|l| !l.starts_with("COPY")).nth(1).unwrap();
+    let cells: Vec<&str> = first_row.split('\t').collect();
+    assert_ne!(cells[0], "v0_0");
+    assert_eq!(cells[1], "v0_1");
+    assert!(elapsed < std::time::Duration::from_secs(30), "{:?}", elapsed);
+}
+
+#[test]
+fn test_custom_format_skip_corrupt_resumes_at_next_block() {
+    use pg_stage_rs::format::custom::CustomHandler;
+
+    let mut dump = build_toc_dat(1, false, &users_toc_entries());
+    // Block 3 claims a chunk far over the size limit, then stops short.
+    dump.push(0x01);
+    put_int(&mut dump, 3);
+    put_int(&mut dump, i32::MAX);
+    dump.extend_from_slice(b"1\tkeep me\n");
+    let corrupt_end = dump.len();
+    dump.push(0x01);
+    put_int(&mut dump, 2);
+    let data = b"1\tjohn@example.com\n";
+    put_int(&mut dump, data.len() as i32);
+    dump.extend_from_slice(data);
+    put_int(&mut dump, 0);
+    dump.push(0x04);
+
+    let mut output = Vec::new();
+    assert!(CustomHandler::new(make_processor())
+        .process(Cursor::new(&dump[5..]), &mut output, &dump[..5])
+        .is_err());
+
+    let mut output = Vec::new();
+    CustomHandler::new(make_processor())
+        .skip_corrupt(true)
+        .process(Cursor::new(&dump[5..]), &mut output, &dump[..5])
+        .unwrap();
+    assert_eq!(output[..corrupt_end], dump[..corrupt_end]);
+    let rest = String::from_utf8_lossy(&output[corrupt_end..]).into_owned();
+    assert!(rest.contains("1\tREDACTED\n"), "{rest:?}");
+    assert!(!rest.contains("john@example.com"));
+    assert_eq!(output.last(), Some(&0x04));
+}
+
+#[test]
+fn test_custom_format_rewrites_toc_data_offsets() {
+    use pg_stage_rs::format::custom::header::parse_header;
+    use pg_stage_rs::format::custom::toc::{parse_toc, DataState};
+    use pg_stage_rs::format::custom::CustomHandler;
+
+    let toc_entries = |dump: &[u8]| {
+        let mut reader = dump;
+        let mut toc = Vec::new();
+        let header = parse_header(&mut reader, &mut toc, &[]).unwrap();
+        let toc_start = toc.len() as u64;
+        let entries = parse_toc(&mut reader, &mut toc, &header).unwrap();
+        (toc_start, entries)
+    };
+
+    let mut dump = build_toc_dat(1, false, &users_toc_entries());
+    for (id, data) in [(2, &b"1\tjohn@example.com\n2\tjane@example.com\n"[..]), (3, b"1\tkeep me\n")] {
+        dump.push(0x01);
+        put_int(&mut dump, id);
+        put_int(&mut dump, data.len() as i32);
+        dump.extend_from_slice(data);
+        put_int(&mut dump, 0);
+    }
+    dump.push(0x04);
+    // Record stale offsets, as if the input had been written to a file.
+    let (toc_start, entries) = toc_entries(&dump);
+    for entry in entries.iter().filter(|e| e.data_state.has_data()) {
+        let at = (toc_start + entry.offset_pos) as usize;
+        dump[at] = 2;
+        dump[at + 1..at + 9].copy_from_slice(&12345u64.to_le_bytes());
+    }
+
+    let mut streamed = Vec::new();
+    CustomHandler::new(make_processor())
+        .process(Cursor::new(&dump[5..]), &mut streamed, &dump[..5])
+        .unwrap();
+    let (_, entries) = toc_entries(&streamed);
+    for entry in entries.iter().filter(|e| e.desc == "TABLE DATA") {
+        assert_eq!((entry.data_state, entry.offset), (DataState::PosNotSet, 0));
+    }
+
+    let mut file = Cursor::new(Vec::new());
+    CustomHandler::new(make_processor())
+        .process_seekable(Cursor::new(&dump[5..]), &mut file, &dump[..5])
+        .unwrap();
+    let output = file.into_inner();
+    assert_eq!(output.len(), streamed.len());
+    assert!(String::from_utf8_lossy(&output).contains("REDACTED"));
+    let (_, entries) = toc_entries(&output);
+    let mut checked = 0;
+    for entry in entries.iter().filter(|e| e.desc == "TABLE DATA") {
+        assert_eq!(entry.data_state, DataState::PosSet);
+        let mut expected = vec![0x01];
+        put_int(&mut expected, entry.dump_id as i32);
+        let at = entry.offset as usize;
+        assert_eq!(output[at..at + expected.len()], expected[..], "block {}", entry.dump_id);
+        checked += 1;
+    }
+    assert_eq!(checked, 2);
+
+    #[cfg(feature = "parallel")]
+    {
+        let mut file = Cursor::new(Vec::new());
+        CustomHandler::new(make_processor())
+            .threads(2)
+            .process_seekable(Cursor::new(&dump[5..]), &mut file, &dump[..5])
+            .unwrap();
+        assert_eq!(file.into_inner(), output);
+    }
+}
+
+#[test]
+fn test_repeated_column_comments_merge_or_replace() {
+    use pg_stage_rs::processor::ProcessorConfig;
+
+    drop(captured_logs());
+    let comments = [
+        "COMMENT ON COLUMN public.users.email IS 'anon: [{\"mutation_name\": \"fixed_value\", \"mutation_kwargs\": {\"value\": \"admin@example.com\"}, \"conditions\": [{\"column_name\": \"role\", \"operation\": \"equal\", \"value\": \"admin\"}]}]';",
+        "COMMENT ON COLUMN public.users.email IS 'anon: [{\"mutation_name\": \"fixed_value\", \"mutation_kwargs\": {\"value\": \"user@example.com\"}}]';",
+    ];
+    let run = |merge: bool| {
+        let mut proc = DataProcessor::builder()
+            .config(ProcessorConfig {
+                merge_comments: merge,
+                ..Default::default()
+            })
+            .build();
+        for comment in comments {
+            assert!(proc.parse_comment(comment));
+        }
+        proc.setup_table("COPY public.users (id, role, email) FROM stdin;");
+        [b"1\tadmin\ta@x.org", b"2\tstaff\tb@x.org"]
+            .map(|line| String::from_utf8(proc.process_line(line).unwrap().to_vec()).unwrap())
+    };
+
+    assert_eq!(
+        run(true),
+        ["1\tadmin\tadmin@example.com", "2\tstaff\tuser@example.com"]
+    );
+    // Without merging the later comment replaces the earlier one.
+    assert_eq!(
+        run(false),
+        ["1\tadmin\tuser@example.com", "2\tstaff\tuser@example.com"]
+    );
+    assert!(captured_logs()
+        .iter()
+        .any(|l| l.starts_with("WARN COMMENT ON COLUMN public.users.email replaces")));
+}
+
+#[test]
+fn test_middle_name_english_full_and_initial() {
+    use pg_stage_rs::mutator::locale::en;
+
+    let input = concat!(
+        "COMMENT ON COLUMN public.people.first IS 'anon: [{\"mutation_name\": \"first_name\", \"mutation_kwargs\": {\"name_group\": \"p\"}}]';\n",
+        "COMMENT ON COLUMN public.people.middle IS 'anon: [{\"mutation_name\": \"middle_name\", \"mutation_kwargs\": {\"name_group\": \"p\"}}]';\n",
+        "COMMENT ON COLUMN public.people.mi IS 'anon: [{\"mutation_name\": \"middle_name\", \"mutation_kwargs\": {\"name_group\": \"p\", \"style\": \"initial\"}}]';\n",
+        "COMMENT ON COLUMN public.people.other IS 'anon: [{\"mutation_name\": \"middle_name\", \"mutation_kwargs\": {\"style\": \"initial\"}}]';\n",
+        "COPY public.people (first, middle, mi, other) FROM stdin;\n",
+        "Alice\tMay\tM.\tQ.\n",
+        "Bob\tLee\tL.\tR.\n",
+        "Carol\tAnn\tA.\tS.\n",
+        "\\.\n",
+    );
+    let mut output = Vec::new();
+    PlainHandler::new(make_processor())
+        .process(Cursor::new(b""), &mut output, input.as_bytes())
+        .unwrap();
+    let output = String::from_utf8(output).unwrap();
+    let rows: Vec<&str> = output
+        .lines()
+        .skip_while(|l| !l.starts_with("COPY"))
+        .skip(1)
+        .take_while(|l| *l != "\\.")
+        .collect();
+    assert_eq!(rows.len(), 3);
+    for row in rows {
+        let cols: Vec<&str> = row.split('\t').collect();
+        assert!(en::FIRST_NAMES.contains(&cols[1]), "{}", row);
+        assert_ne!(cols[1], cols[0], "{}", row);
+        assert_eq!(cols[2], format!("{}.", &cols[1][..1]), "{}", row);
+        assert!(cols[3].len() == 2 && cols[3].ends_with('.'), "{}", row);
+    }
+
+    let mut proc = make_processor();
+    proc.parse_comment(
+        "COMMENT ON COLUMN public.people.middle IS 'anon: [{\"mutation_name\": \"middle_name\", \"mutation_kwargs\": {\"style\": \"short\"}}]';",
+    );
+    // An unknown style fails the mutation, which keeps the original value.
+    assert_eq!(proc.mutate_value("public.people", "middle", "May").as_deref(), Some("May"));
+}
+
+#[test]
+fn test_max_length_caps_email_and_address() {
+    let mut proc = make_ru_processor();
+    proc.parse_comment(
+        "COMMENT ON COLUMN public.users.email IS 'anon: [{\"mutation_name\": \"email\", \"mutation_kwargs\": {\"max_length\": 12}}]';",
+    );
+    proc.parse_comment(
+        "COMMENT ON COLUMN public.users.address IS 'anon: [{\"mutation_name\": \"address\", \"mutation_kwargs\": {\"max_length\": 9}}]';",
+    );
+    for _ in 0..50 {
+        let email = proc.mutate_value("public.users", "email", "a@b.c").unwrap();
+        assert!(email.chars().count() <= 12 && !email.is_empty(), "{email}");
+        // Russian addresses are Cyrillic: the cut must count characters.
+        let address = proc.mutate_value("public.users", "address", "x").unwrap();
+        assert_eq!(address.chars().count(), 9, "{address}");
+    }
+
+    proc.parse_comment(
+        "COMMENT ON COLUMN public.users.email IS 'anon: [{\"mutation_name\": \"email\", \"mutation_kwargs\": {\"max_length\": 0}}]';",
+    );
+    assert_eq!(proc.mutate_value("public.users", "email", "a@b.c").as_deref(), Some("a@b.c"));
+}
+
+#[test]
+fn test_length_warning_names_table_and_column() {
+    use pg_stage_rs::processor::ProcessorConfig;
+
+    drop(captured_logs());
+    let input = concat!(
+        "COMMENT ON COLUMN public.notes.body IS 'anon: [{\"mutation_name\": \"fixed_value\", \"mutation_kwargs\": {\"value\": \"a rather long replacement text\", \"max_length\": 10}}]';\n",
+        "COMMENT ON COLUMN public.notes.title IS 'anon: [{\"mutation_name\": \"fixed_value\", \"mutation_kwargs\": {\"value\": \"short\", \"max_length\": 10}}]';\n",
+        "COPY public.notes (id, title, body) FROM stdin;\n",
+        "1\tt1\tfirst\n",
+        "2\tt2\tsecond\n",
+        "\\.\n",
+    );
+    let processor = DataProcessor::builder()
+        .config(ProcessorConfig {
+            length_warnings: true,
+            ..Default::default()
+        })
+        .build();
+    let mut output = Vec::new();
+    PlainHandler::new(processor)
+        .process(Cursor::new(b""), &mut output, input.as_bytes())
+        .unwrap();
+    // Warned, not enforced.
+    assert!(String::from_utf8(output).unwrap().contains("2\tshort\ta rather long replacement text\n"));
+
+    let records = captured_logs();
+    let warnings: Vec<&String> = records
+        .iter()
+        .filter(|r| r.starts_with("WARN public.notes."))
+        .collect();
+    assert_eq!(
+        warnings,
+        ["WARN public.notes.body row 1: fixed_value produced 30 characters, over max_length 10; later values in this column are not reported"],
+    );
+}
+
+#[test]
+fn test_uuid5_by_source_value_ignores_date_unless_unstable() {
+    let run = |stable: Option<bool>, deterministic: bool| {
+        let kwargs = match stable {
+            Some(s) => format!(", \"stable\": {s}"),
+            None => String::new(),
+        };
+        let input = format!(
+            concat!(
+                // The source is read after mutation, so pin it to a known value.
+                "COMMENT ON COLUMN public.users.email IS 'anon: [{{\"mutation_name\": \"fixed_value\", \"mutation_kwargs\": {{\"value\": \"alice@example.com\"}}}}]';\n",
+                "COMMENT ON COLUMN public.users.ext_id IS 'anon: [{{\"mutation_name\": \"uuid5_by_source_value\", \"mutation_kwargs\": {{\"namespace\": \"6ba7b810-9dad-11d1-80b4-00c04fd430c8\", \"source_column\": \"email\"{}}}}}]';\n",
+                "COPY public.users (email, ext_id) FROM stdin;\n",
+                "bob@example.com\tx\n",
+                "\\.\n",
+            ),
+            kwargs
+        );
+        let mut processor = make_processor();
+        // Deterministic mode pins the clock to 2024-01-01, unlike a normal run.
+        processor.set_deterministic(deterministic);
+        let mut output = Vec::new();
+        PlainHandler::new(processor)
+            .process(Cursor::new(b""), &mut output, input.as_bytes())
+            .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        output.lines().find_map(|l| l.strip_prefix("alice@example.com\t")).unwrap().to_string()
+    };
+
+    // uuid5(DNS namespace, "alice@example.com"): the source value alone.
+    let stable = run(None, false);
+    assert_eq!(stable, "edabafbb-61ad-51d6-9fd8-e976c892a731");
+    assert_eq!(run(None, true), stable);
+    assert_eq!(run(Some(true), false), stable);
+    assert_ne!(run(Some(false), false), stable);
+}
+
+// --- Archive fixtures (directory / tar formats) ---
+
+fn put_int(buf: &mut Vec<u8>, v: i32) {
+    buf.push(if v < 0 { 1 } else { 0 });
+    buf.extend_from_slice(&v.unsigned_abs().to_le_bytes());
+}
+
+fn put_str(buf: &mut Vec<u8>, s: Option<&str>) {
+    match s {
+        Some(s) => {
+            put_int(buf, s.len() as i32);
+            buf.extend_from_slice(s.as_bytes());
+        }
+        None => put_int(buf, -1),
+    }
+}
+
+/// Build a format-1.14 `toc.dat` (int_size 4, offset_size 8) whose entries
+/// end with a data file name, as written by the directory and tar formats,
+/// or with a data state and offset for custom archives (`format` 1).
+/// Each entry is `(dump_id, desc, defn, copy_stmt, filename)`.
+fn build_toc_dat(format: u8, compressed: bool, entries: &[(i32, &str, &str, &str, &str)]) -> Vec<u8> {
+    let mut buf = b"PGDMP".to_vec();
+    buf.extend_from_slice(&[1, 14, 0, 4, 8, format]);
+    put_int(&mut buf, if compressed { -1 } else { 0 });
+    for v in [0, 0, 12, 1, 0, 124, 0] {
+        put_int(&mut buf, v);
+    }
+    put_str(&mut buf, Some("testdb"));
+    put_str(&mut buf, Some("16.0"));
+    put_str(&mut buf, Some("16.0"));
+    put_int(&mut buf, entries.len() as i32);
+    for &(dump_id, desc, defn, copy_stmt, filename) in entries {
+        put_int(&mut buf, dump_id);
+        put_int(&mut buf, 1);
+        put_str(&mut buf, Some("0"));
+        put_str(&mut buf, Some("0"));
+        put_str(&mut buf, Some("users"));
+        put_str(&mut buf, Some(desc));
+        put_int(&mut buf, if desc == "TABLE DATA" { 2 } else { 1 });
+        put_str(&mut buf, Some(defn));
+        put_str(&mut buf, Some(""));
+        put_str(&mut buf, Some(copy_stmt));
+        put_str(&mut buf, Some("public"));
+        put_str(&mut buf, None);
+        put_str(&mut buf, Some("heap"));
+        put_str(&mut buf, Some("postgres"));
+        put_str(&mut buf, Some("false"));
+        put_str(&mut buf, None);
+        if format == 1 {
+            // Custom archives: data state byte (offset not set / no data)
+            // and block offset.
+            buf.push(if matches!(desc, "TABLE DATA" | "BLOBS") { 1 } else { 3 });
+            buf.extend_from_slice(&[0u8; 8]);
+        } else {
+            put_str(&mut buf, if filename.is_empty() { None } else { Some(filename) });
+        }
+    }
+    buf
+}
+
+fn users_toc_entries() -> Vec<(i32, &'static str, &'static str, &'static str, &'static str)> {
+    vec![
+        (
+            1,
+            "COMMENT",
+            "COMMENT ON COLUMN public.users.email IS 'anon: [{\"mutation_name\": \"fixed_value\", \"mutation_kwargs\": {\"value\": \"REDACTED\"}}]';\n",
+            "",
+            "",
+        ),
+        (2, "TABLE DATA", "", "COPY public.users (id, email) FROM stdin;\n", "2.dat"),
+        (3, "TABLE DATA", "", "COPY public.orders (id, note) FROM stdin;\n", "3.dat"),
+    ]
+}
+
+fn temp_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("pg_stage_rs_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[cfg(feature = "zlib")]
+#[test]
+fn test_directory_format_mutates_data_file() {
+    use flate2::read::GzDecoder;
+    use flate2::write::GzEncoder;
+    use pg_stage_rs::format::directory::DirectoryHandler;
+    use std::io::{Read, Write};
+
+    let input = temp_dir("dir_in");
+    let output = temp_dir("dir_out");
+    let toc = build_toc_dat(5, true, &users_toc_entries());
+    std::fs::write(input.join("toc.dat"), &toc).unwrap();
+    let mut gz = GzEncoder::new(Vec::new(), flate2::Compression::default());
+    gz.write_all(b"1\tjohn@example.com\n2\tjane@example.com\n\\.\n").unwrap();
+    std::fs::write(input.join("2.dat.gz"), gz.finish().unwrap()).unwrap();
+    let mut gz = GzEncoder::new(Vec::new(), flate2::Compression::default());
+    gz.write_all(b"1\tkeep me\n\\.\n").unwrap();
+    let untouched = gz.finish().unwrap();
+    std::fs::write(input.join("3.dat.gz"), &untouched).unwrap();
+
+    let mut handler = DirectoryHandler::new(make_processor());
+    handler.process(&input, &output).unwrap();
+
+    assert_eq!(std::fs::read(output.join("toc.dat")).unwrap(), toc);
+    assert_eq!(std::fs::read(output.join("3.dat.gz")).unwrap(), untouched);
+    let mut data = String::new();
+    GzDecoder::new(std::fs::File::open(output.join("2.dat.gz")).unwrap())
+        .read_to_string(&mut data)
+        .unwrap();
+    assert_eq!(data, "1\tREDACTED\n2\tREDACTED\n\\.\n");
+
+    let _ = std::fs::remove_dir_all(&input);
+    let _ = std::fs::remove_dir_all(&output);
+}
+
+#[test]
+fn test_directory_format_rejects_mutated_lz4_data_file() {
+    use pg_stage_rs::error::PgStageError;
+    use pg_stage_rs::format::directory::DirectoryHandler;
+
+    let input = temp_dir("dir_lz4_in");
+    let output = temp_dir("dir_lz4_out");
+    std::fs::write(input.join("toc.dat"), build_toc_dat(5, true, &users_toc_entries())).unwrap();
+    std::fs::write(input.join("2.dat.lz4"), b"not really lz4").unwrap();
+
+    let mut handler = DirectoryHandler::new(make_processor());
+    let err = handler.process(&input, &output).unwrap_err();
+    assert!(matches!(err, PgStageError::UnsupportedVersion(_)), "{:?}", err);
+
+    let _ = std::fs::remove_dir_all(&input);
+    let _ = std::fs::remove_dir_all(&output);
+}
+
+#[test]
+fn test_custom_format_blobs_block_round_trips() {
+    use pg_stage_rs::format::custom::CustomHandler;
+
+    let entries = [
+        (1, "TABLE DATA", "", "COPY public.users (id, email) FROM stdin;\n", ""),
+        (2, "BLOBS", "", "", ""),
+    ];
+    let mut dump = build_toc_dat(1, false, &entries);
+    dump.push(0x01);
+    put_int(&mut dump, 1);
+    let data = b"1\ta@example.com\n";
+    put_int(&mut dump, data.len() as i32);
+    dump.extend_from_slice(data);
+    put_int(&mut dump, 0);
+    // Two large objects; the first split over two chunks.
+    dump.push(0x03);
+    put_int(&mut dump, 2);
+    for (oid, chunks) in [(16385, vec![&b"hello "[..], &b"world"[..]]), (16386, vec![&b"\x00\x01\x02"[..]])] {
+        put_int(&mut dump, oid);
+        for chunk in chunks {
+            put_int(&mut dump, chunk.len() as i32);
+            dump.extend_from_slice(chunk);
+        }
+        put_int(&mut dump, 0);
+    }
+    put_int(&mut dump, 0);
+    dump.push(0x04);
+
+    let mut output = Vec::new();
+    CustomHandler::new(make_processor())
+        .process(Cursor::new(&dump[5..]), &mut output, &dump[..5])
+        .unwrap();
+    assert_eq!(output, dump);
+
+    #[cfg(feature = "parallel")]
+    {
+        let mut output = Vec::new();
+        CustomHandler::new(make_processor())
+            .threads(2)
+            .process(Cursor::new(&dump[5..]), &mut output, &dump[..5])
+            .unwrap();
+        assert_eq!(output, dump);
+    }
+}
+
+/// Build an uncompressed custom archive with `tables` tables `public.t<i>
+/// (id, name, note)`, `name` masked with `first_name`, `rows` rows each.
+#[cfg(feature = "parallel")]
+fn build_custom_dump(tables: usize, rows: usize) -> Vec<u8> {
+    let stmts: Vec<(String, String)> = (0..tables)
+        .map(|i| {
+            (
+                format!("COMMENT ON COLUMN public.t{i}.name IS 'anon: [{{\"mutation_name\": \"first_name\"}}]';\n"),
+                format!("COPY public.t{i} (id, name, note) FROM stdin;\n"),
+            )
+        })
+        .collect();
+    let mut entries = Vec::new();
+    for (i, (comment, copy)) in stmts.iter().enumerate() {
+        entries.push((100 + i as i32, "COMMENT", comment.as_str(), "", ""));
+        entries.push((i as i32 + 1, "TABLE DATA", "", copy.as_str(), ""));
+    }
+    let mut dump = build_toc_dat(1, false, &entries);
+    for i in 1..=tables as i32 {
+        dump.push(0x01);
+        put_int(&mut dump, i);
+        let data: String = (0..rows).map(|r| format!("{r}\toriginal{r}\tnote {r}\n")).collect();
+        put_int(&mut dump, data.len() as i32);
+        dump.extend_from_slice(data.as_bytes());
+        put_int(&mut dump, 0);
+    }
+    dump.push(0x04);
+    dump
+}
+
+#[cfg(feature = "parallel")]
+fn run_custom_dump(dump: &[u8], threads: usize, line_threads: usize) -> Vec<u8> {
+    use pg_stage_rs::format::custom::CustomHandler;
+
+    let processor = DataProcessor::builder().seed(5).build();
+    let mut output = Vec::new();
+    CustomHandler::new(processor)
+        .threads(threads)
+        .line_threads(line_threads)
+        .process(Cursor::new(&dump[5..]), &mut output, &dump[..5])
+        .unwrap();
+    output
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+fn test_custom_format_threads_match_serial_output() {
+    let dump = build_custom_dump(6, 200);
+    let serial = run_custom_dump(&dump, 1, 1);
+    assert!(!String::from_utf8_lossy(&serial).contains("original"));
+    assert_eq!(run_custom_dump(&dump, 4, 1), serial);
+    assert_eq!(run_custom_dump(&dump, 0, 1), serial);
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+fn test_custom_format_threads_summary_counts_worker_rows() {
+    drop(captured_logs());
+    run_custom_dump(&build_custom_dump(3, 7), 3, 1);
+    // Worker rows are counted; per-worker tracker sizes are left out.
+    assert!(captured_logs()
+        .iter()
+        .any(|l| l == "INFO processed rows: 21, mutations applied: 21"));
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+fn test_custom_format_line_threads_keep_row_structure() {
+    // One table large enough to be split into several shares per window.
+    let dump = build_custom_dump(1, 20_000);
+    let serial = run_custom_dump(&dump, 1, 1);
+    let split = run_custom_dump(&dump, 1, 4);
+    assert_eq!(run_custom_dump(&dump, 1, 4), split, "seeded split runs repeat");
+
+    let rows = |out: &[u8]| -> Vec<Vec<String>> {
+        let text = String::from_utf8_lossy(out).into_owned();
+        let start = text.find("0\t").unwrap();
+        text[start..]
+            .lines()
+            .take(20_000)
+            .map(|l| l.split('\t').map(str::to_string).collect())
+            .collect()
+    };
+    let (serial_rows, split_rows) = (rows(&serial), rows(&split));
+    assert_eq!(serial_rows.len(), split_rows.len());
+    for (a, b) in serial_rows.iter().zip(&split_rows) {
+        assert_eq!(a.len(), 3);
+        assert_eq!(b.len(), 3);
+        // id and the unmasked note keep their place; only names are drawn.
+        assert_eq!((&a[0], &a[2]), (&b[0], &b[2]));
+        assert!(!b[1].starts_with("original"));
+    }
+    assert_ne!(serial_rows, split_rows);
+}
+
+/// Append one ustar member (header, data, padding) to `tar`.
+fn put_tar_member(tar: &mut Vec<u8>, name: &str, data: &[u8]) {
+    let mut header = [0u8; 512];
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    header[100..108].copy_from_slice(b"0000600\0");
+    header[108..116].copy_from_slice(b"0000000\0");
+    header[116..124].copy_from_slice(b"0000000\0");
+    header[124..136].copy_from_slice(format!("{:011o}\0", data.len()).as_bytes());
+    header[136..148].copy_from_slice(b"00000000000\0");
+    header[148..156].copy_from_slice(b"        ");
+    header[156] = b'0';
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    let sum: u32 = header.iter().map(|&b| b as u32).sum();
+    header[148..156].copy_from_slice(format!("{:06o}\0 ", sum).as_bytes());
+    tar.extend_from_slice(&header);
+    tar.extend_from_slice(data);
+    tar.resize(tar.len().div_ceil(512) * 512, 0);
+}
+
+/// Split a ustar archive into `(name, data, header_checksum_ok)` members.
+fn read_tar_members(tar: &[u8]) -> Vec<(String, Vec<u8>, bool)> {
+    let mut members = Vec::new();
+    let mut pos = 0;
+    while pos + 512 <= tar.len() && tar[pos..pos + 512].iter().any(|&b| b != 0) {
+        let header = &tar[pos..pos + 512];
+        let name_end = header.iter().position(|&b| b == 0).unwrap();
+        let name = String::from_utf8(header[..name_end].to_vec()).unwrap();
+        let size_text = std::str::from_utf8(&header[124..135]).unwrap();
+        let size = usize::from_str_radix(size_text, 8).unwrap();
+        let stored = usize::from_str_radix(std::str::from_utf8(&header[148..154]).unwrap(), 8).unwrap();
+        let sum: usize = header
+            .iter()
+            .enumerate()
+            .map(|(i, &b)| if (148..156).contains(&i) { b' ' as usize } else { b as usize })
+            .sum();
+        pos += 512;
+        members.push((name, tar[pos..pos + size].to_vec(), stored == sum));
+        pos += size.div_ceil(512) * 512;
+    }
+    members
+}
+
+#[test]
+fn test_tar_format_mutates_data_member() {
+    use pg_stage_rs::format::tar::TarHandler;
+    use pg_stage_rs::format::{detect_format, DumpFormat};
+
+    let toc = build_toc_dat(3, false, &users_toc_entries());
+    let mut tar = Vec::new();
+    put_tar_member(&mut tar, "toc.dat", &toc);
+    put_tar_member(&mut tar, "2.dat", b"1\tj@example.com\n2\tsomeone.else@example.com\n\\.\n");
+    put_tar_member(&mut tar, "3.dat", b"1\tkeep me\n\\.\n");
+    put_tar_member(&mut tar, "restore.sql", b"-- restore script\n");
+    tar.extend_from_slice(&[0u8; 1024]);
+
+    assert_eq!(detect_format(&tar[..5]).unwrap(), DumpFormat::Tar);
+
+    let mut handler = TarHandler::new(make_processor());
+    let mut output = Vec::new();
+    handler.process(Cursor::new(&tar[5..]), &mut output, &tar[..5]).unwrap();
+
+    assert_eq!(output.len() % 512, 0);
+    assert!(output.ends_with(&[0u8; 1024]));
+    let members = read_tar_members(&output);
+    let names: Vec<&str> = members.iter().map(|m| m.0.as_str()).collect();
+    assert_eq!(names, ["toc.dat", "2.dat", "3.dat", "restore.sql"]);
+    assert!(members.iter().all(|m| m.2), "checksums must be valid");
+    assert_eq!(members[0].1, toc);
+    assert_eq!(members[1].1, b"1\tREDACTED\n2\tREDACTED\n\\.\n");
+    assert_eq!(members[2].1, b"1\tkeep me\n\\.\n");
+    assert_eq!(members[3].1, b"-- restore script\n");
+}